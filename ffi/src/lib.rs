@@ -0,0 +1,7 @@
+//! The `spatial` static and shared libraries for C and C++, built with
+//! `cargo build --release` in this directory and declared in
+//! `include/spatial.h`.
+
+extern crate index;
+
+pub use index::ffi::*;
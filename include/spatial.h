@@ -0,0 +1,62 @@
+/*
+ * C interface to the quadtrees and octrees of the `spatial` crate.
+ *
+ * Build the libraries with `cargo build --release` in the `ffi`
+ * directory and link against `libspatial.a` or the `spatial` shared
+ * library from `ffi/target/release`. The functions are described in
+ * `src/ffi.rs`.
+ *
+ * Trees are opaque handles storing float points tagged with a
+ * caller-chosen id, and must be released with the matching `destroy`
+ * function. Points and corners point to two floats for quadtrees and
+ * three for octrees. Queries write the ids of the matching points into
+ * `out`, up to `out_len` of them, and return the total number of
+ * matches, which may exceed `out_len`. Null handles, points and corners
+ * are refused: a constructor returns null, an insert `false` and a
+ * query `0`. Constructors also return null for a capacity of `0` or
+ * above `SPATIAL_MAX_CAPACITY`.
+ */
+
+#ifndef SPATIAL_H
+#define SPATIAL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The largest capacity the constructors accept. */
+#define SPATIAL_MAX_CAPACITY 65536
+
+/* An opaque handle to a quadtree of float points. */
+typedef struct SpatialQuadtree SpatialQuadtree;
+
+/* An opaque handle to an octree of float points. */
+typedef struct SpatialOctree SpatialOctree;
+
+SpatialQuadtree *spatial_quadtree_new(const float *min, const float *max, size_t capacity);
+void spatial_quadtree_destroy(SpatialQuadtree *tree);
+bool spatial_quadtree_insert(SpatialQuadtree *tree, const float *point, uint64_t id);
+size_t spatial_quadtree_len(const SpatialQuadtree *tree);
+size_t spatial_quadtree_query_volume(const SpatialQuadtree *tree, const float *min, const float *max,
+                                     uint64_t *out, size_t out_len);
+size_t spatial_quadtree_query_radius(const SpatialQuadtree *tree, const float *center, float radius,
+                                     uint64_t *out, size_t out_len);
+
+SpatialOctree *spatial_octree_new(const float *min, const float *max, size_t capacity);
+void spatial_octree_destroy(SpatialOctree *tree);
+bool spatial_octree_insert(SpatialOctree *tree, const float *point, uint64_t id);
+size_t spatial_octree_len(const SpatialOctree *tree);
+size_t spatial_octree_query_volume(const SpatialOctree *tree, const float *min, const float *max,
+                                   uint64_t *out, size_t out_len);
+size_t spatial_octree_query_radius(const SpatialOctree *tree, const float *center, float radius,
+                                   uint64_t *out, size_t out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
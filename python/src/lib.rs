@@ -0,0 +1,12 @@
+//! The `spatial` Python extension module, built with
+//! `maturin build --release` in this directory.
+
+extern crate pyo3;
+extern crate index;
+
+use pyo3::prelude::*;
+
+#[pymodule]
+fn spatial(module: &Bound<PyModule>) -> PyResult<()> {
+    index::python::register(module)
+}
@@ -0,0 +1,57 @@
+import numpy as np
+import pytest
+
+import spatial
+
+
+def test_quadtree_queries():
+    points = np.array([[0.1, 0.1], [0.2, 0.8], [0.5, 0.5], [0.9, 0.9], [0.52, 0.48]])
+    tree = spatial.Quadtree([0, 0], [1, 1], capacity=2)
+    tree.insert(points[:3])
+    tree.insert(points[3:])
+    assert len(tree) == 5
+
+    assert sorted(tree.query_radius([0.5, 0.5], 0.05)) == [2, 4]
+    assert sorted(tree.query_box(np.array([0.0, 0.0]), np.array([0.55, 0.55]))) == [0, 2, 4]
+
+    nearest = tree.nearest(np.array([[0.5, 0.5], [1.0, 1.0]]), 2)
+    assert nearest.shape == (2, 2)
+    assert list(nearest[0]) == [2, 4]
+    assert nearest[1][0] == 3
+    assert tree.nearest([[0.0, 0.0]], 10).shape == (1, 5)
+
+
+def test_octree_queries():
+    rng = np.random.default_rng(7)
+    points = rng.random((200, 3))
+    tree = spatial.Octree([0, 0, 0], [1, 1, 1])
+    tree.insert(points)
+
+    center = np.array([0.5, 0.5, 0.5])
+    expected = np.flatnonzero(np.linalg.norm(points - center, axis=1) <= 0.3)
+    assert sorted(tree.query_radius(center, 0.3)) == list(expected)
+
+    nearest = tree.nearest(points[:10], 1)
+    assert list(nearest[:, 0]) == list(range(10))
+
+
+def test_invalid_input():
+    with pytest.raises(ValueError):
+        spatial.Quadtree([1, 1], [0, 0])
+    with pytest.raises(ValueError):
+        spatial.Quadtree([0, 0], [1, 1], capacity=0)
+    with pytest.raises(ValueError):
+        spatial.Quadtree([0, 0], [1, 1], capacity=1 << 40)
+    with pytest.raises(ValueError):
+        spatial.Octree([0, 0, 0], [1, 1, 1], capacity=0)
+    with pytest.raises(ValueError):
+        spatial.Octree([0, 0, 0], [1, 1, 1], capacity=1 << 40)
+
+    tree = spatial.Quadtree([0, 0], [1, 1])
+    with pytest.raises(ValueError):
+        tree.insert(np.zeros((3, 3)))
+    with pytest.raises(ValueError):
+        tree.insert(np.array([[0.5, 0.5], [2.0, 0.5]]))
+    assert len(tree) == 0
+    with pytest.raises(ValueError):
+        tree.nearest([[float("nan"), 0.5]], 1)
@@ -0,0 +1,343 @@
+//! A ball tree over a static set of points of many dimensions.
+
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// The maximum number of points in a ball that isn't split further.
+static LEAF_SIZE: usize = 16;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `BallTree`.
+pub trait Index<T: SpatialKey> {
+    /// Returns the coordinate of the position of `self` along `axis`.
+    fn balltree_coordinate(&self, axis: usize) -> T;
+}
+
+impl<T: SpatialKey> Index<T> for Vec<T> {
+    fn balltree_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+impl<T: SpatialKey> Index<T> for [T; 2] {
+    fn balltree_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+impl<T: SpatialKey> Index<T> for [T; 3] {
+    fn balltree_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+/// An item index paired with a squared distance, ordered so that the
+/// farthest one is popped first from a `BinaryHeap`.
+struct Farthest<T: SpatialKey> {
+    d2: T,
+    index: usize
+}
+
+impl<T: SpatialKey> PartialEq for Farthest<T> {
+    fn eq(&self, other: &Farthest<T>) -> bool {
+        self.d2 == other.d2
+    }
+}
+
+impl<T: SpatialKey> Eq for Farthest<T> {}
+
+impl<T: SpatialKey> PartialOrd for Farthest<T> {
+    fn partial_cmp(&self, other: &Farthest<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey> Ord for Farthest<T> {
+    fn cmp(&self, other: &Farthest<T>) -> Ordering {
+        self.d2.partial_cmp(&other.d2).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A node of a `BallTree`: the smallest ball around the centroid of a
+/// range of items that encloses all of them.
+struct Ball<T: SpatialKey> {
+    radius: T,
+    start: usize,
+    end: usize,
+    /// The indices of the two balls splitting this one, if any.
+    children: Option<(usize, usize)>
+}
+
+/// A ball tree over a fixed set of points of any number of dimensions,
+/// built by recursively splitting the points at their median along the
+/// axis of the largest spread, and bounding every range of them by a
+/// ball rather than a box.
+///
+/// Balls stay tight where the boxes of a `KdTree` become loose, so it
+/// prunes better for the medium to high-dimensional vectors of machine
+/// learning, such as feature vectors and embeddings.
+pub struct BallTree<T: SpatialKey, P: Index<T> + Clone> {
+    dimensions: usize,
+    /// Items, ordered so that each ball covers a range.
+    items: Vec<P>,
+    /// The coordinates of the items, `dimensions` at a time.
+    coords: Vec<T>,
+    /// The balls, the root first.
+    balls: Vec<Ball<T>>,
+    /// The centers of the balls, `dimensions` at a time.
+    centers: Vec<T>
+}
+
+/// Returns the centroid of the points `order` of `coords` in the type
+/// `convert` converts them to, or `None` if one of them doesn't convert.
+fn centroid<T, F, C>(coords: &[T], order: &[usize], dimensions: usize, convert: C) -> Option<Vec<F>>
+    where T: SpatialKey, F: Float, C: Fn(T) -> Option<F> {
+    let mut sum = vec![F::zero(); dimensions];
+    for &i in order.iter() {
+        for axis in 0..dimensions {
+            match convert(coords[i * dimensions + axis]) {
+                Some(x) => sum[axis] = sum[axis] + x,
+                None => return None
+            }
+        }
+    }
+    let n: F = match NumCast::from(order.len()) {
+        Some(n) => n,
+        None => return None
+    };
+    Some(sum.into_iter().map(|s| s / n).collect())
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> BallTree<T, P> {
+    /// Builds a tree over the points `items`, each with `dimensions`
+    /// coordinates.
+    ///
+    /// Panics if `dimensions` is zero.
+    pub fn new(dimensions: usize, items: &[P]) -> BallTree<T, P> {
+        assert!(dimensions > 0);
+        let _span = trace_span!("balltree build", items = items.len());
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let mut coords = Vec::with_capacity(items.len() * dimensions);
+        for item in items.iter() {
+            for axis in 0..dimensions {
+                coords.push(item.balltree_coordinate(axis));
+            }
+        }
+        let mut order: Vec<usize> = (0..items.len()).collect();
+
+        let mut tree = BallTree {
+            dimensions: dimensions,
+            items: Vec::new(),
+            coords: Vec::new(),
+            balls: Vec::new(),
+            centers: Vec::new()
+        };
+        if !items.is_empty() {
+            tree.build(&coords, &mut order, 0);
+        }
+        for &i in order.iter() {
+            tree.items.push(items[i].clone());
+            tree.coords.extend(coords[i * dimensions..(i + 1) * dimensions].iter().cloned());
+        }
+        trace_event!("balltree build: {} items in {} balls in {:?}", tree.items.len(), tree.balls.len(),
+                     start.elapsed());
+        tree
+    }
+
+    /// Returns the number of dimensions of the points in the tree.
+    #[inline]
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the item closest to `point`, or `None` if the tree is
+    /// empty.
+    ///
+    /// Panics if `point` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn nearest(&self, point: &[T]) -> Option<&P> {
+        self.nearest_n(point, 1).pop()
+    }
+
+    /// Returns up to `k` items closest to `point`, ordered from the
+    /// closest to the farthest.
+    ///
+    /// Panics if `point` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn nearest_n(&self, point: &[T], k: usize) -> Vec<&P> {
+        assert_eq!(point.len(), self.dimensions);
+        let mut found = BinaryHeap::new();
+        if k > 0 && !self.balls.is_empty() {
+            let distance = geom::distance2(point, self.center(0)).sqrt();
+            self.nearest_ball(point, k, 0, distance, &mut found);
+        }
+        found.into_sorted_vec().into_iter().map(|entry| &self.items[entry.index]).collect()
+    }
+
+    /// Returns all items within `radius` of `center`, in no particular
+    /// order.
+    ///
+    /// Panics if `center` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn get_in_radius<'a>(&'a self, center: &[T], radius: T) -> Vec<&'a P> {
+        assert_eq!(center.len(), self.dimensions);
+        let mut items = Vec::new();
+        if !self.balls.is_empty() {
+            self.radius_ball(center, radius, 0, &mut items);
+        }
+        items
+    }
+
+    /// Returns the coordinates of the item at `i`.
+    #[inline]
+    fn point(&self, i: usize) -> &[T] {
+        &self.coords[i * self.dimensions..(i + 1) * self.dimensions]
+    }
+
+    /// Returns the center of the ball at `b`.
+    #[inline]
+    fn center(&self, b: usize) -> &[T] {
+        &self.centers[b * self.dimensions..(b + 1) * self.dimensions]
+    }
+
+    /// Adds the ball around the points `order`, which start at `start`
+    /// in the order of the tree, and the balls splitting it. Returns
+    /// the index of the ball.
+    fn build(&mut self, coords: &[T], order: &mut [usize], start: usize) -> usize {
+        let dimensions = self.dimensions;
+
+        // Accumulate the centroid in f64, like the rest of the distance
+        // math, unless the coordinates don't convert to it. Any center
+        // would do, given the radius to the farthest point, so the first
+        // point stands in if not even the count converts.
+        let wide = centroid(coords, order, dimensions, geom::wide)
+            .and_then(|center| center.into_iter().map(geom::narrow).collect::<Option<Vec<T>>>());
+        let center: Vec<T> = match wide.or_else(|| centroid(coords, order, dimensions, Some)) {
+            Some(center) => center,
+            None => coords[order[0] * dimensions..(order[0] + 1) * dimensions].to_vec()
+        };
+        let mut radius2: T = NumCast::from(0).unwrap();
+        for &i in order.iter() {
+            radius2 = radius2.max(geom::distance2(&center, &coords[i * dimensions..(i + 1) * dimensions]));
+        }
+
+        let index = self.balls.len();
+        self.balls.push(Ball { radius: radius2.sqrt(), start: start, end: start + order.len(), children: None });
+        self.centers.extend(center);
+        if order.len() <= LEAF_SIZE {
+            return index;
+        }
+
+        let mut axis = 0;
+        let mut spread: T = Float::neg_infinity();
+        for a in 0..dimensions {
+            let mut min: T = Float::infinity();
+            let mut max: T = Float::neg_infinity();
+            for &i in order.iter() {
+                min = min.min(coords[i * dimensions + a]);
+                max = max.max(coords[i * dimensions + a]);
+            }
+            if max - min > spread {
+                axis = a;
+                spread = max - min;
+            }
+        }
+        order.sort_by(|&a, &b| {
+            coords[a * dimensions + axis].partial_cmp(&coords[b * dimensions + axis]).unwrap_or(Ordering::Equal)
+        });
+
+        let median = order.len() / 2;
+        let (left, right) = order.split_at_mut(median);
+        let l = self.build(coords, left, start);
+        let r = self.build(coords, right, start + median);
+        self.balls[index].children = Some((l, r));
+        index
+    }
+
+    /// Offers the item at `i` to the `k` closest items to `point` found.
+    fn offer(&self, point: &[T], k: usize, i: usize, found: &mut BinaryHeap<Farthest<T>>) {
+        let d2 = geom::distance2(point, self.point(i));
+        if found.len() < k {
+            found.push(Farthest { d2: d2, index: i });
+        } else if found.peek().is_some_and(|farthest| d2 < farthest.d2) {
+            found.pop();
+            found.push(Farthest { d2: d2, index: i });
+        }
+    }
+
+    /// Searches the ball at `b`, whose center is `distance` away from
+    /// `point`, for the `k` closest items to it, visiting the child
+    /// with the closer center first.
+    fn nearest_ball(&self, point: &[T], k: usize, b: usize, distance: T, found: &mut BinaryHeap<Farthest<T>>) {
+        let ball = &self.balls[b];
+        let zero: T = NumCast::from(0).unwrap();
+        let gap = (distance - ball.radius).max(zero);
+        if found.len() == k && found.peek().is_some_and(|farthest| gap * gap >= farthest.d2) {
+            return;
+        }
+
+        match ball.children {
+            Some((l, r)) => {
+                let dl = geom::distance2(point, self.center(l)).sqrt();
+                let dr = geom::distance2(point, self.center(r)).sqrt();
+                if dl <= dr {
+                    self.nearest_ball(point, k, l, dl, found);
+                    self.nearest_ball(point, k, r, dr, found);
+                } else {
+                    self.nearest_ball(point, k, r, dr, found);
+                    self.nearest_ball(point, k, l, dl, found);
+                }
+            },
+            None => for i in ball.start..ball.end {
+                self.offer(point, k, i, found);
+            }
+        }
+    }
+
+    /// Collects the items of the ball at `b` within `radius` of
+    /// `center`.
+    fn radius_ball<'a>(&'a self, center: &[T], radius: T, b: usize, items: &mut Vec<&'a P>) {
+        let ball = &self.balls[b];
+        let distance = geom::distance2(center, self.center(b)).sqrt();
+        if distance - ball.radius > radius {
+            return;
+        }
+        if distance + ball.radius <= radius {
+            // The ball lies entirely inside the query.
+            items.extend(self.items[ball.start..ball.end].iter());
+            return;
+        }
+
+        match ball.children {
+            Some((l, r)) => {
+                self.radius_ball(center, radius, l, items);
+                self.radius_ball(center, radius, r, items);
+            },
+            None => for i in ball.start..ball.end {
+                if geom::point_in_sphere(self.point(i), center, radius) {
+                    items.push(&self.items[i]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! A bounding volume hierarchy over triangles and other primitives.
+
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use octree::{Volume, Ray, BoundedIndex};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// The maximum number of primitives stored in a leaf.
+static LEAF_SIZE: usize = 4;
+
+/// The number of rays traversing the hierarchy together in
+/// `raycast_many`.
+static PACKET_SIZE: usize = 8;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `Bvh`, on top of `BoundedIndex` giving their bounds.
+/// Triangles, given as their three corners, implement it already.
+pub trait Primitive<T: SpatialKey>: BoundedIndex<T> {
+    /// Returns the point `self` is sorted by when the hierarchy is
+    /// built. Defaults to the center of its bounds.
+    fn centroid(&self) -> [T; 3] {
+        self.octree_bounds().center()
+    }
+
+    /// Returns the distance along `ray`, in multiples of its direction,
+    /// where it first hits `self`, along with the surface coordinates
+    /// of the hit, or `None` if the ray misses.
+    fn intersect_ray(&self, ray: &Ray<T>) -> Option<(T, T, T)>;
+
+    /// Returns the point of `self` closest to `p`.
+    fn closest_point(&self, p: &[T; 3]) -> [T; 3];
+}
+
+impl<T: SpatialKey> BoundedIndex<T> for [[T; 3]; 3] {
+    fn octree_bounds(&self) -> Volume<T> {
+        let mut min = self[0];
+        let mut max = self[0];
+        for p in self[1..].iter() {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        Volume::new(min, max)
+    }
+}
+
+impl<T: SpatialKey> Primitive<T> for [[T; 3]; 3] {
+    fn centroid(&self) -> [T; 3] {
+        let val3: T = NumCast::from(3).unwrap();
+        [(self[0][0] + self[1][0] + self[2][0]) / val3,
+         (self[0][1] + self[1][1] + self[2][1]) / val3,
+         (self[0][2] + self[1][2] + self[2][2]) / val3]
+    }
+
+    fn intersect_ray(&self, ray: &Ray<T>) -> Option<(T, T, T)> {
+        geom::ray_intersects_triangle(&ray.origin(), &ray.direction(), self)
+    }
+
+    fn closest_point(&self, p: &[T; 3]) -> [T; 3] {
+        geom::closest_point_on_triangle(p, self)
+    }
+}
+
+/// The closest intersection of a ray with the primitives of a `Bvh`.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit<T: SpatialKey> {
+    /// The distance along the ray, in multiples of its direction.
+    pub t: T,
+    /// The point where the ray hits the primitive.
+    pub point: [T; 3],
+    /// The first surface coordinate of `point`. For triangles, the
+    /// barycentric coordinate toward the second corner.
+    pub u: T,
+    /// The second surface coordinate of `point`. For triangles, the
+    /// barycentric coordinate toward the third corner.
+    pub v: T,
+    /// The index of the primitive that was hit.
+    pub primitive: usize
+}
+
+enum Node<T: SpatialKey> {
+    /// Primitives `indices[start..start + count]`.
+    Leaf { volume: Volume<T>, start: usize, count: usize },
+    /// Two child nodes, stored at the given indices.
+    Branch { volume: Volume<T>, left: usize, right: usize }
+}
+
+impl<T: SpatialKey> Node<T> {
+    fn volume(&self) -> &Volume<T> {
+        match *self {
+            Node::Leaf { ref volume, .. } => volume,
+            Node::Branch { ref volume, .. } => volume
+        }
+    }
+}
+
+/// A bounding volume hierarchy over a fixed set of primitives, built by
+/// recursively splitting the primitives at the median of their
+/// centroids along the longest axis. Holds triangles unless another
+/// `Primitive` is given.
+pub struct Bvh<T: SpatialKey, P: Primitive<T> = [[T; 3]; 3]> {
+    primitives: Vec<P>,
+    /// The layer bits of each primitive.
+    layers: Vec<u32>,
+    /// Primitive indices, ordered so that each leaf covers a range.
+    indices: Vec<usize>,
+    /// The nodes of the hierarchy, with the root first.
+    nodes: Vec<Node<T>>,
+    /// The union of the layer bits of the primitives below each node.
+    node_layers: Vec<u32>
+}
+
+/// Returns the smallest volume enclosing both `a` and `b`.
+fn union<T: SpatialKey>(a: &Volume<T>, b: &Volume<T>) -> Volume<T> {
+    Volume::new([a.min[0].min(b.min[0]), a.min[1].min(b.min[1]), a.min[2].min(b.min[2])],
+                [a.max[0].max(b.max[0]), a.max[1].max(b.max[1]), a.max[2].max(b.max[2])])
+}
+
+impl<T: SpatialKey, P: Primitive<T>> Bvh<T, P> {
+    /// Builds a hierarchy over `primitives`, all of which are in every
+    /// layer.
+    pub fn new(primitives: Vec<P>) -> Bvh<T, P> {
+        let layers = vec![!0; primitives.len()];
+        Bvh::with_layers(primitives, layers)
+    }
+
+    /// Builds a hierarchy over `primitives`, where `layers` holds the
+    /// layer bits of each primitive for `intersect_ray_filtered`.
+    ///
+    /// Panics if `layers` and `primitives` differ in length.
+    pub fn with_layers(primitives: Vec<P>, layers: Vec<u32>) -> Bvh<T, P> {
+        assert_eq!(primitives.len(), layers.len());
+        let mut bvh = Bvh {
+            indices: (0..primitives.len()).collect(),
+            primitives: primitives,
+            layers: layers,
+            nodes: Vec::new(),
+            node_layers: Vec::new()
+        };
+        
+        let _span = trace_span!("bvh build");
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        if !bvh.primitives.is_empty() {
+            let count = bvh.primitives.len();
+            bvh.build(0, count);
+        }
+        trace_event!("bvh build: {} primitives into {} nodes in {:?}", bvh.primitives.len(), bvh.nodes.len(),
+                     start.elapsed());
+        bvh
+    }
+
+    /// Returns the number of primitives in the hierarchy.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.primitives.len()
+    }
+
+    /// Returns `true` if the hierarchy has no primitives.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.primitives.is_empty()
+    }
+
+    /// Returns the primitives of the hierarchy, in the order they were
+    /// given.
+    #[inline]
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
+    }
+
+    /// Returns the layer bits of the primitives, in the order they were
+    /// given.
+    #[inline]
+    pub fn layers(&self) -> &[u32] {
+        &self.layers
+    }
+
+    /// Returns the bounding volume of all primitives, or `None` if the
+    /// hierarchy is empty.
+    #[inline]
+    pub fn bounds(&self) -> Option<&Volume<T>> {
+        self.nodes.first().map(|node| node.volume())
+    }
+
+    /// Returns the closest intersection of `ray` with the primitives, or
+    /// `None` if the ray misses all of them. Nodes are visited front to
+    /// back, skipping those farther away than the closest hit so far.
+    pub fn intersect_ray(&self, ray: &Ray<T>) -> Option<Hit<T>> {
+        self.intersect_ray_filtered(ray, Float::infinity(), !0)
+    }
+
+    /// Returns the closest intersection of `ray` with the primitives
+    /// sharing a layer bit with `mask`, within distance `t_max` along
+    /// the ray. Nodes beyond `t_max`, or without any primitive in the
+    /// layers of `mask`, are skipped entirely.
+    pub fn intersect_ray_filtered(&self, ray: &Ray<T>, t_max: T, mask: u32) -> Option<Hit<T>> {
+        let mut closest: Option<Hit<T>> = None;
+        let mut stack = match self.nodes.first() {
+            Some(root) => match root.volume().intersect_ray(ray) {
+                Some((entry, _)) => vec![(0, entry)],
+                None => return None
+            },
+            None => return None
+        };
+        
+        while let Some((n, entry)) = stack.pop() {
+            if entry > t_max || self.node_layers[n] & mask == 0 || closest.is_some_and(|hit| hit.t < entry) {
+                continue;
+            }
+            
+            match self.nodes[n] {
+                Node::Leaf { start, count, .. } => for &i in self.indices[start..start + count].iter() {
+                    if self.layers[i] & mask == 0 {
+                        continue;
+                    }
+                    match self.primitives[i].intersect_ray(ray) {
+                        Some((t, u, v)) if t <= t_max => {
+                            let closer = match closest {
+                                Some(ref hit) => t < hit.t,
+                                None => true
+                            };
+                            if closer {
+                                closest = Some(Hit { t: t, point: ray.at(t), u: u, v: v, primitive: i });
+                            }
+                        },
+                        _ => {}
+                    }
+                },
+                Node::Branch { left, right, .. } => {
+                    let l = self.nodes[left].volume().intersect_ray(ray);
+                    let r = self.nodes[right].volume().intersect_ray(ray);
+                    match (l, r) {
+                        (Some((l, _)), Some((r, _))) => if l < r {
+                            stack.push((right, r));
+                            stack.push((left, l));
+                        } else {
+                            stack.push((left, l));
+                            stack.push((right, r));
+                        },
+                        (Some((l, _)), None) => stack.push((left, l)),
+                        (None, Some((r, _))) => stack.push((right, r)),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        
+        closest
+    }
+
+    /// Returns the closest intersection of each of `rays` with the
+    /// primitives, like `intersect_ray`. The rays are traced in packets
+    /// of eight that share a single traversal, so each node is fetched
+    /// once for all rays of a packet still able to hit something in it.
+    pub fn raycast_many(&self, rays: &[Ray<T>]) -> Vec<Option<Hit<T>>> {
+        let mut hits = vec![None; rays.len()];
+        for (packet, hits) in rays.chunks(PACKET_SIZE).zip(hits.chunks_mut(PACKET_SIZE)) {
+            self.intersect_packet(packet, hits);
+        }
+        hits
+    }
+
+    /// Returns the point on the primitives closest to `query`, along
+    /// with the index of the primitive it lies on and its distance from
+    /// `query`, or `None` if the hierarchy is empty. Nodes are visited
+    /// nearest first, skipping those farther away than the closest
+    /// point so far.
+    pub fn closest_point(&self, query: &[T; 3]) -> Option<([T; 3], usize, T)> {
+        let mut closest: Option<([T; 3], usize, T)> = None;
+        let mut stack = match self.nodes.first() {
+            Some(root) => vec![(0, geom::aabb_distance2(query, &root.volume().min, &root.volume().max))],
+            None => return None
+        };
+        
+        while let Some((n, d2)) = stack.pop() {
+            if closest.is_some_and(|(_, _, best)| best < d2) {
+                continue;
+            }
+            
+            match self.nodes[n] {
+                Node::Leaf { start, count, .. } => for &i in self.indices[start..start + count].iter() {
+                    let p = self.primitives[i].closest_point(query);
+                    let d2 = geom::distance2(&p, query);
+                    let closer = match closest {
+                        Some((_, _, best)) => d2 < best,
+                        None => true
+                    };
+                    if closer {
+                        closest = Some((p, i, d2));
+                    }
+                },
+                Node::Branch { left, right, .. } => {
+                    let l = self.nodes[left].volume();
+                    let r = self.nodes[right].volume();
+                    let l = (left, geom::aabb_distance2(query, &l.min, &l.max));
+                    let r = (right, geom::aabb_distance2(query, &r.min, &r.max));
+                    if l.1 < r.1 {
+                        stack.push(r);
+                        stack.push(l);
+                    } else {
+                        stack.push(l);
+                        stack.push(r);
+                    }
+                }
+            }
+        }
+        
+        closest.map(|(p, i, d2)| (p, i, d2.sqrt()))
+    }
+
+    /// Stores the closest intersections of up to `PACKET_SIZE` rays in
+    /// `hits`. Each node on the stack carries a bitmask of the rays that
+    /// reached it.
+    fn intersect_packet(&self, rays: &[Ray<T>], hits: &mut [Option<Hit<T>>]) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        
+        let mut stack = vec![(0, (1u32 << rays.len()) - 1)];
+        while let Some((n, active)) = stack.pop() {
+            // Drop the rays that miss the node or already hit something
+            // in front of it.
+            let mut mask = 0;
+            let mut first_entry = None;
+            for (i, ray) in rays.iter().enumerate() {
+                if active & (1 << i) == 0 {
+                    continue;
+                }
+                match self.nodes[n].volume().intersect_ray(ray) {
+                    Some((entry, _)) => {
+                        let ahead = match hits[i] {
+                            Some(ref hit) => entry <= hit.t,
+                            None => true
+                        };
+                        if ahead {
+                            mask |= 1 << i;
+                            first_entry = first_entry.or(Some(i));
+                        }
+                    },
+                    None => {}
+                }
+            }
+            
+            match self.nodes[n] {
+                Node::Leaf { start, count, .. } => for &j in self.indices[start..start + count].iter() {
+                    for (i, ray) in rays.iter().enumerate() {
+                        if mask & (1 << i) == 0 {
+                            continue;
+                        }
+                        match self.primitives[j].intersect_ray(ray) {
+                            Some((t, u, v)) => {
+                                let closer = match hits[i] {
+                                    Some(ref hit) => t < hit.t,
+                                    None => true
+                                };
+                                if closer {
+                                    hits[i] = Some(Hit { t: t, point: ray.at(t), u: u, v: v, primitive: j });
+                                }
+                            },
+                            _ => {}
+                        }
+                    }
+                },
+                Node::Branch { left, right, .. } => match first_entry {
+                    // Visit the child nearer to the first remaining ray
+                    // first.
+                    Some(i) => {
+                        let l = self.nodes[left].volume().intersect_ray(&rays[i]).map(|(entry, _)| entry);
+                        let r = self.nodes[right].volume().intersect_ray(&rays[i]).map(|(entry, _)| entry);
+                        let right_first = match (l, r) {
+                            (Some(l), Some(r)) => r < l,
+                            (None, Some(_)) => true,
+                            _ => false
+                        };
+                        if right_first {
+                            stack.push((left, mask));
+                            stack.push((right, mask));
+                        } else {
+                            stack.push((right, mask));
+                            stack.push((left, mask));
+                        }
+                    },
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Builds the subtree over `indices[start..end]`, returning the
+    /// index of its root node.
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let mut volume = self.primitives[self.indices[start]].octree_bounds();
+        for &i in self.indices[start + 1..end].iter() {
+            volume = union(&volume, &self.primitives[i].octree_bounds());
+        }
+        
+        let index = self.nodes.len();
+        if end - start <= LEAF_SIZE {
+            let layers = self.indices[start..end].iter().fold(0, |layers, &i| layers | self.layers[i]);
+            self.nodes.push(Node::Leaf { volume: volume, start: start, count: end - start });
+            self.node_layers.push(layers);
+            return index;
+        }
+        
+        // Split at the median centroid along the axis they spread most.
+        let first = self.primitives[self.indices[start]].centroid();
+        let (mut lo, mut hi) = (first, first);
+        for &i in self.indices[start + 1..end].iter() {
+            let c = self.primitives[i].centroid();
+            for a in 0..3 {
+                lo[a] = lo[a].min(c[a]);
+                hi[a] = hi[a].max(c[a]);
+            }
+        }
+        let axis = (1..3).fold(0, |best, a| if hi[a] - lo[a] > hi[best] - lo[best] { a } else { best });
+        
+        {
+            let primitives = &self.primitives;
+            // Centroids that are NaN sort last, so that the order stays
+            // total.
+            self.indices[start..end].sort_by(|&a, &b| {
+                let (x, y) = (primitives[a].centroid()[axis], primitives[b].centroid()[axis]);
+                x.partial_cmp(&y).unwrap_or_else(|| x.is_nan().cmp(&y.is_nan()))
+            });
+        }
+        
+        // Reserve the slot so the node precedes its children.
+        self.nodes.push(Node::Leaf { volume: volume, start: start, count: 0 });
+        self.node_layers.push(0);
+        let mid = start + (end - start) / 2;
+        let left = self.build(start, mid);
+        let right = self.build(mid, end);
+        self.nodes[index] = Node::Branch { volume: volume, left: left, right: right };
+        self.node_layers[index] = self.node_layers[left] | self.node_layers[right];
+        index
+    }
+}
@@ -0,0 +1,316 @@
+//! Building trees from Arrow columns, enabled with the `columnar`
+//! feature.
+//!
+//! `Quadtree::from_batches` and `Octree::from_batches` index the rows of
+//! Arrow `RecordBatch`es by their `x`, `y` (and `z`) columns, which may
+//! be `Float32` or `Float64` arrays without nulls. The coordinates are
+//! read straight from the buffers of the arrays, without copying the
+//! columns first. Each batch is a chunk of one table, and its rows are
+//! numbered on from where the previous batch ended, so a `Row` of the
+//! tree tells which row of the table it came from. `from_parquet` reads
+//! the batches from a Parquet file, decoding only the coordinate
+//! columns.
+//!
+//! Query results go back to the table as Arrow selection vectors:
+//! `take_indices` returns the rows found as indices for the `take`
+//! kernel, and `selection` returns them as a mask of one batch for the
+//! `filter` kernels.
+
+use SpatialKey;
+use SpatialError;
+use quadtree;
+use octree;
+use quadtree::Quadtree;
+use octree::Octree;
+use num::NumCast;
+use arrow::array::{BooleanArray, Float32Array, Float64Array, UInt64Array};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::ChunkReader;
+
+/// A point read from a row of a table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Row<P> {
+    /// The position of the point, from the coordinate columns.
+    pub position: P,
+    /// The index of the row in the table, counting the rows of all the
+    /// batches before it.
+    pub row: usize
+}
+
+impl<T: SpatialKey> quadtree::Index<T> for Row<[T; 2]> {
+    fn quadtree_index(&self) -> [T; 2] {
+        self.position
+    }
+}
+
+impl<T: SpatialKey> octree::Index<T> for Row<[T; 3]> {
+    fn octree_index(&self) -> [T; 3] {
+        self.position
+    }
+}
+
+/// The values of a coordinate column.
+enum Column<'a> {
+    F32(&'a [f32]),
+    F64(&'a [f64])
+}
+
+impl<'a> Column<'a> {
+    /// Returns the value of row `i` as a `T`, or `None` if it doesn't
+    /// fit in one.
+    fn get<T: SpatialKey>(&self, i: usize) -> Option<T> {
+        match *self {
+            Column::F32(values) => NumCast::from(values[i]),
+            Column::F64(values) => NumCast::from(values[i])
+        }
+    }
+}
+
+/// Returns the values of the column `name` of `batch`, or
+/// `SpatialError::Serialization` if there's no such column, or if it
+/// isn't a column of floats without nulls.
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<Column<'a>, SpatialError> {
+    let array = match batch.column_by_name(name) {
+        Some(array) => array,
+        None => return Err(SpatialError::Serialization(format!("no column {}", name)))
+    };
+    if array.null_count() > 0 {
+        return Err(SpatialError::Serialization(format!("column {} has nulls", name)));
+    }
+    if let Some(array) = array.as_any().downcast_ref::<Float64Array>() {
+        return Ok(Column::F64(array.values()));
+    }
+    if let Some(array) = array.as_any().downcast_ref::<Float32Array>() {
+        return Ok(Column::F32(array.values()));
+    }
+    Err(SpatialError::Serialization(format!("column {} isn't of floats", name)))
+}
+
+/// Reads the positions of the rows of `batch` from its `columns`.
+/// Returns `SpatialError::Serialization` if a column can't be read, and
+/// `SpatialError::OutOfBounds` if a position doesn't fit in `T`.
+fn positions<T: SpatialKey>(batch: &RecordBatch, columns: &[&str]) -> Result<Vec<Vec<T>>, SpatialError> {
+    let mut values = Vec::with_capacity(columns.len());
+    for name in columns.iter() {
+        match column(batch, name) {
+            Ok(column) => values.push(column),
+            Err(err) => return Err(err)
+        }
+    }
+
+    let mut positions = Vec::with_capacity(batch.num_rows());
+    for i in 0..batch.num_rows() {
+        let mut position = Vec::with_capacity(columns.len());
+        for column in values.iter() {
+            match column.get(i) {
+                Some(x) => position.push(x),
+                None => return Err(SpatialError::OutOfBounds)
+            }
+        }
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+/// Reads the record batches of the Parquet file `reader`, decoding only
+/// the `columns`, and hands them to `insert` in order.
+fn read_parquet<R, F>(reader: R, columns: &[&str], mut insert: F) -> Result<(), SpatialError>
+    where R: ChunkReader + 'static,
+          F: FnMut(&RecordBatch) -> Result<(), SpatialError>
+{
+    let builder = match ParquetRecordBatchReaderBuilder::try_new(reader) {
+        Ok(builder) => builder,
+        Err(err) => return Err(SpatialError::Serialization(err.to_string()))
+    };
+    let mut roots = Vec::with_capacity(columns.len());
+    for name in columns.iter() {
+        match builder.schema().index_of(name) {
+            Ok(i) => roots.push(i),
+            Err(_) => return Err(SpatialError::Serialization(format!("no column {}", name)))
+        }
+    }
+    let mask = ProjectionMask::roots(builder.parquet_schema(), roots);
+    let batches = match builder.with_projection(mask).build() {
+        Ok(batches) => batches,
+        Err(err) => return Err(SpatialError::Serialization(err.to_string()))
+    };
+
+    for batch in batches {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(err) => return Err(SpatialError::Serialization(err.to_string()))
+        };
+        match insert(&batch) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(())
+}
+
+impl<T: SpatialKey> Quadtree<T, Row<[T; 2]>> {
+    /// Inserts the rows of `batch`, numbering them from `first_row`, at
+    /// the positions in its `columns`, such as `["x", "y"]`.
+    ///
+    /// Nothing is inserted if a column can't be read, which returns
+    /// `SpatialError::Serialization`, or if a position lies outside of
+    /// the tree, which returns `SpatialError::OutOfBounds`. If a row
+    /// can't be stored, the rows before it stay inserted.
+    pub fn insert_batch(&mut self, batch: &RecordBatch, columns: [&str; 2], first_row: usize) -> Result<(), SpatialError> {
+        let positions = match positions(batch, &columns) {
+            Ok(positions) => positions,
+            Err(err) => return Err(err)
+        };
+        let bounds = self.bounds();
+        if positions.iter().any(|p| !bounds.contains(&[p[0], p[1]])) {
+            return Err(SpatialError::OutOfBounds);
+        }
+
+        for (i, p) in positions.iter().enumerate() {
+            match self.insert(Row { position: [p[0], p[1]], row: first_row + i }) {
+                Ok(()) => {},
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a quadtree of `vol` from the rows of `batches`, the
+    /// chunks of one table, storing up to `capacity` of them in each
+    /// node. The rows are numbered across the batches.
+    ///
+    /// Returns the error of `insert_batch` if a batch can't be
+    /// inserted.
+    pub fn from_batches<'a, I>(vol: quadtree::Volume<T>, capacity: usize, batches: I,
+                               columns: [&str; 2]) -> Result<Quadtree<T, Row<[T; 2]>>, SpatialError>
+        where I: IntoIterator<Item=&'a RecordBatch>
+    {
+        let mut tree = Quadtree::with_capacity(vol, capacity);
+        let mut rows = 0;
+        for batch in batches {
+            match tree.insert_batch(batch, columns, rows) {
+                Ok(()) => rows += batch.num_rows(),
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Builds a quadtree of `vol` from the rows of the Parquet file
+    /// `reader`, such as a `File`, like `from_batches`.
+    ///
+    /// Returns `SpatialError::Serialization` if the file can't be read.
+    pub fn from_parquet<R>(vol: quadtree::Volume<T>, capacity: usize, reader: R,
+                           columns: [&str; 2]) -> Result<Quadtree<T, Row<[T; 2]>>, SpatialError>
+        where R: ChunkReader + 'static
+    {
+        let mut tree = Quadtree::with_capacity(vol, capacity);
+        let mut rows = 0;
+        let result = read_parquet(reader, &columns, |batch| {
+            let result = tree.insert_batch(batch, columns, rows);
+            rows += batch.num_rows();
+            result
+        });
+        match result {
+            Ok(()) => Ok(tree),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl<T: SpatialKey> Octree<T, Row<[T; 3]>> {
+    /// Inserts the rows of `batch`, numbering them from `first_row`, at
+    /// the positions in its `columns`, such as `["x", "y", "z"]`.
+    ///
+    /// Nothing is inserted if a column can't be read, which returns
+    /// `SpatialError::Serialization`, or if a position lies outside of
+    /// the tree, which returns `SpatialError::OutOfBounds`. If a row
+    /// can't be stored, the rows before it stay inserted.
+    pub fn insert_batch(&mut self, batch: &RecordBatch, columns: [&str; 3], first_row: usize) -> Result<(), SpatialError> {
+        let positions = match positions(batch, &columns) {
+            Ok(positions) => positions,
+            Err(err) => return Err(err)
+        };
+        let bounds = self.bounds();
+        if positions.iter().any(|p| !bounds.contains(&[p[0], p[1], p[2]])) {
+            return Err(SpatialError::OutOfBounds);
+        }
+
+        for (i, p) in positions.iter().enumerate() {
+            match self.insert(Row { position: [p[0], p[1], p[2]], row: first_row + i }) {
+                Ok(()) => {},
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds an octree of `vol` from the rows of `batches`, the chunks
+    /// of one table, storing up to `capacity` of them in each node. The
+    /// rows are numbered across the batches.
+    ///
+    /// Returns the error of `insert_batch` if a batch can't be
+    /// inserted.
+    pub fn from_batches<'a, I>(vol: octree::Volume<T>, capacity: usize, batches: I,
+                               columns: [&str; 3]) -> Result<Octree<T, Row<[T; 3]>>, SpatialError>
+        where I: IntoIterator<Item=&'a RecordBatch>
+    {
+        let mut tree = Octree::with_capacity(vol, capacity);
+        let mut rows = 0;
+        for batch in batches {
+            match tree.insert_batch(batch, columns, rows) {
+                Ok(()) => rows += batch.num_rows(),
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Builds an octree of `vol` from the rows of the Parquet file
+    /// `reader`, such as a `File`, like `from_batches`.
+    ///
+    /// Returns `SpatialError::Serialization` if the file can't be read.
+    pub fn from_parquet<R>(vol: octree::Volume<T>, capacity: usize, reader: R,
+                           columns: [&str; 3]) -> Result<Octree<T, Row<[T; 3]>>, SpatialError>
+        where R: ChunkReader + 'static
+    {
+        let mut tree = Octree::with_capacity(vol, capacity);
+        let mut rows = 0;
+        let result = read_parquet(reader, &columns, |batch| {
+            let result = tree.insert_batch(batch, columns, rows);
+            rows += batch.num_rows();
+            result
+        });
+        match result {
+            Ok(()) => Ok(tree),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+/// Returns the rows of the results of a query in ascending order, as
+/// indices for the `take` kernel.
+pub fn take_indices<'a, P: 'a, I>(results: I) -> UInt64Array
+    where I: IntoIterator<Item=&'a Row<P>>
+{
+    let mut rows: Vec<u64> = results.into_iter().map(|item| item.row as u64).collect();
+    rows.sort();
+    UInt64Array::from(rows)
+}
+
+/// Returns the results of a query as a mask of the `len` rows of the
+/// batch starting at row `first_row`, for the `filter` kernels. Rows of
+/// other batches are left out.
+pub fn selection<'a, P: 'a, I>(results: I, first_row: usize, len: usize) -> BooleanArray
+    where I: IntoIterator<Item=&'a Row<P>>
+{
+    let mut mask = vec![false; len];
+    for item in results {
+        if item.row >= first_row && item.row - first_row < len {
+            mask[item.row - first_row] = true;
+        }
+    }
+    BooleanArray::from(mask)
+}
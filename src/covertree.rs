@@ -0,0 +1,338 @@
+//! A cover tree over items of any metric space.
+
+use SpatialKey;
+use SpatialError;
+use num::NumCast;
+use num::traits::Float;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::mem;
+
+/// An item paired with its distance, ordered so that the farthest one
+/// is popped first from a `BinaryHeap`.
+struct Farthest<'a, T: SpatialKey, P: 'a> {
+    distance: T,
+    item: &'a P
+}
+
+impl<'a, T: SpatialKey, P> PartialEq for Farthest<'a, T, P> {
+    fn eq(&self, other: &Farthest<'a, T, P>) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl<'a, T: SpatialKey, P> Eq for Farthest<'a, T, P> {}
+
+impl<'a, T: SpatialKey, P> PartialOrd for Farthest<'a, T, P> {
+    fn partial_cmp(&self, other: &Farthest<'a, T, P>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T: SpatialKey, P> Ord for Farthest<'a, T, P> {
+    fn cmp(&self, other: &Farthest<'a, T, P>) -> Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A node of a `CoverTree`, holding one item and any others at no
+/// distance from it.
+struct Node<P> {
+    item: P,
+    duplicates: Vec<P>,
+    /// Every child is within `2^level` of the item, and has a lower
+    /// level than the node.
+    level: i32,
+    children: Vec<Node<P>>
+}
+
+/// Returns `2^level`, the distance within which a node at `level`
+/// covers its children.
+#[inline]
+fn cover<T: SpatialKey>(level: i32) -> T {
+    let two: T = NumCast::from(2).unwrap();
+    two.powi(level)
+}
+
+/// Returns `2^(level + 1)`, the distance within which a node at `level`
+/// holds all of its descendants.
+#[inline]
+fn reach<T: SpatialKey>(level: i32) -> T {
+    cover(level.saturating_add(1))
+}
+
+/// A cover tree, for nearest-neighbor and radius queries over a
+/// changing set of items of any metric space. Where the `VpTree` has
+/// to be rebuilt, the cover tree takes insertions and removals, and
+/// its queries stay logarithmic in the number of items for data of
+/// low intrinsic dimension, whatever the space they're embedded in.
+///
+/// This is the simplified cover tree of Izbicki and Shelton: every
+/// node at level `i` covers its children within `2^i`, so all of its
+/// descendants lie within `2^(i + 1)`. Like the `VpTree`, it only ever
+/// measures distances with the function it was created with, which
+/// must be a metric.
+pub struct CoverTree<T: SpatialKey, P, F: Fn(&P, &P) -> T> {
+    root: Option<Node<P>>,
+    len: usize,
+    distance: F
+}
+
+impl<T: SpatialKey, P, F: Fn(&P, &P) -> T> CoverTree<T, P, F> {
+    /// Creates an empty tree, measuring the distances of its items
+    /// with `distance`.
+    pub fn new(distance: F) -> CoverTree<T, P, F> {
+        CoverTree {
+            root: None,
+            len: 0,
+            distance: distance
+        }
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all items from the tree.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Inserts `item` into the tree. Items at no distance from one
+    /// already in the tree are stored alongside it.
+    ///
+    /// Returns `SpatialError::OutOfBounds` if the distance of `item` to
+    /// the root of the tree isn't finite, as no level could cover it.
+    pub fn insert(&mut self, item: P) -> Result<(), SpatialError> {
+        match self.root {
+            Some(ref root) if !(self.distance)(&root.item, &item).is_finite() => {
+                return Err(SpatialError::OutOfBounds);
+            },
+            _ => {}
+        }
+        self.insert_item(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes an item equal to `item` from the tree and returns it,
+    /// or `None` if there is none. The descendants of its node are
+    /// inserted again, which for the root means the whole tree.
+    pub fn remove(&mut self, item: &P) -> Option<P> where P: PartialEq {
+        // `None` once found means the root itself has to go.
+        let found = match self.root {
+            Some(ref mut root) => match take_duplicate(root, item) {
+                Some(removed) => Some((removed, Vec::new())),
+                None if root.item == *item => None,
+                None => match remove_below(root, item, &self.distance) {
+                    Some(found) => Some(found),
+                    None => return None
+                }
+            },
+            None => return None
+        };
+        let (removed, orphans) = match found {
+            Some(found) => found,
+            None => detach(self.root.take().unwrap())
+        };
+
+        for orphan in orphans.into_iter() {
+            self.insert_item(orphan);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Returns the item closest to `query`, or `None` if the tree is
+    /// empty.
+    pub fn nearest(&self, query: &P) -> Option<&P> {
+        self.nearest_n(query, 1).pop()
+    }
+
+    /// Returns up to `k` items closest to `query`, ordered from the
+    /// closest to the farthest.
+    pub fn nearest_n(&self, query: &P, k: usize) -> Vec<&P> {
+        let mut found = BinaryHeap::new();
+        match self.root {
+            Some(ref root) if k > 0 => {
+                let d = (self.distance)(query, &root.item);
+                self.nearest_node(query, k, root, d, &mut found);
+            },
+            _ => {}
+        }
+        found.into_sorted_vec().into_iter().map(|entry| entry.item).collect()
+    }
+
+    /// Returns all items within `radius` of `query`, in no particular
+    /// order.
+    pub fn get_in_radius<'a>(&'a self, query: &P, radius: T) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        match self.root {
+            Some(ref root) => {
+                let d = (self.distance)(query, &root.item);
+                self.radius_node(query, radius, root, d, &mut items);
+            },
+            None => {}
+        }
+        items
+    }
+
+    /// Inserts `item`, whose distance to the root is finite, without
+    /// counting it.
+    fn insert_item(&mut self, item: P) {
+        match self.root {
+            Some(ref mut root) => {
+                let d = (self.distance)(&root.item, &item);
+                let zero: T = NumCast::from(0).unwrap();
+                if d == zero {
+                    root.duplicates.push(item);
+                    return;
+                }
+                // Raise the root until it covers the item. Its children
+                // keep their levels, which stay below its own.
+                while d > cover(root.level) {
+                    root.level += 1;
+                }
+                insert_below(root, item, &self.distance);
+                return;
+            },
+            None => {}
+        }
+        self.root = Some(Node { item: item, duplicates: Vec::new(), level: 0, children: Vec::new() });
+    }
+
+    /// Searches the subtree of `node`, whose item is `distance` away
+    /// from `query`, for the `k` closest items to it, visiting the
+    /// children closest to `query` first.
+    fn nearest_node<'a>(&'a self, query: &P, k: usize, node: &'a Node<P>, distance: T,
+                        found: &mut BinaryHeap<Farthest<'a, T, P>>) {
+        for item in Some(&node.item).into_iter().chain(node.duplicates.iter()) {
+            if found.len() < k {
+                found.push(Farthest { distance: distance, item: item });
+            } else if found.peek().is_some_and(|farthest| distance < farthest.distance) {
+                found.pop();
+                found.push(Farthest { distance: distance, item: item });
+            }
+        }
+
+        let mut children: Vec<(T, &'a Node<P>)> = node.children.iter()
+            .map(|child| ((self.distance)(query, &child.item), child))
+            .collect();
+        children.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        for &(d, child) in children.iter() {
+            // The subtree of the child can only hold items closer than
+            // the farthest one found if its reach gets that close.
+            let tau = if found.len() < k { Float::infinity() } else { found.peek().unwrap().distance };
+            if d - reach(child.level) < tau {
+                self.nearest_node(query, k, child, d, found);
+            }
+        }
+    }
+
+    /// Collects the items of the subtree of `node`, whose item is
+    /// `distance` away from `query`, within `radius` of `query`.
+    fn radius_node<'a>(&'a self, query: &P, radius: T, node: &'a Node<P>, distance: T,
+                       items: &mut Vec<&'a P>) {
+        if distance <= radius {
+            items.push(&node.item);
+            items.extend(node.duplicates.iter());
+        }
+        for child in node.children.iter() {
+            let d = (self.distance)(query, &child.item);
+            if d - reach(child.level) <= radius {
+                self.radius_node(query, radius, child, d, items);
+            }
+        }
+    }
+}
+
+/// Inserts `item`, which `node` covers, below `node`: into the first
+/// child covering it, alongside the item of that child if it's at no
+/// distance from it, or as a new child.
+fn insert_below<T: SpatialKey, P, F: Fn(&P, &P) -> T>(node: &mut Node<P>, item: P, distance: &F) {
+    let zero: T = NumCast::from(0).unwrap();
+    let mut covering = None;
+    for (i, child) in node.children.iter().enumerate() {
+        let d = distance(&child.item, &item);
+        if d <= cover(child.level) {
+            covering = Some((i, d == zero));
+            break;
+        }
+    }
+    match covering {
+        Some((i, true)) => node.children[i].duplicates.push(item),
+        Some((i, false)) => insert_below(&mut node.children[i], item, distance),
+        None => {
+            let level = node.level - 1;
+            node.children.push(Node { item: item, duplicates: Vec::new(), level: level, children: Vec::new() });
+        }
+    }
+}
+
+/// Removes an item equal to `item` from `node` and returns it if the
+/// node keeps another item in its place: if `item` is one of the
+/// duplicates, or the item of the node and a duplicate can take over.
+fn take_duplicate<P: PartialEq>(node: &mut Node<P>, item: &P) -> Option<P> {
+    match node.duplicates.iter().position(|other| other == item) {
+        Some(i) => return Some(node.duplicates.swap_remove(i)),
+        None => {}
+    }
+    if node.item == *item {
+        match node.duplicates.pop() {
+            Some(duplicate) => return Some(mem::replace(&mut node.item, duplicate)),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Removes an item equal to `item` from the subtree of `node`,
+/// searching only the children that can hold it, and returns it along
+/// with the items that have to be inserted again.
+fn remove_below<T: SpatialKey, P: PartialEq, F: Fn(&P, &P) -> T>(node: &mut Node<P>, item: &P, distance: &F)
+                                                                  -> Option<(P, Vec<P>)> {
+    for i in 0..node.children.len() {
+        match take_duplicate(&mut node.children[i], item) {
+            Some(removed) => return Some((removed, Vec::new())),
+            None => {}
+        }
+        if node.children[i].item == *item {
+            return Some(detach(node.children.swap_remove(i)));
+        }
+        if distance(&node.children[i].item, item) <= reach(node.children[i].level) {
+            match remove_below(&mut node.children[i], item, distance) {
+                Some(removed) => return Some(removed),
+                None => {}
+            }
+        }
+    }
+    None
+}
+
+/// Splits a node without duplicates into its item and the items of its
+/// descendants.
+fn detach<P>(node: Node<P>) -> (P, Vec<P>) {
+    let mut orphans = Vec::new();
+    for child in node.children.into_iter() {
+        collect(child, &mut orphans);
+    }
+    (node.item, orphans)
+}
+
+/// Moves the items of the subtree of `node` into `items`.
+fn collect<P>(node: Node<P>, items: &mut Vec<P>) {
+    items.push(node.item);
+    items.extend(node.duplicates);
+    for child in node.children.into_iter() {
+        collect(child, items);
+    }
+}
@@ -0,0 +1,198 @@
+//! Hilbert curves, for ordering points so that points close to each
+//! other along the curve are close in space too.
+//!
+//! Morton order jumps across space at every power of two, while the
+//! Hilbert curve never leaves a cell before visiting all of it, so
+//! sorting items along it keeps the items of a range query in fewer
+//! and longer runs.
+
+use SpatialKey;
+use num::NumCast;
+use quadtree;
+use octree;
+
+/// Returns the cell of `x` in a grid of `2^bits` cells spanning `min`
+/// to `max`. Coordinates outside of the span are clamped onto it.
+fn quantize<T: SpatialKey>(x: T, min: T, max: T, bits: u32) -> u64 {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    let scale: T = NumCast::from((1u64 << bits) - 1).unwrap();
+    let t = (x - min) / (max - min);
+    (t.max(zero).min(one) * scale).to_u64().unwrap_or(0)
+}
+
+/// Transforms the coordinates `x`, of `bits` bits each, into the
+/// transposed form of their Hilbert index, following Skilling's
+/// "Programming the Hilbert curve".
+fn axes_to_transpose(x: &mut [u64], bits: u32) {
+    let n = x.len();
+    let m = 1u64 << (bits - 1);
+
+    // Undo the rotations and reflections of the curve.
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..n {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    // Gray encode.
+    for i in 1..n {
+        x[i] ^= x[i - 1];
+    }
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[n - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for coordinate in x.iter_mut() {
+        *coordinate ^= t;
+    }
+}
+
+/// Transforms the transposed form of a Hilbert index back into the
+/// coordinates `x`, of `bits` bits each.
+fn transpose_to_axes(x: &mut [u64], bits: u32) {
+    let n = x.len();
+
+    // Gray decode.
+    let t = x[n - 1] >> 1;
+    for i in (1..n).rev() {
+        x[i] ^= x[i - 1];
+    }
+    x[0] ^= t;
+
+    // Redo the rotations and reflections of the curve.
+    let mut q = 2;
+    while q != 1u64 << bits {
+        let p = q - 1;
+        for i in (0..n).rev() {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q <<= 1;
+    }
+}
+
+/// Interleaves the transposed form `x` into a Hilbert index, the most
+/// significant bit of the first coordinate first.
+fn pack(x: &[u64], bits: u32) -> u64 {
+    let mut index = 0;
+    for b in (0..bits).rev() {
+        for &xi in x.iter() {
+            index = (index << 1) | ((xi >> b) & 1);
+        }
+    }
+    index
+}
+
+/// Splits the Hilbert index `index` into its transposed form `x`.
+fn unpack(index: u64, x: &mut [u64], bits: u32) {
+    let n = x.len() as u32;
+    for (i, xi) in x.iter_mut().enumerate() {
+        *xi = 0;
+        for b in 0..bits {
+            let shift = b * n + (n - 1 - i as u32);
+            *xi |= ((index >> shift) & 1) << b;
+        }
+    }
+}
+
+/// Returns the index of the grid `cell` along the Hilbert curve through
+/// a grid of `2^bits` by `2^bits` cells.
+///
+/// Panics if `bits` isn't between `1` and `32`.
+pub fn hilbert_encode_2d(cell: [u64; 2], bits: u32) -> u64 {
+    assert!((1..=32).contains(&bits));
+    let mut x = cell;
+    axes_to_transpose(&mut x, bits);
+    pack(&x, bits)
+}
+
+/// Returns the grid cell at `index` along the Hilbert curve through a
+/// grid of `2^bits` by `2^bits` cells. The inverse of
+/// `hilbert_encode_2d`.
+///
+/// Panics if `bits` isn't between `1` and `32`.
+pub fn hilbert_decode_2d(index: u64, bits: u32) -> [u64; 2] {
+    assert!((1..=32).contains(&bits));
+    let mut x = [0; 2];
+    unpack(index, &mut x, bits);
+    transpose_to_axes(&mut x, bits);
+    x
+}
+
+/// Returns the index of the grid `cell` along the Hilbert curve through
+/// a grid of `2^bits` cells along each axis.
+///
+/// Panics if `bits` isn't between `1` and `21`.
+pub fn hilbert_encode_3d(cell: [u64; 3], bits: u32) -> u64 {
+    assert!((1..=21).contains(&bits));
+    let mut x = cell;
+    axes_to_transpose(&mut x, bits);
+    pack(&x, bits)
+}
+
+/// Returns the grid cell at `index` along the Hilbert curve through a
+/// grid of `2^bits` cells along each axis. The inverse of
+/// `hilbert_encode_3d`.
+///
+/// Panics if `bits` isn't between `1` and `21`.
+pub fn hilbert_decode_3d(index: u64, bits: u32) -> [u64; 3] {
+    assert!((1..=21).contains(&bits));
+    let mut x = [0; 3];
+    unpack(index, &mut x, bits);
+    transpose_to_axes(&mut x, bits);
+    x
+}
+
+/// Returns the Hilbert index of `point`, quantized to 32 bits per axis
+/// within `vol`. Points outside of `vol` are clamped onto it.
+pub fn hilbert_key_2d<T: SpatialKey>(vol: &quadtree::Volume<T>, point: &[T; 2]) -> u64 {
+    hilbert_encode_2d([quantize(point[0], vol.min[0], vol.max[0], 32),
+                       quantize(point[1], vol.min[1], vol.max[1], 32)], 32)
+}
+
+/// Returns the Hilbert index of `point`, quantized to 21 bits per axis
+/// within `vol`. Points outside of `vol` are clamped onto it.
+pub fn hilbert_key_3d<T: SpatialKey>(vol: &octree::Volume<T>, point: &[T; 3]) -> u64 {
+    hilbert_encode_3d([quantize(point[0], vol.min[0], vol.max[0], 21),
+                       quantize(point[1], vol.min[1], vol.max[1], 21),
+                       quantize(point[2], vol.min[2], vol.max[2], 21)], 21)
+}
+
+/// Returns `items` sorted along the Hilbert curve through `vol`, such
+/// as for bulk loading them into a tree or laying them out in memory.
+pub fn hilbert_sort_2d<T: SpatialKey, P: quadtree::Index<T>>(vol: &quadtree::Volume<T>, items: Vec<P>) -> Vec<P> {
+    let mut keyed: Vec<(u64, P)> = items.into_iter()
+        .map(|item| (hilbert_key_2d(vol, &item.quadtree_index()), item))
+        .collect();
+    keyed.sort_by_key(|&(key, _)| key);
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Returns `items` sorted along the Hilbert curve through `vol`, such
+/// as for bulk loading them into a tree or laying them out in memory.
+pub fn hilbert_sort_3d<T: SpatialKey, I: octree::Index<T>>(vol: &octree::Volume<T>, items: Vec<I>) -> Vec<I> {
+    let mut keyed: Vec<(u64, I)> = items.into_iter()
+        .map(|item| (hilbert_key_3d(vol, &item.octree_index()), item))
+        .collect();
+    keyed.sort_by_key(|&(key, _)| key);
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
@@ -0,0 +1,561 @@
+//! A dynamic bounding volume tree for broadphase collision detection.
+
+use SpatialKey;
+use num::NumCast;
+use octree::Volume;
+use curves;
+
+/// A change in the overlap of two boxes between two updates of a
+/// `PairManager`, with the smaller proxy id first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PairEvent {
+    /// The fattened bounds of the boxes started to intersect.
+    Begin(usize, usize),
+    /// The fattened bounds of the boxes stopped intersecting.
+    End(usize, usize)
+}
+
+/// Keeps the pairs of a `DynamicAabbTree` from one update to the next,
+/// to report the pairs that begin and end in between rather than all
+/// of them, such as contact events for a physics or trigger system.
+///
+/// A removed box's id may be taken by a new box before the next update,
+/// which would carry the old pairs over, so the pairs of a box should be
+/// ended with `remove` when it's removed from the tree.
+pub struct PairManager {
+    /// The pairs as of the last update, sorted.
+    pairs: Vec<(usize, usize)>
+}
+
+impl PairManager {
+    /// Creates a manager without any pairs.
+    pub fn new() -> PairManager {
+        PairManager {
+            pairs: Vec::new()
+        }
+    }
+
+    /// Returns the pairs as of the last update, sorted.
+    #[inline]
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+
+    /// Finds the pairs of `tree` and returns the events turning the
+    /// pairs of the last update into them: the ones that ended first,
+    /// then the ones that began, each in the order of the pairs.
+    pub fn update<T: SpatialKey, V>(&mut self, tree: &DynamicAabbTree<T, V>) -> Vec<PairEvent> {
+        let pairs = tree.pairs();
+        let mut ended = Vec::new();
+        let mut begun = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.pairs.len() || j < pairs.len() {
+            if j == pairs.len() || (i < self.pairs.len() && self.pairs[i] < pairs[j]) {
+                ended.push(PairEvent::End(self.pairs[i].0, self.pairs[i].1));
+                i += 1;
+            } else if i == self.pairs.len() || pairs[j] < self.pairs[i] {
+                begun.push(PairEvent::Begin(pairs[j].0, pairs[j].1));
+                j += 1;
+            } else {
+                i += 1;
+                j += 1;
+            }
+        }
+        self.pairs = pairs;
+        ended.extend(begun);
+        ended
+    }
+
+    /// Ends all pairs of the box with proxy `id`, returning their
+    /// events, as for a box removed from the tree.
+    pub fn remove(&mut self, id: usize) -> Vec<PairEvent> {
+        let ended = self.pairs.iter()
+            .filter(|&&(a, b)| a == id || b == id)
+            .map(|&(a, b)| PairEvent::End(a, b))
+            .collect();
+        self.pairs.retain(|&(a, b)| a != id && b != id);
+        ended
+    }
+}
+
+impl Default for PairManager {
+    fn default() -> PairManager {
+        PairManager::new()
+    }
+}
+
+/// The index marking a missing node.
+static NULL: usize = !0;
+
+struct Node<T: SpatialKey, V> {
+    /// Bounding volume of the node, fattened by the margin for leaves.
+    volume: Volume<T>,
+    /// Index of the parent, or the next free node for free nodes.
+    parent: usize,
+    /// Indices of the children, `NULL` for leaves.
+    children: [usize; 2],
+    /// Height of the subtree, `0` for leaves and `-1` for free nodes.
+    height: isize,
+    /// The value of a leaf.
+    value: Option<V>
+}
+
+/// Returns the smallest volume enclosing both `a` and `b`.
+fn union<T: SpatialKey>(a: &Volume<T>, b: &Volume<T>) -> Volume<T> {
+    Volume::new([a.min[0].min(b.min[0]), a.min[1].min(b.min[1]), a.min[2].min(b.min[2])],
+                [a.max[0].max(b.max[0]), a.max[1].max(b.max[1]), a.max[2].max(b.max[2])])
+}
+
+/// Returns the surface area of `vol`.
+fn area<T: SpatialKey>(vol: &Volume<T>) -> T {
+    let val2: T = NumCast::from(2).unwrap();
+    let d = [vol.max[0] - vol.min[0], vol.max[1] - vol.min[1], vol.max[2] - vol.min[2]];
+    val2 * (d[0] * d[1] + d[1] * d[2] + d[2] * d[0])
+}
+
+/// Returns `true` if `inner` lies entirely inside `outer`.
+fn encloses<T: SpatialKey>(outer: &Volume<T>, inner: &Volume<T>) -> bool {
+    outer.contains(&inner.min) && outer.contains(&inner.max)
+}
+
+/// A bounding volume tree over moving boxes, in the style of the
+/// broadphase of Box2D and Bullet. Each box is stored in a leaf, fattened
+/// by a margin so that small movements don't change the tree. Leaves are
+/// inserted next to the sibling that grows the surface area of the tree
+/// the least, and the tree is kept balanced by rotations as it changes.
+///
+/// Boxes are identified by the proxy id returned by `insert`, which
+/// stays the same until the box is removed.
+///
+/// Moving boxes can also be given their displacement with
+/// `update_predicted`, which stretches their fattened bounds ahead of
+/// them by a multiple of it, so that a box moving steadily stays in its
+/// leaf for several updates.
+pub struct DynamicAabbTree<T: SpatialKey, V> {
+    nodes: Vec<Node<T, V>>,
+    root: usize,
+    /// Head of the list of free nodes.
+    free: usize,
+    /// Distance by which the boxes are fattened on every side.
+    margin: T,
+    /// How many displacements ahead moving boxes are fattened.
+    prediction: T,
+    len: usize
+}
+
+/// The default of how many displacements ahead moving boxes are
+/// fattened, as in Box2D.
+static DEFAULT_PREDICTION: f32 = 4.0;
+
+impl<T: SpatialKey, V> DynamicAabbTree<T, V> {
+    /// Creates an empty tree, fattening boxes by `margin` on every side,
+    /// and moving boxes by four times their displacement ahead of them.
+    pub fn new(margin: T) -> DynamicAabbTree<T, V> {
+        DynamicAabbTree::with_prediction(margin, NumCast::from(DEFAULT_PREDICTION).unwrap())
+    }
+
+    /// Creates an empty tree, fattening boxes by `margin` on every side,
+    /// and moving boxes by `prediction` times their displacement ahead
+    /// of them in `update_predicted`.
+    pub fn with_prediction(margin: T, prediction: T) -> DynamicAabbTree<T, V> {
+        DynamicAabbTree {
+            nodes: Vec::new(),
+            root: NULL,
+            free: NULL,
+            margin: margin,
+            prediction: prediction,
+            len: 0
+        }
+    }
+
+    /// Returns the distance by which the boxes are fattened on every
+    /// side.
+    #[inline]
+    pub fn margin(&self) -> T {
+        self.margin
+    }
+
+    /// Builds a tree over `boxes` at once, fattening them by `margin` on
+    /// every side. The box at index `i` of `boxes` gets the proxy id
+    /// `i`.
+    ///
+    /// The boxes are sorted along a Hilbert curve through their centers
+    /// and split in halves recursively, which packs boxes close to each
+    /// other under the same nodes and gives a balanced tree much faster
+    /// than inserting them one by one.
+    pub fn bulk_load(margin: T, boxes: Vec<(Volume<T>, V)>) -> DynamicAabbTree<T, V> {
+        let mut tree = DynamicAabbTree::new(margin);
+        if boxes.is_empty() {
+            return tree;
+        }
+
+        let val2: T = NumCast::from(2).unwrap();
+        let mut bounds = boxes[0].0;
+        for (b, _) in boxes.iter() {
+            bounds = union(&bounds, b);
+        }
+        let mut leaves: Vec<(u64, usize)> = Vec::with_capacity(boxes.len());
+        for (volume, value) in boxes.into_iter() {
+            let leaf = tree.allocate();
+            let center = [(volume.min[0] + volume.max[0]) / val2, (volume.min[1] + volume.max[1]) / val2,
+                          (volume.min[2] + volume.max[2]) / val2];
+            leaves.push((curves::hilbert_key_3d(&bounds, &center), leaf));
+            tree.nodes[leaf].volume = tree.fatten(&volume);
+            tree.nodes[leaf].height = 0;
+            tree.nodes[leaf].value = Some(value);
+        }
+        leaves.sort();
+
+        let order: Vec<usize> = leaves.iter().map(|&(_, leaf)| leaf).collect();
+        tree.len = order.len();
+        tree.root = tree.pack(&order);
+        tree
+    }
+
+    /// Returns the number of boxes in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree holds no boxes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the height of the tree, `0` if it holds at most one box.
+    pub fn height(&self) -> usize {
+        if self.root == NULL { 0 } else { self.nodes[self.root].height as usize }
+    }
+
+    /// Returns the value of the box with proxy `id`, or `None` if there
+    /// is no such box.
+    pub fn get(&self, id: usize) -> Option<&V> {
+        self.nodes.get(id).and_then(|node| node.value.as_ref())
+    }
+
+    /// Returns the fattened bounds of the box with proxy `id`, or `None`
+    /// if there is no such box.
+    pub fn fat_bounds(&self, id: usize) -> Option<&Volume<T>> {
+        match self.get(id) {
+            Some(_) => Some(&self.nodes[id].volume),
+            None => None
+        }
+    }
+
+    /// Inserts a box with `bounds` and `value`, returning its proxy id.
+    pub fn insert(&mut self, bounds: Volume<T>, value: V) -> usize {
+        let leaf = self.allocate();
+        self.nodes[leaf].volume = self.fatten(&bounds);
+        self.nodes[leaf].height = 0;
+        self.nodes[leaf].value = Some(value);
+        self.insert_leaf(leaf);
+        self.len += 1;
+        leaf
+    }
+
+    /// Removes the box with proxy `id` and returns its value, or `None`
+    /// if there is no such box. The id may be reused by later inserts.
+    pub fn remove(&mut self, id: usize) -> Option<V> {
+        if self.get(id).is_none() {
+            return None;
+        }
+        self.remove_leaf(id);
+        let value = self.nodes[id].value.take();
+        self.deallocate(id);
+        self.len -= 1;
+        value
+    }
+
+    /// Moves the box with proxy `id` to `bounds`. The tree only changes
+    /// if the box leaves its fattened bounds, in which case it's
+    /// reinserted with new ones and `true` is returned.
+    ///
+    /// Panics if there is no box with proxy `id`.
+    pub fn update(&mut self, id: usize, bounds: Volume<T>) -> bool {
+        assert!(self.get(id).is_some());
+        if encloses(&self.nodes[id].volume, &bounds) {
+            return false;
+        }
+        self.remove_leaf(id);
+        self.nodes[id].volume = self.fatten(&bounds);
+        self.insert_leaf(id);
+        true
+    }
+
+    /// Moves the box with proxy `id` to `bounds`, having moved by
+    /// `displacement` since the last update, like `update`. When the box
+    /// is reinserted, its fattened bounds are stretched by the
+    /// displacement times the prediction of the tree in the direction
+    /// of the movement.
+    ///
+    /// A box that stops after moving fast would keep its stretched
+    /// bounds and collect false pairs, so the box is also reinserted if
+    /// its fattened bounds have grown more than four margins beyond the
+    /// ones it would get now.
+    ///
+    /// Panics if there is no box with proxy `id`.
+    pub fn update_predicted(&mut self, id: usize, bounds: Volume<T>, displacement: [T; 3]) -> bool {
+        assert!(self.get(id).is_some());
+        let zero: T = NumCast::from(0).unwrap();
+        let mut fat = self.fatten(&bounds);
+        for (i, &d) in displacement.iter().enumerate() {
+            let d = d * self.prediction;
+            if d < zero {
+                fat.min[i] = fat.min[i] + d;
+            } else {
+                fat.max[i] = fat.max[i] + d;
+            }
+        }
+
+        if encloses(&self.nodes[id].volume, &bounds) {
+            let val4: T = NumCast::from(4).unwrap();
+            let m = val4 * self.margin;
+            let huge = Volume::new([fat.min[0] - m, fat.min[1] - m, fat.min[2] - m],
+                                   [fat.max[0] + m, fat.max[1] + m, fat.max[2] + m]);
+            if encloses(&huge, &self.nodes[id].volume) {
+                return false;
+            }
+        }
+        self.remove_leaf(id);
+        self.nodes[id].volume = fat;
+        self.insert_leaf(id);
+        true
+    }
+
+    /// Returns the proxy ids of all boxes whose fattened bounds
+    /// intersect `vol`.
+    pub fn query(&self, vol: &Volume<T>) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            if index == NULL || !self.nodes[index].volume.intersects(vol) {
+                continue;
+            }
+            let node = &self.nodes[index];
+            if node.height == 0 {
+                found.push(index);
+            } else {
+                stack.push(node.children[1]);
+                stack.push(node.children[0]);
+            }
+        }
+        found
+    }
+
+    /// Returns the pairs of proxy ids of all boxes whose fattened bounds
+    /// intersect, as potential collisions for a narrowphase to test.
+    /// Each pair is reported once with the smaller id first, and the
+    /// pairs are sorted.
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (id, node) in self.nodes.iter().enumerate() {
+            if node.value.is_none() {
+                continue;
+            }
+            for other in self.query(&node.volume) {
+                if other > id {
+                    pairs.push((id, other));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    fn fatten(&self, bounds: &Volume<T>) -> Volume<T> {
+        let m = self.margin;
+        Volume::new([bounds.min[0] - m, bounds.min[1] - m, bounds.min[2] - m],
+                    [bounds.max[0] + m, bounds.max[1] + m, bounds.max[2] + m])
+    }
+
+    /// Returns the index of an unused node, taken from the free list if
+    /// possible.
+    fn allocate(&mut self) -> usize {
+        if self.free != NULL {
+            let index = self.free;
+            self.free = self.nodes[index].parent;
+            self.nodes[index].parent = NULL;
+            self.nodes[index].children = [NULL, NULL];
+            return index;
+        }
+
+        let zero: T = NumCast::from(0).unwrap();
+        self.nodes.push(Node {
+            volume: Volume::new([zero, zero, zero], [zero, zero, zero]),
+            parent: NULL,
+            children: [NULL, NULL],
+            height: -1,
+            value: None
+        });
+        self.nodes.len() - 1
+    }
+
+    fn deallocate(&mut self, index: usize) {
+        self.nodes[index].parent = self.free;
+        self.nodes[index].height = -1;
+        self.nodes[index].value = None;
+        self.free = index;
+    }
+
+    /// Builds a balanced subtree over the detached `leaves`, pairing
+    /// neighbors in their order, and returns its root.
+    fn pack(&mut self, leaves: &[usize]) -> usize {
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+
+        let mid = leaves.len() / 2;
+        let (a, b) = (self.pack(&leaves[..mid]), self.pack(&leaves[mid..]));
+        let parent = self.allocate();
+        self.nodes[parent].volume = union(&self.nodes[a].volume, &self.nodes[b].volume);
+        self.nodes[parent].height = 1 + self.nodes[a].height.max(self.nodes[b].height);
+        self.nodes[parent].children = [a, b];
+        self.nodes[a].parent = parent;
+        self.nodes[b].parent = parent;
+        parent
+    }
+
+    /// Links the detached `leaf` into the tree next to the sibling whose
+    /// enlargement costs the least surface area.
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL;
+            return;
+        }
+
+        let bounds = self.nodes[leaf].volume;
+        let val2: T = NumCast::from(2).unwrap();
+        let mut index = self.root;
+        while self.nodes[index].height > 0 {
+            let node_area = area(&self.nodes[index].volume);
+            let combined = area(&union(&self.nodes[index].volume, &bounds));
+            // Pairing with this node creates a parent with the combined
+            // area, and descending grows all its ancestors.
+            let cost = val2 * combined;
+            let inheritance = val2 * (combined - node_area);
+            let descend = |child: usize| {
+                let child = &self.nodes[child];
+                let grown = area(&union(&child.volume, &bounds));
+                if child.height == 0 { grown + inheritance } else { grown - area(&child.volume) + inheritance }
+            };
+
+            let children = self.nodes[index].children;
+            let (cost0, cost1) = (descend(children[0]), descend(children[1]));
+            if cost < cost0 && cost < cost1 {
+                break;
+            }
+            index = if cost0 < cost1 { children[0] } else { children[1] };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate();
+        self.nodes[parent].parent = old_parent;
+        self.nodes[parent].volume = union(&bounds, &self.nodes[sibling].volume);
+        self.nodes[parent].height = self.nodes[sibling].height + 1;
+        self.nodes[parent].children = [sibling, leaf];
+        self.replace_child(old_parent, sibling, parent);
+        self.nodes[sibling].parent = parent;
+        self.nodes[leaf].parent = parent;
+
+        self.refit(parent);
+    }
+
+    /// Unlinks `leaf` from the tree, replacing its parent by its sibling.
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let children = self.nodes[parent].children;
+        let sibling = if children[0] == leaf { children[1] } else { children[0] };
+
+        self.replace_child(grandparent, parent, sibling);
+        self.nodes[sibling].parent = grandparent;
+        self.deallocate(parent);
+        if grandparent != NULL {
+            self.refit(grandparent);
+        }
+    }
+
+    /// Makes `new` take the place of `old` among the children of
+    /// `parent`, or as the root if `parent` is `NULL`.
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if parent == NULL {
+            self.root = new;
+        } else if self.nodes[parent].children[0] == old {
+            self.nodes[parent].children[0] = new;
+        } else {
+            self.nodes[parent].children[1] = new;
+        }
+    }
+
+    /// Rebalances and recomputes the bounds and heights of `index` and
+    /// all of its ancestors.
+    fn refit(&mut self, mut index: usize) {
+        while index != NULL {
+            index = self.balance(index);
+            let (volume, height) = {
+                let children = self.nodes[index].children;
+                let (a, b) = (&self.nodes[children[0]], &self.nodes[children[1]]);
+                (union(&a.volume, &b.volume), 1 + a.height.max(b.height))
+            };
+            self.nodes[index].volume = volume;
+            self.nodes[index].height = height;
+            index = self.nodes[index].parent;
+        }
+    }
+
+    /// Rotates the taller child of `a` up if the heights of its children
+    /// differ by more than one, returning the root of the subtree.
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].height < 2 {
+            return a;
+        }
+
+        let children = self.nodes[a].children;
+        let balance = self.nodes[children[1]].height - self.nodes[children[0]].height;
+        if balance > 1 {
+            self.rotate(a, 1)
+        } else if balance < -1 {
+            self.rotate(a, 0)
+        } else {
+            a
+        }
+    }
+
+    /// Rotates child `side` of `a` up into the place of `a`, which takes
+    /// the place of the taller child of the former, and returns the new
+    /// root of the subtree.
+    fn rotate(&mut self, a: usize, side: usize) -> usize {
+        let up = self.nodes[a].children[side];
+        let other = self.nodes[a].children[1 - side];
+        let (f, g) = (self.nodes[up].children[0], self.nodes[up].children[1]);
+
+        // `up` adopts `a` in its first slot and keeps its taller child.
+        let parent = self.nodes[a].parent;
+        self.nodes[up].children[0] = a;
+        self.nodes[up].parent = parent;
+        self.nodes[a].parent = up;
+        self.replace_child(parent, a, up);
+
+        let (keep, give) = if self.nodes[f].height > self.nodes[g].height { (f, g) } else { (g, f) };
+        self.nodes[up].children[1] = keep;
+        self.nodes[a].children[side] = give;
+        self.nodes[give].parent = a;
+
+        let a_volume = union(&self.nodes[other].volume, &self.nodes[give].volume);
+        let a_height = 1 + self.nodes[other].height.max(self.nodes[give].height);
+        self.nodes[a].volume = a_volume;
+        self.nodes[a].height = a_height;
+        self.nodes[up].volume = union(&a_volume, &self.nodes[keep].volume);
+        self.nodes[up].height = 1 + a_height.max(self.nodes[keep].height);
+        up
+    }
+}
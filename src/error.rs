@@ -0,0 +1,54 @@
+//! Errors returned by the data structures.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The ways in which storing or loading spatial data can fail.
+#[derive(Debug)]
+pub enum SpatialError {
+    /// The position or bounds of an item lie outside of the volume of
+    /// the tree, or can't be represented by it.
+    OutOfBounds,
+    /// Storing an item would need a node deeper than the tree allows,
+    /// which happens when too many items share the same position.
+    DepthLimit,
+    /// A volume has its minimum above its maximum on some axis, or
+    /// isn't finite.
+    InvalidVolume,
+    /// There is no item where one was expected, such as at the old
+    /// position given to `update`.
+    NotFound,
+    /// Serialized data is malformed.
+    Serialization(String),
+    /// Reading or writing serialized data failed.
+    Io(io::Error)
+}
+
+impl fmt::Display for SpatialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpatialError::OutOfBounds => f.write_str("item out of bounds"),
+            SpatialError::DepthLimit => f.write_str("tree depth limit reached"),
+            SpatialError::InvalidVolume => f.write_str("invalid volume"),
+            SpatialError::NotFound => f.write_str("item not found"),
+            SpatialError::Serialization(ref message) => write!(f, "malformed data: {}", message),
+            SpatialError::Io(ref err) => write!(f, "i/o error: {}", err)
+        }
+    }
+}
+
+impl Error for SpatialError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SpatialError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SpatialError {
+    fn from(err: io::Error) -> SpatialError {
+        SpatialError::Io(err)
+    }
+}
@@ -0,0 +1,277 @@
+//! A C-compatible interface to the trees, enabled with the `ffi`
+//! feature.
+//!
+//! Trees are created as opaque handles storing `f32` points tagged
+//! with a caller-chosen `u64` id, and must be released with the
+//! matching `destroy` function. Points and corners are passed as
+//! pointers to two or three consecutive floats. Queries write the ids
+//! of the matching points into a caller-provided buffer and return the
+//! total number of matches, which may exceed the size of the buffer.
+//!
+//! Null handles, points and corners are refused rather than read: a
+//! constructor returns null, an insert `false` and a query `0`. So are
+//! capacities above `MAX_CAPACITY`, which a node reserves room for up
+//! front.
+//!
+//! The crate in the `ffi` directory builds these functions into a
+//! static and a shared library, and `include/spatial.h` declares them
+//! for C and C++.
+
+use std::ptr;
+use std::slice;
+use quadtree;
+use octree;
+use quadtree::Quadtree;
+use octree::Octree;
+
+#[derive(Clone)]
+struct Point2 {
+    position: [f32; 2],
+    id: u64
+}
+
+impl quadtree::Index<f32> for Point2 {
+    fn quadtree_index(&self) -> [f32; 2] {
+        self.position
+    }
+}
+
+#[derive(Clone)]
+struct Point3 {
+    position: [f32; 3],
+    id: u64
+}
+
+impl octree::Index<f32> for Point3 {
+    fn octree_index(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+/// The largest capacity the constructors accept.
+pub static MAX_CAPACITY: usize = 1 << 16;
+
+/// An opaque handle to a quadtree of `f32` points.
+pub struct SpatialQuadtree(Quadtree<f32, Point2>);
+
+/// An opaque handle to an octree of `f32` points.
+pub struct SpatialOctree(Octree<f32, Point3>);
+
+/// Reads two consecutive floats from `p`, or returns `None` if it's
+/// null.
+unsafe fn read2(p: *const f32) -> Option<[f32; 2]> {
+    if p.is_null() { None } else { Some([*p, *p.offset(1)]) }
+}
+
+/// Reads three consecutive floats from `p`, or returns `None` if it's
+/// null.
+unsafe fn read3(p: *const f32) -> Option<[f32; 3]> {
+    if p.is_null() { None } else { Some([*p, *p.offset(1), *p.offset(2)]) }
+}
+
+/// Writes the ids of `ids` into `out`, up to `out_len` of them, and
+/// returns how many there are in total. Nothing is written if `out` is
+/// null.
+unsafe fn write_ids<I: Iterator<Item=u64>>(ids: I, out: *mut u64, out_len: usize) -> usize {
+    let out: &mut [u64] = if out.is_null() { &mut [] } else { slice::from_raw_parts_mut(out, out_len) };
+    let mut count = 0;
+    for id in ids {
+        if count < out.len() {
+            out[count] = id;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Creates an empty quadtree spanning from `min` to `max`, whose nodes
+/// hold up to `capacity` points before subdividing. Returns null if a
+/// corner is null, `min` lies above `max` or `capacity` is `0` or above
+/// `MAX_CAPACITY`.
+///
+/// # Safety
+///
+/// `min` and `max` must each be null or point to two readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_new(min: *const f32, max: *const f32,
+                                              capacity: usize) -> *mut SpatialQuadtree {
+    let volume = match (read2(min), read2(max)) {
+        (Some(min), Some(max)) => quadtree::Volume::new(min, max),
+        _ => return ptr::null_mut()
+    };
+    if capacity == 0 || capacity > MAX_CAPACITY || !volume.is_valid() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(SpatialQuadtree(Quadtree::with_capacity(volume, capacity))))
+}
+
+/// Releases a quadtree created with `spatial_quadtree_new`.
+///
+/// # Safety
+///
+/// `tree` must be null or a handle returned by `spatial_quadtree_new`
+/// that hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_destroy(tree: *mut SpatialQuadtree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Inserts the point at `point` with `id`. Returns `false` if the point
+/// is null, lies outside the tree or can't be stored.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, not used by another thread
+/// meanwhile, and `point` null or pointing to two readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_insert(tree: *mut SpatialQuadtree, point: *const f32,
+                                                 id: u64) -> bool {
+    match (tree.is_null(), read2(point)) {
+        (false, Some(position)) => (*tree).0.insert(Point2 { position: position, id: id }).is_ok(),
+        _ => false
+    }
+}
+
+/// Returns the number of points in the tree.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_len(tree: *const SpatialQuadtree) -> usize {
+    if tree.is_null() { 0 } else { (*tree).0.len() }
+}
+
+/// Finds the points between `min` and `max`.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, `min` and `max` null or
+/// pointing to two readable floats each, and `out` null or pointing to
+/// `out_len` writable ids.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_query_volume(tree: *const SpatialQuadtree, min: *const f32,
+                                                       max: *const f32, out: *mut u64,
+                                                       out_len: usize) -> usize {
+    let volume = match (tree.is_null(), read2(min), read2(max)) {
+        (false, Some(min), Some(max)) => quadtree::Volume::new(min, max),
+        _ => return 0
+    };
+    write_ids((*tree).0.get_in_volume(&volume).into_iter().map(|p| p.id), out, out_len)
+}
+
+/// Finds the points within `radius` of `center`.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, `center` null or pointing to
+/// two readable floats, and `out` null or pointing to `out_len`
+/// writable ids.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_quadtree_query_radius(tree: *const SpatialQuadtree, center: *const f32,
+                                                       radius: f32, out: *mut u64,
+                                                       out_len: usize) -> usize {
+    let center = match (tree.is_null(), read2(center)) {
+        (false, Some(center)) => center,
+        _ => return 0
+    };
+    write_ids((*tree).0.get_in_radius(center, radius).into_iter().map(|p| p.id), out, out_len)
+}
+
+/// Creates an empty octree spanning from `min` to `max`, whose nodes
+/// hold up to `capacity` points before subdividing. Returns null if a
+/// corner is null, `min` lies above `max` or `capacity` is `0` or above
+/// `MAX_CAPACITY`.
+///
+/// # Safety
+///
+/// `min` and `max` must each be null or point to three readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_new(min: *const f32, max: *const f32,
+                                            capacity: usize) -> *mut SpatialOctree {
+    let volume = match (read3(min), read3(max)) {
+        (Some(min), Some(max)) => octree::Volume::new(min, max),
+        _ => return ptr::null_mut()
+    };
+    if capacity == 0 || capacity > MAX_CAPACITY || !volume.is_valid() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(SpatialOctree(Octree::with_capacity(volume, capacity))))
+}
+
+/// Releases an octree created with `spatial_octree_new`.
+///
+/// # Safety
+///
+/// `tree` must be null or a handle returned by `spatial_octree_new`
+/// that hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_destroy(tree: *mut SpatialOctree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Inserts the point at `point` with `id`. Returns `false` if the point
+/// is null, lies outside the tree or can't be stored.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, not used by another thread
+/// meanwhile, and `point` null or pointing to three readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_insert(tree: *mut SpatialOctree, point: *const f32,
+                                               id: u64) -> bool {
+    match (tree.is_null(), read3(point)) {
+        (false, Some(position)) => (*tree).0.insert(Point3 { position: position, id: id }).is_ok(),
+        _ => false
+    }
+}
+
+/// Returns the number of points in the tree.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_len(tree: *const SpatialOctree) -> usize {
+    if tree.is_null() { 0 } else { (*tree).0.len() }
+}
+
+/// Finds the points between `min` and `max`.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, `min` and `max` null or
+/// pointing to three readable floats each, and `out` null or pointing
+/// to `out_len` writable ids.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_query_volume(tree: *const SpatialOctree, min: *const f32,
+                                                     max: *const f32, out: *mut u64,
+                                                     out_len: usize) -> usize {
+    let volume = match (tree.is_null(), read3(min), read3(max)) {
+        (false, Some(min), Some(max)) => octree::Volume::new(min, max),
+        _ => return 0
+    };
+    write_ids((*tree).0.get_in_volume(&volume).into_iter().map(|p| p.id), out, out_len)
+}
+
+/// Finds the points within `radius` of `center`.
+///
+/// # Safety
+///
+/// `tree` must be null or a live handle, `center` null or pointing to
+/// three readable floats, and `out` null or pointing to `out_len`
+/// writable ids.
+#[no_mangle]
+pub unsafe extern "C" fn spatial_octree_query_radius(tree: *const SpatialOctree, center: *const f32,
+                                                     radius: f32, out: *mut u64,
+                                                     out_len: usize) -> usize {
+    let center = match (tree.is_null(), read3(center)) {
+        (false, Some(center)) => center,
+        _ => return 0
+    };
+    write_ids((*tree).0.get_in_radius(center, radius).into_iter().map(|p| p.id), out, out_len)
+}
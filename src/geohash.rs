@@ -0,0 +1,363 @@
+//! Geohashes, and an index over latitude and longitude points keyed by
+//! them.
+//!
+//! A geohash names a cell of a grid over the globe with a string of
+//! base 32 characters, each of which splits the cell of the characters
+//! before it into 32. Cells sharing a prefix are nested, so points close
+//! to each other mostly share long prefixes.
+
+use SpatialKey;
+use SpatialError;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use std::f64::consts::PI;
+
+/// The characters of geohashes, in order of their value.
+static BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The number of characters of the geohashes the index is keyed by.
+static MAX_PRECISION: usize = 12;
+
+/// The number of bits of a geohash of `MAX_PRECISION` characters.
+static KEY_BITS: u32 = 60;
+
+/// The mean radius of the Earth, in meters.
+static EARTH_RADIUS: f64 = 6371008.8;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `GeohashIndex`.
+pub trait Index<T: SpatialKey> {
+    /// Returns the position of `self` in order of `[latitude,
+    /// longitude]`, in degrees.
+    fn geohash_index(&self) -> [T; 2];
+}
+
+impl<T: SpatialKey> Index<T> for [T; 2] {
+    fn geohash_index(&self) -> [T; 2] {
+        *self
+    }
+}
+
+/// Returns the number of longitude and latitude bits of a geohash of
+/// `bits` bits. Longitude takes the first bit, and the odd one.
+#[inline]
+fn split_bits(bits: u32) -> (u32, u32) {
+    ((bits + 1) / 2, bits / 2)
+}
+
+/// Returns the geohash of `bits` bits of the cell at `lon` and `lat` in
+/// a grid of the matching numbers of bits.
+fn interleave(lon: u64, lat: u64, bits: u32) -> u64 {
+    let (lon_bits, lat_bits) = split_bits(bits);
+    let mut key = 0;
+    for i in 0..bits {
+        let bit = if i % 2 == 0 { (lon >> (lon_bits - 1 - i / 2)) & 1 } else { (lat >> (lat_bits - 1 - i / 2)) & 1 };
+        key = (key << 1) | bit;
+    }
+    key
+}
+
+/// Returns the longitude and latitude cells of the geohash `key` of
+/// `bits` bits. The inverse of `interleave`.
+fn deinterleave(key: u64, bits: u32) -> (u64, u64) {
+    let (mut lon, mut lat) = (0, 0);
+    for i in 0..bits {
+        let bit = (key >> (bits - 1 - i)) & 1;
+        if i % 2 == 0 { lon = (lon << 1) | bit; } else { lat = (lat << 1) | bit; }
+    }
+    (lon, lat)
+}
+
+/// Returns the 60 bit geohash of `position`, or an error if it isn't a
+/// valid latitude and longitude.
+fn key_of<T: SpatialKey>(position: &[T; 2]) -> Result<u64, SpatialError> {
+    let (lat, lon) = match (geom::wide(position[0]), geom::wide(position[1])) {
+        (Some(lat), Some(lon)) => (lat, lon),
+        _ => return Err(SpatialError::OutOfBounds)
+    };
+    if !((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)) {
+        return Err(SpatialError::OutOfBounds);
+    }
+    let (lon_bits, lat_bits) = split_bits(KEY_BITS);
+    let cell = |t: f64, bits: u32| ((t * (1u64 << bits) as f64) as u64).min((1u64 << bits) - 1);
+    Ok(interleave(cell((lon + 180.0) / 360.0, lon_bits), cell((lat + 90.0) / 180.0, lat_bits), KEY_BITS))
+}
+
+/// Returns the bits of the geohash `hash`, or an error if it isn't one.
+fn parse(hash: &str) -> Result<u64, SpatialError> {
+    if hash.len() > MAX_PRECISION {
+        return Err(SpatialError::Serialization(format!("geohash {} is longer than {} characters", hash,
+                                                       MAX_PRECISION)));
+    }
+    let mut key = 0;
+    for c in hash.bytes() {
+        match BASE32.iter().position(|&b| b == c) {
+            Some(value) => key = (key << 5) | value as u64,
+            None => return Err(SpatialError::Serialization(format!("invalid character in geohash {}", hash)))
+        }
+    }
+    Ok(key)
+}
+
+/// Returns the geohash string of the `precision` characters of `key`.
+fn format(key: u64, precision: usize) -> String {
+    (0..precision).map(|i| BASE32[((key >> (5 * (precision - 1 - i))) & 31) as usize] as char).collect()
+}
+
+/// Returns the geohash of `precision` characters of the cell containing
+/// `lat` and `lon`, in degrees, or `SpatialError::OutOfBounds` if they
+/// aren't a valid latitude and longitude.
+///
+/// Panics if `precision` isn't between `1` and `12`.
+pub fn encode<T: SpatialKey>(lat: T, lon: T, precision: usize) -> Result<String, SpatialError> {
+    assert!(precision >= 1 && precision <= MAX_PRECISION);
+    key_of(&[lat, lon]).map(|key| format(key >> (5 * (MAX_PRECISION - precision)), precision))
+}
+
+/// Returns the corners of the cell of the geohash `hash`, in order of
+/// `[latitude, longitude]`, or an error if it isn't a geohash.
+/// Returns `SpatialError::OutOfBounds` if the corners don't convert to
+/// the coordinate type.
+pub fn bounds<T: SpatialKey>(hash: &str) -> Result<([T; 2], [T; 2]), SpatialError> {
+    let key = match parse(hash) {
+        Ok(key) => key,
+        Err(err) => return Err(err)
+    };
+    let bits = 5 * hash.len() as u32;
+    let (lon_bits, lat_bits) = split_bits(bits);
+    let (lon, lat) = deinterleave(key, bits);
+    let (width, height) = (360.0 / (1u64 << lon_bits) as f64, 180.0 / (1u64 << lat_bits) as f64);
+    let (west, south) = (lon as f64 * width - 180.0, lat as f64 * height - 90.0);
+    match (geom::narrow(south), geom::narrow(west), geom::narrow(south + height), geom::narrow(west + width)) {
+        (Some(south), Some(west), Some(north), Some(east)) => Ok(([south, west], [north, east])),
+        _ => Err(SpatialError::OutOfBounds)
+    }
+}
+
+/// Returns the center of the cell of the geohash `hash`, in order of
+/// `[latitude, longitude]`, or an error if it isn't a geohash, as
+/// `bounds` does.
+pub fn decode<T: SpatialKey>(hash: &str) -> Result<[T; 2], SpatialError> {
+    bounds::<T>(hash).map(|(min, max)| {
+        let val2: T = NumCast::from(2).unwrap();
+        [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2]
+    })
+}
+
+/// Returns the geohashes of the cells around that of `hash`, of the
+/// same precision, in order of north, north-east, east, south-east,
+/// south, south-west, west and north-west. Cells beyond the poles are
+/// left out, and cells across the antimeridian wrap around.
+pub fn neighbors(hash: &str) -> Result<Vec<String>, SpatialError> {
+    let key = match parse(hash) {
+        Ok(key) => key,
+        Err(err) => return Err(err)
+    };
+    let bits = 5 * hash.len() as u32;
+    let offsets = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+    Ok(neighbor_keys(key, bits, &offsets).into_iter().map(|key| format(key, hash.len())).collect())
+}
+
+/// Returns the geohashes of `bits` bits of the cells at `offsets` of
+/// `(east, north)` cells from that of `key`, leaving out those beyond
+/// the poles.
+fn neighbor_keys(key: u64, bits: u32, offsets: &[(i64, i64)]) -> Vec<u64> {
+    let (lon_bits, lat_bits) = split_bits(bits);
+    let (lon, lat) = deinterleave(key, bits);
+    let (columns, rows) = (1i64 << lon_bits, 1i64 << lat_bits);
+    offsets.iter()
+        .map(|&(east, north)| ((lon as i64 + east + columns) % columns, lat as i64 + north))
+        .filter(|&(_, lat)| lat >= 0 && lat < rows)
+        .map(|(lon, lat)| interleave(lon as u64, lat as u64, bits))
+        .collect()
+}
+
+/// Returns the great-circle distance between `a` and `b`, in order of
+/// `[latitude, longitude]` in degrees, in meters on a spherical Earth.
+/// The distance is computed in `f64`, or in the coordinate type if the
+/// coordinates or the distance don't convert, and is infinite if not
+/// even the radius of the Earth and a degree in radians do.
+pub fn distance<T: SpatialKey>(a: &[T; 2], b: &[T; 2]) -> T {
+    let wide = match (geom::wide(a[0]), geom::wide(a[1]), geom::wide(b[0]), geom::wide(b[1])) {
+        (Some(lat_a), Some(lon_a), Some(lat_b), Some(lon_b)) => {
+            geom::narrow(haversine(EARTH_RADIUS, PI / 180.0, [lat_a, lon_a], [lat_b, lon_b]))
+        },
+        _ => None
+    };
+    match wide {
+        Some(d) => d,
+        None => match (geom::narrow(EARTH_RADIUS), geom::narrow(PI / 180.0)) {
+            (Some(radius), Some(degree)) => haversine(radius, degree, *a, *b),
+            _ => Float::infinity()
+        }
+    }
+}
+
+/// Returns the great-circle distance between `a` and `b` on a sphere of
+/// `radius`, given `degree` in radians.
+fn haversine<F: Float>(radius: F, degree: F, a: [F; 2], b: [F; 2]) -> F {
+    let val2 = F::one() + F::one();
+    let (lat_a, lat_b) = (a[0] * degree, b[0] * degree);
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b[1] - a[1]) * degree;
+    let h = (d_lat / val2).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / val2).sin().powi(2);
+    val2 * radius * h.sqrt().min(F::one()).asin()
+}
+
+/// Returns the index of the first of the sorted `keys` that isn't less
+/// than `key`.
+fn lower_bound(keys: &[u64], key: u64) -> usize {
+    let (mut lo, mut hi) = (0, keys.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if keys[mid] < key { lo = mid + 1; } else { hi = mid; }
+    }
+    lo
+}
+
+/// An index over points on the globe, given by latitude and longitude,
+/// sorted by their geohashes. All points whose geohash starts with a
+/// given prefix are next to each other, so they're found with two
+/// binary searches, and radius queries only look at the few cells
+/// around their center.
+pub struct GeohashIndex<T: SpatialKey, P: Index<T>> {
+    /// The geohashes of the items, of 60 bits, sorted.
+    keys: Vec<u64>,
+    /// Items, in the order of `keys`.
+    items: Vec<P>,
+    _coordinate: ::std::marker::PhantomData<T>
+}
+
+impl<T: SpatialKey, P: Index<T>> Default for GeohashIndex<T, P> {
+    fn default() -> GeohashIndex<T, P> {
+        GeohashIndex::new()
+    }
+}
+
+impl<T: SpatialKey, P: Index<T>> GeohashIndex<T, P> {
+    /// Creates an empty index.
+    pub fn new() -> GeohashIndex<T, P> {
+        GeohashIndex {
+            keys: Vec::new(),
+            items: Vec::new(),
+            _coordinate: ::std::marker::PhantomData
+        }
+    }
+
+    /// Returns the number of items in the index.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the index holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Inserts `item`, or returns `SpatialError::OutOfBounds` if its
+    /// position isn't a valid latitude and longitude.
+    pub fn insert(&mut self, item: P) -> Result<(), SpatialError> {
+        let key = match key_of(&item.geohash_index()) {
+            Ok(key) => key,
+            Err(err) => return Err(err)
+        };
+        let i = lower_bound(&self.keys, key + 1);
+        self.keys.insert(i, key);
+        self.items.insert(i, item);
+        Ok(())
+    }
+
+    /// Removes an item equal to `item` from the index and returns it,
+    /// or `None` if there is none.
+    pub fn remove(&mut self, item: &P) -> Option<P> where P: PartialEq {
+        let key = match key_of(&item.geohash_index()) {
+            Ok(key) => key,
+            Err(_) => return None
+        };
+        let start = lower_bound(&self.keys, key);
+        let end = lower_bound(&self.keys, key + 1);
+        match self.items[start..end].iter().position(|other| other == item) {
+            Some(i) => {
+                self.keys.remove(start + i);
+                Some(self.items.remove(start + i))
+            },
+            None => None
+        }
+    }
+
+    /// Removes all items from the index.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.items.clear();
+    }
+
+    /// Returns all items whose geohash starts with `prefix`, in order of
+    /// their geohashes, or an error if `prefix` isn't a geohash.
+    pub fn get_with_prefix<'a>(&'a self, prefix: &str) -> Result<Vec<&'a P>, SpatialError> {
+        parse(prefix).map(|key| {
+            let (start, end) = self.range(key, 5 * prefix.len() as u32);
+            self.items[start..end].iter().collect()
+        })
+    }
+
+    /// Returns all items within `radius` meters of `center`, in order of
+    /// `[latitude, longitude]` in degrees, in no particular order.
+    ///
+    /// Only the cells of the finest precision that the circle doesn't
+    /// reach past the neighbors of are looked at: the cell of the center
+    /// and the eight around it.
+    pub fn get_in_radius(&self, center: [T; 2], radius: T) -> Vec<&P> {
+        let key = match key_of(&center) {
+            Ok(key) => key,
+            Err(_) => return Vec::new()
+        };
+
+        // The extent of the circle in latitude and longitude, in
+        // degrees. It spans all longitudes if it covers a pole.
+        let (lat, angle) = match (geom::wide(center[0]), geom::wide(radius)) {
+            (Some(lat), Some(radius)) => (lat, radius / EARTH_RADIUS),
+            _ => return Vec::new()
+        };
+        let lat_extent = angle * 180.0 / PI;
+        let lon_extent = if lat.abs() + lat_extent < 90.0 {
+            (angle.sin() / (lat * PI / 180.0).cos()).asin() * 180.0 / PI
+        } else {
+            360.0
+        };
+
+        let mut bits = KEY_BITS;
+        while bits > 0 {
+            let (lon_bits, lat_bits) = split_bits(bits);
+            if 360.0 / (1u64 << lon_bits) as f64 >= lon_extent && 180.0 / (1u64 << lat_bits) as f64 >= lat_extent {
+                break;
+            }
+            bits -= 5;
+        }
+
+        let mut cells = neighbor_keys(key >> (KEY_BITS - bits), bits,
+                                      &[(-1, -1), (0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]);
+        cells.sort();
+        cells.dedup();
+
+        let mut items = Vec::new();
+        for &cell in cells.iter() {
+            let (start, end) = self.range(cell, bits);
+            for item in self.items[start..end].iter() {
+                if distance(&center, &item.geohash_index()) <= radius {
+                    items.push(item);
+                }
+            }
+        }
+        items
+    }
+
+    /// Returns the range of the items whose geohash starts with the
+    /// `bits` bits of `prefix`.
+    fn range(&self, prefix: u64, bits: u32) -> (usize, usize) {
+        let shift = KEY_BITS - bits;
+        (lower_bound(&self.keys, prefix << shift), lower_bound(&self.keys, (prefix + 1) << shift))
+    }
+}
@@ -0,0 +1,248 @@
+//! Reading and writing GeoJSON, enabled with the `geojson` feature.
+//!
+//! `read_points` reads a FeatureCollection of Points into `Feature`s,
+//! which a `Quadtree` indexes by their `[longitude, latitude]`, and
+//! `Quadtree::from_geojson` builds a tree of them at once. The results
+//! of a query are written back to a FeatureCollection with
+//! `write_features`, and `write_features_with_bounds` adds the volumes
+//! of the nodes of a tree as Polygons, so that the tree can be looked at
+//! in a GIS alongside the points.
+//!
+//! Only Point geometries are understood. Documents are read and written
+//! with `serde_json`, and the id and the properties of a feature are
+//! kept as its `Value`s and written back as they were.
+
+use SpatialKey;
+use SpatialError;
+use Visit;
+use quadtree;
+use quadtree::{Quadtree, NodeData, Volume};
+use num::NumCast;
+use serde_json::{self, Map, Number};
+
+pub use serde_json::Value;
+
+/// Parses the JSON document `text`, or returns
+/// `SpatialError::Serialization` if it isn't valid JSON.
+pub fn parse(text: &str) -> Result<Value, SpatialError> {
+    serde_json::from_str(text).map_err(|err| SpatialError::Serialization(err.to_string()))
+}
+
+/// A GeoJSON feature with a Point geometry.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Feature<T: SpatialKey> {
+    /// The position of the point, in order of `[longitude, latitude]`.
+    /// Any further coordinates, such as the altitude, aren't kept.
+    pub position: [T; 2],
+    /// The id of the feature, if it has one.
+    pub id: Option<Value>,
+    /// The properties of the feature, which are `Value::Null` if it
+    /// has none.
+    pub properties: Value
+}
+
+impl<T: SpatialKey> quadtree::Index<T> for Feature<T> {
+    fn quadtree_index(&self) -> [T; 2] {
+        self.position
+    }
+}
+
+/// Returns `true` if `value` is an object of GeoJSON type `name`.
+fn is_type(value: &Value, name: &str) -> bool {
+    value.get("type").and_then(Value::as_str) == Some(name)
+}
+
+/// Reads the Points of the GeoJSON FeatureCollection `text`, in order.
+/// Returns `SpatialError::Serialization` if `text` isn't a
+/// FeatureCollection, or if one of its features isn't a Point whose
+/// coordinates fit in `T`.
+pub fn read_points<T: SpatialKey>(text: &str) -> Result<Vec<Feature<T>>, SpatialError> {
+    let collection = match parse(text) {
+        Ok(collection) => collection,
+        Err(err) => return Err(err)
+    };
+    if !is_type(&collection, "FeatureCollection") {
+        return Err(SpatialError::Serialization("not a FeatureCollection".to_string()));
+    }
+    let features = match collection.get("features").and_then(Value::as_array) {
+        Some(features) => features,
+        None => return Err(SpatialError::Serialization("FeatureCollection without features".to_string()))
+    };
+
+    let mut points = Vec::with_capacity(features.len());
+    for (i, feature) in features.iter().enumerate() {
+        match read_point(feature) {
+            Ok(point) => points.push(point),
+            Err(message) => return Err(SpatialError::Serialization(format!("feature {}: {}", i, message)))
+        }
+    }
+    Ok(points)
+}
+
+/// Reads a single Point `feature`, or returns what's wrong with it.
+fn read_point<T: SpatialKey>(feature: &Value) -> Result<Feature<T>, &'static str> {
+    if !is_type(feature, "Feature") {
+        return Err("not a Feature");
+    }
+    let geometry = match feature.get("geometry") {
+        Some(geometry) if is_type(geometry, "Point") => geometry,
+        Some(&Value::Null) | None => return Err("no geometry"),
+        Some(_) => return Err("geometry isn't a Point")
+    };
+    let coordinates = match geometry.get("coordinates").and_then(Value::as_array) {
+        Some(coordinates) if coordinates.len() >= 2 => match (coordinates[0].as_f64(), coordinates[1].as_f64()) {
+            (Some(x), Some(y)) => (NumCast::from(x), NumCast::from(y)),
+            _ => return Err("coordinates aren't numbers")
+        },
+        _ => return Err("no coordinates")
+    };
+    let position: [T; 2] = match coordinates {
+        (Some(x), Some(y)) => [x, y],
+        _ => return Err("coordinates out of range")
+    };
+
+    Ok(Feature {
+        position: position,
+        id: feature.get("id").cloned(),
+        properties: feature.get("properties").cloned().unwrap_or(Value::Null)
+    })
+}
+
+impl<T: SpatialKey> Quadtree<T, Feature<T>> {
+    /// Builds a quadtree of `vol` from the Points of the GeoJSON
+    /// FeatureCollection `text`, storing up to `capacity` of them in each
+    /// node. Points in WGS 84 fit in
+    /// `Volume::new([-180.0, -90.0], [180.0, 90.0])`.
+    ///
+    /// Returns the error of `read_points` if `text` can't be read, and
+    /// the error of `insert` if a point can't be stored.
+    pub fn from_geojson(vol: Volume<T>, capacity: usize, text: &str) -> Result<Quadtree<T, Feature<T>>, SpatialError> {
+        let points = match read_points(text) {
+            Ok(points) => points,
+            Err(err) => return Err(err)
+        };
+        let mut tree = Quadtree::with_capacity(vol, capacity);
+        for point in points.into_iter() {
+            match tree.insert(point) {
+                Ok(()) => {},
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(tree)
+    }
+}
+
+/// Returns `x` as a JSON number, or `SpatialError::Serialization` if
+/// it isn't finite, as JSON has no way to express it.
+fn number<T: SpatialKey>(x: T) -> Result<Value, SpatialError> {
+    match x.to_f64().and_then(Number::from_f64) {
+        Some(x) => Ok(Value::Number(x)),
+        None => Err(SpatialError::Serialization(format!("coordinate {} isn't finite", x)))
+    }
+}
+
+/// Returns a GeoJSON geometry of type `kind` with `coordinates`.
+fn geometry(kind: &str, coordinates: Value) -> Value {
+    let mut geometry = Map::new();
+    geometry.insert("type".to_string(), Value::from(kind));
+    geometry.insert("coordinates".to_string(), coordinates);
+    Value::Object(geometry)
+}
+
+/// Returns a GeoJSON feature with `geometry`, `properties` and the
+/// optional `id`.
+fn feature(id: Option<Value>, geometry: Value, properties: Value) -> Value {
+    let mut feature = Map::new();
+    feature.insert("type".to_string(), Value::from("Feature"));
+    match id {
+        Some(id) => {
+            feature.insert("id".to_string(), id);
+        },
+        None => {}
+    }
+    feature.insert("geometry".to_string(), geometry);
+    feature.insert("properties".to_string(), properties);
+    Value::Object(feature)
+}
+
+/// Returns `point` as a GeoJSON feature, or
+/// `SpatialError::Serialization` if its position isn't finite.
+fn write_point<T: SpatialKey>(point: &Feature<T>) -> Result<Value, SpatialError> {
+    let coordinates = match (number(point.position[0]), number(point.position[1])) {
+        (Ok(x), Ok(y)) => vec![x, y],
+        (Err(err), _) | (_, Err(err)) => return Err(err)
+    };
+    Ok(feature(point.id.clone(), geometry("Point", Value::Array(coordinates)), point.properties.clone()))
+}
+
+/// Returns the volume of a node holding `items` items as a GeoJSON
+/// Polygon feature, with the number of items as its only property.
+fn write_bounds<T: SpatialKey>(vol: &Volume<T>, items: usize) -> Result<Value, SpatialError> {
+    // The exterior ring of a polygon runs counterclockwise.
+    let corners = [(vol.min[0], vol.min[1]), (vol.max[0], vol.min[1]), (vol.max[0], vol.max[1]),
+                   (vol.min[0], vol.max[1]), (vol.min[0], vol.min[1])];
+    let mut ring = Vec::with_capacity(corners.len());
+    for &(x, y) in corners.iter() {
+        match (number(x), number(y)) {
+            (Ok(x), Ok(y)) => ring.push(Value::Array(vec![x, y])),
+            (Err(err), _) | (_, Err(err)) => return Err(err)
+        }
+    }
+    let mut properties = Map::new();
+    properties.insert("items".to_string(), Value::from(items));
+    Ok(feature(None, geometry("Polygon", Value::Array(vec![Value::Array(ring)])), Value::Object(properties)))
+}
+
+/// Returns a FeatureCollection of `members` as GeoJSON.
+fn write_collection(members: Vec<Value>) -> Result<String, SpatialError> {
+    let mut collection = Map::new();
+    collection.insert("type".to_string(), Value::from("FeatureCollection"));
+    collection.insert("features".to_string(), Value::Array(members));
+    serde_json::to_string(&Value::Object(collection)).map_err(|err| SpatialError::Serialization(err.to_string()))
+}
+
+/// Returns a GeoJSON FeatureCollection of `features`, such as the
+/// results of a query, in order. Returns `SpatialError::Serialization`
+/// if the position of a feature isn't finite.
+pub fn write_features<'a, T, I>(features: I) -> Result<String, SpatialError>
+    where T: SpatialKey + 'a, I: IntoIterator<Item=&'a Feature<T>> {
+    let mut members = Vec::new();
+    for point in features.into_iter() {
+        match write_point(point) {
+            Ok(member) => members.push(member),
+            Err(err) => return Err(err)
+        }
+    }
+    write_collection(members)
+}
+
+/// Returns a GeoJSON FeatureCollection of `features`, followed by the
+/// volume of every node of `tree` as a Polygon, parents before their
+/// children. The polygons have the number of items stored in their
+/// node as their `items` property. Returns `SpatialError::Serialization`
+/// if the position of a feature or a bound of a node isn't finite.
+pub fn write_features_with_bounds<'a, T, P, D, I>(features: I, tree: &Quadtree<T, P, D>) -> Result<String, SpatialError>
+    where T: SpatialKey + 'a, P: quadtree::Index<T> + Clone, D: NodeData<T, P>, I: IntoIterator<Item=&'a Feature<T>> {
+    let mut members = Vec::new();
+    for point in features.into_iter() {
+        match write_point(point) {
+            Ok(member) => members.push(member),
+            Err(err) => return Err(err)
+        }
+    }
+    let mut failed = None;
+    tree.visit(|vol, items| match write_bounds(vol, items.len()) {
+        Ok(member) => {
+            members.push(member);
+            Visit::Continue
+        },
+        Err(err) => {
+            failed = Some(err);
+            Visit::Stop
+        }
+    });
+    match failed {
+        Some(err) => Err(err),
+        None => write_collection(members)
+    }
+}
@@ -0,0 +1,411 @@
+//! Geometric predicates shared by the data structures.
+//!
+//! The predicates operate on slices so that they work in any number
+//! of dimensions. Axis-aligned bounding boxes are given as their
+//! `min` and `max` corners, and all tests treat boundaries as
+//! inclusive, so a point on the face of a box is inside it.
+//!
+//! Distances are accumulated in `f64` whatever the coordinate type, so
+//! that `f32` coordinates far from the origin don't lose their
+//! precision when subtracted and squared, and are only narrowed back to
+//! the coordinate type when returned. Predicates compare in `f64`
+//! without narrowing at all. Coordinates that don't convert to `f64`,
+//! or distances that don't convert back, are computed in the
+//! coordinate type itself.
+
+use SpatialKey;
+use num::NumCast;
+use num::traits::Float;
+
+/// Returns `true` if point `p` is inside the box.
+#[inline]
+pub fn point_in_aabb<T: SpatialKey>(p: &[T], min: &[T], max: &[T]) -> bool {
+    (0..p.len()).all(|i| p[i] >= min[i] && p[i] <= max[i])
+}
+
+/// Returns `true` if point `p` is at most `radius` away from `center`.
+#[inline]
+pub fn point_in_sphere<T: SpatialKey>(p: &[T], center: &[T], radius: T) -> bool {
+    within(distance2_in(p, center, wide), radius, || distance2_in(p, center, Some))
+}
+
+/// Returns `true` if the boxes `a` and `b` overlap or touch.
+#[inline]
+pub fn aabb_intersects_aabb<T: SpatialKey>(min_a: &[T], max_a: &[T], min_b: &[T], max_b: &[T]) -> bool {
+    (0..min_a.len()).all(|i| min_a[i] <= max_b[i] && max_a[i] >= min_b[i])
+}
+
+/// Returns `true` if the sphere at `center` with `radius` overlaps or
+/// touches the box.
+#[inline]
+pub fn sphere_intersects_aabb<T: SpatialKey>(center: &[T], radius: T, min: &[T], max: &[T]) -> bool {
+    within(aabb_distance2_in(center, min, max, wide), radius, || aabb_distance2_in(center, min, max, Some))
+}
+
+/// Returns `true` if the boxes `a` and `b` are at most `distance` apart.
+#[inline]
+pub fn aabb_near_aabb<T: SpatialKey>(min_a: &[T], max_a: &[T], min_b: &[T], max_b: &[T], distance: T) -> bool {
+    within(aabb_aabb_distance2_in(min_a, max_a, min_b, max_b, wide), distance,
+           || aabb_aabb_distance2_in(min_a, max_a, min_b, max_b, Some))
+}
+
+/// Returns `true` if point `p` is at most `radius` away from the
+/// segment from `a` to `b`.
+#[inline]
+pub fn point_near_segment<T: SpatialKey>(p: &[T], a: &[T], b: &[T], radius: T) -> bool {
+    within(segment_distance2_in(p, a, b, wide), radius, || segment_distance2_in(p, a, b, Some))
+}
+
+/// Returns the distances along the ray at which it enters and exits the
+/// box, or `None` if the ray misses it. `inv_direction` is the
+/// component-wise reciprocal of the ray's direction. The entry distance
+/// is `0` if the ray starts inside the box.
+#[inline]
+pub fn ray_intersects_aabb<T: SpatialKey>(origin: &[T], inv_direction: &[T],
+                                          min: &[T], max: &[T]) -> Option<(T, T)> {
+    clip(origin, inv_direction, min, max, NumCast::from(0).unwrap(), Float::infinity())
+}
+
+/// Returns `true` if the line segment between `a` and `b` overlaps or
+/// touches the box.
+#[inline]
+pub fn segment_intersects_aabb<T: SpatialKey>(a: &[T], b: &[T], min: &[T], max: &[T]) -> bool {
+    let one: T = NumCast::from(1).unwrap();
+    let inv_direction: Vec<T> = (0..a.len()).map(|i| one / (b[i] - a[i])).collect();
+    clip(a, &inv_direction, min, max, NumCast::from(0).unwrap(), one).is_some()
+}
+
+/// Widens `x` to `f64`, in which distances and sums are accumulated,
+/// or returns `None` if it doesn't convert.
+#[inline]
+pub fn wide<T: SpatialKey>(x: T) -> Option<f64> {
+    x.to_f64()
+}
+
+/// Narrows `x`, accumulated in `f64`, back to the coordinate type, or
+/// returns `None` if it doesn't convert.
+#[inline]
+pub fn narrow<T: SpatialKey>(x: f64) -> Option<T> {
+    NumCast::from(x)
+}
+
+/// Returns the squared distance between points `a` and `b`.
+#[inline]
+pub fn distance2<T: SpatialKey>(a: &[T], b: &[T]) -> T {
+    narrowed(distance2_in(a, b, wide), || distance2_in(a, b, Some))
+}
+
+/// Returns the squared distance between points `a` and `b` as an `f64`,
+/// without narrowing it to the coordinate type, or `None` if the points
+/// don't convert to `f64`.
+#[inline]
+pub fn distance2_wide<T: SpatialKey>(a: &[T], b: &[T]) -> Option<f64> {
+    distance2_in(a, b, wide)
+}
+
+/// Returns the squared distance from point `p` to the closest point of
+/// the box, which is `0` if `p` is inside it.
+#[inline]
+pub fn aabb_distance2<T: SpatialKey>(p: &[T], min: &[T], max: &[T]) -> T {
+    narrowed(aabb_distance2_in(p, min, max, wide), || aabb_distance2_in(p, min, max, Some))
+}
+
+/// Returns the squared distance from point `p` to the closest point of
+/// the box as an `f64`, without narrowing it to the coordinate type, or
+/// `None` if the coordinates don't convert to `f64`.
+#[inline]
+pub fn aabb_distance2_wide<T: SpatialKey>(p: &[T], min: &[T], max: &[T]) -> Option<f64> {
+    aabb_distance2_in(p, min, max, wide)
+}
+
+/// Returns the squared distance between the closest points of the
+/// boxes `a` and `b`, which is `0` if they overlap or touch.
+#[inline]
+pub fn aabb_aabb_distance2<T: SpatialKey>(min_a: &[T], max_a: &[T], min_b: &[T], max_b: &[T]) -> T {
+    narrowed(aabb_aabb_distance2_in(min_a, max_a, min_b, max_b, wide),
+             || aabb_aabb_distance2_in(min_a, max_a, min_b, max_b, Some))
+}
+
+/// Returns the squared distance from point `p` to the closest point of
+/// the segment from `a` to `b`.
+pub fn segment_distance2<T: SpatialKey>(p: &[T], a: &[T], b: &[T]) -> T {
+    narrowed(segment_distance2_in(p, a, b, wide), || segment_distance2_in(p, a, b, Some))
+}
+
+/// Returns the squared distance `wide`, computed in `f64`, narrowed to
+/// the coordinate type, or the one `exact` computes in the coordinate
+/// type if either conversion fails.
+#[inline]
+fn narrowed<T: SpatialKey, E: FnOnce() -> Option<T>>(wide: Option<f64>, exact: E) -> T {
+    match wide.and_then(narrow) {
+        Some(d2) => d2,
+        // Coordinates always convert to their own type.
+        None => exact().unwrap_or_else(Float::infinity)
+    }
+}
+
+/// Returns `true` if the squared distance `wide`, computed in `f64`, is
+/// at most `limit` squared, or the one `exact` computes in the
+/// coordinate type if a value doesn't convert to `f64`.
+#[inline]
+fn within<T: SpatialKey, E: FnOnce() -> Option<T>>(wide: Option<f64>, limit: T, exact: E) -> bool {
+    match (wide, self::wide(limit)) {
+        (Some(d2), Some(limit)) => d2 <= limit * limit,
+        _ => match exact() {
+            Some(d2) => d2 <= limit * limit,
+            None => false
+        }
+    }
+}
+
+/// Returns the squared distance between points `a` and `b` in the type
+/// `convert` converts the coordinates to, or `None` if one of them
+/// doesn't convert.
+#[inline]
+fn distance2_in<T: SpatialKey, F: Float, C: Fn(T) -> Option<F>>(a: &[T], b: &[T], convert: C) -> Option<F> {
+    let mut d2 = F::zero();
+    for i in 0..a.len() {
+        let d = match (convert(a[i]), convert(b[i])) {
+            (Some(a), Some(b)) => a - b,
+            _ => return None
+        };
+        d2 = d2 + d * d;
+    }
+    Some(d2)
+}
+
+/// Returns the squared distance from point `p` to the closest point of
+/// the box in the type `convert` converts the coordinates to, or `None`
+/// if one of them doesn't convert.
+#[inline]
+fn aabb_distance2_in<T: SpatialKey, F: Float, C: Fn(T) -> Option<F>>(p: &[T], min: &[T], max: &[T],
+                                                                     convert: C) -> Option<F> {
+    let mut d2 = F::zero();
+    for i in 0..p.len() {
+        let gap = match (convert(p[i]), convert(min[i]), convert(max[i])) {
+            (Some(x), Some(min), Some(max)) => (min - x).max(x - max).max(F::zero()),
+            _ => return None
+        };
+        d2 = d2 + gap * gap;
+    }
+    Some(d2)
+}
+
+/// Returns the squared distance between the closest points of the
+/// boxes `a` and `b` in the type `convert` converts the coordinates to,
+/// or `None` if one of them doesn't convert.
+#[inline]
+fn aabb_aabb_distance2_in<T: SpatialKey, F: Float, C: Fn(T) -> Option<F>>(min_a: &[T], max_a: &[T], min_b: &[T],
+                                                                          max_b: &[T], convert: C) -> Option<F> {
+    let mut d2 = F::zero();
+    for i in 0..min_a.len() {
+        let gap = match (convert(min_a[i]), convert(max_a[i]), convert(min_b[i]), convert(max_b[i])) {
+            (Some(min_a), Some(max_a), Some(min_b), Some(max_b)) => (min_a - max_b).max(min_b - max_a).max(F::zero()),
+            _ => return None
+        };
+        d2 = d2 + gap * gap;
+    }
+    Some(d2)
+}
+
+/// Returns the squared distance from point `p` to the closest point of
+/// the segment from `a` to `b` in the type `convert` converts the
+/// coordinates to, or `None` if one of them doesn't convert.
+fn segment_distance2_in<T: SpatialKey, F: Float, C: Fn(T) -> Option<F>>(p: &[T], a: &[T], b: &[T],
+                                                                        convert: C) -> Option<F> {
+    let mut length2 = F::zero();
+    let mut projection = F::zero();
+    for i in 0..p.len() {
+        let (p, a, b) = match (convert(p[i]), convert(a[i]), convert(b[i])) {
+            (Some(p), Some(a), Some(b)) => (p, a, b),
+            _ => return None
+        };
+        let ab = b - a;
+        length2 = length2 + ab * ab;
+        projection = projection + (p - a) * ab;
+    }
+
+    let t = if length2 > F::zero() { (projection / length2).max(F::zero()).min(F::one()) } else { F::zero() };
+    let mut d2 = F::zero();
+    for i in 0..p.len() {
+        let d = match (convert(p[i]), convert(a[i]), convert(b[i])) {
+            (Some(p), Some(a), Some(b)) => p - (a + (b - a) * t),
+            _ => return None
+        };
+        d2 = d2 + d * d;
+    }
+    Some(d2)
+}
+
+/// Clips the parameter range `[t0, t1]` of the line through `origin` to
+/// the part inside the box, using the slab method.
+fn clip<T: SpatialKey>(origin: &[T], inv_direction: &[T], min: &[T], max: &[T],
+                       t0: T, t1: T) -> Option<(T, T)> {
+    let zero: T = NumCast::from(0).unwrap();
+    let mut entry = t0;
+    let mut exit = t1;
+    
+    for i in 0..origin.len() {
+        if inv_direction[i].is_infinite() {
+            // Parallel to the slab; the origin must lie within it.
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        
+        let (lo, hi) = if inv_direction[i] < zero { (max[i], min[i]) } else { (min[i], max[i]) };
+        let near = (lo - origin[i]) * inv_direction[i];
+        let far = (hi - origin[i]) * inv_direction[i];
+        if near > exit || far < entry {
+            return None;
+        }
+        entry = entry.max(near);
+        exit = exit.min(far);
+    }
+    
+    Some((entry, exit))
+}
+
+/// Returns `true` if the triangle with corners `tri` overlaps or
+/// touches the box, using the separating axis test of Akenine-Möller.
+pub fn triangle_intersects_aabb<T: SpatialKey>(tri: &[[T; 3]; 3], min: &[T], max: &[T]) -> bool {
+    let val2: T = NumCast::from(2).unwrap();
+    let zero: T = NumCast::from(0).unwrap();
+    let c = [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2, (min[2] + max[2]) / val2];
+    let h = [(max[0] - min[0]) / val2, (max[1] - min[1]) / val2, (max[2] - min[2]) / val2];
+    let v = [sub(&tri[0], &c), sub(&tri[1], &c), sub(&tri[2], &c)];
+    let edges = [sub(&v[1], &v[0]), sub(&v[2], &v[1]), sub(&v[0], &v[2])];
+    
+    // Returns `true` if `axis` separates the triangle from the box.
+    let separates = |axis: [T; 3]| {
+        let p = [dot(&axis, &v[0]), dot(&axis, &v[1]), dot(&axis, &v[2])];
+        let r = h[0] * axis[0].abs() + h[1] * axis[1].abs() + h[2] * axis[2].abs();
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+    
+    // Cross products of the box axes and the triangle edges.
+    for e in edges.iter() {
+        let axes = [[zero, -e[2], e[1]], [e[2], zero, -e[0]], [-e[1], e[0], zero]];
+        if axes.iter().any(|&axis| separates(axis)) {
+            return false;
+        }
+    }
+    
+    // The box axes, followed by the triangle normal.
+    let one: T = NumCast::from(1).unwrap();
+    if separates([one, zero, zero]) || separates([zero, one, zero]) || separates([zero, zero, one]) {
+        return false;
+    }
+    !separates(cross(&edges[0], &edges[1]))
+}
+
+/// Returns the distance along the ray from `origin` toward `direction`
+/// at which it hits the triangle `tri`, followed by the barycentric
+/// coordinates `u` and `v` of the hit point, or `None` if the ray
+/// misses. Uses the Möller-Trumbore algorithm.
+pub fn ray_intersects_triangle<T: SpatialKey>(origin: &[T; 3], direction: &[T; 3],
+                                              tri: &[[T; 3]; 3]) -> Option<(T, T, T)> {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    let e1 = sub(&tri[1], &tri[0]);
+    let e2 = sub(&tri[2], &tri[0]);
+    
+    let p = cross(direction, &e2);
+    let det = dot(&e1, &p);
+    if det == zero {
+        return None;
+    }
+    let inv_det = one / det;
+    
+    let s = sub(origin, &tri[0]);
+    let u = dot(&s, &p) * inv_det;
+    if u < zero || u > one {
+        return None;
+    }
+    
+    let q = cross(&s, &e1);
+    let v = dot(direction, &q) * inv_det;
+    if v < zero || u + v > one {
+        return None;
+    }
+    
+    let t = dot(&e2, &q) * inv_det;
+    if t < zero {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+/// Returns the point of the triangle `tri` closest to `p`, following
+/// Ericson's Real-Time Collision Detection.
+pub fn closest_point_on_triangle<T: SpatialKey>(p: &[T; 3], tri: &[[T; 3]; 3]) -> [T; 3] {
+    let zero: T = NumCast::from(0).unwrap();
+    let (a, b, c) = (&tri[0], &tri[1], &tri[2]);
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    
+    // Vertex region of a.
+    let ap = sub(p, a);
+    let (d1, d2) = (dot(&ab, &ap), dot(&ac, &ap));
+    if d1 <= zero && d2 <= zero {
+        return *a;
+    }
+    
+    // Vertex region of b.
+    let bp = sub(p, b);
+    let (d3, d4) = (dot(&ab, &bp), dot(&ac, &bp));
+    if d3 >= zero && d4 <= d3 {
+        return *b;
+    }
+    
+    // Edge region of ab.
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= zero && d1 >= zero && d3 <= zero {
+        return along(a, &ab, d1 / (d1 - d3));
+    }
+    
+    // Vertex region of c.
+    let cp = sub(p, c);
+    let (d5, d6) = (dot(&ab, &cp), dot(&ac, &cp));
+    if d6 >= zero && d5 <= d6 {
+        return *c;
+    }
+    
+    // Edge region of ac.
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= zero && d2 >= zero && d6 <= zero {
+        return along(a, &ac, d2 / (d2 - d6));
+    }
+    
+    // Edge region of bc.
+    let va = d3 * d6 - d5 * d4;
+    if va <= zero && d4 - d3 >= zero && d5 - d6 >= zero {
+        return along(b, &sub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    
+    // Inside the face.
+    let denom = va + vb + vc;
+    along(&along(a, &ab, vb / denom), &ac, vc / denom)
+}
+
+/// Returns `p + d * t`.
+#[inline]
+fn along<T: SpatialKey>(p: &[T; 3], d: &[T; 3], t: T) -> [T; 3] {
+    [p[0] + d[0] * t, p[1] + d[1] * t, p[2] + d[2] * t]
+}
+
+#[inline]
+fn sub<T: SpatialKey>(a: &[T; 3], b: &[T; 3]) -> [T; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+#[inline]
+fn dot<T: SpatialKey>(a: &[T; 3], b: &[T; 3]) -> T {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[inline]
+fn cross<T: SpatialKey>(a: &[T; 3], b: &[T; 3]) -> [T; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
@@ -0,0 +1,555 @@
+//! A uniform grid over points, hashing them into cells of equal size.
+
+use SpatialKey;
+use SpatialError;
+use geom;
+use num::NumCast;
+use std::collections::HashMap;
+
+/// The largest number of dimensions of a grid.
+static MAX_DIMENSIONS: usize = 3;
+
+/// The number of levels of a `HierarchicalGrid`, each with cells twice
+/// the size of the ones below.
+static LEVELS: usize = 32;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `SpatialHash`.
+pub trait Index<T: SpatialKey> {
+    /// Returns the coordinate of the position of `self` along `axis`,
+    /// in order of x, y and z.
+    fn grid_coordinate(&self, axis: usize) -> T;
+}
+
+impl<T: SpatialKey> Index<T> for [T; 2] {
+    fn grid_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+impl<T: SpatialKey> Index<T> for [T; 3] {
+    fn grid_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `HierarchicalGrid`.
+pub trait BoundedIndex<T: SpatialKey> {
+    /// Returns the lowest and highest coordinate of the bounds of
+    /// `self` along `axis`, in order of x, y and z.
+    fn grid_bounds(&self, axis: usize) -> (T, T);
+}
+
+/// The coordinates of a cell, with `0` along the axes beyond the
+/// dimensions of the grid.
+pub type Cell = [i64; 3];
+
+/// Returns the coordinates of the cell of `cell_size` containing `p`,
+/// or `None` if they can't be represented.
+fn cell_at<T: SpatialKey>(p: &[T], cell_size: T) -> Option<Cell> {
+    let mut cell = [0; 3];
+    for axis in 0..p.len() {
+        cell[axis] = match NumCast::from((p[axis] / cell_size).floor()) {
+            Some(key) => key,
+            None => return None
+        };
+    }
+    Some(cell)
+}
+
+/// Passes every item in the cells from `min` to `max` of `cells` to
+/// `f`.
+fn collect_cells<'a, P, F: FnMut(&'a P)>(cells: &'a HashMap<Cell, Vec<P>>, dimensions: usize,
+                                        min: Cell, max: Cell, f: &mut F) {
+    // Look up the overlapped cells, unless there are more of them than
+    // cells holding items. They're counted in floating point, as the
+    // extent of a huge query overflows the cell coordinates.
+    let overlapped: f64 = (0..dimensions)
+        .map(|axis| max[axis] as f64 - min[axis] as f64 + 1.0)
+        .product();
+    if overlapped > cells.len() as f64 {
+        for (cell, items) in cells.iter() {
+            if (0..dimensions).all(|axis| cell[axis] >= min[axis] && cell[axis] <= max[axis]) {
+                for item in items.iter() {
+                    f(item);
+                }
+            }
+        }
+    } else {
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    match cells.get(&[x, y, z]) {
+                        Some(items) => for item in items.iter() {
+                            f(item);
+                        },
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A spatial hash: an unbounded uniform grid of one to three
+/// dimensions, storing the items of each cell in a hash map under the
+/// coordinates of the cell. Inserting, removing and moving an item
+/// takes constant time, and queries only look at the cells they
+/// overlap, which makes it a good fit for many uniformly distributed
+/// moving items of about the same size, such as particles.
+///
+/// Queries are fastest when the cell size is about the radius of the
+/// typical neighborhood query.
+pub struct SpatialHash<T: SpatialKey, P: Index<T> + Clone> {
+    dimensions: usize,
+    /// Side length of the cells.
+    cell_size: T,
+    len: usize,
+    cells: HashMap<Cell, Vec<P>>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> SpatialHash<T, P> {
+    /// Creates an empty grid of `dimensions` with cells of `cell_size`
+    /// along every axis.
+    ///
+    /// Panics if `dimensions` isn't between 1 and 3, or if `cell_size`
+    /// isn't positive.
+    pub fn new(dimensions: usize, cell_size: T) -> SpatialHash<T, P> {
+        assert!(dimensions > 0 && dimensions <= MAX_DIMENSIONS);
+        assert!(cell_size > NumCast::from(0).unwrap());
+        SpatialHash {
+            dimensions: dimensions,
+            cell_size: cell_size,
+            len: 0,
+            cells: HashMap::new()
+        }
+    }
+
+    /// Returns the number of dimensions of the grid.
+    #[inline]
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Returns the side length of the cells.
+    #[inline]
+    pub fn cell_size(&self) -> T {
+        self.cell_size
+    }
+
+    /// Returns the number of items in the grid.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the grid holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of cells holding items.
+    #[inline]
+    pub fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns the coordinates of the cell containing `p`, or `None` if
+    /// they can't be represented.
+    ///
+    /// Panics if `p` doesn't have as many coordinates as the grid has
+    /// dimensions.
+    pub fn cell_of(&self, p: &[T]) -> Option<Cell> {
+        assert_eq!(p.len(), self.dimensions);
+        cell_at(p, self.cell_size)
+    }
+
+    /// Returns the items of the cell at `cell`.
+    pub fn cell(&self, cell: Cell) -> &[P] {
+        match self.cells.get(&cell) {
+            Some(items) => items,
+            None => &[]
+        }
+    }
+
+    /// Inserts an `item` into the cell containing it. Returns
+    /// `SpatialError::OutOfBounds` if the position of the item isn't
+    /// finite.
+    pub fn insert(&mut self, item: P) -> Result<(), SpatialError> {
+        let cell = match self.cell_of_item(&item) {
+            Some(cell) => cell,
+            None => return Err(SpatialError::OutOfBounds)
+        };
+        self.cells.entry(cell).or_default().push(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes an item equal to `item` from the grid and returns it, or
+    /// `None` if there is no such item.
+    pub fn remove(&mut self, item: &P) -> Option<P> where P: PartialEq {
+        let cell = match self.cell_of_item(item) {
+            Some(cell) => cell,
+            None => return None
+        };
+        let (removed, empty) = match self.cells.get_mut(&cell) {
+            Some(items) => match items.iter().position(|other| other == item) {
+                Some(i) => (items.swap_remove(i), items.is_empty()),
+                None => return None
+            },
+            None => return None
+        };
+        if empty {
+            self.cells.remove(&cell);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Replaces an item equal to `old` with `item`, moving it to the
+    /// cell of its new position if that changed. Returns `false`,
+    /// leaving the grid untouched, if there's no item equal to `old` or
+    /// if the position of `item` isn't finite.
+    pub fn update(&mut self, old: &P, item: P) -> bool where P: PartialEq {
+        let (from, to) = match (self.cell_of_item(old), self.cell_of_item(&item)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false
+        };
+        let empty = match self.cells.get_mut(&from) {
+            Some(items) => match items.iter().position(|other| other == old) {
+                Some(i) => if from == to {
+                    items[i] = item;
+                    return true;
+                } else {
+                    items.swap_remove(i);
+                    items.is_empty()
+                },
+                None => return false
+            },
+            None => return false
+        };
+        if empty {
+            self.cells.remove(&from);
+        }
+        self.cells.entry(to).or_default().push(item);
+        true
+    }
+
+    /// Removes all items from the grid.
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.len = 0;
+    }
+
+    /// Returns all items in the cell containing `p` and in the cells
+    /// adjacent to it, including diagonally, in no particular order.
+    /// With a cell size of at least `r`, these include every item within
+    /// `r` of `p`.
+    ///
+    /// Panics if `p` doesn't have as many coordinates as the grid has
+    /// dimensions.
+    pub fn get_in_neighborhood<'a>(&'a self, p: &[T]) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        match self.cell_of(p) {
+            Some(cell) => {
+                let mut min = cell;
+                let mut max = cell;
+                for axis in 0..self.dimensions {
+                    min[axis] = min[axis].saturating_sub(1);
+                    max[axis] = max[axis].saturating_add(1);
+                }
+                self.collect(min, max, &mut |item| items.push(item));
+            },
+            None => {}
+        }
+        items
+    }
+
+    /// Returns all items inside the box from `min` to `max`, in no
+    /// particular order.
+    ///
+    /// Panics if `min` or `max` don't have as many coordinates as the
+    /// grid has dimensions.
+    pub fn get_in_range<'a>(&'a self, min: &[T], max: &[T]) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        match (self.cell_of(min), self.cell_of(max)) {
+            (Some(first), Some(last)) => self.collect(first, last, &mut |item| {
+                if geom::point_in_aabb(&self.position(item), min, max) {
+                    items.push(item);
+                }
+            }),
+            _ => {}
+        }
+        items
+    }
+
+    /// Returns all items within `radius` of `center`, in no particular
+    /// order.
+    ///
+    /// Panics if `center` doesn't have as many coordinates as the grid
+    /// has dimensions.
+    pub fn get_in_radius<'a>(&'a self, center: &[T], radius: T) -> Vec<&'a P> {
+        let min: Vec<T> = center.iter().map(|&x| x - radius).collect();
+        let max: Vec<T> = center.iter().map(|&x| x + radius).collect();
+        let mut items = Vec::new();
+        match (self.cell_of(&min), self.cell_of(&max)) {
+            (Some(first), Some(last)) => self.collect(first, last, &mut |item| {
+                if geom::point_in_sphere(&self.position(item), center, radius) {
+                    items.push(item);
+                }
+            }),
+            _ => {}
+        }
+        items
+    }
+
+    /// Returns the coordinates of the cell containing `item`.
+    fn cell_of_item(&self, item: &P) -> Option<Cell> {
+        let mut position = [NumCast::from(0).unwrap(); 3];
+        for (axis, coordinate) in position.iter_mut().enumerate().take(self.dimensions) {
+            *coordinate = item.grid_coordinate(axis);
+        }
+        self.cell_of(&position[..self.dimensions])
+    }
+
+    /// Returns the coordinates of the position of `item`.
+    fn position(&self, item: &P) -> Vec<T> {
+        (0..self.dimensions).map(|axis| item.grid_coordinate(axis)).collect()
+    }
+
+    /// Passes every item in the cells from `min` to `max` to `f`.
+    #[inline]
+    fn collect<'a, F: FnMut(&'a P)>(&'a self, min: Cell, max: Cell, f: &mut F) {
+        collect_cells(&self.cells, self.dimensions, min, max, f)
+    }
+}
+
+/// A hierarchical hash grid over items with an extent: a stack of
+/// spatial hashes, level `k` of which has cells of `2^k` times the base
+/// cell size. Each item is stored in the lowest level whose cells are at
+/// least as large as the item, in the cell containing its lowest
+/// corner, so tiny and huge items don't have to share a cell size.
+///
+/// Queries visit each level holding items, so as a broadphase it stays
+/// fast when scenes mix items of very different sizes.
+pub struct HierarchicalGrid<T: SpatialKey, B: BoundedIndex<T> + Clone> {
+    dimensions: usize,
+    /// Side length of the cells of the lowest level.
+    cell_size: T,
+    len: usize,
+    /// The cells of each level, created as items are stored in them.
+    levels: Vec<HashMap<Cell, Vec<B>>>
+}
+
+impl<T: SpatialKey, B: BoundedIndex<T> + Clone> HierarchicalGrid<T, B> {
+    /// Creates an empty grid of `dimensions` whose lowest level has
+    /// cells of `cell_size` along every axis.
+    ///
+    /// Panics if `dimensions` isn't between 1 and 3, or if `cell_size`
+    /// isn't positive.
+    pub fn new(dimensions: usize, cell_size: T) -> HierarchicalGrid<T, B> {
+        assert!(dimensions > 0 && dimensions <= MAX_DIMENSIONS);
+        assert!(cell_size > NumCast::from(0).unwrap());
+        HierarchicalGrid {
+            dimensions: dimensions,
+            cell_size: cell_size,
+            len: 0,
+            levels: Vec::new()
+        }
+    }
+
+    /// Returns the number of dimensions of the grid.
+    #[inline]
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Returns the number of items in the grid.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the grid holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the side length of the cells of `level`.
+    pub fn level_cell_size(&self, level: usize) -> T {
+        let two: T = NumCast::from(2).unwrap();
+        self.cell_size * two.powi(level as i32)
+    }
+
+    /// Returns the number of items stored in `level`.
+    pub fn level_len(&self, level: usize) -> usize {
+        match self.levels.get(level) {
+            Some(cells) => cells.values().map(|items| items.len()).sum(),
+            None => 0
+        }
+    }
+
+    /// Inserts an `item` into the level matching its size. Returns
+    /// `SpatialError::InvalidVolume` if the bounds of the item are
+    /// inverted or not finite, or `SpatialError::OutOfBounds` if the
+    /// item is too large for the highest level, or its position can't be
+    /// represented.
+    pub fn insert(&mut self, item: B) -> Result<(), SpatialError> {
+        let (level, cell) = match self.locate(&item) {
+            Ok(location) => location,
+            Err(err) => return Err(err)
+        };
+        while self.levels.len() <= level {
+            self.levels.push(HashMap::new());
+        }
+        self.levels[level].entry(cell).or_default().push(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes an item equal to `item` from the grid and returns it, or
+    /// `None` if there is no such item.
+    pub fn remove(&mut self, item: &B) -> Option<B> where B: PartialEq {
+        let (level, cell) = match self.locate(item) {
+            Ok(location) => location,
+            Err(_) => return None
+        };
+        let (removed, empty) = match self.levels.get_mut(level).and_then(|cells| cells.get_mut(&cell)) {
+            Some(items) => match items.iter().position(|other| other == item) {
+                Some(i) => (items.swap_remove(i), items.is_empty()),
+                None => return None
+            },
+            None => return None
+        };
+        if empty {
+            self.levels[level].remove(&cell);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Moves `old` to the bounds of `item` by removing the former and
+    /// inserting the latter. Returns `false`, leaving the grid
+    /// untouched, if there's no item equal to `old` or if `item` can't
+    /// be inserted.
+    pub fn update(&mut self, old: &B, item: B) -> bool where B: PartialEq {
+        if self.locate(&item).is_err() || self.remove(old).is_none() {
+            return false;
+        }
+        self.insert(item).is_ok()
+    }
+
+    /// Removes all items from the grid.
+    pub fn clear(&mut self) {
+        self.levels.clear();
+        self.len = 0;
+    }
+
+    /// Returns all items whose bounds intersect the box from `min` to
+    /// `max`, in no particular order.
+    ///
+    /// Panics if `min` or `max` don't have as many coordinates as the
+    /// grid has dimensions.
+    pub fn get_in_range<'a>(&'a self, min: &[T], max: &[T]) -> Vec<&'a B> {
+        assert_eq!(min.len(), self.dimensions);
+        assert_eq!(max.len(), self.dimensions);
+        let mut items = Vec::new();
+        self.collect(min, max, &mut |item| {
+            if self.overlaps(item, min, max) {
+                items.push(item);
+            }
+        });
+        items
+    }
+
+    /// Returns all items whose bounds contain the point `p`.
+    ///
+    /// Panics if `p` doesn't have as many coordinates as the grid has
+    /// dimensions.
+    #[inline]
+    pub fn get_at<'a>(&'a self, p: &[T]) -> Vec<&'a B> {
+        self.get_in_range(p, p)
+    }
+
+    /// Returns all items whose bounds intersect the sphere at `center`
+    /// with `radius`, in no particular order.
+    ///
+    /// Panics if `center` doesn't have as many coordinates as the grid
+    /// has dimensions.
+    pub fn get_in_radius<'a>(&'a self, center: &[T], radius: T) -> Vec<&'a B> {
+        assert_eq!(center.len(), self.dimensions);
+        let min: Vec<T> = center.iter().map(|&x| x - radius).collect();
+        let max: Vec<T> = center.iter().map(|&x| x + radius).collect();
+        let mut items = Vec::new();
+        self.collect(&min, &max, &mut |item| {
+            let (lo, hi) = self.bounds(item);
+            if geom::sphere_intersects_aabb(center, radius, &lo, &hi) {
+                items.push(item);
+            }
+        });
+        items
+    }
+
+    /// Returns the level and the cell an item is stored in.
+    fn locate(&self, item: &B) -> Result<(usize, Cell), SpatialError> {
+        let (min, max) = self.bounds(item);
+        let mut extent: T = NumCast::from(0).unwrap();
+        for axis in 0..self.dimensions {
+            // A NaN bound makes the extent NaN, which isn't finite.
+            if min[axis] > max[axis] || !(max[axis] - min[axis]).is_finite() {
+                return Err(SpatialError::InvalidVolume);
+            }
+            extent = extent.max(max[axis] - min[axis]);
+        }
+
+        let mut level = 0;
+        let mut size = self.cell_size;
+        while size < extent {
+            level += 1;
+            if level == LEVELS {
+                return Err(SpatialError::OutOfBounds);
+            }
+            size = size + size;
+        }
+        match cell_at(&min, size) {
+            Some(cell) => Ok((level, cell)),
+            None => Err(SpatialError::OutOfBounds)
+        }
+    }
+
+    /// Returns the lowest and highest corner of the bounds of `item`.
+    fn bounds(&self, item: &B) -> (Vec<T>, Vec<T>) {
+        (0..self.dimensions).map(|axis| item.grid_bounds(axis)).unzip()
+    }
+
+    /// Returns `true` if the bounds of `item` intersect the box from
+    /// `min` to `max`.
+    fn overlaps(&self, item: &B, min: &[T], max: &[T]) -> bool {
+        (0..self.dimensions).all(|axis| {
+            let (lo, hi) = item.grid_bounds(axis);
+            lo <= max[axis] && hi >= min[axis]
+        })
+    }
+
+    /// Passes every item of every level that might intersect the box
+    /// from `min` to `max` to `f`.
+    fn collect<'a, F: FnMut(&'a B)>(&'a self, min: &[T], max: &[T], f: &mut F) {
+        for (level, cells) in self.levels.iter().enumerate() {
+            if cells.is_empty() {
+                continue;
+            }
+            // Items are stored by their lowest corner and are no larger
+            // than the cells, so those intersecting the box have their
+            // corner at most one cell below it.
+            let size = self.level_cell_size(level);
+            let lowered: Vec<T> = min.iter().map(|&x| x - size).collect();
+            match (cell_at(&lowered, size), cell_at(max, size)) {
+                (Some(first), Some(last)) => collect_cells(cells, self.dimensions, first, last, f),
+                _ => {}
+            }
+        }
+    }
+}
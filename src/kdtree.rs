@@ -0,0 +1,344 @@
+//! A k-d tree over a static set of points.
+
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// The maximum number of points in a range of the tree that is scanned
+/// rather than split further.
+static LEAF_SIZE: usize = 8;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `KdTree`.
+pub trait Index<T: SpatialKey> {
+    /// Returns the coordinate of the position of `self` along `axis`,
+    /// in order of x, y, z and so on.
+    fn kdtree_coordinate(&self, axis: usize) -> T;
+}
+
+impl<T: SpatialKey> Index<T> for [T; 2] {
+    fn kdtree_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+impl<T: SpatialKey> Index<T> for [T; 3] {
+    fn kdtree_coordinate(&self, axis: usize) -> T {
+        self[axis]
+    }
+}
+
+/// An item index paired with a squared distance, ordered so that the
+/// farthest one is popped first from a `BinaryHeap`.
+struct Farthest<T: SpatialKey> {
+    d2: T,
+    index: usize
+}
+
+impl<T: SpatialKey> PartialEq for Farthest<T> {
+    fn eq(&self, other: &Farthest<T>) -> bool {
+        self.d2 == other.d2
+    }
+}
+
+impl<T: SpatialKey> Eq for Farthest<T> {}
+
+impl<T: SpatialKey> PartialOrd for Farthest<T> {
+    fn partial_cmp(&self, other: &Farthest<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey> Ord for Farthest<T> {
+    fn cmp(&self, other: &Farthest<T>) -> Ordering {
+        self.d2.partial_cmp(&other.d2).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A k-d tree over a fixed set of points of any number of dimensions,
+/// built by recursively splitting the points at their median along the
+/// axis of the largest spread.
+///
+/// The tree is stored implicitly: the items are ordered so that the
+/// median of every range of them splits it into its two subtrees,
+/// making the tree no larger than the items themselves.
+pub struct KdTree<T: SpatialKey, P: Index<T> + Clone> {
+    dimensions: usize,
+    /// Items, ordered so that each subtree covers a range.
+    items: Vec<P>,
+    /// The coordinates of the items, `dimensions` at a time.
+    coords: Vec<T>,
+    /// The splitting axis of the subtree whose median is at each index.
+    axes: Vec<usize>
+}
+
+/// Reorders `order` so that the item at `k` is the one that would be
+/// there if `order` was sorted by `key`, with no greater keys before it
+/// and no smaller ones after it.
+fn select<T: SpatialKey, F: Fn(usize) -> T>(order: &mut [usize], k: usize, key: F) {
+    let (mut lo, mut hi) = (0, order.len());
+    while hi - lo > 1 {
+        // Partition into keys less than, equal to and greater than the
+        // pivot, so runs of equal keys don't degrade the selection.
+        let pivot = key(order[lo + (hi - lo) / 2]);
+        let (mut lt, mut i, mut gt) = (lo, lo, hi);
+        while i < gt {
+            let value = key(order[i]);
+            if value < pivot {
+                order.swap(lt, i);
+                lt += 1;
+                i += 1;
+            } else if value > pivot {
+                gt -= 1;
+                order.swap(i, gt);
+            } else {
+                i += 1;
+            }
+        }
+
+        if k < lt {
+            hi = lt;
+        } else if k >= gt {
+            lo = gt;
+        } else {
+            return;
+        }
+    }
+}
+
+/// Splits the subtree over the points `order`, recording its splitting
+/// axis in `axes` at the position of its median.
+fn split<T: SpatialKey>(coords: &[T], dimensions: usize, order: &mut [usize], axes: &mut [usize]) {
+    if order.len() <= LEAF_SIZE {
+        return;
+    }
+
+    let mut axis = 0;
+    let mut spread: T = Float::neg_infinity();
+    for a in 0..dimensions {
+        let mut min: T = Float::infinity();
+        let mut max: T = Float::neg_infinity();
+        for &i in order.iter() {
+            min = min.min(coords[i * dimensions + a]);
+            max = max.max(coords[i * dimensions + a]);
+        }
+        if max - min > spread {
+            axis = a;
+            spread = max - min;
+        }
+    }
+
+    let median = order.len() / 2;
+    select(order, median, |i| coords[i * dimensions + axis]);
+    axes[median] = axis;
+
+    let (left, right) = order.split_at_mut(median);
+    let (left_axes, right_axes) = axes.split_at_mut(median);
+    split(coords, dimensions, left, left_axes);
+    split(coords, dimensions, &mut right[1..], &mut right_axes[1..]);
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> KdTree<T, P> {
+    /// Builds a tree over the points `items`, each with `dimensions`
+    /// coordinates.
+    ///
+    /// Panics if `dimensions` is zero.
+    pub fn new(dimensions: usize, items: &[P]) -> KdTree<T, P> {
+        assert!(dimensions > 0);
+        let _span = trace_span!("kdtree build", items = items.len());
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let mut coords = Vec::with_capacity(items.len() * dimensions);
+        for item in items.iter() {
+            for axis in 0..dimensions {
+                coords.push(item.kdtree_coordinate(axis));
+            }
+        }
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        let mut axes = vec![0; items.len()];
+        split(&coords, dimensions, &mut order, &mut axes);
+
+        let mut tree = KdTree {
+            dimensions: dimensions,
+            items: Vec::with_capacity(items.len()),
+            coords: Vec::with_capacity(coords.len()),
+            axes: axes
+        };
+        for &i in order.iter() {
+            tree.items.push(items[i].clone());
+            tree.coords.extend(coords[i * dimensions..(i + 1) * dimensions].iter().cloned());
+        }
+        trace_event!("kdtree build: {} items in {:?}", tree.items.len(), start.elapsed());
+        tree
+    }
+
+    /// Returns the number of dimensions of the points in the tree.
+    #[inline]
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the item closest to `point`, or `None` if the tree is
+    /// empty.
+    ///
+    /// Panics if `point` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn nearest(&self, point: &[T]) -> Option<&P> {
+        self.nearest_n(point, 1).pop()
+    }
+
+    /// Returns up to `k` items closest to `point`, ordered from the
+    /// closest to the farthest.
+    ///
+    /// Panics if `point` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn nearest_n(&self, point: &[T], k: usize) -> Vec<&P> {
+        assert_eq!(point.len(), self.dimensions);
+        let mut found = BinaryHeap::new();
+        if k > 0 {
+            self.nearest_node(point, k, 0, self.items.len(), &mut found);
+        }
+        found.into_sorted_vec().into_iter().map(|entry| &self.items[entry.index]).collect()
+    }
+
+    /// Returns all items inside the box from `min` to `max`, in no
+    /// particular order.
+    ///
+    /// Panics if `min` or `max` don't have as many coordinates as the
+    /// tree has dimensions.
+    pub fn get_in_range<'a>(&'a self, min: &[T], max: &[T]) -> Vec<&'a P> {
+        assert_eq!(min.len(), self.dimensions);
+        assert_eq!(max.len(), self.dimensions);
+        let mut items = Vec::new();
+        self.range_node(min, max, 0, self.items.len(), &mut items);
+        items
+    }
+
+    /// Returns all items within `radius` of `center`, in no particular
+    /// order.
+    ///
+    /// Panics if `center` doesn't have as many coordinates as the tree
+    /// has dimensions.
+    pub fn get_in_radius<'a>(&'a self, center: &[T], radius: T) -> Vec<&'a P> {
+        assert_eq!(center.len(), self.dimensions);
+        let mut items = Vec::new();
+        self.radius_node(center, radius, 0, self.items.len(), &mut items);
+        items
+    }
+
+    /// Returns the coordinates of the item at `i`.
+    #[inline]
+    fn point(&self, i: usize) -> &[T] {
+        &self.coords[i * self.dimensions..(i + 1) * self.dimensions]
+    }
+
+    /// Offers the item at `i` to the `k` closest items to `point` found.
+    fn offer(&self, point: &[T], k: usize, i: usize, found: &mut BinaryHeap<Farthest<T>>) {
+        let d2 = geom::distance2(point, self.point(i));
+        if found.len() < k {
+            found.push(Farthest { d2: d2, index: i });
+        } else if found.peek().is_some_and(|farthest| d2 < farthest.d2) {
+            found.pop();
+            found.push(Farthest { d2: d2, index: i });
+        }
+    }
+
+    /// Searches the subtree over the items `start..end` for the `k`
+    /// closest items to `point`, visiting the side of `point` first.
+    fn nearest_node(&self, point: &[T], k: usize, start: usize, end: usize,
+                    found: &mut BinaryHeap<Farthest<T>>) {
+        if end - start <= LEAF_SIZE {
+            for i in start..end {
+                self.offer(point, k, i, found);
+            }
+            return;
+        }
+
+        let median = start + (end - start) / 2;
+        self.offer(point, k, median, found);
+
+        let axis = self.axes[median];
+        let diff = point[axis] - self.point(median)[axis];
+        let (near, far) = if diff <= NumCast::from(0).unwrap() {
+            ((start, median), (median + 1, end))
+        } else {
+            ((median + 1, end), (start, median))
+        };
+        self.nearest_node(point, k, near.0, near.1, found);
+        if found.len() < k || found.peek().is_some_and(|farthest| diff * diff < farthest.d2) {
+            self.nearest_node(point, k, far.0, far.1, found);
+        }
+    }
+
+    /// Collects the items of the subtree over the items `start..end`
+    /// inside the box from `min` to `max`.
+    fn range_node<'a>(&'a self, min: &[T], max: &[T], start: usize, end: usize, items: &mut Vec<&'a P>) {
+        if end - start <= LEAF_SIZE {
+            for i in start..end {
+                if geom::point_in_aabb(self.point(i), min, max) {
+                    items.push(&self.items[i]);
+                }
+            }
+            return;
+        }
+
+        let median = start + (end - start) / 2;
+        if geom::point_in_aabb(self.point(median), min, max) {
+            items.push(&self.items[median]);
+        }
+
+        let axis = self.axes[median];
+        let split = self.point(median)[axis];
+        if min[axis] <= split {
+            self.range_node(min, max, start, median, items);
+        }
+        if max[axis] >= split {
+            self.range_node(min, max, median + 1, end, items);
+        }
+    }
+
+    /// Collects the items of the subtree over the items `start..end`
+    /// within `radius` of `center`.
+    fn radius_node<'a>(&'a self, center: &[T], radius: T, start: usize, end: usize, items: &mut Vec<&'a P>) {
+        if end - start <= LEAF_SIZE {
+            for i in start..end {
+                if geom::point_in_sphere(self.point(i), center, radius) {
+                    items.push(&self.items[i]);
+                }
+            }
+            return;
+        }
+
+        let median = start + (end - start) / 2;
+        if geom::point_in_sphere(self.point(median), center, radius) {
+            items.push(&self.items[median]);
+        }
+
+        let axis = self.axes[median];
+        let split = self.point(median)[axis];
+        if center[axis] - radius <= split {
+            self.radius_node(center, radius, start, median, items);
+        }
+        if center[axis] + radius >= split {
+            self.radius_node(center, radius, median + 1, end, items);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Support for coordinate types other than `f32` and `f64`.
+//!
+//! Any numeric type can be used as the coordinates of the data
+//! structures by implementing `SpatialKey` for it, such as a half
+//! precision float, a software double or an interval type. The traits
+//! `SpatialKey` requires are re-exported here, so that they're
+//! implemented from the same version of `num` this crate is built
+//! against, and `spatial_key!` implements `SpatialKey` itself.
+//!
+//! As usual, a type from another crate has to be wrapped in a newtype
+//! of your own before any of these traits can be implemented for it.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate spatial;
+//!
+//! use spatial::key::{Float, Num, NumCast, ToPrimitive, Zero, One};
+//!
+//! #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+//! struct Half(half::f16);
+//!
+//! // impl Float, Num, NumCast, ToPrimitive, Zero, One, Display and
+//! // the arithmetic operators for Half...
+//!
+//! spatial_key!(Half);
+//! ```
+
+pub use num::traits::{Float, Num, NumCast, ToPrimitive, Zero, One};
+
+/// Implements `SpatialKey` for each of the given types, which must
+/// already implement all of the traits it requires.
+#[macro_export]
+macro_rules! spatial_key {
+    ($($t:ty),*) => {
+        $(impl $crate::SpatialKey for $t {})*
+    }
+}
@@ -8,6 +8,40 @@
 //!
 //! * `Quadtree`, usually used for partitioning two-dimensional space.
 //! * `Octree`, used for partitioning three-dimensional space.
+//! * `Bvh`, a bounding volume hierarchy for casting rays against
+//!   triangles or other primitives.
+//! * `DynamicAabbTree`, a bounding volume tree over moving boxes for
+//!   broadphase collision detection, with a `PairManager` reporting
+//!   the pairs of boxes that begin and end between updates.
+//! * `BoundedQuadtree` and `BoundedOctree`, for items with an extent
+//!   rather than a single position.
+//! * `LooseQuadtree` and `LooseOctree`, loose variants of the former
+//!   suited for moving items.
+//! * `LinearQuadtree` and `LinearOctree`, static variants stored as
+//!   arrays of items sorted in Morton order, for large data sets that
+//!   don't change.
+//! * `KdTree`, a static tree over points of any number of dimensions
+//!   for fast nearest-neighbor queries.
+//! * `SpatialHash`, a uniform grid for many moving points of about the
+//!   same size, such as particles.
+//! * `HierarchicalGrid`, a stack of grids of growing cell sizes for
+//!   broadphase collision detection between items of any size.
+//! * `RangeTree`, a static tree over points in the plane for counting
+//!   and reporting the points in a rectangle in logarithmic time.
+//! * `VpTree`, a static tree over items of any metric space, given a
+//!   distance function, for nearest-neighbor queries.
+//! * `BallTree`, a static tree over points of many dimensions, such as
+//!   feature vectors, for nearest-neighbor and radius queries.
+//! * `CoverTree`, a dynamic tree over items of any metric space for
+//!   nearest-neighbor queries while items come and go.
+//! * `GeohashIndex`, an index over latitude and longitude points sorted
+//!   by their geohashes, for prefix and radius queries on the globe.
+//!
+//! All of them are generic over their coordinate type, which can be
+//! `f32`, `f64` or any other type implementing `SpatialKey`.
+//!
+//! The `curves` module orders points along Hilbert curves, for laying
+//! out or bulk loading items so that neighbors in space stay close.
 //!
 //! # Indexing
 //!
@@ -39,21 +73,146 @@
 //! ```
 //!
 //! The `Index`-traits are everything needed to start populating the
-//! trees.
+//! trees. Inserting an item returns a `SpatialError` if it can't be
+//! stored, such as when it lies outside of the tree.
+//!
+//! Trees too large for memory can be built from items spilled to
+//! temporary files and stored as a paged snapshot, one page for each
+//! subtree below a given depth, which `PagedQuadtree` and `PagedOctree`
+//! query by reading only the pages they need, as described in the
+//! `paging` module. The pages can be compressed one by one with LZ4,
+//! through `lz4_flex` with the `lz4` feature, or with Zstandard with the
+//! `zstd` feature, so a query decompresses only the pages it reads.
+//!
+//! The `surface` feature adds `OccupancyOctree::extract_surface`, which
+//! turns the occupied voxels of the tree into a closed triangle mesh
+//! with marching cubes, as vertex and index buffers ready for drawing.
+//!
+//! The `ffi` feature adds the `ffi` module, with `extern "C"` functions
+//! creating, filling and querying quadtrees and octrees of float points
+//! through opaque handles, declared for C and C++ in
+//! `include/spatial.h`.
+//!
+//! The `python` feature adds the `python` module, with `Quadtree` and
+//! `Octree` classes indexing the rows of numpy arrays, and the crate in
+//! the `python` directory builds them into a Python extension module.
+//!
+//! The `columnar` feature adds the `columnar` module, which builds
+//! `Quadtree`s and `Octree`s from the coordinate columns of Arrow record
+//! batches or Parquet files, and returns the rows found by queries as
+//! Arrow selection vectors.
+//!
+//! The `geojson` feature adds the `geojson` module, which builds a
+//! `Quadtree` from a GeoJSON FeatureCollection of Points and writes the
+//! results of queries, and the node volumes of a tree, back to GeoJSON
+//! for use in GIS tools. It reads and writes JSON with `serde_json`.
+//!
+//! The `tracing` feature runs the builds and queries of the data
+//! structures in `tracing` spans, as described in the `trace` module.
+//!
+//! # Thread safety
+//!
+//! None of the data structures use interior mutability, so they are
+//! `Send` and `Sync` whenever their coordinate, item and node data types
+//! are. Queries only need a shared reference, so a tree that is no
+//! longer modified can be shared between threads behind an `Arc` and
+//! queried from all of them at once.
+//!
+//! To give other parts of a program only the queries of a tree, hand
+//! them the `TreeView` returned by its `view` method rather than the
+//! tree itself.
+//!
+//! # Serialization
+//!
+//! With the `serde` feature enabled, `Quadtree`, `Octree` and their
+//! volumes and node paths implement `Serialize` and `Deserialize`, so a
+//! populated tree can be stored or sent over the network and restored
+//! with its nodes as they were, without inserting its items again.
+//! Items and node data must implement the traits too. A rebuild in
+//! progress isn't kept. Deserializing checks that the nodes fit
+//! together, as loading a snapshot does, so malformed data is refused
+//! with an error rather than making later operations on the tree fail.
+//!
+//! Without any further dependencies, the trees can also be saved to a
+//! compact binary snapshot and loaded from it, as described in the
+//! `snapshot` module.
+//!
+//! With the `rkyv` feature enabled, `to_archive` archives a `Quadtree`
+//! or an `Octree` with `rkyv`, flattened into arrays of nodes and items.
+//! `FlatQuadtree::archived` and `FlatOctree::archived` check such an
+//! archive and return it in place, ready for volume and radius queries,
+//! so a large tree can be memory-mapped from a file and queried at once
+//! without deserializing it.
+
+// The crate's style spells out field names, single-arm matches and
+// error propagation, so clippy's lints against them are turned off.
+#![allow(clippy::redundant_field_names, clippy::single_match, clippy::question_mark)]
 
-//#[unstable]
-#![feature(box_syntax)]
-#![feature(collections)]
-#![feature(core)]
-#![feature(convert)]
+#[macro_use]
+mod trace;
+#[macro_use]
+pub mod key;
 
 pub use quadtree::Quadtree;
 pub use octree::Octree;
+pub use bvh::Bvh;
+pub use dynamic::{DynamicAabbTree, PairManager, PairEvent};
+pub use kdtree::KdTree;
+pub use grid::{SpatialHash, HierarchicalGrid};
+pub use rangetree::RangeTree;
+pub use vptree::VpTree;
+pub use balltree::BallTree;
+pub use covertree::CoverTree;
+pub use geohash::GeohashIndex;
+pub use error::SpatialError;
 pub mod quadtree;
 pub mod octree;
+pub mod geom;
+pub mod bvh;
+pub mod dynamic;
+pub mod kdtree;
+pub mod grid;
+pub mod rangetree;
+pub mod vptree;
+pub mod balltree;
+pub mod covertree;
+pub mod error;
+pub mod snapshot;
+pub mod paging;
+pub mod curves;
+pub mod geohash;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "geojson")]
+pub mod geojson;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "columnar")]
+pub mod columnar;
 
 extern crate core;
 extern crate num;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "python")]
+extern crate numpy;
+#[cfg(feature = "columnar")]
+extern crate arrow;
+#[cfg(feature = "columnar")]
+extern crate parquet;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
+#[cfg(feature = "geojson")]
+extern crate serde_json;
 use num::traits::Float;
 use num::NumCast;
 use core::ops::Add;
@@ -62,6 +221,9 @@ use core::ops::Mul;
 use core::ops::Div;
 use std::fmt::Display;
 
+/// The coordinate type of the data structures. It's implemented for
+/// `f32` and `f64`, and can be implemented for other numeric types with
+/// `spatial_key!`, as described in the `key` module.
 pub trait SpatialKey : Float 
 		+ Display 
 		+ PartialOrd 
@@ -72,6 +234,47 @@ pub trait SpatialKey : Float
 		+ NumCast
 		+ Copy {}
 
-impl SpatialKey for f32 {}
+/// Tells `visit` how to go on after visiting a node.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Visit {
+    /// Visit the children of the node next.
+    Continue,
+    /// Don't visit the children of the node, but go on with the rest.
+    Skip,
+    /// Stop the traversal.
+    Stop
+}
+
+/// The order in which a tree keeps the items of each of its nodes,
+/// chosen when the tree is created. `get_in_volume` and `get_in_radius`
+/// return their results in the same order.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ItemOrder {
+    /// No particular order, which costs nothing to maintain.
+    Unordered,
+    /// The order in which the items were inserted. Moving an item with
+    /// `update` keeps its place.
+    Insertion,
+    /// The Morton order of the positions of the items, which keeps
+    /// items close to each other in space close in the results.
+    Morton
+}
+
+/// Statistics of the traversal done by a query, returned by its
+/// `_with_stats` variant to help find out why a query is slow and how
+/// to tune the capacity of a tree.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct QueryStats {
+    /// Number of nodes entered.
+    pub nodes_visited: usize,
+    /// Number of items tested against the query.
+    pub items_tested: usize,
+    /// Depth of the deepest node entered, the root being at depth `0`.
+    pub max_depth: usize,
+    /// Number of nodes skipped along with their subtrees, because they
+    /// were empty or outside the query.
+    pub nodes_pruned: usize
+}
 
-impl SpatialKey for f64 {}
\ No newline at end of file
+spatial_key!(f32, f64);
\ No newline at end of file
@@ -0,0 +1,82 @@
+use SpatialKey;
+use super::{Octree, NodeData, Index, Volume};
+
+/// A trait for node data summing up the items in the subtree of its
+/// node, such as the total, minimum or maximum of a field, for
+/// `Octree::aggregate_in_volume`.
+///
+/// The data keeps the summary of the items of the node itself up to
+/// date in `on_insert` and `on_remove`, and the summary of the items
+/// below it in `on_children_change`, from the `value`s of the children.
+/// In `on_merge` the summary of the items below becomes part of the
+/// node's own.
+pub trait Aggregate<T: SpatialKey, I>: NodeData<T, I> {
+    /// The summary of a set of items.
+    type Value;
+
+    /// Returns the summary of no items.
+    fn empty() -> Self::Value;
+
+    /// Returns the summary of `item` alone.
+    fn of_item(item: &I) -> Self::Value;
+
+    /// Combines the summaries of two sets of items.
+    fn combine(a: Self::Value, b: Self::Value) -> Self::Value;
+
+    /// Returns the summary of the items in the subtree of the node.
+    fn value(&self) -> Self::Value;
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the number of items inside `vol`. Subtrees lying entirely
+    /// inside `vol` are counted whole from the number of items cached in
+    /// them, and only the items of nodes on its boundary are tested.
+    pub fn count_in_volume(&self, vol: &Volume<T>) -> usize {
+        if !self.volume.intersects(vol) {
+            return 0;
+        }
+        if vol.contains(&self.volume.min) && vol.contains(&self.volume.max) {
+            return self.count;
+        }
+
+        let mut count = self.items.iter().filter(|item| vol.contains(&item.octree_index())).count();
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    count += node.count_in_volume(vol);
+                }
+            },
+            None => {}
+        }
+        count
+    }
+
+    /// Returns the summary of the items inside `vol`, as kept by the
+    /// data of the nodes. Subtrees lying entirely inside `vol` add the
+    /// `value` of their root, and only the items of nodes on its
+    /// boundary are summed up one by one.
+    pub fn aggregate_in_volume(&self, vol: &Volume<T>) -> D::Value where D: Aggregate<T, I> {
+        if !self.volume.intersects(vol) {
+            return D::empty();
+        }
+        if vol.contains(&self.volume.min) && vol.contains(&self.volume.max) {
+            return self.data.value();
+        }
+
+        let mut value = D::empty();
+        for item in self.items.iter() {
+            if vol.contains(&item.octree_index()) {
+                value = D::combine(value, D::of_item(item));
+            }
+        }
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    value = D::combine(value, node.aggregate_in_volume(vol));
+                }
+            },
+            None => {}
+        }
+        value
+    }
+}
@@ -0,0 +1,205 @@
+use SpatialKey;
+use SpatialError;
+use geom;
+use rkyv::{self, Archive, Archived, Serialize, AlignedVec};
+use rkyv::ser::serializers::AllocSerializer;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::CheckBytes;
+use super::{Octree, NodeData, Index, Volume};
+
+/// A node of a `FlatOctree`.
+#[derive(Archive, Serialize, Clone, Copy, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct FlatNode<T> {
+    /// The minimum corner of the node.
+    pub min: [T; 3],
+    /// The maximum corner of the node.
+    pub max: [T; 3],
+    /// Index of the first of the eight consecutive octants of the
+    /// node, or `u32::MAX` if the node is a leaf.
+    pub first_child: u32,
+    /// Index of the first item of the node.
+    pub first_item: u32,
+    /// The number of items stored in the node itself.
+    pub item_count: u32
+}
+
+/// An `Octree` flattened into arrays for archiving with `rkyv`, as
+/// returned by `Octree::to_flat`. Its archived form,
+/// `ArchivedFlatOctree`, answers queries in place, so a tree archived
+/// with `Octree::to_archive` can be memory-mapped and queried at
+/// once, without deserializing it first.
+#[derive(Archive, Serialize)]
+#[archive(check_bytes)]
+pub struct FlatOctree<T, I> {
+    /// The nodes, with the root first.
+    pub nodes: Vec<FlatNode<T>>,
+    /// The positions of the items, in the order of `items`.
+    pub positions: Vec<[T; 3]>,
+    /// The items, in depth-first order.
+    pub items: Vec<I>
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Flattens the tree into a `FlatOctree` of the same layout as
+    /// `to_gpu_buffers`, cloning its items. The node data isn't kept.
+    pub fn to_flat(&self) -> FlatOctree<T, I> {
+        let mut flat = FlatOctree { nodes: Vec::new(), positions: Vec::new(), items: Vec::new() };
+        flat.nodes.push(self.flat_node());
+        self.fill_flat(0, &mut flat);
+        flat
+    }
+
+    /// Archives the tree with `rkyv`, as flattened by `to_flat`. The
+    /// bytes can be written to a file and read back with
+    /// `FlatOctree::archived`. Returns `SpatialError::Serialization`
+    /// if an item can't be archived.
+    pub fn to_archive(&self) -> Result<AlignedVec, SpatialError>
+        where FlatOctree<T, I>: Serialize<AllocSerializer<256>>
+    {
+        rkyv::to_bytes::<_, 256>(&self.to_flat()).map_err(|err| SpatialError::Serialization(err.to_string()))
+    }
+
+    /// Returns the node without its children or items filled in.
+    fn flat_node(&self) -> FlatNode<T> {
+        FlatNode {
+            min: self.volume.min,
+            max: self.volume.max,
+            first_child: u32::MAX,
+            first_item: 0,
+            item_count: 0
+        }
+    }
+
+    /// Fills in the children and items of this node, stored at `index`.
+    fn fill_flat(&self, index: usize, flat: &mut FlatOctree<T, I>) {
+        flat.nodes[index].first_item = flat.items.len() as u32;
+        flat.nodes[index].item_count = self.items.len() as u32;
+        for item in self.items.iter() {
+            flat.positions.push(item.octree_index());
+            flat.items.push(item.clone());
+        }
+
+        match self.octants {
+            Some(ref octants) => {
+                let first = flat.nodes.len();
+                flat.nodes[index].first_child = first as u32;
+                for node in octants.iter() {
+                    flat.nodes.push(node.flat_node());
+                }
+                for (i, node) in octants.iter().enumerate() {
+                    node.fill_flat(first + i, flat);
+                }
+            },
+            None => {}
+        }
+    }
+}
+
+impl<T, I> FlatOctree<T, I>
+    where T: SpatialKey + Archive<Archived=T>, I: Archive
+{
+    /// Returns the archived tree at the end of `bytes`, as written by
+    /// `Octree::to_archive`, without copying or deserializing it.
+    /// `bytes` must be aligned to 16 bytes, as a memory-mapped file is.
+    ///
+    /// Returns `SpatialError::Serialization` if the bytes aren't a valid
+    /// archive, or if its nodes don't make up a tree, such as when a
+    /// node refers to items or octants beyond the end of the archive,
+    /// or to octants another node already has.
+    pub fn archived<'a>(bytes: &'a [u8]) -> Result<&'a ArchivedFlatOctree<T, I>, SpatialError>
+        where ArchivedFlatOctree<T, I>: CheckBytes<DefaultValidator<'a>>
+    {
+        let tree = match rkyv::check_archived_root::<FlatOctree<T, I>>(bytes) {
+            Ok(tree) => tree,
+            Err(err) => return Err(SpatialError::Serialization(err.to_string()))
+        };
+        if tree.nodes.is_empty() {
+            return Err(SpatialError::Serialization("archive without nodes".to_string()));
+        }
+        if tree.positions.len() != tree.items.len() {
+            return Err(SpatialError::Serialization("positions and items differ in number".to_string()));
+        }
+        // The nodes below the root come in blocks of 8 octants, each of
+        // which must be claimed by exactly one node before it, so that
+        // the nodes make up a tree rather than a graph a query would
+        // visit some nodes of more than once.
+        if (tree.nodes.len() - 1) % 8 != 0 {
+            return Err(SpatialError::Serialization("nodes don't come in blocks of octants".to_string()));
+        }
+        let mut claimed = vec![false; (tree.nodes.len() - 1) / 8];
+        for (i, node) in tree.nodes.iter().enumerate() {
+            if node.first_child != u32::MAX {
+                let first_child = node.first_child as usize;
+                if first_child <= i || (first_child - 1) % 8 != 0 || claimed.get((first_child - 1) / 8) != Some(&false) {
+                    return Err(SpatialError::Serialization(format!("node {} has invalid octants", i)));
+                }
+                claimed[(first_child - 1) / 8] = true;
+            }
+            if node.first_item as usize + node.item_count as usize > tree.items.len() {
+                return Err(SpatialError::Serialization(format!("node {} has invalid items", i)));
+            }
+        }
+        if claimed.contains(&false) {
+            return Err(SpatialError::Serialization("nodes outside of the tree".to_string()));
+        }
+        Ok(tree)
+    }
+}
+
+impl<T, I> ArchivedFlatOctree<T, I>
+    where T: SpatialKey + Archive<Archived=T>, I: Archive
+{
+    /// Returns the volume the tree covers.
+    pub fn bounds(&self) -> Volume<T> {
+        Volume::new(self.nodes[0].min, self.nodes[0].max)
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns all archived items inside the volume `vol`.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&Archived<I>> {
+        self.query(|min, max| geom::aabb_intersects_aabb(min, max, &vol.min, &vol.max),
+                   |p| vol.contains(p))
+    }
+
+    /// Returns all archived items within `radius` of `center`.
+    pub fn get_in_radius(&self, center: [T; 3], radius: T) -> Vec<&Archived<I>> {
+        self.query(|min, max| geom::sphere_intersects_aabb(&center, radius, min, max),
+                   |p| geom::point_in_sphere(p, &center, radius))
+    }
+
+    /// Returns the items at positions accepted by `item`, in the nodes
+    /// whose volumes are accepted by `node`.
+    fn query<N, F>(&self, node: N, item: F) -> Vec<&Archived<I>>
+        where N: Fn(&[T; 3], &[T; 3]) -> bool, F: Fn(&[T; 3]) -> bool
+    {
+        let mut results = Vec::new();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let current = &self.nodes[index];
+            if !node(&current.min, &current.max) {
+                continue;
+            }
+            let first = current.first_item as usize;
+            for i in first..first + current.item_count as usize {
+                if item(&self.positions[i]) {
+                    results.push(&self.items[i]);
+                }
+            }
+            if current.first_child != u32::MAX {
+                let first_child = current.first_child as usize;
+                stack.extend((first_child..first_child + 8).rev());
+            }
+        }
+        results
+    }
+}
@@ -0,0 +1,217 @@
+use SpatialKey;
+use SpatialError;
+use num::NumCast;
+use super::{Volume, DEFAULT_CAPACITY};
+
+/// A trait that must be implemented by types that are going to be
+/// inserted into a `BoundedOctree`.
+pub trait BoundedIndex<T: SpatialKey> {
+    /// Returns the bounding volume of `self` in 3D-space.
+    fn octree_bounds(&self) -> Volume<T>;
+}
+
+/// An octree storing items with an extent, such as walls or
+/// entities with a size, rather than points. Items crossing the
+/// boundaries of the octants stay in the node enclosing them, so
+/// every item lives in exactly one node and is never returned twice.
+pub struct BoundedOctree<T: SpatialKey, B: BoundedIndex<T> + Clone> {
+    /// Maximum number of items to store before subdivision.
+    capacity: usize,
+    /// Items in the node.
+    items: Vec<B>,
+    /// Bounding volume of the node.
+    volume: Volume<T>,
+    /// The octants of the node, in order of NW, NE, SW, SE, starting
+    /// from the upper half.
+    octants: Option<[Box<BoundedOctree<T, B>>; 8]>,
+    /// Bit `i` is set if the subtree of child `i` holds any items.
+    occupied: u8
+}
+
+/// Returns `true` if `inner` lies entirely inside `outer`.
+fn encloses<T: SpatialKey>(outer: &Volume<T>, inner: &Volume<T>) -> bool {
+    outer.contains(&inner.min) && outer.contains(&inner.max)
+}
+
+impl<T: SpatialKey, B: BoundedIndex<T> + Clone> BoundedOctree<T, B> {
+    /// Creates an empty octree with volume `vol`.
+    #[inline]
+    pub fn new(vol: Volume<T>) -> BoundedOctree<T, B> {
+        BoundedOctree::with_capacity(vol, DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty octree with volume `vol` and `capacity`.
+    #[inline]
+    pub fn with_capacity(vol: Volume<T>, capacity: usize) -> BoundedOctree<T, B> {
+        BoundedOctree {
+            capacity: capacity,
+            items: Vec::with_capacity(capacity),
+            volume: vol,
+            octants: None,
+            occupied: 0
+        }
+    }
+
+    /// Returns the bounding volume of the tree.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        let mut len = self.items.len();
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                len += node.len();
+            },
+            None => {}
+        }
+        len
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.occupied == 0
+    }
+
+    /// Inserts an `item` into the tree, subdividing it if necessary.
+    /// Items go to the first node on their way down with room for
+    /// them, or to the deepest node enclosing them if all of those are
+    /// full. Returns `SpatialError::InvalidVolume` if the bounds of the
+    /// item are inverted, or `SpatialError::OutOfBounds` if the item
+    /// doesn't fit inside the tree.
+    pub fn insert(&mut self, item: B) -> Result<(), SpatialError> {
+        let bounds = item.octree_bounds();
+        if !bounds.is_valid() {
+            return Err(SpatialError::InvalidVolume);
+        }
+        if !encloses(&self.volume, &bounds) {
+            return Err(SpatialError::OutOfBounds);
+        }
+        self.insert_enclosed(item, &bounds);
+        Ok(())
+    }
+
+    /// Stores `item`, whose `bounds` this node encloses, in this node
+    /// or in the child enclosing it.
+    fn insert_enclosed(&mut self, item: B, bounds: &Volume<T>) {
+        if self.items.len() < self.capacity {
+            self.items.push(item);
+            return;
+        }
+
+        if self.octants.is_none() {
+            self.subdivide();
+        }
+
+        match self.octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                if encloses(&node.volume, bounds) {
+                    node.insert_enclosed(item, bounds);
+                    self.occupied |= 1 << i;
+                    return;
+                }
+            },
+            None => {}
+        }
+
+        // The item straddles the octants, so it stays here.
+        self.items.push(item);
+    }
+
+    /// Removes an item equal to `item` from the tree and returns it,
+    /// or `None` if there is no such item.
+    pub fn remove(&mut self, item: &B) -> Option<B> where B: PartialEq {
+        self.remove_enclosed(item, &item.octree_bounds())
+    }
+
+    /// Removes an item equal to `item`, with the given `bounds`, from
+    /// this node or its children.
+    fn remove_enclosed(&mut self, item: &B, bounds: &Volume<T>) -> Option<B> where B: PartialEq {
+        if !encloses(&self.volume, bounds) {
+            return None;
+        }
+        match self.items.iter().position(|other| other == item) {
+            Some(i) => return Some(self.items.swap_remove(i)),
+            None => {}
+        }
+
+        match self.octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                if self.occupied & (1 << i) == 0 {
+                    continue;
+                }
+                match node.remove_enclosed(item, bounds) {
+                    Some(removed) => {
+                        if node.is_empty() {
+                            self.occupied &= !(1 << i);
+                        }
+                        return Some(removed);
+                    },
+                    None => {}
+                }
+            },
+            None => {}
+        }
+        None
+    }
+
+    /// Returns all items whose bounds intersect the volume `vol`.
+    pub fn get_in_volume<'a>(&'a self, vol: &Volume<T>) -> Vec<&'a B> {
+        let mut items = Vec::new();
+        self.collect(vol, &mut items);
+        items
+    }
+
+    /// Returns all items whose bounds contain the point `p`.
+    pub fn get_at<'a>(&'a self, p: &[T; 3]) -> Vec<&'a B> {
+        self.get_in_volume(&Volume::new(*p, *p))
+    }
+
+    /// Collects the items of this node and its children whose bounds
+    /// intersect `vol`.
+    fn collect<'a>(&'a self, vol: &Volume<T>, items: &mut Vec<&'a B>) {
+        if !self.volume.intersects(vol) {
+            return;
+        }
+
+        for item in self.items.iter() {
+            if item.octree_bounds().intersects(vol) {
+                items.push(item);
+            }
+        }
+
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.collect(vol, items);
+                }
+            },
+            None => {}
+        }
+    }
+
+    fn subdivide(&mut self) {
+        let cap = self.capacity;
+        let min = self.volume.min;
+        let max = self.volume.max;
+
+        let val2: T = NumCast::from(2).unwrap();
+        let mid = [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2, (min[2] + max[2]) / val2];
+
+        self.octants = Some([
+            // upper
+            Box::new(BoundedOctree::with_capacity(Volume::new([min[0], min[1], min[2]], [mid[0], mid[1], mid[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([mid[0], min[1], min[2]], [max[0], mid[1], mid[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([min[0], mid[1], min[2]], [mid[0], max[1], mid[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([mid[0], mid[1], min[2]], [max[0], max[1], mid[2]]), cap)),
+            // lower
+            Box::new(BoundedOctree::with_capacity(Volume::new([min[0], min[1], mid[2]], [mid[0], mid[1], max[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([mid[0], min[1], mid[2]], [max[0], mid[1], max[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([min[0], mid[1], mid[2]], [mid[0], max[1], max[2]]), cap)),
+            Box::new(BoundedOctree::with_capacity(Volume::new([mid[0], mid[1], mid[2]], [max[0], max[1], max[2]]), cap))
+                ]);
+    }
+}
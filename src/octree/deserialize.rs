@@ -0,0 +1,107 @@
+use SpatialKey;
+use SpatialError;
+use ItemOrder;
+use std::convert::TryFrom;
+use super::{Octree, NodeData, Index, Volume, MAX_DEPTH};
+
+/// The fields of a serialized `Octree` node, read as they are and
+/// only turned into a tree once they've been checked to make up a
+/// valid one.
+#[derive(Deserialize)]
+#[serde(rename = "Octree")]
+pub struct OctreeData<T: SpatialKey, I, D> {
+    capacity: usize,
+    items: Vec<I>,
+    count: usize,
+    volume: Volume<T>,
+    octants: Option<[Box<OctreeData<T, I, D>>; 8]>,
+    occupied: u8,
+    data: D,
+    order: ItemOrder,
+    sequence: Vec<u64>,
+    next_sequence: u64
+}
+
+impl<T, I, D> OctreeData<T, I, D>
+    where T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I> {
+    /// Turns the data into a node and its descendants, unchecked.
+    fn into_node(self) -> Octree<T, I, D> {
+        Octree {
+            capacity: self.capacity,
+            items: self.items,
+            count: self.count,
+            volume: self.volume,
+            octants: self.octants.map(|octants| octants.map(|node| Box::new(node.into_node()))),
+            occupied: self.occupied,
+            data: self.data,
+            rebuild: None,
+            order: self.order,
+            sequence: self.sequence,
+            next_sequence: self.next_sequence,
+            changes: None
+        }
+    }
+}
+
+impl<T, I, D> TryFrom<OctreeData<T, I, D>> for Octree<T, I, D>
+    where T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I> {
+    type Error = SpatialError;
+
+    /// Restores a deserialized tree, or returns
+    /// `SpatialError::Serialization` if its nodes don't fit together,
+    /// with the same checks as `load_from`.
+    fn try_from(data: OctreeData<T, I, D>) -> Result<Octree<T, I, D>, SpatialError> {
+        let tree = data.into_node();
+        if !tree.volume.is_valid() {
+            return Err(SpatialError::Serialization("invalid volume".to_string()));
+        }
+        match tree.check_node(0, tree.capacity, tree.order) {
+            Ok(()) => Ok(tree),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl<T, I, D> Octree<T, I, D>
+    where T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I> {
+    /// Returns an error unless this node, found at `depth`, and its
+    /// descendants make up a valid subtree of a tree with `capacity`
+    /// whose items are kept in `order`, with the volumes, item counts
+    /// and occupancy its children would have been given by inserting.
+    fn check_node(&self, depth: usize, capacity: usize, order: ItemOrder) -> Result<(), SpatialError> {
+        if self.capacity != capacity || self.order != order {
+            return Err(SpatialError::Serialization("node unlike the root of its tree".to_string()));
+        }
+        match self.check_items() {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+
+        let (mut occupied, mut count) = (0, self.items.len());
+        match self.octants {
+            Some(ref octants) => {
+                if depth >= MAX_DEPTH {
+                    return Err(SpatialError::Serialization("node deeper than the depth limit".to_string()));
+                }
+                for ((i, node), vol) in octants.iter().enumerate().zip(self.octant_volumes().iter()) {
+                    if node.volume.min != vol.min || node.volume.max != vol.max {
+                        return Err(SpatialError::Serialization("node volume doesn't match its parent".to_string()));
+                    }
+                    match node.check_node(depth + 1, capacity, order) {
+                        Ok(()) => {},
+                        Err(err) => return Err(err)
+                    }
+                    if !node.items.is_empty() || node.occupied != 0 {
+                        occupied |= 1 << i;
+                    }
+                    count += node.count;
+                }
+            },
+            None => {}
+        }
+        if self.occupied != occupied || self.count != count {
+            return Err(SpatialError::Serialization("item count of a node doesn't match its items".to_string()));
+        }
+        Ok(())
+    }
+}
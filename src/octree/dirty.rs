@@ -0,0 +1,136 @@
+use SpatialKey;
+use std::collections::HashMap;
+use super::{Octree, NodeData, Index, NodePath};
+
+/// The net change to the items of a node of a `Octree` since the last
+/// call to `collect_dirty`. A copy of the tree is brought up to date by
+/// removing the `removed` items from the node at `path` and then adding
+/// the `added` ones.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NodeDelta<I> {
+    /// The path of the node from the root.
+    pub path: NodePath,
+    /// Items stored in the node.
+    pub added: Vec<I>,
+    /// Items taken out of the node, including those of a node that has
+    /// been merged into its parent.
+    pub removed: Vec<I>
+}
+
+/// The changes to the items of a node since the last call to
+/// `collect_dirty`, kept in every node once the tree is tracked.
+pub struct Changes<I> {
+    /// Items stored in the node, in order.
+    added: Vec<I>,
+    /// Items taken out of the node, in order.
+    removed: Vec<I>,
+    /// The changes of descendants that have been dropped since, by
+    /// their path from this node.
+    dropped: Vec<(Vec<usize>, Vec<I>, Vec<I>)>
+}
+
+impl<I: Clone> Changes<I> {
+    /// Returns the changes of a node that hasn't changed.
+    pub fn new() -> Changes<I> {
+        Changes {
+            added: Vec::new(),
+            removed: Vec::new(),
+            dropped: Vec::new()
+        }
+    }
+
+    /// Records that `item` has been stored in the node.
+    #[inline]
+    pub fn add(&mut self, item: &I) {
+        self.added.push(item.clone());
+    }
+
+    /// Records that `item` has been taken out of the node.
+    #[inline]
+    pub fn remove(&mut self, item: &I) {
+        self.removed.push(item.clone());
+    }
+
+    /// Takes over the `changes` of child `i`, which is being dropped
+    /// along with its remaining `items`.
+    pub fn drop_child(&mut self, i: usize, changes: Changes<I>, items: &[I]) {
+        let mut removed = changes.removed;
+        removed.extend(items.iter().cloned());
+        self.dropped.push((vec![i], changes.added, removed));
+        for (mut path, added, removed) in changes.dropped.into_iter() {
+            path.insert(0, i);
+            self.dropped.push((path, added, removed));
+        }
+    }
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the changes to the items of every node since the last
+    /// call, at most one per node and in depth-first order, so that a
+    /// server can send them to clients keeping a copy of the tree.
+    /// Nodes that have been merged into their parent since are reported
+    /// with all of their items removed.
+    ///
+    /// The tree only starts recording its changes with the first call,
+    /// which reports every item as added to its node. Items changed in
+    /// place, through `iter_mut` or the `_mut` queries, aren't noticed.
+    pub fn collect_dirty(&mut self) -> Vec<NodeDelta<I>> where I: PartialEq {
+        if self.changes.is_none() {
+            self.track();
+        }
+
+        let mut deltas: Vec<NodeDelta<I>> = Vec::new();
+        let mut lookup = HashMap::new();
+        self.collect_changes(&mut Vec::new(), &mut |path, added, removed| {
+            let i = *lookup.entry(path).or_insert_with(|| {
+                deltas.push(NodeDelta { path: path, added: Vec::new(), removed: Vec::new() });
+                deltas.len() - 1
+            });
+            deltas[i].added.extend(added);
+            deltas[i].removed.extend(removed);
+        });
+
+        // Items taken out again cancel out with those stored, whatever
+        // their order, as only the difference between the old and the
+        // new items of a node matters.
+        for delta in deltas.iter_mut() {
+            let mut removed = Vec::new();
+            for item in delta.removed.drain(..) {
+                match delta.added.iter().position(|other| *other == item) {
+                    Some(i) => { delta.added.remove(i); },
+                    None => removed.push(item)
+                }
+            }
+            delta.removed = removed;
+        }
+        deltas.retain(|delta| !delta.added.is_empty() || !delta.removed.is_empty());
+        deltas
+    }
+
+    /// Passes the changes recorded in this node, found at `path`, and in
+    /// its descendants to `f`, starting over with none.
+    fn collect_changes<F: FnMut(NodePath, Vec<I>, Vec<I>)>(&mut self, path: &mut Vec<usize>, f: &mut F) {
+        match self.changes.take() {
+            Some(changes) => {
+                let changes = *changes;
+                for (relative, added, removed) in changes.dropped.into_iter() {
+                    let mut dropped = path.clone();
+                    dropped.extend(relative);
+                    f(NodePath::from_indices(&dropped).unwrap(), added, removed);
+                }
+                f(NodePath::from_indices(path).unwrap(), changes.added, changes.removed);
+            },
+            None => {}
+        }
+        self.changes = Some(Box::new(Changes::new()));
+
+        match self.octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                path.push(i);
+                node.collect_changes(path, f);
+                path.pop();
+            },
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use SpatialKey;
+use std::mem;
+use std::slice;
+use num::NumCast;
+use super::{Octree, NodeData, Index};
+
+/// A node of an `Octree` laid out for upload to the GPU, 48 bytes in
+/// size and aligned for `std430` storage buffers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpuNode {
+    /// The minimum corner of the node.
+    pub min: [f32; 3],
+    /// Index of the first of the eight consecutive octants of the node,
+    /// or `u32::MAX` if the node is a leaf.
+    pub first_child: u32,
+    /// The maximum corner of the node.
+    pub max: [f32; 3],
+    /// Index of the first item of the node.
+    pub first_item: u32,
+    /// The number of items stored in the node itself.
+    pub item_count: u32,
+    pub _padding: [u32; 3]
+}
+
+/// An item of an `Octree` laid out for upload to the GPU, 16 bytes in
+/// size.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpuItem {
+    /// The position of the item.
+    pub position: [f32; 3],
+    pub _padding: u32
+}
+
+/// Flat node and item arrays of an `Octree`, as returned by
+/// `Octree::to_gpu_buffers`.
+pub struct GpuBuffers {
+    /// The nodes, with the root first.
+    pub nodes: Vec<GpuNode>,
+    /// The items, in the depth-first order of `neighbor_lists`.
+    pub items: Vec<GpuItem>
+}
+
+impl GpuBuffers {
+    /// Returns the raw bytes of `nodes`.
+    pub fn node_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.nodes.as_ptr() as *const u8,
+                                  self.nodes.len() * mem::size_of::<GpuNode>())
+        }
+    }
+
+    /// Returns the raw bytes of `items`.
+    pub fn item_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.items.as_ptr() as *const u8,
+                                  self.items.len() * mem::size_of::<GpuItem>())
+        }
+    }
+}
+
+/// Converts `p` to single precision.
+fn to_f32<T: SpatialKey>(p: &[T; 3]) -> [f32; 3] {
+    [NumCast::from(p[0]).unwrap(), NumCast::from(p[1]).unwrap(), NumCast::from(p[2]).unwrap()]
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Flattens the tree into arrays of plain `#[repr(C)]` structs that
+    /// can be uploaded to the GPU as storage buffers, so that compute
+    /// shaders can traverse the same tree. The octants of each node are
+    /// stored next to each other, and the position of an item in the
+    /// item array is its index in depth-first order.
+    pub fn to_gpu_buffers(&self) -> GpuBuffers {
+        let mut buffers = GpuBuffers { nodes: Vec::new(), items: Vec::new() };
+        buffers.nodes.push(self.gpu_node());
+        self.fill_gpu_buffers(0, &mut buffers);
+        buffers
+    }
+    
+    /// Returns the node without its children or items filled in.
+    fn gpu_node(&self) -> GpuNode {
+        GpuNode {
+            min: to_f32(&self.volume.min),
+            first_child: u32::MAX,
+            max: to_f32(&self.volume.max),
+            first_item: 0,
+            item_count: 0,
+            _padding: [0; 3]
+        }
+    }
+    
+    /// Fills in the children and items of this node, stored at `index`.
+    fn fill_gpu_buffers(&self, index: usize, buffers: &mut GpuBuffers) {
+        buffers.nodes[index].first_item = buffers.items.len() as u32;
+        buffers.nodes[index].item_count = self.items.len() as u32;
+        for item in self.items.iter() {
+            buffers.items.push(GpuItem { position: to_f32(&item.octree_index()), _padding: 0 });
+        }
+        
+        match self.octants {
+            Some(ref octants) => {
+                let first = buffers.nodes.len();
+                buffers.nodes[index].first_child = first as u32;
+                for node in octants.iter() {
+                    buffers.nodes.push(node.gpu_node());
+                }
+                for (i, node) in octants.iter().enumerate() {
+                    node.fill_gpu_buffers(first + i, buffers);
+                }
+            },
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use SpatialKey;
+use Visit;
+use std::slice;
+use std::vec;
+use super::{Octree, NodeData, Index, Volume};
+
+/// An iterator over the items of an `Octree`, created by `Octree::iter`.
+pub struct Iter<'a, T: SpatialKey + 'a, I: Index<T> + Clone + 'a, D: NodeData<T, I> + 'a = ()> {
+    /// Nodes still to be visited, the next one last.
+    stack: Vec<&'a Octree<T, I, D>>,
+    /// The remaining items of the current node.
+    items: slice::Iter<'a, I>
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Iterator for Iter<'a, T, I, D> {
+    type Item = &'a I;
+
+    fn next(&mut self) -> Option<&'a I> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            self.items = node.items.iter();
+            match node.octants {
+                Some(ref octants) => for child in octants.iter().rev() {
+                    self.stack.push(&**child);
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// A mutable iterator over the items of an `Octree`, created by
+/// `Octree::iter_mut`.
+pub struct IterMut<'a, T: SpatialKey + 'a, I: Index<T> + Clone + 'a, D: NodeData<T, I> + 'a = ()> {
+    stack: Vec<&'a mut Octree<T, I, D>>,
+    items: slice::IterMut<'a, I>
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Iterator for IterMut<'a, T, I, D> {
+    type Item = &'a mut I;
+
+    fn next(&mut self) -> Option<&'a mut I> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            let Octree { ref mut items, ref mut octants, .. } = *node;
+            self.items = items.iter_mut();
+            match *octants {
+                Some(ref mut octants) => for child in octants.iter_mut().rev() {
+                    self.stack.push(&mut **child);
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// A lazy iterator over the items of an `Octree` inside a volume,
+/// created by `Octree::query`.
+pub struct Query<'a, T: SpatialKey + 'a, I: Index<T> + Clone + 'a, D: NodeData<T, I> + 'a = ()> {
+    volume: Volume<T>,
+    stack: Vec<&'a Octree<T, I, D>>,
+    items: slice::Iter<'a, I>
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Iterator for Query<'a, T, I, D> {
+    type Item = &'a I;
+
+    fn next(&mut self) -> Option<&'a I> {
+        loop {
+            for item in self.items.by_ref() {
+                if self.volume.contains(&item.octree_index()) {
+                    return Some(item);
+                }
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            self.items = node.items.iter();
+            match node.octants {
+                Some(ref octants) => for (i, child) in octants.iter().enumerate().rev() {
+                    if node.occupied & (1 << i) != 0 && child.volume.intersects(&self.volume) {
+                        self.stack.push(&**child);
+                    }
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// An iterator moving the items out of an `Octree`, created by
+/// `into_iter`.
+pub struct IntoIter<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I> = ()> {
+    stack: Vec<Box<Octree<T, I, D>>>,
+    items: vec::IntoIter<I>
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Iterator for IntoIter<T, I, D> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<I> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => *node,
+                None => return None
+            };
+            let Octree { items, octants, .. } = node;
+            self.items = items.into_iter();
+            match octants {
+                Some(octants) => {
+                    let octants: Box<[Box<Octree<T, I, D>>]> = Box::new(octants);
+                    self.stack.extend(octants.into_vec().into_iter().rev());
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns an iterator over all items of the tree, in the same
+    /// depth-first order as `neighbor_lists`. Nodes are entered only as
+    /// the iteration reaches them.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T, I, D> {
+        Iter { stack: vec![self], items: [].iter() }
+    }
+
+    /// Returns an iterator over the items inside `vol`, like
+    /// `get_in_volume` but without collecting them. Nodes are entered
+    /// only as the iteration reaches them, and the only allocation is
+    /// the stack of nodes still to visit.
+    pub fn query<'a>(&'a self, vol: &Volume<T>) -> Query<'a, T, I, D> {
+        let stack = if self.volume.intersects(vol) { vec![self] } else { Vec::new() };
+        Query { volume: *vol, stack: stack, items: [].iter() }
+    }
+
+    /// Calls `f` with the volume and the items of every node, parents
+    /// before their children and children in order. The result of `f`
+    /// decides whether the children of the node are visited, and
+    /// whether to go on at all.
+    pub fn visit<F: FnMut(&Volume<T>, &[I]) -> Visit>(&self, mut f: F) {
+        self.visit_node(&mut f);
+    }
+
+    /// Visits this node and its children, returning `false` if the
+    /// traversal was stopped.
+    fn visit_node<F: FnMut(&Volume<T>, &[I]) -> Visit>(&self, f: &mut F) -> bool {
+        match f(&self.volume, &self.items) {
+            Visit::Stop => return false,
+            Visit::Skip => return true,
+            Visit::Continue => {}
+        }
+        
+        match self.octants {
+            Some(ref octants) => octants.iter().all(|node| node.visit_node(f)),
+            None => true
+        }
+    }
+
+    /// Returns an iterator over mutable references to all items of the
+    /// tree, in the same order as `iter`. The position of an item must
+    /// not be changed through the reference, as the item would no longer
+    /// be found in the right node.
+    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, T, I, D> {
+        self.rebuild = None;
+        IterMut { stack: vec![self], items: [].iter_mut() }
+    }
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> IntoIterator for Octree<T, I, D> {
+    type Item = I;
+    type IntoIter = IntoIter<T, I, D>;
+
+    fn into_iter(self) -> IntoIter<T, I, D> {
+        IntoIter { stack: vec![Box::new(self)], items: Vec::new().into_iter() }
+    }
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> IntoIterator for &'a Octree<T, I, D> {
+    type Item = &'a I;
+    type IntoIter = Iter<'a, T, I, D>;
+
+    fn into_iter(self) -> Iter<'a, T, I, D> {
+        self.iter()
+    }
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> IntoIterator for &'a mut Octree<T, I, D> {
+    type Item = &'a mut I;
+    type IntoIter = IterMut<'a, T, I, D>;
+
+    fn into_iter(self) -> IterMut<'a, T, I, D> {
+        self.iter_mut()
+    }
+}
@@ -0,0 +1,175 @@
+use SpatialKey;
+use geom;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use super::{Index, Volume};
+use super::nearest::{morton, quantize, interleave};
+
+/// The number of bits per axis of the Morton codes of a
+/// `LinearOctree`.
+static BITS: u32 = 21;
+
+/// The maximum number of items in a range of Morton codes that is
+/// scanned rather than split further by a query.
+static LEAF_SIZE: usize = 16;
+
+/// Returns the offset of the last Morton code of a cell at `level` from
+/// its first one.
+#[inline]
+fn span(level: u32) -> u64 {
+    (1u64 << (3 * level)) - 1
+}
+
+/// A volume query, with the grid cells its corners fall into.
+struct VolumeQuery<'q, T: SpatialKey + 'q> {
+    vol: &'q Volume<T>,
+    min: [u64; 3],
+    max: [u64; 3]
+}
+
+/// Returns the index of the first of the sorted `keys` that is greater
+/// than `key`.
+fn upper_bound(keys: &[u64], key: u64) -> usize {
+    let (mut lo, mut hi) = (0, keys.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if keys[mid] <= key { lo = mid + 1; } else { hi = mid; }
+    }
+    lo
+}
+
+/// A static octree stored as a flat array of items sorted by the 64-bit
+/// Morton codes of their positions, without any nodes. Every node of
+/// the equivalent pointer-based tree is a contiguous range of the
+/// array, so a box query splits its box into such ranges and scans
+/// them, descending only where the box cuts through a range holding
+/// more than a few items.
+///
+/// That takes far less memory than an `Octree` and keeps the items of
+/// a query close together, which makes it the better choice for huge
+/// point clouds. The sorted codes can be uploaded to a GPU as they
+/// are.
+pub struct LinearOctree<T: SpatialKey, I: Index<T> + Clone> {
+    /// The volume the Morton codes are quantized in.
+    volume: Volume<T>,
+    /// The Morton codes of the items, sorted.
+    keys: Vec<u64>,
+    /// Items, in the order of `keys`.
+    items: Vec<I>
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone> LinearOctree<T, I> {
+    /// Builds a tree over `items` covering `vol`. Items outside of
+    /// `vol` are kept, but share the cells along its border.
+    pub fn new(vol: Volume<T>, items: &[I]) -> LinearOctree<T, I> {
+        let _span = trace_span!("linear octree build", items = items.len());
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let mut keyed: Vec<(u64, usize)> = items.iter().enumerate()
+            .map(|(i, item)| (morton(&vol, &item.octree_index()), i))
+            .collect();
+        keyed.sort();
+        let tree = LinearOctree {
+            volume: vol,
+            keys: keyed.iter().map(|&(key, _)| key).collect(),
+            items: keyed.iter().map(|&(_, i)| items[i].clone()).collect()
+        };
+        trace_event!("linear octree build: {} items in {:?}", tree.items.len(), start.elapsed());
+        tree
+    }
+
+    /// Returns the volume the tree covers.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the items in Morton order.
+    #[inline]
+    pub fn items(&self) -> &[I] {
+        &self.items
+    }
+
+    /// Returns the Morton codes of the items, in the same order as
+    /// `items`.
+    #[inline]
+    pub fn keys(&self) -> &[u64] {
+        &self.keys
+    }
+
+    /// Returns all items inside the volume `vol`, in Morton order.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&I> {
+        let mut items = Vec::new();
+        if vol.is_valid() {
+            let query = VolumeQuery {
+                vol: vol,
+                min: quantize(&self.volume, &vol.min),
+                max: quantize(&self.volume, &vol.max)
+            };
+            self.volume_range(&query, [0, 0, 0], BITS, 0, self.items.len(), &mut items);
+        }
+        items
+    }
+
+    /// Returns all items within `radius` of `center`, in Morton order.
+    pub fn get_in_radius(&self, center: [T; 3], radius: T) -> Vec<&I> {
+        let min = [center[0] - radius, center[1] - radius, center[2] - radius];
+        let max = [center[0] + radius, center[1] + radius, center[2] + radius];
+
+        self.get_in_volume(&Volume::new(min, max)).into_iter()
+            .filter(|item| geom::point_in_sphere(&item.octree_index(), &center, radius))
+            .collect()
+    }
+
+    /// Collects the items `start..end`, which are those of the grid
+    /// cell at `cell` of `2^level` cells per edge, that are inside
+    /// the volume of `query`.
+    fn volume_range<'a>(&'a self, query: &VolumeQuery<T>, cell: [u64; 3], level: u32,
+                        start: usize, end: usize, items: &mut Vec<&'a I>) {
+        let (vol, min, max) = (query.vol, &query.min, &query.max);
+        if start == end {
+            return;
+        }
+        let size = 1u64 << level;
+        if (0..3).any(|i| cell[i] > max[i] || cell[i] + (size - 1) < min[i]) {
+            return;
+        }
+
+        let covered = (0..3).all(|i| cell[i] >= min[i] && cell[i] + (size - 1) <= max[i]);
+        if covered || level == 0 || end - start <= LEAF_SIZE {
+            // Quantization truncates, so even the items of covered
+            // cells are tested.
+            for item in self.items[start..end].iter() {
+                if vol.contains(&item.octree_index()) {
+                    items.push(item);
+                }
+            }
+            return;
+        }
+
+        // The eight octants of the cell follow each other in Morton
+        // order.
+        let half = size / 2;
+        let mut first = start;
+        for i in 0..8 {
+            let octant = [cell[0] + (i & 1) * half, cell[1] + ((i >> 1) & 1) * half, cell[2] + (i >> 2) * half];
+            let last = interleave(&octant) + span(level - 1);
+            let next = first + upper_bound(&self.keys[first..end], last);
+            self.volume_range(query, octant, level - 1, first, next, items);
+            first = next;
+        }
+    }
+}
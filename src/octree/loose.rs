@@ -0,0 +1,250 @@
+use SpatialKey;
+use SpatialError;
+use num::NumCast;
+use super::{Volume, BoundedIndex, DEFAULT_CAPACITY};
+
+/// A loose octree, whose nodes accept items reaching beyond their
+/// volume by up to `looseness - 1` times its half size. An item goes to
+/// the node containing its center, so sized and moving items fit into
+/// exactly one node found from their center and size alone, instead of
+/// getting stuck high up in the tree as they would in a
+/// `BoundedOctree`.
+pub struct LooseOctree<T: SpatialKey, B: BoundedIndex<T> + Clone> {
+    /// Maximum number of items to store before subdivision.
+    capacity: usize,
+    /// Factor by which the node volumes are enlarged.
+    looseness: T,
+    /// Items in the node.
+    items: Vec<B>,
+    /// Bounding volume of the node, before enlarging it.
+    volume: Volume<T>,
+    /// The octants of the node, in order of NW, NE, SW, SE, starting
+    /// from the upper half.
+    octants: Option<[Box<LooseOctree<T, B>>; 8]>,
+    /// Bit `i` is set if the subtree of child `i` holds any items.
+    occupied: u8
+}
+
+impl<T: SpatialKey, B: BoundedIndex<T> + Clone> LooseOctree<T, B> {
+    /// Creates an empty octree with volume `vol`, whose nodes are
+    /// enlarged by `looseness`. A `looseness` of `2`, doubling the size
+    /// of each node, is the usual choice.
+    ///
+    /// Panics if `looseness` is less than `1`.
+    #[inline]
+    pub fn new(vol: Volume<T>, looseness: T) -> LooseOctree<T, B> {
+        LooseOctree::with_capacity(vol, looseness, DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty octree with volume `vol`, `looseness` and
+    /// `capacity`.
+    pub fn with_capacity(vol: Volume<T>, looseness: T, capacity: usize) -> LooseOctree<T, B> {
+        assert!(looseness >= NumCast::from(1).unwrap());
+        LooseOctree {
+            capacity: capacity,
+            looseness: looseness,
+            items: Vec::with_capacity(capacity),
+            volume: vol,
+            octants: None,
+            occupied: 0
+        }
+    }
+
+    /// Returns the bounding volume of the tree, before enlarging it.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the enlarged bounding volume of the tree, which encloses
+    /// all of its items.
+    pub fn loose_bounds(&self) -> Volume<T> {
+        let val2: T = NumCast::from(2).unwrap();
+        let mut min = self.volume.min;
+        let mut max = self.volume.max;
+        for i in 0..3 {
+            let grow = (self.looseness - NumCast::from(1).unwrap()) * (max[i] - min[i]) / val2;
+            min[i] = min[i] - grow;
+            max[i] = max[i] + grow;
+        }
+        Volume::new(min, max)
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        let mut len = self.items.len();
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                len += node.len();
+            },
+            None => {}
+        }
+        len
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.occupied == 0
+    }
+
+    /// Inserts an `item` into the tree, subdividing it if necessary.
+    /// Returns `SpatialError::InvalidVolume` if the bounds of the item
+    /// are inverted, or `SpatialError::OutOfBounds` if its center is
+    /// outside the tree or it's too large for it.
+    pub fn insert(&mut self, item: B) -> Result<(), SpatialError> {
+        let bounds = item.octree_bounds();
+        if !bounds.is_valid() {
+            return Err(SpatialError::InvalidVolume);
+        }
+        if !self.fits(&bounds) {
+            return Err(SpatialError::OutOfBounds);
+        }
+        self.insert_fitting(item, &bounds);
+        Ok(())
+    }
+
+    /// Moves `old` to the position of `item` by removing the former and
+    /// inserting the latter. Returns `false`, leaving the tree
+    /// untouched, if there's no item equal to `old` or if `item` doesn't
+    /// fit into the tree.
+    pub fn update(&mut self, old: &B, item: B) -> bool where B: PartialEq {
+        if !self.fits(&item.octree_bounds()) || self.remove(old).is_none() {
+            return false;
+        }
+        self.insert(item).is_ok()
+    }
+
+    /// Removes an item equal to `item` from the tree and returns it,
+    /// or `None` if there is no such item.
+    pub fn remove(&mut self, item: &B) -> Option<B> where B: PartialEq {
+        let center = item.octree_bounds().center();
+        if !self.volume.contains(&center) {
+            return None;
+        }
+        self.remove_from(item, &center)
+    }
+
+    /// Returns all items whose bounds intersect the volume `vol`.
+    pub fn get_in_volume<'a>(&'a self, vol: &Volume<T>) -> Vec<&'a B> {
+        let mut items = Vec::new();
+        self.collect(vol, &mut items);
+        items
+    }
+
+    /// Returns all items whose bounds contain the point `p`.
+    pub fn get_at<'a>(&'a self, p: &[T; 3]) -> Vec<&'a B> {
+        self.get_in_volume(&Volume::new(*p, *p))
+    }
+
+    /// Returns `true` if an item with `bounds` fits into this node: its
+    /// center is inside the volume, and it's small enough to stay inside
+    /// the enlarged volume wherever the center is.
+    fn fits(&self, bounds: &Volume<T>) -> bool {
+        let slack = self.looseness - NumCast::from(1).unwrap();
+        self.volume.contains(&bounds.center()) && (0..3).all(|i| {
+            bounds.max[i] - bounds.min[i] <= slack * (self.volume.max[i] - self.volume.min[i])
+        })
+    }
+
+    /// Returns the index of the octant containing `p`.
+    fn octant_of(&self, p: &[T; 3]) -> usize {
+        let mid = self.volume.center();
+        (0..3).fold(0, |i, axis| if p[axis] > mid[axis] { i | 1 << axis } else { i })
+    }
+
+    /// Stores `item`, with `bounds` fitting into this node, in this node
+    /// or in the octant its center is in.
+    fn insert_fitting(&mut self, item: B, bounds: &Volume<T>) {
+        if self.items.len() < self.capacity {
+            self.items.push(item);
+            return;
+        }
+
+        if self.octants.is_none() {
+            self.subdivide();
+        }
+
+        let i = self.octant_of(&bounds.center());
+        match self.octants {
+            Some(ref mut octants) if octants[i].fits(bounds) => {
+                octants[i].insert_fitting(item, bounds);
+                self.occupied |= 1 << i;
+                return;
+            },
+            _ => {}
+        }
+
+        // The item is too large for the octant, so it stays here.
+        self.items.push(item);
+    }
+
+    /// Removes an item equal to `item`, centered at `center`, from this
+    /// node or the octants along the way to its center.
+    fn remove_from(&mut self, item: &B, center: &[T; 3]) -> Option<B> where B: PartialEq {
+        match self.items.iter().position(|other| other == item) {
+            Some(i) => return Some(self.items.swap_remove(i)),
+            None => {}
+        }
+
+        let i = self.octant_of(center);
+        if self.occupied & (1 << i) == 0 {
+            return None;
+        }
+        match self.octants {
+            Some(ref mut octants) => {
+                let removed = octants[i].remove_from(item, center);
+                if octants[i].is_empty() {
+                    self.occupied &= !(1 << i);
+                }
+                removed
+            },
+            None => None
+        }
+    }
+
+    /// Collects the items of this node and its children whose bounds
+    /// intersect `vol`.
+    fn collect<'a>(&'a self, vol: &Volume<T>, items: &mut Vec<&'a B>) {
+        if !self.loose_bounds().intersects(vol) {
+            return;
+        }
+
+        for item in self.items.iter() {
+            if item.octree_bounds().intersects(vol) {
+                items.push(item);
+            }
+        }
+
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.collect(vol, items);
+                }
+            },
+            None => {}
+        }
+    }
+
+    fn subdivide(&mut self) {
+        let (cap, looseness) = (self.capacity, self.looseness);
+        let min = self.volume.min;
+        let max = self.volume.max;
+
+        let val2: T = NumCast::from(2).unwrap();
+        let mid = [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2, (min[2] + max[2]) / val2];
+
+        self.octants = Some([
+            // upper
+            Box::new(LooseOctree::with_capacity(Volume::new([min[0], min[1], min[2]], [mid[0], mid[1], mid[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([mid[0], min[1], min[2]], [max[0], mid[1], mid[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([min[0], mid[1], min[2]], [mid[0], max[1], mid[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([mid[0], mid[1], min[2]], [max[0], max[1], mid[2]]), looseness, cap)),
+            // lower
+            Box::new(LooseOctree::with_capacity(Volume::new([min[0], min[1], mid[2]], [mid[0], mid[1], max[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([mid[0], min[1], mid[2]], [max[0], mid[1], max[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([min[0], mid[1], mid[2]], [mid[0], max[1], max[2]]), looseness, cap)),
+            Box::new(LooseOctree::with_capacity(Volume::new([mid[0], mid[1], mid[2]], [max[0], max[1], max[2]]), looseness, cap))
+                ]);
+    }
+}
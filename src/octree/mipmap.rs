@@ -0,0 +1,150 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use super::{Octree, NodeData, Index, Volume};
+
+/// A trait for items carrying per-point attributes, such as a color or
+/// an intensity, that can be averaged by `Octree::mipmap`.
+pub trait Attributes<T: SpatialKey>: Index<T> {
+    /// Returns the attributes of `self`, e.g. `[r, g, b, a]`. Unused
+    /// channels should be left at zero.
+    fn attributes(&self) -> [T; 4];
+}
+
+/// A representative point returned by `AttributeMipmap::lod`, standing
+/// in for either a single item or a whole node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Splat<T: SpatialKey> {
+    /// The mean position of the represented items.
+    pub position: [T; 3],
+    /// The mean attributes of the represented items.
+    pub attributes: [T; 4],
+    /// The number of represented items.
+    pub count: usize,
+    /// The longest side of the represented node, or `0` for a single
+    /// item.
+    pub size: T
+}
+
+/// A node of an `AttributeMipmap`, mirroring a node of an `Octree`.
+struct Node<T: SpatialKey> {
+    volume: Volume<T>,
+    /// The average over the node and all of its descendants.
+    average: Splat<T>,
+    /// Positions and attributes of the items stored in the node itself.
+    items: Vec<([T; 3], [T; 4])>,
+    children: Vec<Node<T>>
+}
+
+/// Per-node averages of the item attributes of an `Octree`, so that
+/// distant nodes can be drawn as a single representative point.
+pub struct AttributeMipmap<T: SpatialKey> {
+    root: Node<T>
+}
+
+/// Returns the longest side of `vol`.
+fn longest_side<T: SpatialKey>(vol: &Volume<T>) -> T {
+    let mut size = vol.max[0] - vol.min[0];
+    for axis in 1..3 {
+        size = size.max(vol.max[axis] - vol.min[axis]);
+    }
+    size
+}
+
+impl<T: SpatialKey> AttributeMipmap<T> {
+    /// Returns the bounding volume of the root.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.root.volume
+    }
+
+    /// Returns the average over all items of the tree, or `None` if
+    /// the tree is empty.
+    #[inline]
+    pub fn average(&self) -> Option<Splat<T>> {
+        if self.root.average.count == 0 { None } else { Some(self.root.average) }
+    }
+
+    /// Returns representative points for viewing the tree from `eye`.
+    /// A node is refined while its longest side is larger than
+    /// `threshold` times its distance from `eye`, so nearby items are
+    /// returned individually and distant nodes as their average.
+    pub fn lod(&self, eye: &[T; 3], threshold: T) -> Vec<Splat<T>> {
+        let mut splats = Vec::new();
+        lod_node(&self.root, eye, threshold, &mut splats);
+        splats
+    }
+}
+
+/// Collects the representative points of `node` into `splats`.
+fn lod_node<T: SpatialKey>(node: &Node<T>, eye: &[T; 3], threshold: T, splats: &mut Vec<Splat<T>>) {
+    if node.average.count == 0 {
+        return;
+    }
+    
+    let distance = geom::aabb_distance2(eye, &node.volume.min, &node.volume.max).sqrt();
+    if node.average.size <= threshold * distance {
+        splats.push(node.average);
+        return;
+    }
+    
+    let zero: T = NumCast::from(0).unwrap();
+    for &(position, attributes) in node.items.iter() {
+        splats.push(Splat { position: position, attributes: attributes, count: 1, size: zero });
+    }
+    for child in node.children.iter() {
+        lod_node(child, eye, threshold, splats);
+    }
+}
+
+impl<T: SpatialKey, I: Attributes<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Builds per-node averages of the positions and attributes of the
+    /// items, for level-of-detail rendering such as point splatting.
+    /// The result is a snapshot and has to be rebuilt after inserting.
+    pub fn mipmap(&self) -> AttributeMipmap<T> {
+        AttributeMipmap { root: self.mipmap_node() }
+    }
+    
+    fn mipmap_node(&self) -> Node<T> {
+        let zero: T = NumCast::from(0).unwrap();
+        let items: Vec<([T; 3], [T; 4])> = self.items.iter()
+            .map(|item| (item.octree_index(), item.attributes()))
+            .collect();
+        let children: Vec<Node<T>> = match self.octants {
+            Some(ref octants) => octants.iter().map(|node| node.mipmap_node()).collect(),
+            None => Vec::new()
+        };
+        
+        // Sum everything up first, weighting each child by its count.
+        let mut position = [zero; 3];
+        let mut attributes = [zero; 4];
+        let mut count = items.len();
+        for &(p, a) in items.iter() {
+            for i in 0..3 { position[i] = position[i] + p[i]; }
+            for i in 0..4 { attributes[i] = attributes[i] + a[i]; }
+        }
+        for child in children.iter() {
+            let weight: T = NumCast::from(child.average.count).unwrap();
+            for (p, &x) in position.iter_mut().zip(child.average.position.iter()) { *p = *p + x * weight; }
+            for (a, &x) in attributes.iter_mut().zip(child.average.attributes.iter()) { *a = *a + x * weight; }
+            count += child.average.count;
+        }
+        if count > 0 {
+            let n: T = NumCast::from(count).unwrap();
+            for p in position.iter_mut() { *p = *p / n; }
+            for a in attributes.iter_mut() { *a = *a / n; }
+        }
+        
+        Node {
+            volume: self.volume,
+            average: Splat {
+                position: position,
+                attributes: attributes,
+                count: count,
+                size: longest_side(&self.volume)
+            },
+            items: items,
+            children: children
+        }
+    }
+}
@@ -1,31 +1,208 @@
-pub use self::volume::Volume;
+pub use self::volume::{Volume, Ray, Plane, Frustum};
+pub use self::occupancy::{OccupancyOctree, Fill};
+#[cfg(feature = "surface")]
+pub use self::occupancy::SurfaceMesh;
+pub use self::weighted::{Weighted, ForceSource, Mass};
+pub use self::rebase::Translate;
+pub use self::transformed::TransformedView;
+pub use self::view::TreeView;
+pub use self::loose::LooseOctree;
+pub use self::aggregate::Aggregate;
+pub use self::world::WorldIndex;
+pub use self::layered::LayeredIndex;
+pub use self::bounded::{BoundedIndex, BoundedOctree};
+pub use self::paged::{PagedBuilder, PagedOctree};
+pub use self::gpu::{GpuBuffers, GpuNode, GpuItem};
+pub use self::iter::{Iter, IterMut, IntoIter, Query};
+pub use self::mipmap::{Attributes, AttributeMipmap, Splat};
+pub use self::linear::LinearOctree;
+pub use self::path::NodePath;
+pub use self::dirty::NodeDelta;
+#[cfg(feature = "rkyv")]
+pub use self::archive::{FlatNode, FlatOctree, ArchivedFlatNode, ArchivedFlatOctree};
+use self::rebuild::Rebuild;
+use self::dirty::Changes;
+use self::nearest::morton;
 use SpatialKey;
+use QueryStats;
+use ItemOrder;
+use SpatialError;
+use geom;
 use num::NumCast;
-use num::traits::Float;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
 
 mod volume;
+mod neighbors;
+mod gpu;
+mod iter;
+mod occupancy;
+mod weighted;
+mod raycast;
+mod aggregate;
+mod paged;
+mod pointcloud;
+mod nearest;
+mod mipmap;
+mod bounded;
+mod loose;
+mod world;
+mod rebuild;
+mod rebase;
+mod transformed;
+mod view;
+mod overlap;
+mod layered;
+mod histogram;
+mod sample;
+mod linear;
+mod path;
+mod snapshot;
+mod dirty;
+#[cfg(feature = "serde")]
+mod deserialize;
+#[cfg(feature = "rkyv")]
+mod archive;
 
 /// The default capacity of an octree's node until it's subdivided.
 static DEFAULT_CAPACITY: usize = 8;
 
+/// The maximum depth of a node, beyond which more items at the same
+/// position can't be stored.
+static MAX_DEPTH: usize = 64;
+
 /// A trait that must be implemented by types that are going to be
 /// inserted into an `Octree`.
 pub trait Index<T: SpatialKey> {
     /// This method returns the position for `self` in 3D-space. The
     /// return format should be in order of `[x, y, z]`.
     fn octree_index(&self) -> [T; 3];
+
+    /// Returns the layer bits of `self`, which `raycast_filtered` tests
+    /// against its mask. Items are in every layer by default.
+    #[inline]
+    fn octree_layers(&self) -> u32 {
+        !0
+    }
+}
+
+/// A trait for data kept in every node of an `Octree` and updated as
+/// items are stored below it, such as the `Mass` of its subtree, or for
+/// user data like render handles or physics proxies. The data of a new
+/// node is created with `Default`, and the tree calls back into it as
+/// the node changes.
+pub trait NodeData<T: SpatialKey, I>: Default {
+    /// Called after `item` has been stored in the node itself.
+    #[allow(unused_variables)]
+    fn on_insert(&mut self, item: &I) {}
+
+    /// Called after the node, covering `volume`, has been subdivided.
+    #[allow(unused_variables)]
+    fn on_subdivide(&mut self, volume: &Volume<T>) {}
+
+    /// Called after `item` has been removed from the node itself.
+    #[allow(unused_variables)]
+    fn on_remove(&mut self, item: &I) {}
+
+    /// Called after items have been stored in or removed from the
+    /// subtrees of the children of the node, with the data of the
+    /// children, so that data summing up the whole subtree of the node
+    /// can catch up with them.
+    #[allow(unused_variables)]
+    fn on_children_change(&mut self, children: [&Self; 8]) {}
+
+    /// Called after the children of the node have been merged back
+    /// into it, along with their items, which are the node's own from
+    /// then on.
+    fn on_merge(&mut self) {}
+}
+
+impl<T: SpatialKey, I> NodeData<T, I> for () {}
+
+/// A trait for receiving structural changes of an `Octree` from
+/// `insert_observed` and `remove_observed`, so external copies of the tree can be updated
+/// incrementally. Nodes are identified by their path of octant indices
+/// from the root.
+pub trait Observer<T: SpatialKey, I> {
+    /// Called after `item` has been stored in the node at `path`.
+    #[allow(unused_variables)]
+    fn on_insert(&mut self, path: &[usize], item: &I) {}
+
+    /// Called after the node at `path`, covering `volume`, has been
+    /// subdivided.
+    #[allow(unused_variables)]
+    fn on_subdivide(&mut self, path: &[usize], volume: &Volume<T>) {}
+
+    /// Called after `item` has been removed from the node at `path`.
+    #[allow(unused_variables)]
+    fn on_remove(&mut self, path: &[usize], item: &I) {}
+
+    /// Called after the children of the node at `path` have been
+    /// merged back into it, moving their items into the node.
+    #[allow(unused_variables)]
+    fn on_merge(&mut self, path: &[usize]) {}
+}
+
+impl<T: SpatialKey, I> Observer<T, I> for () {}
+
+/// The outcome of moving an item within a subtree.
+enum Moved<I> {
+    /// There's no item at the old position; the new one is handed back.
+    NotFound(I),
+    /// The item has been stored at its new position.
+    Done,
+    /// The old item, handed back last, has been removed, but the new
+    /// one lies outside the subtree and has to be inserted further up
+    /// with the insertion sequence number of the old one.
+    Outside(I, u64, I),
+    /// The new item couldn't be stored and the old one has been put
+    /// back.
+    Failed(SpatialError)
 }
 
-pub struct Octree<T: SpatialKey, I: Index<T> + Clone> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "deserialize::OctreeData<T, I, D>",
+                                    bound(deserialize = "T: ::serde::Deserialize<'de>, I: ::serde::Deserialize<'de>, D: ::serde::Deserialize<'de>")))]
+pub struct Octree<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I> = ()> {
     /// Maximum number of items to store before subdivision.
     capacity: usize,
     /// Items in the node.
     items: Vec<I>,
+    /// Number of items in the node and its descendants.
+    count: usize,
     /// Bounding volume of the node.
     volume: Volume<T>,
     /// The octants of the node, in order of NW, NE, SW, SE, starting
     /// from the upper half.
-    octants: Option<[Box<Octree<T, I>>; 8]>
+    octants: Option<[Box<Octree<T, I, D>>; 8]>,
+    /// Bit `i` is set if the subtree of child `i` holds any items.
+    occupied: u8,
+    /// Data kept in the node.
+    data: D,
+    /// A rebuild in progress, started by `rebuild_incremental`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rebuild: Option<Box<Rebuild<T, I, D>>>,
+    /// The order in which the items of the node are kept.
+    order: ItemOrder,
+    /// Insertion sequence numbers of the items, if they're kept in
+    /// insertion order.
+    sequence: Vec<u64>,
+    /// Sequence number of the next item inserted into the tree, only
+    /// used at the root.
+    next_sequence: u64,
+    /// The changes to the items of the node since the last call to
+    /// `collect_dirty`, once the tree is tracked.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changes: Option<Box<Changes<I>>>
+}
+
+/// A reference to a node of an `Octree`, along with the path of
+/// octant indices leading to it from the root.
+pub struct NodeRef<'a, T: SpatialKey + 'a, I: Index<T> + Clone + 'a, D: NodeData<T, I> + 'a = ()> {
+    /// Octant indices from the root to the node.
+    pub path: Vec<usize>,
+    /// The referenced node.
+    pub node: &'a Octree<T, I, D>
 }
 
 impl<T: SpatialKey, I: Index<T> + Clone> Octree<T, I> {
@@ -33,140 +210,914 @@ impl<T: SpatialKey, I: Index<T> + Clone> Octree<T, I> {
     /// and default node capacity of `DEFAULT_CAPACITY`.
     #[inline]
     pub fn new(vol: Volume<T>) -> Octree<T, I> {
-        Octree {
-            capacity: DEFAULT_CAPACITY,
-            items: Vec::with_capacity(DEFAULT_CAPACITY),
-            volume: vol,
-            octants: None
-        }
+        Octree::with_node_data(vol, DEFAULT_CAPACITY)
     }
 
     /// Creates an empty `Octree` with volume `vol` and `capacity`.
     #[inline]
     pub fn with_capacity(vol: Volume<T>, capacity: usize) -> Octree<T, I> {
+        Octree::with_node_data(vol, capacity)
+    }
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Creates an empty `Octree` with volume `vol` and `capacity`, whose
+    /// nodes keep data of type `D`.
+    #[inline]
+    pub fn with_node_data(vol: Volume<T>, capacity: usize) -> Octree<T, I, D> {
+        Octree::with_item_order(vol, capacity, ItemOrder::Unordered)
+    }
+
+    /// Creates an empty tree with volume `vol` and `capacity`, whose
+    /// nodes carry user data of type `D` and keep their items in
+    /// `order`.
+    pub fn with_item_order(vol: Volume<T>, capacity: usize, order: ItemOrder) -> Octree<T, I, D> {
+        let mut tree = Octree::empty(vol, capacity, order);
+        tree.items.reserve(capacity);
+        tree
+    }
+
+    /// Creates an empty node like `with_item_order`, but without
+    /// reserving room for `capacity` items up front. Used for the nodes
+    /// made by subdividing, rebuilding and loading snapshots, where the
+    /// capacity may be far more than the items the node ends up with.
+    fn empty(vol: Volume<T>, capacity: usize, order: ItemOrder) -> Octree<T, I, D> {
         Octree {
             capacity: capacity,
-            items: Vec::with_capacity(capacity),
+            items: Vec::new(),
+            count: 0,
             volume: vol,
-            octants: None
+            octants: None,
+            occupied: 0,
+            data: D::default(),
+            rebuild: None,
+            order: order,
+            sequence: Vec::new(),
+            next_sequence: 0,
+            changes: None
         }
     }
 
-    /// Returns the number of items in the tree.
+    /// Returns the order in which the tree keeps its items.
     #[inline]
-    pub fn len(&self) -> usize {
-        let mut len = self.items.len();
+    pub fn item_order(&self) -> ItemOrder {
+        self.order
+    }
+
+    /// Returns the data of the node.
+    #[inline]
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    /// Returns the data of the node mutably.
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    /// Returns a 8-bit mask of the children whose subtrees hold any
+    /// items, where bit `i` stands for child `i`. Always `0` for leaves.
+    #[inline]
+    pub fn occupancy(&self) -> u8 {
+        self.occupied
+    }
+
+    /// Returns the bounding volume of the tree.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the maximum number of items a node stores before it's
+    /// subdivided.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of levels below this node. A tree that
+    /// has never been subdivided has a depth of `0`.
+    #[inline]
+    pub fn depth(&self) -> usize {
         match self.octants {
-            Some(ref octants) => for ref node in octants.iter() {
-                len += node.len();
+            Some(ref octants) => 1 + octants.iter().map(|node| node.depth()).max().unwrap_or(0),
+            None => 0
+        }
+    }
+
+    /// Returns the number of nodes in the tree, including this one.
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        let mut count = 1;
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                count += node.node_count();
             },
             None => {}
         }
-        len
+        count
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
     }
 
     /// Inserts an `item` into the tree, subdividing it if necessary.
     #[inline]
-    pub fn insert(&mut self, item: I) -> bool {
+    pub fn insert(&mut self, item: I) -> Result<(), SpatialError> {
+        self.insert_observed(item, &mut ())
+    }
+
+    /// Inserts an `item` into the tree like `insert`, reporting where
+    /// it's stored and which nodes are subdivided to `observer`.
+    pub fn insert_observed<O: Observer<T, I>>(&mut self, item: I, observer: &mut O)
+                                               -> Result<(), SpatialError> {
+        self.rebuild = None;
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.insert_at(item, sequence, &mut Vec::new(), observer)
+    }
+
+    /// Inserts `item`, with insertion `sequence` number, into this node,
+    /// found at `path`.
+    fn insert_at<O: Observer<T, I>>(&mut self, item: I, sequence: u64, path: &mut Vec<usize>,
+                                    observer: &mut O) -> Result<(), SpatialError> {
         // item must exist inside this quads' space.
         if !self.volume.contains(&item.octree_index()) {
-            return false;
+            return Err(SpatialError::OutOfBounds);
         }
         
         if self.items.len() < self.capacity {
-            self.items.push(item.clone());
-            return true;
+            self.store(item.clone(), sequence);
+            self.count += 1;
+            self.data.on_insert(&item);
+            observer.on_insert(path, &item);
+            trace_event!("octree insert at depth {}", path.len());
+            return Ok(());
         }
         
+        if path.len() >= MAX_DEPTH {
+            return Err(SpatialError::DepthLimit);
+        }
+
+        if self.octants.is_none() {
+            self.subdivide();
+            observer.on_subdivide(path, &self.volume);
+            trace_event!("octree subdivide {} at depth {}", self.volume, path.len());
+        }
+        
+        let mut stored = false;
         match self.octants {
-            Some(ref mut octants) => for node in octants.iter_mut() {
-                if node.insert(item.clone()) {
-                    return true;
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                path.push(i);
+                let inserted = node.insert_at(item.clone(), sequence, path, observer);
+                path.pop();
+                match inserted {
+                    Ok(()) => {
+                        self.occupied |= 1 << i;
+                        stored = true;
+                        self.count += 1;
+                        break;
+                    },
+                    Err(SpatialError::OutOfBounds) => {},
+                    Err(err) => return Err(err)
+                }
+            },
+            None => {}
+        }
+        
+        if !stored {
+            return Err(SpatialError::OutOfBounds);
+        }
+        self.children_changed();
+        Ok(())
+    }
+
+    /// Removes an item equal to `item` from the tree and returns it,
+    /// or `None` if there is no such item. Subtrees whose items all fit
+    /// into their parent again are merged back into it.
+    pub fn remove(&mut self, item: &I) -> Option<I> where I: PartialEq {
+        self.remove_observed(item, &mut ())
+    }
+
+    /// Removes an item equal to `item` like `remove`, reporting the
+    /// removal and any merged nodes to `observer`.
+    pub fn remove_observed<O: Observer<T, I>>(&mut self, item: &I, observer: &mut O) -> Option<I>
+        where I: PartialEq {
+        self.rebuild = None;
+        self.remove_from(&item.octree_index(), &mut |other| other == item, &mut Vec::new(), observer)
+    }
+
+    /// Removes an item located exactly at `position` from the tree and
+    /// returns it, or `None` if there is no such item. If several items
+    /// share the position, the one found first is removed.
+    pub fn remove_at(&mut self, position: &[T; 3]) -> Option<I> {
+        self.rebuild = None;
+        self.remove_from(position, &mut |_| true, &mut Vec::new(), &mut ())
+    }
+
+    /// Removes the first item at `position` for which `matches` returns
+    /// `true` from this node, found at `path`, or from its children.
+    fn remove_from<F, O>(&mut self, position: &[T; 3], matches: &mut F, path: &mut Vec<usize>,
+                         observer: &mut O) -> Option<I>
+        where F: FnMut(&I) -> bool, O: Observer<T, I> {
+        if !self.volume.contains(position) {
+            return None;
+        }
+        
+        let mut removed = None;
+        match self.items.iter().position(|item| item.octree_index() == *position && matches(item)) {
+            Some(i) => {
+                let (item, _) = self.take(i);
+                self.data.on_remove(&item);
+                observer.on_remove(path, &item);
+                removed = Some(item);
+            },
+            None => match self.octants {
+                Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                    path.push(i);
+                    removed = node.remove_from(position, matches, path, observer);
+                    path.pop();
+                    if removed.is_some() {
+                        if node.items.is_empty() && node.occupied == 0 {
+                            self.occupied &= !(1 << i);
+                        }
+                        break;
+                    }
+                },
+                None => {}
+            }
+        }
+        
+        if removed.is_none() {
+            return None;
+        }
+        self.count -= 1;
+        self.children_changed();
+        if self.octants.is_some() && self.count <= self.capacity {
+            self.merge();
+            observer.on_merge(path);
+        }
+        removed
+    }
+
+    /// Moves the item at `old_position` by replacing it with `item`,
+    /// which carries the new position. The item is only re-inserted if
+    /// it has left the bounds of its node, and then starting from the
+    /// smallest enclosing node rather than the root.
+    ///
+    /// Returns `SpatialError::NotFound` if there's no item at
+    /// `old_position`, and `SpatialError::OutOfBounds` if the new
+    /// position lies outside of the tree. If the item can't be stored at
+    /// its new position, the old item is put back and the error of the
+    /// insert is returned. Either way the tree holds the same items as
+    /// before.
+    pub fn update(&mut self, old_position: &[T; 3], item: I) -> Result<(), SpatialError> {
+        self.rebuild = None;
+        if !self.volume.contains(&item.octree_index()) {
+            return Err(SpatialError::OutOfBounds);
+        }
+        
+        let mut path = Vec::new();
+        match self.move_item(old_position, item, &mut path) {
+            Moved::NotFound(_) => Err(SpatialError::NotFound),
+            Moved::Done => Ok(()),
+            Moved::Outside(item, sequence, previous) => self.reinsert(item, sequence, previous, &mut path),
+            Moved::Failed(err) => Err(err)
+        }
+    }
+
+    /// Inserts `item`, moved out of a descendant, into this node, found
+    /// at `path`. If it can't be stored, `previous`, the item it
+    /// replaced, is put back with the same insertion `sequence` number.
+    /// It fitted where it was, and taking it out only made room there,
+    /// so storing it again can't fail.
+    fn reinsert(&mut self, item: I, sequence: u64, previous: I, path: &mut Vec<usize>)
+                -> Result<(), SpatialError> {
+        match self.insert_at(item, sequence, path, &mut ()) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let restored = self.insert_at(previous, sequence, path, &mut ());
+                debug_assert!(restored.is_ok());
+                Err(err)
+            }
+        }
+    }
+
+    /// Replaces the first item at `old` in this subtree, found at
+    /// `path`, with `item`.
+    fn move_item(&mut self, old: &[T; 3], item: I, path: &mut Vec<usize>) -> Moved<I> {
+        if !self.volume.contains(old) {
+            return Moved::NotFound(item);
+        }
+        
+        match self.items.iter().position(|other| other.octree_index() == *old) {
+            Some(i) => {
+                let (previous, sequence) = self.take(i);
+                self.data.on_remove(&previous);
+                if self.volume.contains(&item.octree_index()) {
+                    self.store(item.clone(), sequence);
+                    self.data.on_insert(&item);
+                    return Moved::Done;
+                }
+                self.count -= 1;
+                if self.octants.is_some() && self.count <= self.capacity {
+                    self.merge();
+                }
+                return Moved::Outside(item, sequence, previous);
+            },
+            None => {}
+        }
+        
+        let mut unmoved = Some(item);
+        let mut outside = None;
+        match self.octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                if self.occupied & (1 << i) == 0 {
+                    continue;
+                }
+                path.push(i);
+                let moved = node.move_item(old, unmoved.take().unwrap(), path);
+                path.pop();
+                match moved {
+                    Moved::NotFound(item) => unmoved = Some(item),
+                    Moved::Done => {
+                        self.children_changed();
+                        return Moved::Done;
+                    },
+                    Moved::Failed(err) => {
+                        self.children_changed();
+                        return Moved::Failed(err);
+                    },
+                    Moved::Outside(moved, sequence, previous) => {
+                        if node.items.is_empty() && node.occupied == 0 {
+                            self.occupied &= !(1 << i);
+                        }
+                        outside = Some((moved, sequence, previous));
+                        break;
+                    }
                 }
             },
-            None => self.subdivide()
+            None => {}
         }
         
-        false
+        match outside {
+            Some((moved, sequence, previous)) => {
+                self.count -= 1;
+                self.children_changed();
+                if self.volume.contains(&moved.octree_index()) {
+                    match self.reinsert(moved, sequence, previous, path) {
+                        Ok(()) => Moved::Done,
+                        Err(err) => Moved::Failed(err)
+                    }
+                } else {
+                    if self.count <= self.capacity {
+                        self.merge();
+                    }
+                    Moved::Outside(moved, sequence, previous)
+                }
+            },
+            None => Moved::NotFound(unmoved.unwrap())
+        }
+    }
+
+    /// Removes all items and nodes from the tree, keeping its bounds
+    /// and capacity. The user data of the root is reset to its default.
+    pub fn clear(&mut self) {
+        self.rebuild = None;
+        if self.changes.is_some() {
+            self.drop_children();
+            match self.changes {
+                Some(ref mut changes) => for item in self.items.iter() {
+                    changes.remove(item);
+                },
+                None => {}
+            }
+        }
+        self.items.clear();
+        self.count = 0;
+        self.sequence.clear();
+        self.octants = None;
+        self.occupied = 0;
+        self.data = D::default();
+    }
+
+    /// Removes all items for which `f` returns `false`. Nodes keep
+    /// their place in the tree, except for subtrees whose remaining
+    /// items fit into their parent, which are merged like in `remove`.
+    pub fn retain<F: FnMut(&I) -> bool>(&mut self, mut f: F) {
+        self.rebuild = None;
+        self.retain_in(&mut f);
+    }
+
+    fn retain_in<F: FnMut(&I) -> bool>(&mut self, f: &mut F) {
+        let mut i = 0;
+        while i < self.items.len() {
+            if f(&self.items[i]) {
+                i += 1;
+            } else {
+                let (item, _) = self.take(i);
+                self.count -= 1;
+                self.data.on_remove(&item);
+            }
+        }
+        
+        match self.octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                if self.occupied & (1 << i) == 0 {
+                    continue;
+                }
+                let before = node.count;
+                node.retain_in(f);
+                self.count -= before - node.count;
+                if node.items.is_empty() && node.occupied == 0 {
+                    self.occupied &= !(1 << i);
+                }
+            },
+            None => return
+        }
+        
+        self.children_changed();
+        if self.count <= self.capacity {
+            self.merge();
+        }
     }
 
     /// Returns all items inside the volume `vol`.
     #[inline]
     pub fn get_in_volume<'a>(&'a self, vol: &Volume<T>) -> Vec<&'a I> {
+        self.get_in_volume_with_stats(vol).0
+    }
+    
+    /// Returns all items within `radius` of `center`, including those at
+    /// exactly `radius`.
+    #[inline]
+    pub fn get_in_radius(&self, center: [T; 3], radius: T) -> Vec<&I> {
+        let min = [center[0] - radius, center[1] - radius, center[2] - radius];
+        let max = [center[0] + radius, center[1] + radius, center[2] + radius];
+        
+        self.get_in_volume(&Volume::new(min, max)).into_iter()
+            .filter(|item| geom::point_in_sphere(&item.octree_index(), &center, radius))
+            .collect()
+    }
+    
+    /// Returns the same items as `get_in_volume`, along with statistics
+    /// of the traversal that found them.
+    pub fn get_in_volume_with_stats<'a>(&'a self, vol: &Volume<T>) -> (Vec<&'a I>, QueryStats) {
+        let _span = trace_span!("octree query", volume = %vol);
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
         let mut items = Vec::new();
-
-        // Return empty vector if vol does not intersect.
+        let mut sequence = Vec::new();
+        let mut stats = QueryStats::default();
+        self.volume_node(vol, 0, &mut items, &mut sequence, &mut stats);
+        trace_event!("octree query {}: {} items, {:?} in {:?}", vol, items.len(), stats, start.elapsed());
+        let items = match self.order {
+            ItemOrder::Unordered => items,
+            ItemOrder::Insertion => sort_by_keys(items, &sequence),
+            ItemOrder::Morton => {
+                let codes: Vec<u64> = items.iter().map(|item| morton(&self.volume, &item.octree_index())).collect();
+                sort_by_keys(items, &codes)
+            }
+        };
+        (items, stats)
+    }
+    
+    /// Returns the same items as `get_in_radius`, along with statistics
+    /// of the traversal. The items tested are those tested against the
+    /// bounding box of the sphere.
+    pub fn get_in_radius_with_stats(&self, center: [T; 3], radius: T) -> (Vec<&I>, QueryStats) {
+        let min = [center[0] - radius, center[1] - radius, center[2] - radius];
+        let max = [center[0] + radius, center[1] + radius, center[2] + radius];
+        
+        let (items, stats) = self.get_in_volume_with_stats(&Volume::new(min, max));
+        let items = items.into_iter()
+            .filter(|item| geom::point_in_sphere(&item.octree_index(), &center, radius))
+            .collect();
+        (items, stats)
+    }
+    
+    /// Collects the items of this node, at `depth`, and of its children
+    /// that are inside `vol`, along with their insertion sequence
+    /// numbers if they're kept in insertion order.
+    fn volume_node<'a>(&'a self, vol: &Volume<T>, depth: usize, items: &mut Vec<&'a I>,
+                       sequence: &mut Vec<u64>, stats: &mut QueryStats) {
         if !self.volume.intersects(vol) {
-            return items;
+            stats.nodes_pruned += 1;
+            return;
         }
-
-        // Add items for this node.
-        for item in self.items.iter() {
+        stats.nodes_visited += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        
+        for (i, item) in self.items.iter().enumerate() {
+            stats.items_tested += 1;
             if vol.contains(&item.octree_index()) {
                 items.push(item);
+                if self.order == ItemOrder::Insertion {
+                    sequence.push(self.sequence[i]);
+                }
             }
         }
         
         match self.octants {
-            Some(ref octants) => {
-                for ref node in octants.iter() {
-                    items.push_all(node.get_in_volume(vol).as_slice());
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.volume_node(vol, depth + 1, items, sequence, stats);
+                } else {
+                    stats.nodes_pruned += 1;
                 }
-                items
             },
-            None => items
+            None => {}
         }
     }
     
+    /// Returns all items on the positive side of all six `planes`, such
+    /// as those of a `Frustum`. Subtrees wholly outside a plane are
+    /// skipped, and items of subtrees wholly inside all of them are
+    /// returned without testing.
+    pub fn get_in_frustum<'a>(&'a self, planes: &[Plane<T>; 6]) -> Vec<&'a I> {
+        let mut items = Vec::new();
+        self.frustum_node(planes, 0x3f, &mut items);
+        items
+    }
+    
+    /// Collects the items of this node and its children, testing them
+    /// only against the planes whose bits are set in `straddled`.
+    fn frustum_node<'a>(&'a self, planes: &[Plane<T>; 6], mut straddled: u8, items: &mut Vec<&'a I>) {
+        let zero: T = NumCast::from(0).unwrap();
+        for (i, plane) in planes.iter().enumerate() {
+            if straddled & (1 << i) == 0 {
+                continue;
+            }
+            
+            // The corners farthest along and against the normal.
+            let mut inner = self.volume.min;
+            let mut outer = self.volume.max;
+            for axis in 0..3 {
+                if plane.normal[axis] < zero {
+                    inner[axis] = self.volume.max[axis];
+                    outer[axis] = self.volume.min[axis];
+                }
+            }
+            if plane.distance(&outer) < zero {
+                return;
+            }
+            if plane.distance(&inner) >= zero {
+                straddled &= !(1 << i);
+            }
+        }
+        
+        if straddled == 0 {
+            items.extend(self.iter());
+            return;
+        }
+        
+        for item in self.items.iter() {
+            let p = item.octree_index();
+            if (0..6).all(|i| straddled & (1 << i) == 0 || planes[i].distance(&p) >= zero) {
+                items.push(item);
+            }
+        }
+        
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.frustum_node(planes, straddled, items);
+                }
+            },
+            None => {}
+        }
+    }
+    
+    /// Returns mutable references to all items inside the volume `vol`.
+    /// The position of an item must not be changed through the
+    /// reference, as the item would no longer be found in the right
+    /// node.
+    pub fn get_in_volume_mut<'a>(&'a mut self, vol: &Volume<T>) -> Vec<&'a mut I> {
+        self.rebuild = None;
+        let mut found = Vec::new();
+        if !self.volume.intersects(vol) {
+            return found;
+        }
+        
+        let occupied = self.occupied;
+        let Octree { ref mut items, ref mut octants, .. } = *self;
+        for item in items.iter_mut() {
+            if vol.contains(&item.octree_index()) {
+                found.push(item);
+            }
+        }
+        
+        match *octants {
+            Some(ref mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                if occupied & (1 << i) != 0 {
+                    found.extend(node.get_in_volume_mut(vol));
+                }
+            },
+            None => {}
+        }
+        found
+    }
+    
+    /// Returns mutable references to all items within `radius` of
+    /// `center`, with the same restriction as `get_in_volume_mut`.
     #[inline]
-    pub fn get_in_radius<'a>(&'a self, center: [T; 3] , radius: T) -> Vec<&'a I> {
+    pub fn get_in_radius_mut(&mut self, center: [T; 3], radius: T) -> Vec<&mut I> {
         let min = [center[0] - radius, center[1] - radius, center[2] - radius];
         let max = [center[0] + radius, center[1] + radius, center[2] + radius];
         
-        let volume = Volume::new(min, max);
-        let mut in_box = self.get_in_volume( &volume );
+        self.get_in_volume_mut(&Volume::new(min, max)).into_iter()
+            .filter(|item| geom::point_in_sphere(&item.octree_index(), &center, radius))
+            .collect()
+    }
+    
+    /// Returns the leaves sharing a face with the leaf at `path`, where
+    /// `path` lists the octant indices leading to the leaf from this
+    /// node. The neighbors may be larger or smaller than the leaf
+    /// itself. Returns an empty vector if `path` doesn't lead to a
+    /// leaf.
+    pub fn leaf_neighbors<'a>(&'a self, path: &[usize]) -> Vec<NodeRef<'a, T, I, D>> {
+        let mut neighbors = Vec::new();
         
-        let mut in_sphere = Vec::new();
+        let leaf = match self.node_at(path) {
+            Some(node) if node.octants.is_none() => node,
+            _ => return neighbors
+        };
         
-        let val2 : T = NumCast::from(2).unwrap();
-       
-        for item in in_box.drain() {
-        	let index = item.octree_index();
-        	let d0 = (index[0] - center[0]).powf(val2);
-        	let d1 = (index[1] - center[1]).powf(val2);
-        	let d2 = (index[2] - center[2]).powf(val2);
-        	
-        	let distance = (d0 + d1 + d2).sqrt();
-        	
-        	if distance < radius  {
-        		in_sphere.push( item );
-        	}
+        self.collect_face_neighbors(&leaf.volume, &mut Vec::new(), &mut neighbors);
+        neighbors
+    }
+    
+    /// Returns where an item equal to `item` is stored: the depth of its
+    /// node, the path leading to the node from this one, and the volume
+    /// of the node. Returns `None` if there is no such item.
+    pub fn locate(&self, item: &I) -> Option<(usize, NodePath, &Volume<T>)> where I: PartialEq {
+        let mut path = Vec::new();
+        match self.locate_in(&item.octree_index(), item, &mut path) {
+            Some(volume) => Some((path.len(), NodePath::from_indices(&path).unwrap(), volume)),
+            None => None
+        }
+    }
+
+    /// Returns the volume of the node of this subtree, found at `path`,
+    /// holding an item equal to `item` at `position`, leaving the path
+    /// to it in `path`.
+    fn locate_in<'a>(&'a self, position: &[T; 3], item: &I, path: &mut Vec<usize>) -> Option<&'a Volume<T>>
+        where I: PartialEq {
+        if !self.volume.contains(position) {
+            return None;
+        }
+        if self.items.iter().any(|other| other == item) {
+            return Some(&self.volume);
+        }
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) == 0 {
+                    continue;
+                }
+                path.push(i);
+                match node.locate_in(position, item, path) {
+                    Some(volume) => return Some(volume),
+                    None => {}
+                }
+                path.pop();
+            },
+            None => {}
+        }
+        None
+    }
+    
+    /// Returns the node at `path`, if there is one.
+    fn node_at(&self, path: &[usize]) -> Option<&Octree<T, I, D>> {
+        let mut node = self;
+        for &i in path.iter() {
+            node = match node.octants {
+                Some(ref octants) if i < 8 => &*octants[i],
+                _ => return None
+            };
+        }
+        Some(node)
+    }
+    
+    /// Pushes every leaf below this node that shares a face with `vol`
+    /// into `out`.
+    fn collect_face_neighbors<'a>(&'a self, vol: &Volume<T>, path: &mut Vec<usize>,
+                                  out: &mut Vec<NodeRef<'a, T, I, D>>) {
+        if !self.volume.intersects(vol) {
+            return;
         }
         
-        return in_sphere;
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                path.push(i);
+                node.collect_face_neighbors(vol, path, out);
+                path.pop();
+            },
+            None => if face_adjacent(&self.volume, vol) {
+                out.push(NodeRef { path: path.clone(), node: self });
+            }
+        }
     }
     
-    /// Creates eight equal sized subtrees for this node.
-    #[inline]
-    fn subdivide(&mut self) {
-        let cap = self.capacity;
+    /// Passes the data of the children of this node to its own data,
+    /// after the items below them have changed.
+    fn children_changed(&mut self) {
+        match self.octants {
+            Some(ref octants) => self.data.on_children_change([&octants[0].data, &octants[1].data, &octants[2].data, &octants[3].data,
+                                                              &octants[4].data, &octants[5].data, &octants[6].data, &octants[7].data]),
+            None => {}
+        }
+    }
+    
+    /// Stores `item`, with insertion `sequence` number, at its place
+    /// among the items of this node.
+    fn store(&mut self, item: I, sequence: u64) {
+        match self.changes {
+            Some(ref mut changes) => changes.add(&item),
+            None => {}
+        }
+        match self.order {
+            ItemOrder::Unordered => self.items.push(item),
+            ItemOrder::Insertion => {
+                // Items are mostly inserted last, so look from the back.
+                let i = self.sequence.iter().rposition(|&other| other < sequence).map_or(0, |i| i + 1);
+                self.items.insert(i, item);
+                self.sequence.insert(i, sequence);
+            },
+            ItemOrder::Morton => {
+                let code = morton(&self.volume, &item.octree_index());
+                let i = self.items.iter().position(|other| morton(&self.volume, &other.octree_index()) > code)
+                    .unwrap_or(self.items.len());
+                self.items.insert(i, item);
+            }
+        }
+    }
+
+    /// Removes the item at `i` from this node, returning it along with
+    /// its insertion sequence number.
+    fn take(&mut self, i: usize) -> (I, u64) {
+        let sequence = if self.order == ItemOrder::Insertion { self.sequence.remove(i) } else { 0 };
+        let item = self.items.remove(i);
+        match self.changes {
+            Some(ref mut changes) => changes.remove(&item),
+            None => {}
+        }
+        (item, sequence)
+    }
+
+    /// Restores the order of the items of this node after the items of
+    /// its children have been appended to them.
+    fn sort_items(&mut self) {
+        let keys: Vec<u64> = match self.order {
+            ItemOrder::Unordered => return,
+            ItemOrder::Insertion => self.sequence.clone(),
+            ItemOrder::Morton => self.items.iter().map(|item| morton(&self.volume, &item.octree_index())).collect()
+        };
+        self.items = sort_by_keys(self.items.drain(..).collect(), &keys);
+        self.sequence.sort();
+    }
+
+    /// Moves the items of all descendants into this node and drops its
+    /// children.
+    fn merge(&mut self) {
+        match self.octants.take() {
+            Some(mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                node.merge();
+                match (&mut self.changes, node.changes.take()) {
+                    (&mut Some(ref mut changes), Some(dropped)) => {
+                        changes.drop_child(i, *dropped, &node.items);
+                        for item in node.items.iter() {
+                            changes.add(item);
+                        }
+                    },
+                    _ => {}
+                }
+                self.items.append(&mut node.items);
+                self.sequence.append(&mut node.sequence);
+            },
+            None => return
+        }
+        self.sort_items();
+        self.occupied = 0;
+        self.data.on_merge();
+        trace_event!("octree merge {} into {} items", self.volume, self.items.len());
+    }
+    
+    /// Returns the volumes of the eight octants of this node, the upper
+    /// four first.
+    fn octant_volumes(&self) -> [Volume<T>; 8] {
         let min = self.volume.min;
         let max = self.volume.max;
         
-        let val2 = NumCast::from(2).unwrap();
-        let (hw, hh, hd) = (max[0].div(val2), max[1].div(val2), max[2].div(val2));
+        let val2: T = NumCast::from(2).unwrap();
+        let mid = [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2, (min[2] + max[2]) / val2];
         
-        self.octants = Some([
+        [
             // upper
-            box Octree::with_capacity(Volume::new([min[0], min[1], min[2]], [hw, hh, hd]), cap),
-            box Octree::with_capacity(Volume::new([min[0] + hh, min[1], min[2]], [max[0], hh, hd]), cap),
-            box Octree::with_capacity(Volume::new([min[0], min[1] + hh, min[2]], [hw, max[1], hd]), cap),
-            box Octree::with_capacity(Volume::new([min[0] + hw, min[1] + hh, min[2]], [max[0], max[1], hd]), cap),
+            Volume::new([min[0], min[1], min[2]], [mid[0], mid[1], mid[2]]),
+            Volume::new([mid[0], min[1], min[2]], [max[0], mid[1], mid[2]]),
+            Volume::new([min[0], mid[1], min[2]], [mid[0], max[1], mid[2]]),
+            Volume::new([mid[0], mid[1], min[2]], [max[0], max[1], mid[2]]),
             // lower
-            box Octree::with_capacity(Volume::new([min[0], min[1], hd], [hw, hh, max[2]]), cap),
-            box Octree::with_capacity(Volume::new([min[0] + hh, min[1], hd], [max[0], hh, max[2]]), cap),
-            box Octree::with_capacity(Volume::new([min[0], min[1] + hh, hd], [hw, max[1], max[2]]), cap),
-            box Octree::with_capacity(Volume::new([min[0] + hw, min[1] + hh, hd], [max[0], max[1], max[2]]), cap)
-                ]);
+            Volume::new([min[0], min[1], mid[2]], [mid[0], mid[1], max[2]]),
+            Volume::new([mid[0], min[1], mid[2]], [max[0], mid[1], max[2]]),
+            Volume::new([min[0], mid[1], mid[2]], [mid[0], max[1], max[2]]),
+            Volume::new([mid[0], mid[1], mid[2]], [max[0], max[1], max[2]])
+        ]
+    }
+
+    /// Returns an error unless the items of this node fit it: there are
+    /// no more of them than the capacity, they lie inside its volume,
+    /// and each has a sequence number if the tree keeps insertion order.
+    /// Shared by the snapshot loader and deserialization, which restore
+    /// nodes without inserting their items.
+    fn check_items(&self) -> Result<(), SpatialError> {
+        if self.items.len() > self.capacity {
+            return Err(SpatialError::Serialization("more items in a node than its capacity".to_string()));
+        }
+        if !self.items.iter().all(|item| self.volume.contains(&item.octree_index())) {
+            return Err(SpatialError::Serialization("item outside of its node".to_string()));
+        }
+        let sequenced = if self.order == ItemOrder::Insertion { self.items.len() } else { 0 };
+        if self.sequence.len() != sequenced {
+            return Err(SpatialError::Serialization("sequence numbers don't match the items of a node".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Creates eight equal sized subtrees for this node.
+    #[inline]
+    fn subdivide(&mut self) {
+        let (cap, order) = (self.capacity, self.order);
+        self.octants = Some(self.octant_volumes().map(|vol| Box::new(Octree::empty(vol, cap, order))));
+        if self.changes.is_some() {
+            match self.octants {
+                Some(ref mut octants) => for node in octants.iter_mut() {
+                    node.changes = Some(Box::new(Changes::new()));
+                },
+                None => {}
+            }
+        }
+        self.data.on_subdivide(&self.volume);
     }
-}
\ No newline at end of file
+
+    /// Drops the children of this node, recording their items as
+    /// removed along with them.
+    fn drop_children(&mut self) {
+        match self.octants.take() {
+            Some(mut octants) => for (i, node) in octants.iter_mut().enumerate() {
+                node.drop_children();
+                match (&mut self.changes, node.changes.take()) {
+                    (&mut Some(ref mut changes), Some(dropped)) => changes.drop_child(i, *dropped, &node.items),
+                    _ => {}
+                }
+            },
+            None => {}
+        }
+        self.occupied = 0;
+    }
+
+    /// Starts recording the changes to the items of this subtree for
+    /// `collect_dirty`, as if all of its items had just been stored.
+    fn track(&mut self) {
+        let mut changes = match self.changes.take() {
+            Some(changes) => changes,
+            None => Box::new(Changes::new())
+        };
+        for item in self.items.iter() {
+            changes.add(item);
+        }
+        self.changes = Some(changes);
+        match self.octants {
+            Some(ref mut octants) => for node in octants.iter_mut() {
+                node.track();
+            },
+            None => {}
+        }
+    }
+}
+
+/// Returns `values` ordered by their `keys`, keeping values with equal
+/// keys in their current order.
+fn sort_by_keys<V>(values: Vec<V>, keys: &[u64]) -> Vec<V> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+    let mut slots: Vec<Option<V>> = values.into_iter().map(Some).collect();
+    order.iter().map(|&i| slots[i].take().unwrap()).collect()
+}
+
+/// Returns `true` if `a` and `b` share a face of non-zero area.
+fn face_adjacent<T: SpatialKey>(a: &Volume<T>, b: &Volume<T>) -> bool {
+    let overlapping = (0..3).filter(|&i| a.min[i] < b.max[i] && a.max[i] > b.min[i]).count();
+    let touching = (0..3).filter(|&i| a.max[i] == b.min[i] || a.min[i] == b.max[i]).count();
+    overlapping == 2 && touching == 1
+}
@@ -0,0 +1,235 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ptr;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use super::{Octree, NodeData, Index, Volume};
+
+/// A value paired with a squared distance, ordered so that the farthest
+/// one is popped first from a `BinaryHeap`.
+struct Farthest<T: SpatialKey, V> {
+    d2: T,
+    value: V
+}
+
+impl<T: SpatialKey, V> PartialEq for Farthest<T, V> {
+    fn eq(&self, other: &Farthest<T, V>) -> bool {
+        self.d2 == other.d2
+    }
+}
+
+impl<T: SpatialKey, V> Eq for Farthest<T, V> {}
+
+impl<T: SpatialKey, V> PartialOrd for Farthest<T, V> {
+    fn partial_cmp(&self, other: &Farthest<T, V>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey, V> Ord for Farthest<T, V> {
+    fn cmp(&self, other: &Farthest<T, V>) -> Ordering {
+        self.d2.partial_cmp(&other.d2).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A value paired with a squared distance, ordered so that the closest
+/// one is popped first from a `BinaryHeap`.
+struct Closest<T: SpatialKey, V> {
+    d2: T,
+    value: V
+}
+
+impl<T: SpatialKey, V> PartialEq for Closest<T, V> {
+    fn eq(&self, other: &Closest<T, V>) -> bool {
+        self.d2 == other.d2
+    }
+}
+
+impl<T: SpatialKey, V> Eq for Closest<T, V> {}
+
+impl<T: SpatialKey, V> PartialOrd for Closest<T, V> {
+    fn partial_cmp(&self, other: &Closest<T, V>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey, V> Ord for Closest<T, V> {
+    fn cmp(&self, other: &Closest<T, V>) -> Ordering {
+        other.d2.partial_cmp(&self.d2).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Returns the Morton code of `point`, quantized to 21 bits per axis
+/// within `vol`. Points outside of `vol` are clamped onto it.
+pub fn morton<T: SpatialKey>(vol: &Volume<T>, point: &[T; 3]) -> u64 {
+    interleave(&quantize(vol, point))
+}
+
+/// Returns the cell of `point` in a grid of `2^21` cells per axis
+/// covering `vol`. Points outside of `vol` are clamped onto it.
+pub fn quantize<T: SpatialKey>(vol: &Volume<T>, point: &[T; 3]) -> [u64; 3] {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    let scale: T = NumCast::from((1u64 << 21) - 1).unwrap();
+    let mut cell = [0; 3];
+    for axis in 0..3 {
+        let t = (point[axis] - vol.min[axis]) / (vol.max[axis] - vol.min[axis]);
+        cell[axis] = (t.max(zero).min(one) * scale).to_u64().unwrap_or(0);
+    }
+    cell
+}
+
+/// Returns the Morton code of the grid `cell`, interleaving the bits of
+/// its coordinates.
+pub fn interleave(cell: &[u64; 3]) -> u64 {
+    let mut code = 0u64;
+    for (axis, &coordinate) in cell.iter().enumerate() {
+        for bit in 0..21 {
+            code |= ((coordinate >> bit) & 1) << (bit * 3 + axis);
+        }
+    }
+    code
+}
+
+/// Returns up to `k` items of `tree` closest to `point` for which
+/// `accept` returns `true`, paired with their squared distances and
+/// ordered from the closest to the farthest. Items with a squared
+/// distance above `max_d2`, or outside of `region` if one is given, are
+/// ignored.
+///
+/// Nodes are visited in order of their distance from `point`, and the
+/// search stops as soon as the next node is farther away than the
+/// `k`th closest item found so far.
+pub fn k_nearest_where<'a, T, I, D, F>(tree: &'a Octree<T, I, D>, point: &[T; 3], k: usize, max_d2: T,
+                                    region: Option<&Volume<T>>, mut accept: F) -> Vec<(T, &'a I)>
+    where T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>, F: FnMut(&I) -> bool {
+    let _span = trace_span!("octree nearest", k = k);
+    #[cfg(feature = "tracing")]
+    let start = Instant::now();
+    let mut found: BinaryHeap<Farthest<T, &'a I>> = BinaryHeap::new();
+    if k == 0 {
+        return Vec::new();
+    }
+    
+    let mut nodes = BinaryHeap::new();
+    if region.is_some_and(|region| !region.intersects(&tree.volume)) {
+        return Vec::new();
+    }
+    nodes.push(Closest { d2: geom::aabb_distance2(point, &tree.volume.min, &tree.volume.max), value: tree });
+    
+    while let Some(Closest { d2, value: node }) = nodes.pop() {
+        if d2 > max_d2 || found.len() == k && found.peek().is_some_and(|farthest| d2 > farthest.d2) {
+            break;
+        }
+        
+        for item in node.items.iter() {
+            if region.is_some_and(|region| !region.contains(&item.octree_index())) || !accept(item) {
+                continue;
+            }
+            
+            let d2 = geom::distance2(point, &item.octree_index());
+            if d2 > max_d2 {
+                continue;
+            }
+            if found.len() < k {
+                found.push(Farthest { d2: d2, value: item });
+            } else if found.peek().is_some_and(|farthest| d2 < farthest.d2) {
+                found.pop();
+                found.push(Farthest { d2: d2, value: item });
+            }
+        }
+        
+        match node.octants {
+            Some(ref octants) => for (i, child) in octants.iter().enumerate() {
+                if node.occupied & (1 << i) == 0
+                    || region.is_some_and(|region| !region.intersects(&child.volume)) {
+                    continue;
+                }
+                let d2 = geom::aabb_distance2(point, &child.volume.min, &child.volume.max);
+                nodes.push(Closest { d2: d2, value: &**child });
+            },
+            None => {}
+        }
+    }
+    
+    let mut found: Vec<(T, &'a I)> = found.into_iter().map(|entry| (entry.d2, entry.value)).collect();
+    found.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    trace_event!("octree nearest: found {} of {} neighbors in {:?}", found.len(), k, start.elapsed());
+    found
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the item closest to `point`, or `None` if the tree is
+    /// empty.
+    pub fn nearest(&self, point: &[T; 3]) -> Option<&I> {
+        k_nearest_where(self, point, 1, Float::infinity(), None, |_| true).pop()
+            .map(|(_, item)| item)
+    }
+
+    /// Returns up to `k` items closest to `point`, ordered from the
+    /// closest to the farthest.
+    pub fn nearest_n(&self, point: &[T; 3], k: usize) -> Vec<&I> {
+        k_nearest_where(self, point, k, Float::infinity(), None, |_| true).into_iter()
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    /// Returns the item closest to `point` for which `exclude` returns
+    /// `false`, or `None` if there is none.
+    pub fn nearest_excluding<F: Fn(&I) -> bool>(&self, point: &[T; 3], exclude: F) -> Option<&I> {
+        k_nearest_where(self, point, 1, Float::infinity(), None, |item| !exclude(item)).pop()
+            .map(|(_, item)| item)
+    }
+
+    /// Returns the item closest to `item` other than itself, or `None`
+    /// if it's the only one. `item` must be a reference into the tree,
+    /// such as one returned by a query; other items equal to it, such
+    /// as duplicates, are still found.
+    pub fn nearest_other<'a>(&'a self, item: &I) -> Option<&'a I> {
+        self.nearest_excluding(&item.octree_index(), |other| ptr::eq(other, item))
+    }
+
+    /// Returns the `k` nearest items of each of `queries`, like calling
+    /// `nearest_n` for each of them. The queries are processed in Morton
+    /// order, and the neighbors of each query bound the search for the
+    /// next one, which prunes most of the tree for nearby queries.
+    pub fn k_nearest_batch(&self, queries: &[[T; 3]], k: usize) -> Vec<Vec<&I>> {
+        let codes: Vec<u64> = queries.iter().map(|point| morton(&self.volume, point)).collect();
+        let mut order: Vec<usize> = (0..queries.len()).collect();
+        order.sort_by(|&a, &b| codes[a].cmp(&codes[b]));
+        
+        let mut results = vec![Vec::new(); queries.len()];
+        let mut previous: Vec<&I> = Vec::new();
+        for &q in order.iter() {
+            let point = &queries[q];
+            // The previous neighbors are all within this distance of the
+            // query, so its own neighbors can't be any farther.
+            let max_d2 = if k > 0 && previous.len() == k {
+                previous.iter()
+                    .map(|item| geom::distance2(point, &item.octree_index()))
+                    .fold(NumCast::from(0).unwrap(), |a: T, b| a.max(b))
+            } else {
+                Float::infinity()
+            };
+            
+            previous = k_nearest_where(self, point, k, max_d2, None, |_| true).into_iter()
+                .map(|(_, item)| item)
+                .collect();
+            results[q] = previous.clone();
+        }
+        results
+    }
+
+    /// Returns up to `k` items inside `vol` that are closest to
+    /// `point`, ordered from the closest to the farthest. Nodes outside
+    /// of `vol` are never visited.
+    pub fn k_nearest_in_volume(&self, vol: &Volume<T>, point: &[T; 3], k: usize) -> Vec<&I> {
+        k_nearest_where(self, point, k, Float::infinity(), Some(vol), |_| true).into_iter()
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
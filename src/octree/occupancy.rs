@@ -0,0 +1,479 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use super::Volume;
+#[cfg(feature = "surface")]
+use std::collections::{HashMap, HashSet};
+
+/// How the inside of a closed mesh is treated by
+/// `OccupancyOctree::voxelize`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fill {
+    /// Only voxels touching the surface are occupied.
+    Surface,
+    /// Voxels enclosed by the surface are occupied as well. Requires
+    /// the mesh to be closed.
+    Solid
+}
+
+/// A triangle mesh of the boundary of the occupied region of an
+/// `OccupancyOctree`, as returned by `extract_surface`.
+#[cfg(feature = "surface")]
+#[derive(Clone, PartialEq, Debug)]
+pub struct SurfaceMesh<T> {
+    /// The vertices of the mesh, each shared by all the triangles
+    /// meeting at it.
+    pub vertices: Vec<[T; 3]>,
+    /// Three indices into `vertices` for each triangle, wound
+    /// counterclockwise when seen from the unoccupied side.
+    pub indices: Vec<u32>
+}
+
+/// A node of an `OccupancyOctree`.
+enum Node {
+    Empty,
+    Full,
+    Split(Box<[Node; 8]>)
+}
+
+/// An octree recording which parts of a volume are occupied, down to
+/// voxels of a fixed maximum depth. Uniform regions are stored as
+/// single nodes.
+pub struct OccupancyOctree<T: SpatialKey> {
+    /// Bounding volume of the root.
+    volume: Volume<T>,
+    /// Depth of the smallest voxels.
+    max_depth: usize,
+    root: Node
+}
+
+/// Returns the volume of the octant `i` of `vol`, in the same order as
+/// the octants of an `Octree`.
+fn octant<T: SpatialKey>(vol: &Volume<T>, i: usize) -> Volume<T> {
+    let mid = vol.center();
+    let mut min = vol.min;
+    let mut max = vol.max;
+    for axis in 0..3 {
+        if i & (1 << axis) == 0 {
+            max[axis] = mid[axis];
+        } else {
+            min[axis] = mid[axis];
+        }
+    }
+    Volume::new(min, max)
+}
+
+/// Marks the parts of `node`, covering `vol` at `depth`, that overlap
+/// `region` as occupied.
+fn fill_node<T: SpatialKey>(node: &mut Node, vol: &Volume<T>, region: &Volume<T>, depth: usize, max_depth: usize) {
+    let overlaps = (0..3).all(|i| vol.min[i] < region.max[i] && region.min[i] < vol.max[i]);
+    if !overlaps {
+        return;
+    }
+    let covered = (0..3).all(|i| region.min[i] <= vol.min[i] && vol.max[i] <= region.max[i]);
+    if covered || depth == max_depth {
+        *node = Node::Full;
+        return;
+    }
+    
+    match *node {
+        Node::Full => return,
+        Node::Empty => *node = Node::Split(Box::new([Node::Empty, Node::Empty, Node::Empty, Node::Empty,
+                                                     Node::Empty, Node::Empty, Node::Empty, Node::Empty])),
+        Node::Split(_) => {}
+    }
+    let all_full = match *node {
+        Node::Split(ref mut children) => {
+            for (i, child) in children.iter_mut().enumerate() {
+                fill_node(child, &octant(vol, i), region, depth + 1, max_depth);
+            }
+            children.iter().all(|child| matches!(*child, Node::Full))
+        },
+        _ => false
+    };
+    if all_full {
+        *node = Node::Full;
+    }
+}
+
+/// Returns `true` if `p` lies inside the closed mesh `triangles`,
+/// counting how many triangles a ray cast from `p` crosses.
+fn inside<T: SpatialKey>(triangles: &[[[T; 3]; 3]], p: &[T; 3]) -> bool {
+    // A direction unlikely to graze the edges of axis-aligned meshes.
+    let direction = [NumCast::from(1.0).unwrap(), NumCast::from(0.0127).unwrap(),
+                     NumCast::from(0.0311).unwrap()];
+    let crossings = triangles.iter()
+        .filter(|tri| geom::ray_intersects_triangle(p, &direction, tri).is_some())
+        .count();
+    crossings % 2 == 1
+}
+
+/// Builds the node covering `vol` at `depth` from the `candidates`
+/// among `triangles` that may overlap it.
+fn voxelize_node<T: SpatialKey>(triangles: &[[[T; 3]; 3]], candidates: &[usize], vol: &Volume<T>,
+                                depth: usize, max_depth: usize, fill: Fill) -> Node {
+    let overlapping: Vec<usize> = candidates.iter().cloned()
+        .filter(|&i| geom::triangle_intersects_aabb(&triangles[i], &vol.min, &vol.max))
+        .collect();
+    
+    if overlapping.is_empty() {
+        // Nothing crosses the node, so it's either wholly inside or
+        // wholly outside the mesh.
+        return if fill == Fill::Solid && inside(triangles, &vol.center()) {
+            Node::Full
+        } else {
+            Node::Empty
+        };
+    }
+    if depth == max_depth {
+        return Node::Full;
+    }
+    
+    let child = |i| voxelize_node(triangles, &overlapping, &octant(vol, i), depth + 1, max_depth, fill);
+    let children = [child(0), child(1), child(2), child(3), child(4), child(5), child(6), child(7)];
+    
+    if children.iter().all(|node| matches!(*node, Node::Full)) {
+        Node::Full
+    } else if children.iter().all(|node| matches!(*node, Node::Empty)) {
+        Node::Empty
+    } else {
+        Node::Split(Box::new(children))
+    }
+}
+
+impl<T: SpatialKey> OccupancyOctree<T> {
+    /// Creates an empty `OccupancyOctree` with volume `vol`, whose
+    /// smallest voxels lie `max_depth` levels below the root.
+    pub fn new(vol: Volume<T>, max_depth: usize) -> OccupancyOctree<T> {
+        OccupancyOctree {
+            volume: vol,
+            max_depth: max_depth,
+            root: Node::Empty
+        }
+    }
+
+    /// Voxelizes a triangle soup into cubic voxels `max_depth` levels
+    /// below a root that tightly encloses the triangles. A node is
+    /// occupied if any triangle overlaps it, and, when `fill` is
+    /// `Fill::Solid`, if it lies inside the mesh.
+    pub fn voxelize(triangles: &[[[T; 3]; 3]], max_depth: usize, fill: Fill) -> OccupancyOctree<T> {
+        let zero: T = NumCast::from(0).unwrap();
+        let mut min = [zero, zero, zero];
+        let mut max = [zero, zero, zero];
+        for (n, p) in triangles.iter().flat_map(|tri| tri.iter()).enumerate() {
+            for i in 0..3 {
+                min[i] = if n == 0 { p[i] } else { min[i].min(p[i]) };
+                max[i] = if n == 0 { p[i] } else { max[i].max(p[i]) };
+            }
+        }
+        
+        let size = (max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]);
+        let volume = Volume::new(min, [min[0] + size, min[1] + size, min[2] + size]);
+        
+        let candidates: Vec<usize> = (0..triangles.len()).collect();
+        let root = voxelize_node(triangles, &candidates, &volume, 0, max_depth, fill);
+        
+        OccupancyOctree {
+            volume: volume,
+            max_depth: max_depth,
+            root: root
+        }
+    }
+
+    /// Marks every voxel overlapping `region` as occupied. Voxels that
+    /// only touch it stay as they are.
+    pub fn fill(&mut self, region: &Volume<T>) {
+        let vol = self.volume;
+        fill_node(&mut self.root, &vol, region, 0, self.max_depth);
+    }
+
+    /// Returns the bounding volume of the tree.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the depth of the smallest voxels.
+    #[inline]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Returns `true` if `p` lies in an occupied part of the tree.
+    pub fn is_occupied(&self, p: &[T; 3]) -> bool {
+        if !self.volume.contains(p) {
+            return false;
+        }
+        
+        let mut node = &self.root;
+        let mut vol = self.volume;
+        loop {
+            match *node {
+                Node::Empty => return false,
+                Node::Full => return true,
+                Node::Split(ref children) => {
+                    let mid = vol.center();
+                    let i = (0..3).fold(0, |i, axis| if p[axis] > mid[axis] { i | 1 << axis } else { i });
+                    vol = octant(&vol, i);
+                    node = &children[i];
+                }
+            }
+        }
+    }
+
+    /// Returns the volumes of all occupied nodes. Uniformly occupied
+    /// regions are returned as single volumes rather than as individual
+    /// voxels.
+    pub fn voxels(&self) -> Vec<Volume<T>> {
+        let mut voxels = Vec::new();
+        let mut stack = vec![(&self.root, self.volume)];
+        while let Some((node, vol)) = stack.pop() {
+            match *node {
+                Node::Empty => {},
+                Node::Full => voxels.push(vol),
+                Node::Split(ref children) => for (i, child) in children.iter().enumerate().rev() {
+                    stack.push((child, octant(&vol, i)));
+                }
+            }
+        }
+        voxels
+    }
+
+    /// Returns the signed distance from `p` to the boundary of the
+    /// occupied region: negative inside it, positive outside of it.
+    /// Everything outside the bounds of the tree counts as unoccupied.
+    pub fn sdf_sample(&self, p: &[T; 3]) -> T {
+        if self.is_occupied(p) {
+            // The space outside the root is empty, too.
+            let mut best: T = Float::infinity();
+            for (i, &x) in p.iter().enumerate() {
+                best = best.min(x - self.volume.min[i]).min(self.volume.max[i] - x);
+            }
+            -self.nearest(p, false, best * best).sqrt()
+        } else {
+            self.nearest(p, true, Float::infinity()).sqrt()
+        }
+    }
+
+    /// Samples the signed distance field at the centers of a grid of
+    /// `dims` cells spanning the bounds of the tree. The samples are
+    /// ordered with `x` varying fastest, then `y`, then `z`.
+    pub fn bake_sdf(&self, dims: [usize; 3]) -> Vec<T> {
+        let half: T = NumCast::from(0.5).unwrap();
+        let mut step = [half, half, half];
+        for i in 0..3 {
+            step[i] = (self.volume.max[i] - self.volume.min[i]) / NumCast::from(dims[i]).unwrap();
+        }
+        let coord = |i: usize, n: usize| {
+            let n: T = NumCast::from(n).unwrap();
+            self.volume.min[i] + (n + half) * step[i]
+        };
+        
+        let mut samples = Vec::with_capacity(dims[0] * dims[1] * dims[2]);
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    samples.push(self.sdf_sample(&[coord(0, x), coord(1, y), coord(2, z)]));
+                }
+            }
+        }
+        samples
+    }
+
+    /// Returns the squared distance from `p` to the closest node that
+    /// is occupied if `full` is `true`, or unoccupied otherwise, or
+    /// `best` if none is closer than that.
+    fn nearest(&self, p: &[T; 3], full: bool, mut best: T) -> T {
+        let mut stack = vec![(&self.root, self.volume)];
+        while let Some((node, vol)) = stack.pop() {
+            let d2 = geom::aabb_distance2(p, &vol.min, &vol.max);
+            if d2 >= best {
+                continue;
+            }
+            
+            match *node {
+                Node::Empty => if !full { best = d2 },
+                Node::Full => if full { best = d2 },
+                Node::Split(ref children) => for (i, child) in children.iter().enumerate() {
+                    stack.push((child, octant(&vol, i)));
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Returns the polygons of the surface crossing a marching cubes cell
+/// whose occupied corners are the bits of `mask`, as loops of the cell
+/// edges they cross. Corners are numbered like octants, and edge
+/// `3 * c + axis` runs from corner `c` along `axis`.
+///
+/// Each face of the cell is cut into segments on its own, keeping
+/// occupied corners that only meet diagonally apart, so the two cells
+/// sharing a face always agree on its segments and the mesh has no
+/// holes.
+#[cfg(feature = "surface")]
+fn cell_polygons(mask: u8) -> Vec<Vec<usize>> {
+    let occupied = |c: usize| mask & (1 << c) != 0;
+    let edge = |a: usize, b: usize| 3 * (a & b) + (a ^ b).trailing_zeros() as usize;
+    
+    // The segments run from the edge where the boundary of the face
+    // enters the occupied corners to the edge where it leaves them,
+    // going counterclockwise seen from outside of the cell.
+    let mut next = [None; 24];
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in 0..2 {
+            let base = side << axis;
+            let mut corners = [base, base | 1 << u, base | 1 << u | 1 << v, base | 1 << v];
+            if side == 0 {
+                corners.swap(1, 3);
+            }
+            for k in 0..4 {
+                if !occupied(corners[k]) || occupied(corners[(k + 3) % 4]) {
+                    continue;
+                }
+                let mut last = k;
+                while occupied(corners[(last + 1) % 4]) {
+                    last = (last + 1) % 4;
+                }
+                next[edge(corners[(k + 3) % 4], corners[k])] = Some(edge(corners[last], corners[(last + 1) % 4]));
+            }
+        }
+    }
+    
+    let mut polygons = Vec::new();
+    let mut visited = [false; 24];
+    for start in 0..24 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut polygon = Vec::new();
+        let mut e = start;
+        while !visited[e] {
+            visited[e] = true;
+            polygon.push(e);
+            e = next[e].unwrap();
+        }
+        polygons.push(polygon);
+    }
+    polygons
+}
+
+#[cfg(feature = "surface")]
+impl<T: SpatialKey> OccupancyOctree<T> {
+    /// Extracts the boundary of the occupied region as a triangle mesh
+    /// with marching cubes, for drawing voxelized or simulated volumes.
+    /// Everything outside the bounds of the tree counts as unoccupied,
+    /// so the mesh is closed.
+    ///
+    /// The cells of the cubes join the centers of neighboring voxels of
+    /// the maximum depth, and only the cells around the faces of the
+    /// occupied leaves are visited, so large uniform regions cost
+    /// nothing inside. Where a large leaf meets smaller ones, it's
+    /// sampled at the resolution of the smallest, so the triangles on
+    /// either side of a change in depth share their vertices and leave
+    /// no cracks. The vertices lie halfway between the voxel centers.
+    pub fn extract_surface(&self) -> SurfaceMesh<T> {
+        let mut cells = HashSet::new();
+        for &(origin, size) in self.full_blocks().iter() {
+            // The cells with a corner inside the block and one outside
+            // of it form a shell around the block.
+            let last = [origin[0] + size - 1, origin[1] + size - 1, origin[2] + size - 1];
+            for x in origin[0] - 1..last[0] + 1 {
+                for y in origin[1] - 1..last[1] + 1 {
+                    let rim = x == origin[0] - 1 || x == last[0] || y == origin[1] - 1 || y == last[1];
+                    let mut z = origin[2] - 1;
+                    while z <= last[2] {
+                        cells.insert([x, y, z]);
+                        z = if rim || z == last[2] { z + 1 } else { last[2] };
+                    }
+                }
+            }
+        }
+        let mut cells: Vec<[i64; 3]> = cells.into_iter().collect();
+        cells.sort();
+        
+        let half: T = NumCast::from(0.5).unwrap();
+        let scale: T = NumCast::from(1u64 << self.max_depth).unwrap();
+        let mut step = [half, half, half];
+        for (i, step) in step.iter_mut().enumerate() {
+            *step = (self.volume.max[i] - self.volume.min[i]) / scale;
+        }
+        
+        let mut mesh = SurfaceMesh { vertices: Vec::new(), indices: Vec::new() };
+        let mut vertices = HashMap::new();
+        for cell in cells.iter() {
+            let corner = |c: usize| [cell[0] + (c & 1) as i64, cell[1] + (c >> 1 & 1) as i64, cell[2] + (c >> 2 & 1) as i64];
+            let mask = (0..8).fold(0u8, |mask, c| if self.voxel(corner(c)) { mask | 1 << c } else { mask });
+            
+            for polygon in cell_polygons(mask).iter() {
+                let mut indices = Vec::with_capacity(polygon.len());
+                for &e in polygon.iter() {
+                    let (start, axis) = (corner(e / 3), e % 3);
+                    let count = vertices.len() as u32;
+                    let index = *vertices.entry((start, axis)).or_insert(count);
+                    if index == count {
+                        let mut p = self.volume.min;
+                        for i in 0..3 {
+                            let n: T = NumCast::from(start[i]).unwrap();
+                            let offset = if i == axis { half + half } else { half };
+                            p[i] = self.volume.min[i] + (n + offset) * step[i];
+                        }
+                        mesh.vertices.push(p);
+                    }
+                    indices.push(index);
+                }
+                for i in 1..indices.len() - 1 {
+                    mesh.indices.extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Returns the occupied leaves as the voxel coordinates of their
+    /// minimum corners and their sizes in voxels.
+    fn full_blocks(&self) -> Vec<([i64; 3], i64)> {
+        let mut blocks = Vec::new();
+        let mut stack = vec![(&self.root, [0i64; 3], 1i64 << self.max_depth)];
+        while let Some((node, origin, size)) = stack.pop() {
+            match *node {
+                Node::Empty => {},
+                Node::Full => blocks.push((origin, size)),
+                Node::Split(ref children) => for (i, child) in children.iter().enumerate() {
+                    let mut child_origin = origin;
+                    for (axis, x) in child_origin.iter_mut().enumerate() {
+                        *x += (i >> axis & 1) as i64 * size / 2;
+                    }
+                    stack.push((child, child_origin, size / 2));
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Returns `true` if the voxel of the maximum depth at the voxel
+    /// coordinates `v` is occupied. Voxels outside the tree aren't.
+    fn voxel(&self, v: [i64; 3]) -> bool {
+        let size = 1i64 << self.max_depth;
+        if v.iter().any(|&x| x < 0 || x >= size) {
+            return false;
+        }
+        
+        let mut node = &self.root;
+        let mut half = size / 2;
+        loop {
+            match *node {
+                Node::Empty => return false,
+                Node::Full => return true,
+                Node::Split(ref children) => {
+                    let i = (0..3).fold(0, |i, axis| if v[axis] & half != 0 { i | 1 << axis } else { i });
+                    node = &children[i];
+                    half /= 2;
+                }
+            }
+        }
+    }
+}
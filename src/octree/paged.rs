@@ -0,0 +1,382 @@
+use SpatialKey;
+use SpatialError;
+use geom;
+use snapshot::Snapshot;
+use paging::{self, Spill, PagedFile, PageCache, PageStats, Compression, MAX_PAGE_DEPTH};
+use std::convert::TryFrom;
+use std::io::{Read, Write, Seek};
+use std::marker::PhantomData;
+use super::{Octree, Index, Volume};
+
+/// Returns the coordinates of the cell of `vol` at `depth` holding `p`,
+/// the cell of the node at `depth` an `Octree` over `vol` stores it in.
+/// Points outside `vol` give the nearest cell.
+fn cell_of<T: SpatialKey>(vol: &Volume<T>, depth: usize, p: &[T; 3]) -> [usize; 3] {
+    let mut cell = [0; 3];
+    let mut vol = *vol;
+    for _ in 0..depth {
+        let mid = vol.center();
+        let (mut min, mut max) = (vol.min, vol.max);
+        for axis in 0..3 {
+            cell[axis] *= 2;
+            // Items on the middle go to the lower octant, as in the tree.
+            if p[axis] > mid[axis] {
+                cell[axis] += 1;
+                min[axis] = mid[axis];
+            } else {
+                max[axis] = mid[axis];
+            }
+        }
+        vol = Volume::new(min, max);
+    }
+    cell
+}
+
+/// Returns the cell of `vol` at `depth` holding `p`, numbered with `x`
+/// varying fastest.
+fn page_of<T: SpatialKey>(vol: &Volume<T>, depth: usize, p: &[T; 3]) -> usize {
+    let cell = cell_of(vol, depth, p);
+    cell[0] + ((cell[1] + (cell[2] << depth)) << depth)
+}
+
+/// Returns the cells of `vol` at `depth` overlapping the box from `min`
+/// to `max`, in ascending order.
+fn pages_between<T: SpatialKey>(vol: &Volume<T>, depth: usize, min: &[T; 3], max: &[T; 3]) -> Vec<usize> {
+    let (low, high) = (cell_of(vol, depth, min), cell_of(vol, depth, max));
+    let mut pages = Vec::new();
+    for z in low[2]..high[2] + 1 {
+        for y in low[1]..high[1] + 1 {
+            for x in low[0]..high[0] + 1 {
+                pages.push(x + ((y + (z << depth)) << depth));
+            }
+        }
+    }
+    pages
+}
+
+/// Returns the volume of cell `page` of `vol` at `depth`.
+fn page_volume<T: SpatialKey>(vol: &Volume<T>, depth: usize, page: usize) -> Volume<T> {
+    let mask = (1 << depth) - 1;
+    let cell = [page & mask, (page >> depth) & mask, page >> (2 * depth)];
+    let mut vol = *vol;
+    for level in (0..depth).rev() {
+        let mid = vol.center();
+        let (mut min, mut max) = (vol.min, vol.max);
+        for axis in 0..3 {
+            if cell[axis] >> level & 1 == 1 {
+                min[axis] = mid[axis];
+            } else {
+                max[axis] = mid[axis];
+            }
+        }
+        vol = Volume::new(min, max);
+    }
+    vol
+}
+
+/// Builds a paged snapshot of an octree from more items than fit in
+/// memory, spilling them to temporary files, as described in the
+/// `paging` module.
+pub struct PagedBuilder<T: SpatialKey, I> {
+    volume: Volume<T>,
+    capacity: usize,
+    /// The depth of the nodes whose subtrees are the pages.
+    depth: usize,
+    spill: Spill,
+    compression: Compression,
+    len: usize,
+    items: PhantomData<I>
+}
+
+impl<T, I> PagedBuilder<T, I>
+    where T: SpatialKey + Snapshot, I: Index<T> + Clone + Snapshot
+{
+    /// Creates a builder of an octree over `vol` with nodes of
+    /// `capacity`, paged at the subtrees of the nodes at `depth`, which
+    /// keeps up to `budget` bytes of items in memory before spilling
+    /// them.
+    ///
+    /// Panics if `depth` is beyond `MAX_PAGE_DEPTH`.
+    pub fn new(vol: Volume<T>, capacity: usize, depth: usize, budget: usize) -> PagedBuilder<T, I> {
+        assert!(depth <= MAX_PAGE_DEPTH);
+        PagedBuilder {
+            volume: vol,
+            capacity: capacity,
+            depth: depth,
+            spill: Spill::new(budget),
+            compression: Compression::None,
+            len: 0,
+            items: PhantomData
+        }
+    }
+
+    /// Returns the number of items added.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no items have been added.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sets how the pages are compressed when they're written, each on
+    /// its own. Pages are stored as they are by default.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Adds `item` to the page of its cell. Returns
+    /// `SpatialError::OutOfBounds` if it lies outside of the tree, and
+    /// `SpatialError::Io` if it can't be spilled.
+    pub fn insert(&mut self, item: I) -> Result<(), SpatialError> {
+        let p = item.octree_index();
+        if !self.volume.contains(&p) {
+            return Err(SpatialError::OutOfBounds);
+        }
+        match self.spill.push(page_of(&self.volume, self.depth, &p), &item) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Builds the subtree of each cell in turn and writes the paged
+    /// snapshot to `writer`. Returns the error of `insert` if an item
+    /// can't be stored in its subtree, such as `SpatialError::DepthLimit`,
+    /// and `SpatialError::Io` if a file can't be read or written.
+    pub fn finish<W: Write>(mut self, writer: &mut W) -> Result<(), SpatialError> {
+        let mut header = Vec::new();
+        paging::write_paged_header(&mut header, 3);
+        self.capacity.write_snapshot(&mut header);
+        (self.depth as u8).write_snapshot(&mut header);
+        self.volume.min.write_snapshot(&mut header);
+        self.volume.max.write_snapshot(&mut header);
+
+        let (volume, capacity, depth, compression) = (self.volume, self.capacity, self.depth, self.compression);
+        let spill = &mut self.spill;
+        paging::write_paged(writer, &header, 1 << (3 * depth), compression, |page| {
+            let items = spill.count(page);
+            if items == 0 {
+                return Ok(None);
+            }
+            let bytes = match spill.take(page) {
+                Ok(bytes) => bytes,
+                Err(err) => return Err(err)
+            };
+            let mut input = &bytes[..];
+            let mut tree: Octree<T, I> = Octree::with_capacity(page_volume(&volume, depth, page), capacity);
+            while !input.is_empty() {
+                let inserted = I::read_snapshot(&mut input).and_then(|item| tree.insert(item));
+                match inserted {
+                    Ok(()) => {},
+                    Err(err) => return Err(err)
+                }
+            }
+            let mut out = Vec::new();
+            tree.save_to(&mut out).map(|()| Some((out, items)))
+        })
+    }
+}
+
+/// An octree stored as a paged snapshot, as written by `PagedBuilder`,
+/// whose pages are loaded when queries first touch them and stay
+/// loaded until they're unloaded, or until the least recently used
+/// pages are unloaded to stay within a budget of bytes.
+pub struct PagedOctree<T: SpatialKey, I: Index<T> + Clone, R> {
+    file: PagedFile<R>,
+    /// The subtrees of the loaded pages.
+    pages: PageCache<Octree<T, I>>,
+    volume: Volume<T>,
+    /// The depth of the nodes whose subtrees are the pages.
+    depth: usize,
+    len: usize
+}
+
+impl<T, I, R> PagedOctree<T, I, R>
+    where T: SpatialKey + Snapshot, I: Index<T> + Clone + Snapshot, R: Read + Seek
+{
+    /// Opens the paged snapshot in `reader`, such as a `File`, reading
+    /// only its header and table of pages. Returns
+    /// `SpatialError::Serialization` if it isn't a paged snapshot of an
+    /// octree in the current format or is malformed.
+    pub fn open(reader: R) -> Result<PagedOctree<T, I, R>, SpatialError> {
+        let (file, fields) = match PagedFile::open(reader, 3) {
+            Ok(opened) => opened,
+            Err(err) => return Err(err)
+        };
+        let mut input = &fields[..];
+        let read = usize::read_snapshot(&mut input).and_then(|_| u8::read_snapshot(&mut input))
+            .and_then(|depth| <([T; 3], [T; 3])>::read_snapshot(&mut input).map(|bounds| (depth as usize, bounds)));
+        let (depth, (min, max)) = match read {
+            Ok(fields) => fields,
+            Err(err) => return Err(err)
+        };
+        let volume = Volume::new(min, max);
+        if !volume.is_valid() {
+            return Err(SpatialError::Serialization("invalid volume".to_string()));
+        }
+        if depth > MAX_PAGE_DEPTH || file.table.len() != 1 << (3 * depth) {
+            return Err(SpatialError::Serialization("invalid table of pages".to_string()));
+        }
+
+        let mut len: usize = 0;
+        for entry in file.table.iter() {
+            match usize::try_from(entry.items).ok().and_then(|items| len.checked_add(items)) {
+                Some(sum) => len = sum,
+                None => return Err(SpatialError::Serialization("too many items".to_string()))
+            }
+        }
+        Ok(PagedOctree {
+            pages: PageCache::new(file.table.len()),
+            file: file,
+            volume: volume,
+            depth: depth,
+            len: len
+        })
+    }
+
+    /// Returns the bounding volume of the tree.
+    #[inline]
+    pub fn bounds(&self) -> &Volume<T> {
+        &self.volume
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of pages, one for each cell at the depth of
+    /// the pages, with or without items.
+    #[inline]
+    pub fn page_count(&self) -> usize {
+        self.file.table.len()
+    }
+
+    /// Returns the number of loaded pages.
+    #[inline]
+    pub fn resident_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the size of the uncompressed snapshots of the loaded
+    /// pages in bytes, about the memory they take.
+    #[inline]
+    pub fn resident_bytes(&self) -> usize {
+        self.pages.bytes()
+    }
+
+    /// Returns `true` if `page` is loaded.
+    pub fn is_resident(&self, page: usize) -> bool {
+        page < self.page_count() && self.pages.get(page).is_some()
+    }
+
+    /// Unloads `page`, to be loaded again by the next query touching
+    /// it. Returns `true` if it was loaded.
+    pub fn unload(&mut self, page: usize) -> bool {
+        page < self.page_count() && self.pages.remove(page)
+    }
+
+    /// Unloads all pages, such as when memory runs low.
+    pub fn unload_all(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Returns the number of bytes the loaded pages may take, which is
+    /// unlimited until it's set.
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.pages.budget()
+    }
+
+    /// Sets the number of bytes the loaded pages may take, counting the
+    /// size of their snapshots, and unloads the least recently used
+    /// pages beyond it. A page larger than the budget is still loaded
+    /// for the query that touches it.
+    pub fn set_budget(&mut self, bytes: usize) {
+        self.pages.set_budget(bytes);
+    }
+
+    /// Returns how many times queries found their pages loaded or had
+    /// to load them, and how many pages were unloaded to stay within
+    /// the budget.
+    #[inline]
+    pub fn stats(&self) -> PageStats {
+        self.pages.stats()
+    }
+
+    /// Resets the statistics returned by `stats` to zero.
+    pub fn reset_stats(&mut self) {
+        self.pages.reset_stats();
+    }
+
+    /// Returns copies of all items inside the volume `vol`, loading the
+    /// pages it touches. Returns `SpatialError::Io` if a page can't be
+    /// read, and `SpatialError::Serialization` if it's malformed.
+    pub fn get_in_volume(&mut self, vol: &Volume<T>) -> Result<Vec<I>, SpatialError> {
+        self.query(&vol.min, &vol.max, |page| page.intersects(vol), |tree| tree.get_in_volume(vol))
+    }
+
+    /// Returns copies of all items within `radius` of `center`, like
+    /// `get_in_volume`.
+    pub fn get_in_radius(&mut self, center: [T; 3], radius: T) -> Result<Vec<I>, SpatialError> {
+        let (min, max) = ([center[0] - radius, center[1] - radius, center[2] - radius], [center[0] + radius, center[1] + radius, center[2] + radius]);
+        self.query(&min, &max, |page| geom::sphere_intersects_aabb(&center, radius, &page.min, &page.max),
+                   |tree| tree.get_in_radius(center, radius))
+    }
+
+    /// Collects the results of `query` on the pages of the cells
+    /// overlapping the box from `min` to `max` and accepted by `touches`.
+    fn query<F, Q>(&mut self, min: &[T; 3], max: &[T; 3], touches: F, query: Q) -> Result<Vec<I>, SpatialError>
+        where F: Fn(&Volume<T>) -> bool, Q: for<'a> Fn(&'a Octree<T, I>) -> Vec<&'a I>
+    {
+        let mut results = Vec::new();
+        for page in pages_between(&self.volume, self.depth, min, max) {
+            if self.file.table[page].items == 0 || !touches(&page_volume(&self.volume, self.depth, page)) {
+                continue;
+            }
+            if !self.pages.touch(page) {
+                match self.load(page) {
+                    Ok(tree) => {
+                        let size = self.file.table[page].size as usize;
+                        self.pages.insert(page, tree, size);
+                    },
+                    Err(err) => return Err(err)
+                }
+            }
+            match self.pages.get(page) {
+                Some(tree) => results.extend(query(tree).into_iter().cloned()),
+                None => {}
+            }
+        }
+        Ok(results)
+    }
+
+    /// Reads the subtree of `page`, checking that it covers its cell.
+    fn load(&mut self, page: usize) -> Result<Octree<T, I>, SpatialError> {
+        let bytes = match self.file.read_page(page) {
+            Ok(bytes) => bytes,
+            Err(err) => return Err(err)
+        };
+        let tree: Octree<T, I> = match Octree::load_from(&mut &bytes[..]) {
+            Ok(tree) => tree,
+            Err(err) => return Err(err)
+        };
+        let cell = page_volume(&self.volume, self.depth, page);
+        if tree.bounds().min != cell.min || tree.bounds().max != cell.max || tree.len() as u64 != self.file.table[page].items {
+            return Err(SpatialError::Serialization(format!("page {} doesn't match its cell", page)));
+        }
+        Ok(tree)
+    }
+}
@@ -0,0 +1,131 @@
+use SpatialKey;
+use num::NumCast;
+use super::{Octree, NodeData, Index, Volume, MAX_DEPTH};
+
+/// The number of levels of a `NodePath` packed into each word.
+static LEVELS_PER_WORD: usize = 21;
+
+/// The path of octant indices leading to a node from the root of a
+/// `Octree`, packed into three bits per level.
+///
+/// A path only depends on the octants chosen, not on the tree, so it
+/// can be kept from frame to frame, used as a key of caches outside of
+/// the tree, or sent to another tree covering the same volume to name
+/// the same region there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodePath {
+    /// The octant chosen at level `i`, in bits `3 * (i % 21)` of word
+    /// `i / 21`.
+    bits: [u64; 4],
+    /// The number of levels of the path.
+    depth: u8
+}
+
+impl NodePath {
+    /// Returns the path of the root, of no levels.
+    #[inline]
+    pub fn root() -> NodePath {
+        NodePath { bits: [0; 4], depth: 0 }
+    }
+
+    /// Returns the path of octant `indices`, or `None` if an index
+    /// isn't a octant or the path is deeper than a tree can grow.
+    pub fn from_indices(indices: &[usize]) -> Option<NodePath> {
+        if indices.len() > MAX_DEPTH || indices.iter().any(|&i| i >= 8) {
+            return None;
+        }
+        Some(indices.iter().fold(NodePath::root(), |path, &i| path.child(i)))
+    }
+
+    /// Returns the number of levels of the path, which is the depth of
+    /// the node it leads to.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth as usize
+    }
+
+    /// Returns `true` if this is the path of the root.
+    #[inline]
+    pub fn is_root(&self) -> bool {
+        self.depth == 0
+    }
+
+    /// Returns the octant chosen at `level`, or `None` if the path
+    /// isn't that deep.
+    #[inline]
+    pub fn get(&self, level: usize) -> Option<usize> {
+        if level >= self.depth() {
+            return None;
+        }
+        Some(((self.bits[level / LEVELS_PER_WORD] >> (3 * (level % LEVELS_PER_WORD))) & 7) as usize)
+    }
+
+    /// Returns the octant indices of the path, from the root down.
+    pub fn indices(&self) -> Vec<usize> {
+        (0..self.depth()).map(|level| self.get(level).unwrap()).collect()
+    }
+
+    /// Returns the path of octant `i` of the node of this path.
+    ///
+    /// Panics if `i` isn't a octant or the path is already as deep as
+    /// a tree can grow.
+    pub fn child(&self, i: usize) -> NodePath {
+        assert!(i < 8 && self.depth() < MAX_DEPTH);
+        let level = self.depth();
+        let mut path = *self;
+        path.bits[level / LEVELS_PER_WORD] |= (i as u64) << (3 * (level % LEVELS_PER_WORD));
+        path.depth += 1;
+        path
+    }
+
+    /// Returns the path of the parent of the node of this path, or
+    /// `None` if this is the path of the root.
+    pub fn parent(&self) -> Option<NodePath> {
+        if self.is_root() {
+            return None;
+        }
+        let level = self.depth() - 1;
+        let mut path = *self;
+        path.bits[level / LEVELS_PER_WORD] &= !(7 << (3 * (level % LEVELS_PER_WORD)));
+        path.depth -= 1;
+        Some(path)
+    }
+
+    /// Returns `true` if the node of this path is `other`'s or one of
+    /// its ancestors.
+    pub fn is_prefix_of(&self, other: &NodePath) -> bool {
+        self.depth <= other.depth && (0..self.depth()).all(|level| self.get(level) == other.get(level))
+    }
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the node at `path` from this one, or `None` if the tree
+    /// isn't subdivided that far.
+    pub fn subtree(&self, path: &NodePath) -> Option<&Octree<T, I, D>> {
+        let mut node = self;
+        for level in 0..path.depth() {
+            node = match node.octants {
+                Some(ref octants) => &*octants[path.get(level).unwrap()],
+                None => return None
+            };
+        }
+        Some(node)
+    }
+
+    /// Returns the volume of the node at `path` from this one, whether
+    /// or not the tree is subdivided that far. It's the same volume the
+    /// node gets when the tree grows there.
+    pub fn volume_of(&self, path: &NodePath) -> Volume<T> {
+        let val2: T = NumCast::from(2).unwrap();
+        let (mut min, mut max) = (self.volume.min, self.volume.max);
+        for level in 0..path.depth() {
+            let i = path.get(level).unwrap();
+            let mid = [(min[0] + max[0]) / val2, (min[1] + max[1]) / val2, (min[2] + max[2]) / val2];
+            if i & 1 == 0 { max[0] = mid[0]; } else { min[0] = mid[0]; }
+            if i & 2 == 0 { max[1] = mid[1]; } else { min[1] = mid[1]; }
+            if i & 4 == 0 { max[2] = mid[2]; } else { min[2] = mid[2]; }
+        }
+        Volume::new(min, max)
+    }
+}
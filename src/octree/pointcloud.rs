@@ -0,0 +1,327 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::btree_map::Entry;
+use std::ptr;
+use super::{Octree, NodeData, Index};
+use super::nearest;
+
+/// Returns the grid cell containing `p`, for cells of `cell_size`
+/// aligned to `origin`.
+fn cell_of<T: SpatialKey>(p: &[T; 3], origin: &[T; 3], cell_size: T) -> [i64; 3] {
+    let mut cell = [0; 3];
+    for i in 0..3 {
+        cell[i] = ((p[i] - origin[i]) / cell_size).floor().to_i64().unwrap();
+    }
+    cell
+}
+
+/// Adds `count` points summing up to `sum` to `cell`.
+fn accumulate<F: Float>(cells: &mut BTreeMap<[i64; 3], ([F; 3], usize)>, cell: [i64; 3],
+                        sum: [F; 3], count: usize) {
+    match cells.entry(cell) {
+        Entry::Occupied(mut entry) => {
+            let total = entry.get_mut();
+            for (total, &x) in total.0.iter_mut().zip(sum.iter()) {
+                *total = *total + x;
+            }
+            total.1 += count;
+        },
+        Entry::Vacant(entry) => {
+            entry.insert((sum, count));
+        }
+    }
+}
+
+/// Returns the centroid of `points`, summed up in `f64` unless they
+/// don't convert to it.
+fn mean_of<T: SpatialKey>(points: &[[T; 3]]) -> [T; 3] {
+    let wide = match centroid_in(points, geom::wide) {
+        Some(c) => match (geom::narrow(c[0]), geom::narrow(c[1]), geom::narrow(c[2])) {
+            (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+            _ => None
+        },
+        None => None
+    };
+    match wide.or_else(|| centroid_in(points, Some)) {
+        Some(mean) => mean,
+        None => [Float::nan(); 3]
+    }
+}
+
+/// Returns the centroid of `points` in the type `convert` converts them
+/// to, or `None` if one of them doesn't convert.
+fn centroid_in<T, F, C>(points: &[[T; 3]], convert: C) -> Option<[F; 3]>
+    where T: SpatialKey, F: Float, C: Fn(T) -> Option<F> {
+    let mut sum = [F::zero(); 3];
+    for p in points.iter() {
+        for i in 0..3 {
+            match convert(p[i]) {
+                Some(x) => sum[i] = sum[i] + x,
+                None => return None
+            }
+        }
+    }
+    let n: F = match NumCast::from(points.len()) {
+        Some(n) => n,
+        None => return None
+    };
+    Some([sum[0] / n, sum[1] / n, sum[2] / n])
+}
+
+/// Returns the eigenvector belonging to the smallest eigenvalue of the
+/// symmetric matrix `m`, found with Jacobi eigenvalue iteration.
+fn smallest_eigenvector<T: SpatialKey>(m: [[T; 3]; 3]) -> [T; 3] {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    let val2: T = NumCast::from(2).unwrap();
+    let epsilon: T = NumCast::from(1e-12).unwrap();
+    let mut a = m;
+    let mut v = [[one, zero, zero], [zero, one, zero], [zero, zero, one]];
+    
+    for _ in 0..50 {
+        // Rotate away the largest off-diagonal element.
+        let (p, q) = [(0, 1), (0, 2), (1, 2)].iter().cloned()
+            .fold((0, 1), |best, (p, q)| if a[p][q].abs() > a[best.0][best.1].abs() { (p, q) } else { best });
+        let scale = a[0][0].abs() + a[1][1].abs() + a[2][2].abs();
+        if a[p][q].abs() <= epsilon * scale {
+            break;
+        }
+        
+        let theta = (a[q][q] - a[p][p]) / (val2 * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + one).sqrt());
+        let c = one / (t * t + one).sqrt();
+        let s = t * c;
+        
+        for row in a.iter_mut() {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = c * kp - s * kq;
+            row[q] = s * kp + c * kq;
+        }
+        let (mut row_p, mut row_q) = (a[p], a[q]);
+        for (pk, qk) in row_p.iter_mut().zip(row_q.iter_mut()) {
+            let (x, y) = (*pk, *qk);
+            *pk = c * x - s * y;
+            *qk = s * x + c * y;
+        }
+        a[p] = row_p;
+        a[q] = row_q;
+        for row in v.iter_mut() {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = c * kp - s * kq;
+            row[q] = s * kp + c * kq;
+        }
+    }
+    
+    let i = (1..3).fold(0, |best, i| if a[i][i] < a[best][best] { i } else { best });
+    [v[0][i], v[1][i], v[2][i]]
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Estimates a surface normal for every item by fitting a plane to
+    /// it and its `k - 1` nearest neighbors, and taking the direction
+    /// in which they spread the least. The normals are of unit length
+    /// but not consistently oriented, and are ordered in the same
+    /// depth-first order as the lists of `neighbor_lists`. `k` should
+    /// be at least `3`.
+    pub fn estimate_normals(&self, k: usize) -> Vec<[T; 3]> {
+        let mut items = Vec::new();
+        self.collect_items(&mut items);
+        
+        items.iter().map(|item| {
+            let neighbors = nearest::k_nearest_where(self, &item.octree_index(), k, Float::infinity(), None, |_| true);
+            let n: T = NumCast::from(neighbors.len()).unwrap();
+            let zero: T = NumCast::from(0).unwrap();
+            
+            let positions: Vec<[T; 3]> = neighbors.iter().map(|&(_, neighbor)| neighbor.octree_index()).collect();
+            let mean = mean_of(&positions);
+            
+            let mut covariance = [[zero; 3]; 3];
+            for p in positions.iter() {
+                for r in 0..3 {
+                    for c in 0..3 {
+                        covariance[r][c] = covariance[r][c] + (p[r] - mean[r]) * (p[c] - mean[c]) / n;
+                    }
+                }
+            }
+            
+            smallest_eigenvector(covariance)
+        }).collect()
+    }
+    
+    /// Finds, for each point in `source`, the closest item of the tree
+    /// within `max_dist` of it. Returns the index of that item in the
+    /// depth-first order of `neighbor_lists` along with its distance,
+    /// or `None` if no item is close enough.
+    ///
+    /// The points are matched independently, so large batches can be
+    /// split into chunks and matched from several threads sharing the
+    /// tree.
+    pub fn correspondences(&self, source: &[[T; 3]], max_dist: T) -> Vec<Option<(usize, T)>> {
+        let mut items = Vec::new();
+        self.collect_items(&mut items);
+        let indices: HashMap<*const I, usize> = items.iter().enumerate()
+            .map(|(i, &item)| (item as *const I, i))
+            .collect();
+        
+        source.iter().map(|p| {
+            nearest::k_nearest_where(self, p, 1, max_dist * max_dist, None, |_| true).first()
+                .map(|&(d2, item)| (indices[&(item as *const I)], d2.sqrt()))
+        }).collect()
+    }
+    
+    /// Returns the items that have at least `min_neighbors` other items
+    /// within `radius` of them, dropping isolated ones.
+    pub fn remove_radius_outliers(&self, min_neighbors: usize, radius: T) -> Vec<&I> {
+        let mut items = Vec::new();
+        self.collect_items(&mut items);
+        
+        self.neighbor_lists(radius).iter().zip(items)
+            .filter(|&(neighbors, _)| neighbors.len() >= min_neighbors)
+            .map(|(_, item)| item)
+            .collect()
+    }
+    
+    /// Returns the items whose mean distance to their `k` nearest
+    /// neighbors is at most `std_ratio` standard deviations above the
+    /// average of that mean over all items, dropping the ones lying far
+    /// away from the rest.
+    pub fn remove_statistical_outliers(&self, k: usize, std_ratio: T) -> Vec<&I> {
+        let mut items = Vec::new();
+        self.collect_items(&mut items);
+        if items.is_empty() {
+            return items;
+        }
+        
+        let zero: T = NumCast::from(0).unwrap();
+        let means: Vec<T> = items.iter().map(|&item| {
+            let neighbors = nearest::k_nearest_where(self, &item.octree_index(), k, Float::infinity(), None,
+                                                     |other| !ptr::eq(other, item));
+            let n: T = NumCast::from(neighbors.len().max(1)).unwrap();
+            neighbors.iter().fold(zero, |sum, &(d2, _)| sum + d2.sqrt()) / n
+        }).collect();
+        
+        let n: T = NumCast::from(means.len()).unwrap();
+        let mean = means.iter().fold(zero, |sum, &d| sum + d) / n;
+        let variance = means.iter().fold(zero, |sum, &d| sum + (d - mean) * (d - mean)) / n;
+        let threshold = mean + std_ratio * variance.sqrt();
+        
+        means.iter().zip(items)
+            .filter(|&(&d, _)| d <= threshold)
+            .map(|(_, item)| item)
+            .collect()
+    }
+    
+    /// Pushes all items of the subtree into `out`, in depth-first order.
+    fn collect_items<'a>(&'a self, out: &mut Vec<&'a I>) {
+        for item in self.items.iter() {
+            out.push(item);
+        }
+        
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                node.collect_items(out);
+            },
+            None => {}
+        }
+    }
+    
+    /// Returns one representative point for each cell of a grid with
+    /// cells of `cell_size`, aligned to the minimum corner of the tree:
+    /// the centroid of the items in the cell. Empty cells are skipped,
+    /// and the points are ordered by cell.
+    ///
+    /// Nodes lying entirely within a single cell are added to it as a
+    /// whole, without looking up the cell of each of their items.
+    pub fn downsample(&self, cell_size: T) -> Vec<[T; 3]> {
+        // Sum up in f64, unless the positions don't convert to it.
+        let mut cells = BTreeMap::new();
+        if self.accumulate_cells(&self.volume.min, cell_size, &geom::wide, &mut cells) {
+            let points: Option<Vec<[T; 3]>> = cells.values().map(|&(sum, count)| {
+                let n = count as f64;
+                match (geom::narrow(sum[0] / n), geom::narrow(sum[1] / n), geom::narrow(sum[2] / n)) {
+                    (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+                    _ => None
+                }
+            }).collect();
+            match points {
+                Some(points) => return points,
+                None => {}
+            }
+        }
+        
+        let mut cells = BTreeMap::new();
+        self.accumulate_cells(&self.volume.min, cell_size, &Some, &mut cells);
+        cells.values().map(|&(sum, count)| {
+            let n: T = NumCast::from(count).unwrap();
+            [sum[0] / n, sum[1] / n, sum[2] / n]
+        }).collect()
+    }
+    
+    /// Adds the positions of the items in the subtree, converted with
+    /// `convert`, to the sums of their cells. Returns `false` if a
+    /// position doesn't convert.
+    fn accumulate_cells<F, C>(&self, origin: &[T; 3], cell_size: T, convert: &C,
+                              cells: &mut BTreeMap<[i64; 3], ([F; 3], usize)>) -> bool
+        where F: Float, C: Fn(T) -> Option<F> {
+        let cell = cell_of(&self.volume.min, origin, cell_size);
+        if cell == cell_of(&self.volume.max, origin, cell_size) {
+            let mut sum = [F::zero(); 3];
+            let mut count = 0;
+            if !self.sum_positions(convert, &mut sum, &mut count) {
+                return false;
+            }
+            if count > 0 {
+                accumulate(cells, cell, sum, count);
+            }
+            return true;
+        }
+        
+        for item in self.items.iter() {
+            let p = item.octree_index();
+            match (convert(p[0]), convert(p[1]), convert(p[2])) {
+                (Some(x), Some(y), Some(z)) => accumulate(cells, cell_of(&p, origin, cell_size), [x, y, z], 1),
+                _ => return false
+            }
+        }
+        
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                if !node.accumulate_cells(origin, cell_size, convert, cells) {
+                    return false;
+                }
+            },
+            None => {}
+        }
+        true
+    }
+    
+    /// Adds the positions of all items in the subtree, converted with
+    /// `convert`, to `sum`, and their number to `count`. Returns `false`
+    /// if a position doesn't convert.
+    fn sum_positions<F, C>(&self, convert: &C, sum: &mut [F; 3], count: &mut usize) -> bool
+        where F: Float, C: Fn(T) -> Option<F> {
+        for item in self.items.iter() {
+            let p = item.octree_index();
+            for i in 0..3 {
+                match convert(p[i]) {
+                    Some(x) => sum[i] = sum[i] + x,
+                    None => return false
+                }
+            }
+        }
+        *count += self.items.len();
+        
+        match self.octants {
+            Some(ref octants) => for node in octants.iter() {
+                if !node.sum_positions(convert, sum, count) {
+                    return false;
+                }
+            },
+            None => {}
+        }
+        true
+    }
+}
@@ -0,0 +1,135 @@
+use SpatialKey;
+use num::NumCast;
+use num::traits::Float;
+use std::cmp::Ordering;
+use super::{Octree, NodeData, Index, Ray};
+
+/// The number of rays traversing the tree together in `raycast_many`.
+static PACKET_SIZE: usize = 8;
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the items of all nodes that `ray` passes through, paired
+    /// with the distance along the ray at which it enters their node and
+    /// ordered by it. Children are visited front to back, and those the
+    /// ray misses are never entered. Useful for picking and line of
+    /// sight checks, where the items are then tested exactly.
+    pub fn raycast<'a>(&'a self, ray: &Ray<T>) -> Vec<(T, &'a I)> {
+        self.raycast_filtered(ray, Float::infinity(), !0)
+    }
+
+    /// Returns the items of the nodes that `ray` enters within distance
+    /// `t_max` along it, like `raycast`, keeping only those sharing a
+    /// layer bit with `mask`. Nodes entered beyond `t_max` are skipped
+    /// entirely.
+    pub fn raycast_filtered<'a>(&'a self, ray: &Ray<T>, t_max: T, mask: u32) -> Vec<(T, &'a I)> {
+        let mut hits = Vec::new();
+        match self.volume.intersect_ray(ray) {
+            Some((entry, _)) if entry <= t_max => self.raycast_node(ray, entry, t_max, mask, &mut hits),
+            _ => {}
+        }
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        hits
+    }
+    
+    /// Returns the items of all nodes that each of `rays` passes
+    /// through, like `raycast`. The rays are traced in packets of eight
+    /// that share a single traversal, so each node is fetched once for
+    /// all rays of a packet entering it, as when baking lighting or
+    /// simulating sensors with many rays.
+    pub fn raycast_many<'a>(&'a self, rays: &[Ray<T>]) -> Vec<Vec<(T, &'a I)>> {
+        let zero: T = NumCast::from(0).unwrap();
+        let mut hits = vec![Vec::new(); rays.len()];
+        for (packet, hits) in rays.chunks(PACKET_SIZE).zip(hits.chunks_mut(PACKET_SIZE)) {
+            let mut entries = [zero; 8];
+            let mut active = 0;
+            for (i, ray) in packet.iter().enumerate() {
+                match self.volume.intersect_ray(ray) {
+                    Some((entry, _)) => {
+                        entries[i] = entry;
+                        active |= 1 << i;
+                    },
+                    None => {}
+                }
+            }
+            if active != 0 {
+                self.raycast_packet(packet, active, &entries, hits);
+            }
+        }
+        for hits in hits.iter_mut() {
+            hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        }
+        hits
+    }
+
+    /// Collects the items of this node and of its children for the rays
+    /// of `packet` whose bits are set in `active`, which enter the node
+    /// at `entries`. Each child is only tested against the rays that
+    /// entered this node.
+    fn raycast_packet<'a>(&'a self, packet: &[Ray<T>], active: u32, entries: &[T; 8],
+                          hits: &mut [Vec<(T, &'a I)>]) {
+        for i in 0..packet.len() {
+            if active & (1 << i) != 0 {
+                for item in self.items.iter() {
+                    hits[i].push((entries[i], item));
+                }
+            }
+        }
+        
+        match self.octants {
+            Some(ref octants) => for (j, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << j) == 0 {
+                    continue;
+                }
+                let mut child_entries = *entries;
+                let mut child_active = 0;
+                for (i, ray) in packet.iter().enumerate() {
+                    if active & (1 << i) == 0 {
+                        continue;
+                    }
+                    match node.volume.intersect_ray(ray) {
+                        Some((entry, _)) => {
+                            child_entries[i] = entry;
+                            child_active |= 1 << i;
+                        },
+                        None => {}
+                    }
+                }
+                if child_active != 0 {
+                    node.raycast_packet(packet, child_active, &child_entries, hits);
+                }
+            },
+            None => {}
+        }
+    }
+
+    /// Collects the items of this node, entered at `entry`, and of the
+    /// children hit by `ray` within `t_max`, that share a layer bit with
+    /// `mask`.
+    fn raycast_node<'a>(&'a self, ray: &Ray<T>, entry: T, t_max: T, mask: u32, hits: &mut Vec<(T, &'a I)>) {
+        for item in self.items.iter() {
+            if item.octree_layers() & mask != 0 {
+                hits.push((entry, item));
+            }
+        }
+        
+        match self.octants {
+            Some(ref octants) => {
+                let mut children: Vec<(T, &Octree<T, I, D>)> = Vec::new();
+                for (i, node) in octants.iter().enumerate() {
+                    if self.occupied & (1 << i) == 0 {
+                        continue;
+                    }
+                    match node.volume.intersect_ray(ray) {
+                        Some((entry, _)) if entry <= t_max => children.push((entry, &**node)),
+                        _ => {}
+                    }
+                }
+                children.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+                for &(entry, node) in children.iter() {
+                    node.raycast_node(ray, entry, t_max, mask, hits);
+                }
+            },
+            None => {}
+        }
+    }
+}
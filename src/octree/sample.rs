@@ -0,0 +1,44 @@
+use SpatialKey;
+use num::NumCast;
+use std::collections::BTreeMap;
+use super::{Octree, NodeData, Index};
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns up to `per_cell` items from each cell of a grid with
+    /// cells of `cell_size`, aligned to the minimum corner of the tree,
+    /// so that densely populated areas don't dominate the sample. The
+    /// items are ordered by cell.
+    ///
+    /// The items of each cell are picked by `rng`, which must return a
+    /// random index below the number it's given; every subset of a
+    /// cell is then equally likely if `rng` is uniform.
+    ///
+    /// Panics if `cell_size` isn't positive.
+    pub fn stratified_sample<R: FnMut(usize) -> usize>(&self, cell_size: T, per_cell: usize,
+                                                      mut rng: R) -> Vec<&I> {
+        let zero: T = NumCast::from(0).unwrap();
+        assert!(cell_size > zero);
+        let mut cells: BTreeMap<[i64; 3], Vec<&I>> = BTreeMap::new();
+        for item in self.iter() {
+            let p = item.octree_index();
+            let mut cell = [0; 3];
+            for i in 0..3 {
+                cell[i] = ((p[i] - self.volume.min[i]) / cell_size).floor().to_i64().unwrap_or(0);
+            }
+            cells.entry(cell).or_default().push(item);
+        }
+        
+        let mut sample = Vec::new();
+        for (_, mut items) in cells.into_iter() {
+            // Shuffle just the front of the cell.
+            let n = items.len();
+            for i in 0..per_cell.min(n) {
+                let j = i + rng(n - i);
+                items.swap(i, j);
+            }
+            items.truncate(per_cell);
+            sample.extend(items);
+        }
+        sample
+    }
+}
@@ -0,0 +1,122 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use super::{Octree, NodeData, Index, Volume};
+
+/// Returns the point `p` transformed by the affine matrix `m`.
+fn apply<T: SpatialKey>(m: &[[T; 4]; 4], p: &[T; 3]) -> [T; 3] {
+    let mut q = [p[0]; 3];
+    for r in 0..3 {
+        q[r] = m[r][0] * p[0] + m[r][1] * p[1] + m[r][2] * p[2] + m[r][3];
+    }
+    q
+}
+
+/// Returns the inverse of the affine matrix `m`, or `None` if it can't
+/// be inverted.
+fn invert<T: SpatialKey>(m: &[[T; 4]; 4]) -> Option<[[T; 4]; 4]> {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    // The inverse of the linear part is its adjugate over its
+    // determinant.
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    if det == zero || !det.is_finite() {
+        return None;
+    }
+
+    let mut inverse = [[zero; 4]; 4];
+    for (r, row) in inverse.iter_mut().take(3).enumerate() {
+        for (c, value) in row.iter_mut().take(3).enumerate() {
+            *value = cofactor(c, r) / det;
+        }
+        row[3] = -(row[0] * m[0][3] + row[1] * m[1][3] + row[2] * m[2][3]);
+    }
+    inverse[3][3] = one;
+    Some(inverse)
+}
+
+/// A view of an `Octree` stored in local space, placed in world space
+/// by an affine transform. Queries are given in world space and mapped
+/// into the tree, so the tree doesn't need to be rebuilt when the
+/// object it belongs to moves.
+pub struct TransformedView<'a, T: SpatialKey + 'a, I: Index<T> + Clone + 'a, D: NodeData<T, I> + 'a> {
+    tree: &'a Octree<T, I, D>,
+    /// Transform from local to world space.
+    matrix: [[T; 4]; 4],
+    /// Transform from world to local space.
+    inverse: [[T; 4]; 4]
+}
+
+impl<T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns a view of the tree placed in world space by the affine
+    /// transform `m`, or `None` if `m` can't be inverted. The matrix is
+    /// indexed as `m[row][column]` and transforms column vectors; its
+    /// last row is ignored.
+    pub fn transformed<'a>(&'a self, m: &[[T; 4]; 4]) -> Option<TransformedView<'a, T, I, D>> {
+        invert(m).map(|inverse| TransformedView {
+            tree: self,
+            matrix: *m,
+            inverse: inverse
+        })
+    }
+}
+
+impl<'a, T: SpatialKey, I: Index<T> + Clone, D: NodeData<T, I>> TransformedView<'a, T, I, D> {
+    /// Returns the tree seen through the view.
+    #[inline]
+    pub fn tree(&self) -> &'a Octree<T, I, D> {
+        self.tree
+    }
+
+    /// Maps `p` from the local space of the tree to world space.
+    #[inline]
+    pub fn to_world(&self, p: &[T; 3]) -> [T; 3] {
+        apply(&self.matrix, p)
+    }
+
+    /// Maps `p` from world space to the local space of the tree.
+    #[inline]
+    pub fn to_local(&self, p: &[T; 3]) -> [T; 3] {
+        apply(&self.inverse, p)
+    }
+
+    /// Returns all items whose position in world space is inside the
+    /// world space volume `vol`.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&'a I> {
+        self.candidates(vol).into_iter()
+            .filter(|item| vol.contains(&self.to_world(&item.octree_index())))
+            .collect()
+    }
+
+    /// Returns all items whose position in world space is within
+    /// `radius` of the world space point `center`.
+    pub fn get_in_radius(&self, center: [T; 3], radius: T) -> Vec<&'a I> {
+        let vol = Volume::new([center[0] - radius, center[1] - radius, center[2] - radius],
+                              [center[0] + radius, center[1] + radius, center[2] + radius]);
+        self.candidates(&vol).into_iter()
+            .filter(|item| geom::point_in_sphere(&self.to_world(&item.octree_index()), &center, radius))
+            .collect()
+    }
+
+    /// Returns the items inside the local space bounding box of the
+    /// world space volume `vol`.
+    fn candidates(&self, vol: &Volume<T>) -> Vec<&'a I> {
+        let first = self.to_local(&vol.min);
+        let (mut min, mut max) = (first, first);
+        for corner in 1..8 {
+            let p = self.to_local(&[if corner & 1 == 0 { vol.min[0] } else { vol.max[0] },
+                                    if corner & 2 == 0 { vol.min[1] } else { vol.max[1] },
+                                    if corner & 4 == 0 { vol.min[2] } else { vol.max[2] }]);
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        self.tree.get_in_volume(&Volume::new(min, max))
+    }
+}
@@ -1,8 +1,12 @@
 use SpatialKey;
+use num::NumCast;
+use geom;
 use std::fmt;
 use std::fmt::Display;
 
 /// A three-dimensional bounding volume for an `Octree` node.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Volume<T: SpatialKey> {
     /// The upper-top-left corner.
     pub min: [T; 3],
@@ -33,26 +37,114 @@ impl<T: SpatialKey> Volume<T> {
         self.max
     }
     
+    /// Returns the center point of the volume.
+    #[inline]
+    pub fn center(&self) -> [T; 3] {
+        let val2: T = NumCast::from(2).unwrap();
+        [(self.min[0] + self.max[0]) / val2,
+         (self.min[1] + self.max[1]) / val2,
+         (self.min[2] + self.max[2]) / val2]
+    }
+    
     /// Returns `true` if `p` is inside the volume, `false` otherwise.
     #[inline]
     pub fn contains(&self, p: &[T; 3]) -> bool {
-        let min = self.min;
-        let max = self.max;
-        
-        p[0] >= min[0] && p[0] <= max[0] &&
-            p[1] >= min[1] && p[1] <= max[1] &&
-            p[2] >= min[2] && p[2] <= max[2]
+        geom::point_in_aabb(p, &self.min, &self.max)
+    }
+    
+    /// Returns `true` if the minimum of the volume is at or below its
+    /// maximum on every axis, `false` otherwise, including when either
+    /// is NaN.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        (0..3).all(|i| self.min[i] <= self.max[i])
     }
     
     /// Returns `true` if `other` intersects the volume, `false`
-    /// otherwise.
+    /// otherwise. Volumes that only touch each other are considered
+    /// intersecting.
     #[inline]
     pub fn intersects(&self, other: &Volume<T>) -> bool {
-        let min = self.min;
-        let max = self.max;
-        min[0] < other.max[0] && max[0] > other.min[0] &&
-            min[1] < other.max[1] && max[1] > other.min[1] &&
-            min[2] < other.max[2] && max[2] > other.min[2]
+        geom::aabb_intersects_aabb(&self.min, &self.max, &other.min, &other.max)
+    }
+    
+    /// Returns the distances along `ray` at which it enters and exits
+    /// the volume, or `None` if the ray misses it. The entry distance
+    /// is `0` if the ray starts inside the volume.
+    #[inline]
+    pub fn intersect_ray(&self, ray: &Ray<T>) -> Option<(T, T)> {
+        geom::ray_intersects_aabb(&ray.origin, &ray.inv_direction, &self.min, &self.max)
+    }
+}
+
+/// A ray in 3D-space. The reciprocal of the direction is precomputed
+/// to speed up repeated intersection tests.
+#[derive(Clone, Copy)]
+pub struct Ray<T: SpatialKey> {
+    origin: [T; 3],
+    direction: [T; 3],
+    inv_direction: [T; 3],
+    /// `1` for each axis along which the direction is negative, `0`
+    /// otherwise.
+    sign: [usize; 3]
+}
+
+impl<T: SpatialKey> Ray<T> {
+    /// Creates a new ray starting from `origin` and extending toward
+    /// `direction`, which doesn't need to be normalized.
+    #[inline]
+    pub fn new(origin: [T; 3], direction: [T; 3]) -> Ray<T> {
+        let one: T = NumCast::from(1).unwrap();
+        let zero: T = NumCast::from(0).unwrap();
+        let mut inv_direction = direction;
+        let mut sign = [0, 0, 0];
+        for i in 0..3 {
+            inv_direction[i] = one / direction[i];
+            sign[i] = if direction[i] < zero { 1 } else { 0 };
+        }
+        
+        Ray {
+            origin: origin,
+            direction: direction,
+            inv_direction: inv_direction,
+            sign: sign
+        }
+    }
+
+    /// Returns the starting point of the ray.
+    #[inline]
+    pub fn origin(&self) -> [T; 3] {
+        self.origin
+    }
+
+    /// Returns the direction of the ray.
+    #[inline]
+    pub fn direction(&self) -> [T; 3] {
+        self.direction
+    }
+
+    /// Returns the component-wise reciprocal of the direction.
+    #[inline]
+    pub fn inv_direction(&self) -> [T; 3] {
+        self.inv_direction
+    }
+
+    /// Returns `1` for each axis along which the direction is negative,
+    /// `0` otherwise.
+    #[inline]
+    pub fn sign(&self) -> [usize; 3] {
+        self.sign
+    }
+
+    /// Returns the point at distance `t` along the ray, measured in
+    /// multiples of the direction.
+    #[inline]
+    pub fn at(&self, t: T) -> [T; 3] {
+        let mut p = self.origin;
+        for (x, &d) in p.iter_mut().zip(self.direction.iter()) {
+            *x = *x + d * t;
+        }
+        p
     }
 }
 
@@ -64,3 +156,71 @@ impl<T: SpatialKey> Display for Volume<T> {
                min[0], min[1], min[2], max[0], max[1], max[2])
     }
 }
+
+/// A plane in 3D-space, consisting of all points `p` for which
+/// `normal · p + d == 0`.
+#[derive(Clone, Copy)]
+pub struct Plane<T: SpatialKey> {
+    /// The unit normal of the plane, pointing to its positive side.
+    pub normal: [T; 3],
+    /// The signed distance of the origin from the plane.
+    pub d: T
+}
+
+impl<T: SpatialKey> Plane<T> {
+    /// Creates a plane from the coefficients `[a, b, c, d]` of the
+    /// equation `ax + by + cz + d = 0`, normalizing them so that
+    /// `[a, b, c]` is of unit length.
+    #[inline]
+    pub fn from_coefficients(c: [T; 4]) -> Plane<T> {
+        let len = (c[0] * c[0] + c[1] * c[1] + c[2] * c[2]).sqrt();
+        Plane {
+            normal: [c[0] / len, c[1] / len, c[2] / len],
+            d: c[3] / len
+        }
+    }
+
+    /// Returns the signed distance from the plane to `p`, positive on
+    /// the side the normal points to.
+    #[inline]
+    pub fn distance(&self, p: &[T; 3]) -> T {
+        self.normal[0] * p[0] + self.normal[1] * p[1] + self.normal[2] * p[2] + self.d
+    }
+}
+
+/// A view frustum bounded by six planes with their normals pointing
+/// inward, in order of left, right, bottom, top, near and far.
+#[derive(Clone, Copy)]
+pub struct Frustum<T: SpatialKey> {
+    /// The bounding planes.
+    pub planes: [Plane<T>; 6]
+}
+
+impl<T: SpatialKey> Frustum<T> {
+    /// Extracts the frustum planes from a view-projection matrix `m`.
+    /// The matrix is indexed as `m[row][column]` and transforms column
+    /// vectors, with clip-space depth ranging from `-w` to `w` as in
+    /// OpenGL.
+    pub fn from_matrix(m: &[[T; 4]; 4]) -> Frustum<T> {
+        let plane = |sign: T, row: usize| {
+            Plane::from_coefficients([m[3][0] + sign * m[row][0],
+                                      m[3][1] + sign * m[row][1],
+                                      m[3][2] + sign * m[row][2],
+                                      m[3][3] + sign * m[row][3]])
+        };
+        let one: T = NumCast::from(1).unwrap();
+        
+        Frustum {
+            planes: [plane(one, 0), plane(-one, 0),
+                     plane(one, 1), plane(-one, 1),
+                     plane(one, 2), plane(-one, 2)]
+        }
+    }
+
+    /// Returns `true` if `p` is inside the frustum, `false` otherwise.
+    #[inline]
+    pub fn contains(&self, p: &[T; 3]) -> bool {
+        let zero: T = NumCast::from(0).unwrap();
+        self.planes.iter().all(|plane| plane.distance(p) >= zero)
+    }
+}
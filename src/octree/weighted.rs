@@ -0,0 +1,194 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use super::{Octree, NodeData, Index, Volume};
+
+/// A trait for items carrying a weight, such as a population, an
+/// influence or a mass, for `Octree::weighted_centroid` and
+/// `Octree::for_each_force_source`.
+pub trait Weighted<T: SpatialKey>: Index<T> {
+    /// Returns the weight of `self`.
+    fn weight(&self) -> T;
+}
+
+/// A source of force acting on a point, found by
+/// `Octree::for_each_force_source`.
+pub enum ForceSource<'a, T: SpatialKey, I: 'a> {
+    /// The items of a subtree far enough from the point to act as a
+    /// single body, with their total weight at their weighted centroid.
+    Node(T, [T; 3]),
+    /// An item close to the point, acting on its own.
+    Item(&'a I)
+}
+
+/// Node data keeping the total weight of the items in the subtree of its
+/// node and the sum of their positions times their weights, for
+/// `Octree::for_each_force_source`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mass<T: SpatialKey> {
+    /// The total weight and weighted sum of the items of the node.
+    own: (T, [T; 3]),
+    /// The total weight and weighted sum of the items below the node.
+    below: (T, [T; 3])
+}
+
+/// Returns the total weight and weighted sum of no items.
+fn no_mass<T: SpatialKey>() -> (T, [T; 3]) {
+    let zero: T = NumCast::from(0).unwrap();
+    (zero, [zero; 3])
+}
+
+/// Returns the weight of `item` and its position times its weight.
+fn mass_of<T: SpatialKey, I: Weighted<T>>(item: &I) -> (T, [T; 3]) {
+    let (p, w) = (item.octree_index(), item.weight());
+    (w, [p[0] * w, p[1] * w, p[2] * w])
+}
+
+/// Returns the total weight and weighted sum of the items of `a` and `b`.
+fn add_mass<T: SpatialKey>(a: (T, [T; 3]), b: (T, [T; 3])) -> (T, [T; 3]) {
+    (a.0 + b.0, [a.1[0] + b.1[0], a.1[1] + b.1[1], a.1[2] + b.1[2]])
+}
+
+impl<T: SpatialKey> Default for Mass<T> {
+    fn default() -> Mass<T> {
+        Mass { own: no_mass(), below: no_mass() }
+    }
+}
+
+impl<T: SpatialKey> Mass<T> {
+    /// Returns the total weight of the items in the subtree.
+    #[inline]
+    pub fn weight(&self) -> T {
+        self.own.0 + self.below.0
+    }
+
+    /// Returns the weighted centroid of the items in the subtree, or
+    /// `None` if their total weight is zero.
+    pub fn centroid(&self) -> Option<[T; 3]> {
+        let (weight, sum) = add_mass(self.own, self.below);
+        if weight == NumCast::from(0).unwrap() {
+            return None;
+        }
+        Some([sum[0] / weight, sum[1] / weight, sum[2] / weight])
+    }
+}
+
+impl<T: SpatialKey, I: Weighted<T>> NodeData<T, I> for Mass<T> {
+    fn on_insert(&mut self, item: &I) {
+        self.own = add_mass(self.own, mass_of(item));
+    }
+
+    fn on_remove(&mut self, item: &I) {
+        let (w, sum) = mass_of(item);
+        self.own = add_mass(self.own, (-w, [-sum[0], -sum[1], -sum[2]]));
+    }
+
+    fn on_children_change(&mut self, children: [&Mass<T>; 8]) {
+        self.below = children.iter().fold(no_mass(), |below, child| add_mass(below, add_mass(child.own, child.below)));
+    }
+
+    fn on_merge(&mut self) {
+        self.own = add_mass(self.own, self.below);
+        self.below = no_mass();
+    }
+}
+
+/// Returns the weighted centroid of `items` and their total weight in
+/// the type `convert` converts them to, or `None` if one of them doesn't
+/// convert.
+fn weighted_mean<T, I, F, C>(items: &[&I], convert: C) -> Option<([F; 3], F)>
+    where T: SpatialKey, I: Weighted<T>, F: Float, C: Fn(T) -> Option<F> {
+    let mut sum = [F::zero(); 3];
+    let mut total = F::zero();
+    for item in items.iter() {
+        let p = item.octree_index();
+        let w = match convert(item.weight()) {
+            Some(w) => w,
+            None => return None
+        };
+        for axis in 0..3 {
+            match convert(p[axis]) {
+                Some(x) => sum[axis] = sum[axis] + x * w,
+                None => return None
+            }
+        }
+        total = total + w;
+    }
+    Some(([sum[0] / total, sum[1] / total, sum[2] / total], total))
+}
+
+impl<T: SpatialKey, I: Weighted<T> + Clone, D: NodeData<T, I>> Octree<T, I, D> {
+    /// Returns the weighted centroid of the items inside `vol` along
+    /// with their total weight, or `None` if the total weight is zero.
+    pub fn weighted_centroid(&self, vol: &Volume<T>) -> Option<([T; 3], T)> {
+        let items = self.get_in_volume(vol);
+        // Sum up in f64 so that many items far from the origin don't
+        // drown each other's contributions, unless they don't convert
+        // to it.
+        let narrowed = match weighted_mean(&items, geom::wide) {
+            Some((c, total)) => match (geom::narrow(c[0]), geom::narrow(c[1]), geom::narrow(c[2]), geom::narrow(total)) {
+                (Some(x), Some(y), Some(z), Some(total)) => Some(([x, y, z], total)),
+                _ => None
+            },
+            None => None
+        };
+        let (centroid, total) = match narrowed.or_else(|| weighted_mean(&items, Some)) {
+            Some(mean) => mean,
+            None => return None
+        };
+        
+        let zero: T = NumCast::from(0).unwrap();
+        if total == zero {
+            return None;
+        }
+        Some((centroid, total))
+    }
+}
+
+impl<T: SpatialKey, I: Weighted<T> + Clone> Octree<T, I, Mass<T>> {
+    /// Calls `f` with the sources of force acting on `point` under the
+    /// Barnes-Hut approximation, for n-body simulations and force
+    /// directed layouts. A subtree whose size is less than `theta` times
+    /// its distance from `point` acts as a single body at the weighted
+    /// centroid of its items, while the items of closer ones act on
+    /// their own. A `theta` of zero yields every item, and greater ones
+    /// fewer and coarser sources, with `0.5` a common choice. Subtrees
+    /// around `point` are never taken as a whole.
+    pub fn for_each_force_source<F>(&self, point: [T; 3], theta: T, mut f: F)
+        where F: FnMut(ForceSource<T, I>)
+    {
+        self.force_sources(&point, theta, &mut f);
+    }
+
+    fn force_sources<'a, F>(&'a self, point: &[T; 3], theta: T, f: &mut F)
+        where F: FnMut(ForceSource<'a, T, I>)
+    {
+        if !self.volume.contains(point) {
+            match self.data.centroid() {
+                Some(centroid) => {
+                    let extent = [self.volume.max[0] - self.volume.min[0], self.volume.max[1] - self.volume.min[1],
+                                  self.volume.max[2] - self.volume.min[2]];
+                    let size = extent[0].max(extent[1]).max(extent[2]);
+                    if size * size < theta * theta * geom::distance2(point, &centroid) {
+                        f(ForceSource::Node(self.data.weight(), centroid));
+                        return;
+                    }
+                },
+                None => {}
+            }
+        }
+        
+        for item in self.items.iter() {
+            f(ForceSource::Item(item));
+        }
+        match self.octants {
+            Some(ref octants) => for (i, node) in octants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.force_sources(point, theta, f);
+                }
+            },
+            None => {}
+        }
+    }
+}
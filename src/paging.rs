@@ -0,0 +1,655 @@
+//! Trees larger than memory, stored as pages of a file.
+//!
+//! `octree::PagedBuilder` and `quadtree::PagedBuilder` build a tree
+//! from more items than fit in memory. The bounds of the tree are cut
+//! into the cells of the nodes at a given depth, and the items are
+//! sorted into the cells in memory until a budget of bytes is used up,
+//! then appended to a temporary file for each cell. Once all items are
+//! in, the subtree of each cell is built on its own and written out as
+//! a page, a snapshot of the subtree as written by `save_to`. Only the
+//! items of one cell are in memory at a time, so the depth should be
+//! chosen for the subtree of a cell to fit.
+//!
+//! `PagedOctree` and `PagedQuadtree` open such a paged snapshot and
+//! answer queries by loading only the pages of the cells they touch,
+//! when they first touch them. Loaded pages stay in memory for later
+//! queries until they're unloaded, such as when memory runs low, or
+//! until they're the least recently used ones once the loaded pages
+//! take more than a budget of bytes. `PageStats` count how often the
+//! queries found their pages loaded, to tune the budget.
+//!
+//! The builders can compress each page on its own, as chosen with
+//! `set_compression`, so the pages take less space on disk and loading
+//! a page decompresses only that page. LZ4 needs the `lz4` feature and
+//! Zstandard the `zstd` feature, neither of which is enabled by default.
+//! Pages that don't get any smaller, or whose compression isn't enabled,
+//! are stored as they are, and pages compressed with a codec that isn't
+//! enabled can't be loaded.
+//!
+//! A paged snapshot starts with `PAGED_MAGIC`, the `PAGED_VERSION` of
+//! the format and the number of dimensions of the tree, then the
+//! capacity, page depth and bounds of the tree. The pages follow, one
+//! for each cell holding items, then a table of the offsets, stored and
+//! uncompressed lengths, item counts and compression of the pages of
+//! all cells, with `x` varying fastest. The snapshot ends with the
+//! offsets of the first page and of the table.
+
+use SpatialError;
+use snapshot::{self, Snapshot};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The bytes every paged snapshot starts with.
+pub static PAGED_MAGIC: &[u8; 4] = b"SPPG";
+
+/// The version of the format written by the builders. Paged snapshots
+/// of other versions are refused by `open`.
+pub static PAGED_VERSION: u8 = 2;
+
+/// The deepest nodes whose cells can be made pages of.
+pub static MAX_PAGE_DEPTH: usize = 6;
+
+/// The most bytes a single byte of an LZ4 block can decompress into, as
+/// a match takes a byte of length for every 255 bytes it copies.
+static LZ4_MAX_RATIO: u64 = 255;
+
+/// The level pages are compressed with by Zstandard.
+#[cfg(feature = "zstd")]
+static ZSTD_LEVEL: i32 = 3;
+
+/// Numbers the spills of this process, to name their temporary directories.
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// How the pages of a paged snapshot are compressed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    /// Pages are stored as they are.
+    None,
+    /// Pages are compressed as blocks in the LZ4 block format, which is
+    /// fast to decompress.
+    Lz4,
+    /// Pages are compressed as Zstandard frames, which are smaller than
+    /// LZ4 blocks but slower to decompress.
+    Zstd
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Result<Compression, SpatialError> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            2 => Ok(Compression::Zstd),
+            _ => Err(SpatialError::Serialization(format!("unknown compression {}", byte)))
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2
+        }
+    }
+
+    /// Compresses `bytes`, or returns `None` if this codec isn't
+    /// enabled.
+    fn compress(self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some(::lz4_flex::block::compress(bytes)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => ::zstd::bulk::compress(bytes, ZSTD_LEVEL).ok(),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = bytes;
+                None
+            }
+        }
+    }
+
+    /// Decompresses `bytes` into `size` bytes. Returns
+    /// `SpatialError::Serialization` if they decompress into any other
+    /// length or this codec isn't enabled.
+    fn decompress(self, bytes: Vec<u8>, size: u64) -> Result<Vec<u8>, SpatialError> {
+        let out = match self {
+            Compression::None => Some(bytes),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => ::lz4_flex::block::decompress(&bytes, size as usize).ok(),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                // The size isn't trusted for more than where to stop,
+                // so the output grows with what the frame holds.
+                let mut out = Vec::new();
+                match ::zstd::stream::read::Decoder::new(&bytes[..]).and_then(|decoder| decoder.take(size + 1).read_to_end(&mut out)) {
+                    Ok(_) => Some(out),
+                    Err(_) => None
+                }
+            },
+            #[allow(unreachable_patterns)]
+            _ => return Err(SpatialError::Serialization(format!("{:?} compression isn't enabled", self)))
+        };
+        match out {
+            Some(out) if out.len() as u64 == size => Ok(out),
+            _ => Err(SpatialError::Serialization("page doesn't decompress into its length".to_string()))
+        }
+    }
+}
+
+/// Where a page lies in a paged snapshot.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PageEntry {
+    /// The offset of the page from the start of the snapshot.
+    pub offset: u64,
+    /// The length of the page in bytes as stored, `0` for cells without
+    /// items.
+    pub length: u64,
+    /// The length of the page in bytes once decompressed.
+    pub size: u64,
+    /// The number of items in the page.
+    pub items: u64,
+    /// How the page is compressed.
+    pub compression: Compression
+}
+
+/// Items sorted into pages, kept in memory up to a budget of bytes and
+/// appended to a temporary file for each page beyond it. The files are
+/// kept in a directory only the current user can access, created on the
+/// first spill and removed with the spill.
+pub struct Spill {
+    /// The snapshots of the items of each page not spilled yet.
+    buffers: HashMap<usize, Vec<u8>>,
+    /// The number of bytes in `buffers`.
+    buffered: usize,
+    /// The number of bytes to keep in memory.
+    budget: usize,
+    /// The number of items of each page.
+    counts: HashMap<usize, u64>,
+    /// The directory of the temporary files, once created.
+    dir: Option<PathBuf>,
+    /// The pages with a temporary file.
+    spilled: Vec<usize>
+}
+
+impl Spill {
+    /// Creates a spill keeping up to `budget` bytes of items in memory.
+    pub fn new(budget: usize) -> Spill {
+        Spill {
+            buffers: HashMap::new(),
+            buffered: 0,
+            budget: budget,
+            counts: HashMap::new(),
+            dir: None,
+            spilled: Vec::new()
+        }
+    }
+
+    /// Returns the number of items in `page`.
+    pub fn count(&self, page: usize) -> u64 {
+        self.counts.get(&page).cloned().unwrap_or(0)
+    }
+
+    /// Adds `item` to `page`, spilling all pages to their files once
+    /// the budget is used up. Returns `SpatialError::Io` if a file
+    /// can't be written.
+    pub fn push<I: Snapshot>(&mut self, page: usize, item: &I) -> Result<(), SpatialError> {
+        let buffer = self.buffers.entry(page).or_default();
+        let before = buffer.len();
+        item.write_snapshot(buffer);
+        self.buffered += buffer.len() - before;
+        *self.counts.entry(page).or_insert(0) += 1;
+        if self.buffered > self.budget {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    /// Takes the snapshots of the items of `page` out of memory and its
+    /// file, which is removed.
+    pub fn take(&mut self, page: usize) -> Result<Vec<u8>, SpatialError> {
+        let mut bytes = Vec::new();
+        if let Some(i) = self.spilled.iter().position(|&spilled| spilled == page) {
+            self.spilled.swap_remove(i);
+            let path = self.path(page);
+            let read = File::open(&path).and_then(|mut file| file.read_to_end(&mut bytes));
+            let removed = fs::remove_file(&path);
+            match read.and(removed) {
+                Ok(_) => {},
+                Err(err) => return Err(SpatialError::Io(err))
+            }
+        }
+        match self.buffers.remove(&page) {
+            Some(buffer) => {
+                self.buffered -= buffer.len();
+                bytes.extend_from_slice(&buffer);
+            },
+            None => {}
+        }
+        Ok(bytes)
+    }
+
+    /// Appends the buffered items of every page to its file, creating
+    /// the directory of the files first if needed.
+    fn flush(&mut self) -> Result<(), SpatialError> {
+        let dir = match self.dir {
+            Some(ref dir) => dir.clone(),
+            None => match create_spill_dir() {
+                Ok(dir) => dir,
+                Err(err) => return Err(SpatialError::Io(err))
+            }
+        };
+        self.dir = Some(dir.clone());
+        for (&page, buffer) in self.buffers.iter_mut() {
+            let path = dir.join(page.to_string());
+            // A page's file is created by its first spill, so a file
+            // that's already there wasn't written by this spill.
+            let first = !self.spilled.contains(&page);
+            let written = OpenOptions::new().write(true).create_new(first).append(!first).open(&path)
+                .and_then(|mut file| file.write_all(buffer));
+            match written {
+                Ok(()) => {},
+                Err(err) => return Err(SpatialError::Io(err))
+            }
+            if first {
+                self.spilled.push(page);
+            }
+            buffer.clear();
+        }
+        self.buffered = 0;
+        Ok(())
+    }
+
+    fn path(&self, page: usize) -> PathBuf {
+        match self.dir {
+            Some(ref dir) => dir.join(page.to_string()),
+            None => PathBuf::new()
+        }
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        for &page in self.spilled.iter() {
+            let _ = fs::remove_file(self.path(page));
+        }
+        if let Some(ref dir) = self.dir {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Creates a new directory for the files of a spill, which only the
+/// current user can access on Unix.
+fn create_spill_dir() -> io::Result<PathBuf> {
+    loop {
+        let name = format!("spatial-spill-{}-{}", process::id(), SPILLS.fetch_add(1, Ordering::SeqCst));
+        let dir = env::temp_dir().join(name);
+        let mut builder = fs::DirBuilder::new();
+        set_private(&mut builder);
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            // Left behind by an earlier process with the same id.
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+            Err(err) => return Err(err)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_private(builder: &mut fs::DirBuilder) {
+    use std::os::unix::fs::DirBuilderExt;
+    builder.mode(0o700);
+}
+
+#[cfg(not(unix))]
+fn set_private(_: &mut fs::DirBuilder) {}
+
+/// Appends the start of a paged snapshot of a tree of `dimensions` to
+/// `out`, up to the fields of the tree.
+pub fn write_paged_header(out: &mut Vec<u8>, dimensions: u8) {
+    out.extend(PAGED_MAGIC.iter().cloned());
+    out.push(PAGED_VERSION);
+    out.push(dimensions);
+}
+
+/// Writes a paged snapshot with `header` and the pages of `count`
+/// cells to `writer`, each compressed with `compression` unless that
+/// doesn't make it smaller. `page` is called for each cell in order,
+/// and returns the page and the number of its items, or `None` if the
+/// cell holds no items.
+pub fn write_paged<W, F>(writer: &mut W, header: &[u8], count: usize, compression: Compression, mut page: F)
+                         -> Result<(), SpatialError>
+    where W: Write, F: FnMut(usize) -> Result<Option<(Vec<u8>, u64)>, SpatialError>
+{
+    match writer.write_all(header) {
+        Ok(()) => {},
+        Err(err) => return Err(SpatialError::Io(err))
+    }
+    let first = header.len() as u64;
+    let mut offset = first;
+    let mut table = Vec::new();
+    count.write_snapshot(&mut table);
+    for i in 0..count {
+        let (bytes, items) = match page(i) {
+            Ok(Some(page)) => page,
+            Ok(None) => (Vec::new(), 0),
+            Err(err) => return Err(err)
+        };
+        let size = bytes.len() as u64;
+        let compressed = if bytes.is_empty() { None } else { compression.compress(&bytes) };
+        let (stored, used) = match compressed {
+            Some(compressed) if compressed.len() < bytes.len() => (compressed, compression),
+            _ => (bytes, Compression::None)
+        };
+        match writer.write_all(&stored) {
+            Ok(()) => {},
+            Err(err) => return Err(SpatialError::Io(err))
+        }
+        offset.write_snapshot(&mut table);
+        (stored.len() as u64).write_snapshot(&mut table);
+        size.write_snapshot(&mut table);
+        items.write_snapshot(&mut table);
+        table.push(used.to_byte());
+        offset += stored.len() as u64;
+    }
+    first.write_snapshot(&mut table);
+    offset.write_snapshot(&mut table);
+    writer.write_all(&table).map_err(SpatialError::Io)
+}
+
+/// A paged snapshot being read, with its table of pages.
+pub struct PagedFile<R> {
+    reader: R,
+    /// The pages of all cells.
+    pub table: Vec<PageEntry>
+}
+
+impl<R: Read + Seek> PagedFile<R> {
+    /// Opens the paged snapshot of a tree of `dimensions` in `reader`,
+    /// returning it along with the fields of the tree from its header.
+    /// Returns `SpatialError::Serialization` if it isn't a paged
+    /// snapshot in the current format, if a page lies outside of it, or
+    /// if a page is larger than its stored bytes could decompress into.
+    pub fn open(mut reader: R, dimensions: u8) -> Result<(PagedFile<R>, Vec<u8>), SpatialError> {
+        let mut trailer = [0; 16];
+        let read = reader.seek(SeekFrom::End(-16)).and_then(|end| {
+            reader.read_exact(&mut trailer).map(|()| end)
+        });
+        let end = match read {
+            Ok(end) => end,
+            Err(_) => return Err(SpatialError::Serialization("not a paged snapshot".to_string()))
+        };
+        let mut input = &trailer[..];
+        let first = match u64::read_snapshot(&mut input) {
+            Ok(first) => first,
+            Err(err) => return Err(err)
+        };
+        let table_offset = match u64::read_snapshot(&mut input) {
+            Ok(table_offset) => table_offset,
+            Err(err) => return Err(err)
+        };
+        if first > table_offset || table_offset > end {
+            return Err(SpatialError::Serialization("not a paged snapshot".to_string()));
+        }
+
+        let mut header = vec![0; first as usize];
+        match reader.seek(SeekFrom::Start(0)).and_then(|_| reader.read_exact(&mut header)) {
+            Ok(()) => {},
+            Err(err) => return Err(SpatialError::Io(err))
+        }
+        let mut input = &header[..];
+        let start = match snapshot::take(&mut input, PAGED_MAGIC.len() + 2) {
+            Ok(start) => start,
+            Err(_) => return Err(SpatialError::Serialization("not a paged snapshot".to_string()))
+        };
+        if start[..PAGED_MAGIC.len()] != PAGED_MAGIC[..] {
+            return Err(SpatialError::Serialization("not a paged snapshot".to_string()));
+        }
+        if start[PAGED_MAGIC.len()] != PAGED_VERSION {
+            return Err(SpatialError::Serialization(format!("unsupported snapshot version {}", start[PAGED_MAGIC.len()])));
+        }
+        if start[PAGED_MAGIC.len() + 1] != dimensions {
+            return Err(SpatialError::Serialization(format!("snapshot of a tree of {} dimensions, not {}",
+                                                           start[PAGED_MAGIC.len() + 1], dimensions)));
+        }
+        let fields = input.to_vec();
+
+        let mut bytes = vec![0; (end - table_offset) as usize];
+        match reader.seek(SeekFrom::Start(table_offset)).and_then(|_| reader.read_exact(&mut bytes)) {
+            Ok(()) => {},
+            Err(err) => return Err(SpatialError::Io(err))
+        }
+        let mut input = &bytes[..];
+        let count = match usize::read_snapshot(&mut input) {
+            Ok(count) => count,
+            Err(err) => return Err(err)
+        };
+        if count > input.len() / 33 {
+            return Err(SpatialError::Serialization("snapshot ends early".to_string()));
+        }
+        let mut table = Vec::with_capacity(count);
+        for _ in 0..count {
+            let entry = <(u64, (u64, (u64, (u64, u8))))>::read_snapshot(&mut input).and_then(|(offset, (length, (size, (items, compression))))| {
+                Compression::from_byte(compression).map(|compression| PageEntry {
+                    offset: offset,
+                    length: length,
+                    size: size,
+                    items: items,
+                    compression: compression
+                })
+            });
+            match entry {
+                Ok(entry) => {
+                    let inside = match entry.offset.checked_add(entry.length) {
+                        Some(end) => entry.offset >= first && end <= table_offset,
+                        None => false
+                    };
+                    if !inside {
+                        return Err(SpatialError::Serialization("page outside of the snapshot".to_string()));
+                    }
+                    // A page can't decompress into more than LZ4 packs
+                    // into its bytes, so a larger size isn't allocated
+                    // for. Zstandard packs far more, so the size of its
+                    // pages is only checked as they're decompressed.
+                    let limit = match entry.compression {
+                        Compression::None => entry.length,
+                        Compression::Lz4 => entry.length.saturating_mul(LZ4_MAX_RATIO),
+                        Compression::Zstd => u64::MAX
+                    };
+                    if entry.size > limit {
+                        return Err(SpatialError::Serialization("page larger than its compressed length allows".to_string()));
+                    }
+                    table.push(entry);
+                },
+                Err(err) => return Err(err)
+            }
+        }
+
+        Ok((PagedFile { reader: reader, table: table }, fields))
+    }
+
+    /// Reads the bytes of page `i`, decompressing them. Returns
+    /// `SpatialError::Serialization` if they don't decompress into the
+    /// length of the page.
+    pub fn read_page(&mut self, i: usize) -> Result<Vec<u8>, SpatialError> {
+        let entry = self.table[i];
+        let mut bytes = vec![0; entry.length as usize];
+        match self.reader.seek(SeekFrom::Start(entry.offset)).and_then(|_| self.reader.read_exact(&mut bytes)) {
+            Ok(()) => {},
+            Err(err) => return Err(SpatialError::Io(err))
+        }
+        entry.compression.decompress(bytes, entry.size)
+    }
+}
+
+/// Statistics of the pages touched by the queries of a paged tree,
+/// to tune its budget.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PageStats {
+    /// Number of times a query touched a loaded page.
+    pub hits: usize,
+    /// Number of times a query had to load a page.
+    pub misses: usize,
+    /// Number of pages unloaded to stay within the budget.
+    pub evictions: usize
+}
+
+/// A loaded page, with the size of its snapshot and when it was last
+/// touched.
+struct Resident<P> {
+    page: P,
+    size: usize,
+    used: u64
+}
+
+/// The pages of a paged snapshot loaded into memory. The sizes of their
+/// snapshots stand in for the memory they take, and once they add up to
+/// more than a budget, the least recently used pages are unloaded.
+pub struct PageCache<P> {
+    pages: Vec<Option<Resident<P>>>,
+    /// The number of loaded pages.
+    len: usize,
+    /// The sum of the sizes of the loaded pages.
+    bytes: usize,
+    /// The number of bytes the loaded pages may take.
+    budget: usize,
+    /// Counts the pages touched, to order them by their last use.
+    clock: u64,
+    stats: PageStats
+}
+
+impl<P> PageCache<P> {
+    /// Creates a cache for `count` pages, none of them loaded, without
+    /// a budget.
+    pub fn new(count: usize) -> PageCache<P> {
+        PageCache {
+            pages: (0..count).map(|_| None).collect(),
+            len: 0,
+            bytes: 0,
+            budget: usize::MAX,
+            clock: 0,
+            stats: PageStats::default()
+        }
+    }
+
+    /// Returns the number of loaded pages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no page is loaded.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the sum of the sizes of the loaded pages.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns the number of bytes the loaded pages may take.
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Sets the number of bytes the loaded pages may take, unloading
+    /// the least recently used ones beyond it.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(None);
+    }
+
+    /// Returns the statistics since the cache was created or they were
+    /// last reset.
+    #[inline]
+    pub fn stats(&self) -> PageStats {
+        self.stats
+    }
+
+    /// Resets the statistics to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = PageStats::default();
+    }
+
+    /// Returns page `i` if it's loaded.
+    pub fn get(&self, i: usize) -> Option<&P> {
+        self.pages[i].as_ref().map(|resident| &resident.page)
+    }
+
+    /// Marks page `i` as used by a query, counting a hit if it's loaded
+    /// and a miss otherwise. Returns `true` if it's loaded.
+    pub fn touch(&mut self, i: usize) -> bool {
+        self.clock += 1;
+        match self.pages[i] {
+            Some(ref mut resident) => {
+                resident.used = self.clock;
+                self.stats.hits += 1;
+                true
+            },
+            None => {
+                self.stats.misses += 1;
+                false
+            }
+        }
+    }
+
+    /// Keeps the loaded page `i` of `size` bytes as the most recently
+    /// used one, unloading the least recently used others beyond the
+    /// budget. The page is kept even if it alone is over the budget.
+    pub fn insert(&mut self, i: usize, page: P, size: usize) {
+        self.remove(i);
+        self.clock += 1;
+        self.pages[i] = Some(Resident { page: page, size: size, used: self.clock });
+        self.len += 1;
+        self.bytes += size;
+        self.evict(Some(i));
+    }
+
+    /// Drops page `i`, returning `true` if it was loaded.
+    pub fn remove(&mut self, i: usize) -> bool {
+        match self.pages[i].take() {
+            Some(resident) => {
+                self.len -= 1;
+                self.bytes -= resident.size;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Drops all pages.
+    pub fn clear(&mut self) {
+        for page in self.pages.iter_mut() {
+            *page = None;
+        }
+        self.len = 0;
+        self.bytes = 0;
+    }
+
+    /// Unloads the least recently used pages but `keep` until the rest
+    /// fit in the budget.
+    fn evict(&mut self, keep: Option<usize>) {
+        while self.bytes > self.budget {
+            let oldest = self.pages.iter().enumerate()
+                .filter(|&(i, _)| Some(i) != keep)
+                .filter_map(|(i, page)| page.as_ref().map(|resident| (resident.used, i)))
+                .min();
+            match oldest {
+                Some((_, i)) => {
+                    self.remove(i);
+                    self.stats.evictions += 1;
+                },
+                None => return
+            }
+        }
+    }
+}
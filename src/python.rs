@@ -0,0 +1,308 @@
+//! Python classes for the trees, enabled with the `python` feature.
+//!
+//! `register` adds the `Quadtree` and `Octree` classes to a Python
+//! module, and the crate in the `python` directory builds the extension
+//! module that calls it with maturin. The classes index the rows of
+//! numpy arrays of points, of shape `(n, 2)` for the quadtree and
+//! `(n, 3)` for the octree, and queries return the row indices of the
+//! points found as numpy arrays, so that results map straight back to
+//! the data the points came from.
+//!
+//! Coordinates are converted to `f64`, so anything numpy can turn into
+//! an array of floats, such as a list or an array of integers, is
+//! accepted as well.
+
+use SpatialError;
+use quadtree;
+use octree;
+use quadtree::Quadtree;
+use octree::Octree;
+use numpy::{AllowTypeChange, IntoPyArray, PyArray1, PyArray2, PyArrayLike1, PyArrayLike2};
+use numpy::ndarray::Array2;
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyIOError, PyValueError};
+
+/// The largest capacity the constructors accept, as a node reserves room
+/// for that many points up front.
+static MAX_CAPACITY: usize = 1 << 16;
+
+#[derive(Clone)]
+struct Row2 {
+    position: [f64; 2],
+    row: usize
+}
+
+impl quadtree::Index<f64> for Row2 {
+    fn quadtree_index(&self) -> [f64; 2] {
+        self.position
+    }
+}
+
+#[derive(Clone)]
+struct Row3 {
+    position: [f64; 3],
+    row: usize
+}
+
+impl octree::Index<f64> for Row3 {
+    fn octree_index(&self) -> [f64; 3] {
+        self.position
+    }
+}
+
+impl From<SpatialError> for PyErr {
+    fn from(err: SpatialError) -> PyErr {
+        match err {
+            SpatialError::Io(err) => PyIOError::new_err(err.to_string()),
+            err => PyValueError::new_err(err.to_string())
+        }
+    }
+}
+
+/// Returns the `dimensions` coordinates of the one-dimensional array
+/// `point`.
+fn coordinates(point: &PyArrayLike1<f64, AllowTypeChange>, dimensions: usize) -> PyResult<Vec<f64>> {
+    let point = point.as_array();
+    if point.len() != dimensions {
+        return Err(PyValueError::new_err(format!("expected {} coordinates, got {}", dimensions, point.len())));
+    }
+    Ok(point.to_vec())
+}
+
+/// Returns an error unless `points` is an array of `dimensions` columns.
+fn check_columns(points: &PyArrayLike2<f64, AllowTypeChange>, dimensions: usize) -> PyResult<()> {
+    let columns = points.as_array().ncols();
+    if columns != dimensions {
+        return Err(PyValueError::new_err(format!("expected {} columns, got {}", dimensions, columns)));
+    }
+    Ok(())
+}
+
+/// A quadtree over the rows of `(n, 2)` arrays of points.
+#[pyclass(name = "Quadtree")]
+pub struct PyQuadtree {
+    tree: Quadtree<f64, Row2>,
+    /// The number of rows passed to `insert` so far.
+    rows: usize
+}
+
+#[pymethods]
+impl PyQuadtree {
+    /// Creates an empty tree spanning from `min` to `max`, whose nodes
+    /// hold up to `capacity` points before subdividing. Raises
+    /// `ValueError` if `capacity` is `0` or above 65536.
+    #[new]
+    #[pyo3(signature = (min, max, capacity = 8))]
+    fn new(min: PyArrayLike1<f64, AllowTypeChange>, max: PyArrayLike1<f64, AllowTypeChange>,
+           capacity: usize) -> PyResult<PyQuadtree> {
+        let (min, max) = match (coordinates(&min, 2), coordinates(&max, 2)) {
+            (Ok(min), Ok(max)) => ([min[0], min[1]], [max[0], max[1]]),
+            (Err(err), _) | (_, Err(err)) => return Err(err)
+        };
+        let vol = quadtree::Volume::new(min, max);
+        if !vol.is_valid() {
+            return Err(PyValueError::new_err("min must not exceed max"));
+        }
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(PyValueError::new_err(format!("capacity must be between 1 and {}", MAX_CAPACITY)));
+        }
+        Ok(PyQuadtree { tree: Quadtree::with_capacity(vol, capacity), rows: 0 })
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Inserts the rows of `points`, an `(n, 2)` array. Rows are
+    /// numbered across calls, in the order they were passed, so the
+    /// rows of a second array follow those of the first.
+    ///
+    /// Nothing is inserted if a point lies outside of the tree. If a
+    /// point can't be stored, the points before it stay inserted.
+    fn insert(&mut self, points: PyArrayLike2<f64, AllowTypeChange>) -> PyResult<()> {
+        match check_columns(&points, 2) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        let points = points.as_array();
+        let bounds = self.tree.bounds();
+        if points.rows().into_iter().any(|p| !bounds.contains(&[p[0], p[1]])) {
+            return Err(SpatialError::OutOfBounds.into());
+        }
+
+        let first = self.rows;
+        self.rows += points.nrows();
+        for (i, p) in points.rows().into_iter().enumerate() {
+            match self.tree.insert(Row2 { position: [p[0], p[1]], row: first + i }) {
+                Ok(()) => {},
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the rows of the points within `radius` of `center`.
+    fn query_radius<'py>(&self, py: Python<'py>, center: PyArrayLike1<f64, AllowTypeChange>,
+                         radius: f64) -> PyResult<Bound<'py, PyArray1<usize>>> {
+        let center = match coordinates(&center, 2) {
+            Ok(center) => [center[0], center[1]],
+            Err(err) => return Err(err)
+        };
+        let rows = self.tree.get_in_radius(center, radius).iter().map(|item| item.row).collect();
+        Ok(PyArray1::from_vec_bound(py, rows))
+    }
+
+    /// Returns the rows of the points in the box from `min` to `max`.
+    fn query_box<'py>(&self, py: Python<'py>, min: PyArrayLike1<f64, AllowTypeChange>,
+                      max: PyArrayLike1<f64, AllowTypeChange>) -> PyResult<Bound<'py, PyArray1<usize>>> {
+        let vol = match (coordinates(&min, 2), coordinates(&max, 2)) {
+            (Ok(min), Ok(max)) => quadtree::Volume::new([min[0], min[1]], [max[0], max[1]]),
+            (Err(err), _) | (_, Err(err)) => return Err(err)
+        };
+        let rows = self.tree.get_in_volume(&vol).iter().map(|item| item.row).collect();
+        Ok(PyArray1::from_vec_bound(py, rows))
+    }
+
+    /// Returns the rows of the `k` points nearest to each row of
+    /// `points`, an `(m, 2)` array, as an `(m, k)` array ordered from
+    /// the nearest. There are fewer columns if the tree holds fewer
+    /// than `k` points.
+    fn nearest<'py>(&self, py: Python<'py>, points: PyArrayLike2<f64, AllowTypeChange>,
+                    k: usize) -> PyResult<Bound<'py, PyArray2<usize>>> {
+        match check_columns(&points, 2) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        let points = points.as_array();
+        let k = k.min(self.tree.len());
+        let mut rows = Vec::with_capacity(points.nrows() * k);
+        for p in points.rows() {
+            let p = [p[0], p[1]];
+            if !p.iter().all(|x| x.is_finite()) {
+                return Err(PyValueError::new_err("coordinates must be finite"));
+            }
+            rows.extend(self.tree.nearest_n(&p, k).iter().map(|item| item.row));
+        }
+        let rows = Array2::from_shape_vec((points.nrows(), k), rows).unwrap();
+        Ok(rows.into_pyarray_bound(py))
+    }
+}
+
+/// An octree over the rows of `(n, 3)` arrays of points.
+#[pyclass(name = "Octree")]
+pub struct PyOctree {
+    tree: Octree<f64, Row3>,
+    /// The number of rows passed to `insert` so far.
+    rows: usize
+}
+
+#[pymethods]
+impl PyOctree {
+    /// Creates an empty tree spanning from `min` to `max`, whose nodes
+    /// hold up to `capacity` points before subdividing. Raises
+    /// `ValueError` if `capacity` is `0` or above 65536.
+    #[new]
+    #[pyo3(signature = (min, max, capacity = 8))]
+    fn new(min: PyArrayLike1<f64, AllowTypeChange>, max: PyArrayLike1<f64, AllowTypeChange>,
+           capacity: usize) -> PyResult<PyOctree> {
+        let (min, max) = match (coordinates(&min, 3), coordinates(&max, 3)) {
+            (Ok(min), Ok(max)) => ([min[0], min[1], min[2]], [max[0], max[1], max[2]]),
+            (Err(err), _) | (_, Err(err)) => return Err(err)
+        };
+        let vol = octree::Volume::new(min, max);
+        if !vol.is_valid() {
+            return Err(PyValueError::new_err("min must not exceed max"));
+        }
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(PyValueError::new_err(format!("capacity must be between 1 and {}", MAX_CAPACITY)));
+        }
+        Ok(PyOctree { tree: Octree::with_capacity(vol, capacity), rows: 0 })
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Inserts the rows of `points`, an `(n, 3)` array. Rows are
+    /// numbered across calls, in the order they were passed, so the
+    /// rows of a second array follow those of the first.
+    ///
+    /// Nothing is inserted if a point lies outside of the tree. If a
+    /// point can't be stored, the points before it stay inserted.
+    fn insert(&mut self, points: PyArrayLike2<f64, AllowTypeChange>) -> PyResult<()> {
+        match check_columns(&points, 3) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        let points = points.as_array();
+        let bounds = self.tree.bounds();
+        if points.rows().into_iter().any(|p| !bounds.contains(&[p[0], p[1], p[2]])) {
+            return Err(SpatialError::OutOfBounds.into());
+        }
+
+        let first = self.rows;
+        self.rows += points.nrows();
+        for (i, p) in points.rows().into_iter().enumerate() {
+            match self.tree.insert(Row3 { position: [p[0], p[1], p[2]], row: first + i }) {
+                Ok(()) => {},
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the rows of the points within `radius` of `center`.
+    fn query_radius<'py>(&self, py: Python<'py>, center: PyArrayLike1<f64, AllowTypeChange>,
+                         radius: f64) -> PyResult<Bound<'py, PyArray1<usize>>> {
+        let center = match coordinates(&center, 3) {
+            Ok(center) => [center[0], center[1], center[2]],
+            Err(err) => return Err(err)
+        };
+        let rows = self.tree.get_in_radius(center, radius).iter().map(|item| item.row).collect();
+        Ok(PyArray1::from_vec_bound(py, rows))
+    }
+
+    /// Returns the rows of the points in the box from `min` to `max`.
+    fn query_box<'py>(&self, py: Python<'py>, min: PyArrayLike1<f64, AllowTypeChange>,
+                      max: PyArrayLike1<f64, AllowTypeChange>) -> PyResult<Bound<'py, PyArray1<usize>>> {
+        let vol = match (coordinates(&min, 3), coordinates(&max, 3)) {
+            (Ok(min), Ok(max)) => octree::Volume::new([min[0], min[1], min[2]], [max[0], max[1], max[2]]),
+            (Err(err), _) | (_, Err(err)) => return Err(err)
+        };
+        let rows = self.tree.get_in_volume(&vol).iter().map(|item| item.row).collect();
+        Ok(PyArray1::from_vec_bound(py, rows))
+    }
+
+    /// Returns the rows of the `k` points nearest to each row of
+    /// `points`, an `(m, 3)` array, as an `(m, k)` array ordered from
+    /// the nearest. There are fewer columns if the tree holds fewer
+    /// than `k` points.
+    fn nearest<'py>(&self, py: Python<'py>, points: PyArrayLike2<f64, AllowTypeChange>,
+                    k: usize) -> PyResult<Bound<'py, PyArray2<usize>>> {
+        match check_columns(&points, 3) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        let points = points.as_array();
+        let k = k.min(self.tree.len());
+        let mut rows = Vec::with_capacity(points.nrows() * k);
+        for p in points.rows() {
+            let p = [p[0], p[1], p[2]];
+            if !p.iter().all(|x| x.is_finite()) {
+                return Err(PyValueError::new_err("coordinates must be finite"));
+            }
+            rows.extend(self.tree.nearest_n(&p, k).iter().map(|item| item.row));
+        }
+        let rows = Array2::from_shape_vec((points.nrows(), k), rows).unwrap();
+        Ok(rows.into_pyarray_bound(py))
+    }
+}
+
+/// Adds the `Quadtree` and `Octree` classes to `module`.
+pub fn register(module: &Bound<PyModule>) -> PyResult<()> {
+    match module.add_class::<PyQuadtree>() {
+        Ok(()) => {},
+        Err(err) => return Err(err)
+    }
+    module.add_class::<PyOctree>()
+}
@@ -0,0 +1,205 @@
+use SpatialKey;
+use SpatialError;
+use geom;
+use rkyv::{self, Archive, Archived, Serialize, AlignedVec};
+use rkyv::ser::serializers::AllocSerializer;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::CheckBytes;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// A node of a `FlatQuadtree`.
+#[derive(Archive, Serialize, Clone, Copy, Debug, PartialEq)]
+#[archive(check_bytes)]
+pub struct FlatNode<T> {
+    /// The minimum corner of the node.
+    pub min: [T; 2],
+    /// The maximum corner of the node.
+    pub max: [T; 2],
+    /// Index of the first of the four consecutive quadrants of the
+    /// node, or `u32::MAX` if the node is a leaf.
+    pub first_child: u32,
+    /// Index of the first item of the node.
+    pub first_item: u32,
+    /// The number of items stored in the node itself.
+    pub item_count: u32
+}
+
+/// A `Quadtree` flattened into arrays for archiving with `rkyv`, as
+/// returned by `Quadtree::to_flat`. Its archived form,
+/// `ArchivedFlatQuadtree`, answers queries in place, so a tree archived
+/// with `Quadtree::to_archive` can be memory-mapped and queried at
+/// once, without deserializing it first.
+#[derive(Archive, Serialize)]
+#[archive(check_bytes)]
+pub struct FlatQuadtree<T, P> {
+    /// The nodes, with the root first.
+    pub nodes: Vec<FlatNode<T>>,
+    /// The positions of the items, in the order of `items`.
+    pub positions: Vec<[T; 2]>,
+    /// The items, in depth-first order.
+    pub items: Vec<P>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Flattens the tree into a `FlatQuadtree` of the same layout as
+    /// `to_gpu_buffers`, cloning its items. The node data isn't kept.
+    pub fn to_flat(&self) -> FlatQuadtree<T, P> {
+        let mut flat = FlatQuadtree { nodes: Vec::new(), positions: Vec::new(), items: Vec::new() };
+        flat.nodes.push(self.flat_node());
+        self.fill_flat(0, &mut flat);
+        flat
+    }
+
+    /// Archives the tree with `rkyv`, as flattened by `to_flat`. The
+    /// bytes can be written to a file and read back with
+    /// `FlatQuadtree::archived`. Returns `SpatialError::Serialization`
+    /// if an item can't be archived.
+    pub fn to_archive(&self) -> Result<AlignedVec, SpatialError>
+        where FlatQuadtree<T, P>: Serialize<AllocSerializer<256>>
+    {
+        rkyv::to_bytes::<_, 256>(&self.to_flat()).map_err(|err| SpatialError::Serialization(err.to_string()))
+    }
+
+    /// Returns the node without its children or items filled in.
+    fn flat_node(&self) -> FlatNode<T> {
+        FlatNode {
+            min: self.volume.min,
+            max: self.volume.max,
+            first_child: u32::MAX,
+            first_item: 0,
+            item_count: 0
+        }
+    }
+
+    /// Fills in the children and items of this node, stored at `index`.
+    fn fill_flat(&self, index: usize, flat: &mut FlatQuadtree<T, P>) {
+        flat.nodes[index].first_item = flat.items.len() as u32;
+        flat.nodes[index].item_count = self.items.len() as u32;
+        for item in self.items.iter() {
+            flat.positions.push(item.quadtree_index());
+            flat.items.push(item.clone());
+        }
+
+        match self.quadrants {
+            Some(ref quadrants) => {
+                let first = flat.nodes.len();
+                flat.nodes[index].first_child = first as u32;
+                for node in quadrants.iter() {
+                    flat.nodes.push(node.flat_node());
+                }
+                for (i, node) in quadrants.iter().enumerate() {
+                    node.fill_flat(first + i, flat);
+                }
+            },
+            None => {}
+        }
+    }
+}
+
+impl<T, P> FlatQuadtree<T, P>
+    where T: SpatialKey + Archive<Archived=T>, P: Archive
+{
+    /// Returns the archived tree at the end of `bytes`, as written by
+    /// `Quadtree::to_archive`, without copying or deserializing it.
+    /// `bytes` must be aligned to 16 bytes, as a memory-mapped file is.
+    ///
+    /// Returns `SpatialError::Serialization` if the bytes aren't a valid
+    /// archive, or if its nodes don't make up a tree, such as when a
+    /// node refers to items or quadrants beyond the end of the archive,
+    /// or to quadrants another node already has.
+    pub fn archived<'a>(bytes: &'a [u8]) -> Result<&'a ArchivedFlatQuadtree<T, P>, SpatialError>
+        where ArchivedFlatQuadtree<T, P>: CheckBytes<DefaultValidator<'a>>
+    {
+        let tree = match rkyv::check_archived_root::<FlatQuadtree<T, P>>(bytes) {
+            Ok(tree) => tree,
+            Err(err) => return Err(SpatialError::Serialization(err.to_string()))
+        };
+        if tree.nodes.is_empty() {
+            return Err(SpatialError::Serialization("archive without nodes".to_string()));
+        }
+        if tree.positions.len() != tree.items.len() {
+            return Err(SpatialError::Serialization("positions and items differ in number".to_string()));
+        }
+        // The nodes below the root come in blocks of 4 quadrants, each of
+        // which must be claimed by exactly one node before it, so that
+        // the nodes make up a tree rather than a graph a query would
+        // visit some nodes of more than once.
+        if (tree.nodes.len() - 1) % 4 != 0 {
+            return Err(SpatialError::Serialization("nodes don't come in blocks of quadrants".to_string()));
+        }
+        let mut claimed = vec![false; (tree.nodes.len() - 1) / 4];
+        for (i, node) in tree.nodes.iter().enumerate() {
+            if node.first_child != u32::MAX {
+                let first_child = node.first_child as usize;
+                if first_child <= i || (first_child - 1) % 4 != 0 || claimed.get((first_child - 1) / 4) != Some(&false) {
+                    return Err(SpatialError::Serialization(format!("node {} has invalid quadrants", i)));
+                }
+                claimed[(first_child - 1) / 4] = true;
+            }
+            if node.first_item as usize + node.item_count as usize > tree.items.len() {
+                return Err(SpatialError::Serialization(format!("node {} has invalid items", i)));
+            }
+        }
+        if claimed.contains(&false) {
+            return Err(SpatialError::Serialization("nodes outside of the tree".to_string()));
+        }
+        Ok(tree)
+    }
+}
+
+impl<T, P> ArchivedFlatQuadtree<T, P>
+    where T: SpatialKey + Archive<Archived=T>, P: Archive
+{
+    /// Returns the volume the tree covers.
+    pub fn bounds(&self) -> Volume<T> {
+        Volume::new(self.nodes[0].min, self.nodes[0].max)
+    }
+
+    /// Returns the number of items in the tree.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns all archived items inside the volume `vol`.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&Archived<P>> {
+        self.query(|min, max| geom::aabb_intersects_aabb(min, max, &vol.min, &vol.max),
+                   |p| vol.contains(p))
+    }
+
+    /// Returns all archived items within `radius` of `center`.
+    pub fn get_in_radius(&self, center: [T; 2], radius: T) -> Vec<&Archived<P>> {
+        self.query(|min, max| geom::sphere_intersects_aabb(&center, radius, min, max),
+                   |p| geom::point_in_sphere(p, &center, radius))
+    }
+
+    /// Returns the items at positions accepted by `item`, in the nodes
+    /// whose volumes are accepted by `node`.
+    fn query<N, I>(&self, node: N, item: I) -> Vec<&Archived<P>>
+        where N: Fn(&[T; 2], &[T; 2]) -> bool, I: Fn(&[T; 2]) -> bool
+    {
+        let mut results = Vec::new();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let current = &self.nodes[index];
+            if !node(&current.min, &current.max) {
+                continue;
+            }
+            let first = current.first_item as usize;
+            for i in first..first + current.item_count as usize {
+                if item(&self.positions[i]) {
+                    results.push(&self.items[i]);
+                }
+            }
+            if current.first_child != u32::MAX {
+                let first_child = current.first_child as usize;
+                stack.extend((first_child..first_child + 4).rev());
+            }
+        }
+        results
+    }
+}
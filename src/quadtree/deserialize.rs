@@ -0,0 +1,107 @@
+use SpatialKey;
+use SpatialError;
+use ItemOrder;
+use std::convert::TryFrom;
+use super::{Quadtree, NodeData, Index, Volume, MAX_DEPTH};
+
+/// The fields of a serialized `Quadtree` node, read as they are and
+/// only turned into a tree once they've been checked to make up a
+/// valid one.
+#[derive(Deserialize)]
+#[serde(rename = "Quadtree")]
+pub struct QuadtreeData<T: SpatialKey, P, D> {
+    capacity: usize,
+    items: Vec<P>,
+    count: usize,
+    volume: Volume<T>,
+    quadrants: Option<[Box<QuadtreeData<T, P, D>>; 4]>,
+    occupied: u8,
+    data: D,
+    order: ItemOrder,
+    sequence: Vec<u64>,
+    next_sequence: u64
+}
+
+impl<T, P, D> QuadtreeData<T, P, D>
+    where T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P> {
+    /// Turns the data into a node and its descendants, unchecked.
+    fn into_node(self) -> Quadtree<T, P, D> {
+        Quadtree {
+            capacity: self.capacity,
+            items: self.items,
+            count: self.count,
+            volume: self.volume,
+            quadrants: self.quadrants.map(|quadrants| quadrants.map(|node| Box::new(node.into_node()))),
+            occupied: self.occupied,
+            data: self.data,
+            rebuild: None,
+            order: self.order,
+            sequence: self.sequence,
+            next_sequence: self.next_sequence,
+            changes: None
+        }
+    }
+}
+
+impl<T, P, D> TryFrom<QuadtreeData<T, P, D>> for Quadtree<T, P, D>
+    where T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P> {
+    type Error = SpatialError;
+
+    /// Restores a deserialized tree, or returns
+    /// `SpatialError::Serialization` if its nodes don't fit together,
+    /// with the same checks as `load_from`.
+    fn try_from(data: QuadtreeData<T, P, D>) -> Result<Quadtree<T, P, D>, SpatialError> {
+        let tree = data.into_node();
+        if !tree.volume.is_valid() {
+            return Err(SpatialError::Serialization("invalid volume".to_string()));
+        }
+        match tree.check_node(0, tree.capacity, tree.order) {
+            Ok(()) => Ok(tree),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl<T, P, D> Quadtree<T, P, D>
+    where T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P> {
+    /// Returns an error unless this node, found at `depth`, and its
+    /// descendants make up a valid subtree of a tree with `capacity`
+    /// whose items are kept in `order`, with the volumes, item counts
+    /// and occupancy its children would have been given by inserting.
+    fn check_node(&self, depth: usize, capacity: usize, order: ItemOrder) -> Result<(), SpatialError> {
+        if self.capacity != capacity || self.order != order {
+            return Err(SpatialError::Serialization("node unlike the root of its tree".to_string()));
+        }
+        match self.check_items() {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+
+        let (mut occupied, mut count) = (0, self.items.len());
+        match self.quadrants {
+            Some(ref quadrants) => {
+                if depth >= MAX_DEPTH {
+                    return Err(SpatialError::Serialization("node deeper than the depth limit".to_string()));
+                }
+                for ((i, node), vol) in quadrants.iter().enumerate().zip(self.quadrant_volumes().iter()) {
+                    if node.volume.min != vol.min || node.volume.max != vol.max {
+                        return Err(SpatialError::Serialization("node volume doesn't match its parent".to_string()));
+                    }
+                    match node.check_node(depth + 1, capacity, order) {
+                        Ok(()) => {},
+                        Err(err) => return Err(err)
+                    }
+                    if !node.items.is_empty() || node.occupied != 0 {
+                        occupied |= 1 << i;
+                    }
+                    count += node.count;
+                }
+            },
+            None => {}
+        }
+        if self.occupied != occupied || self.count != count {
+            return Err(SpatialError::Serialization("item count of a node doesn't match its items".to_string()));
+        }
+        Ok(())
+    }
+}
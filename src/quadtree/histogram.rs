@@ -0,0 +1,115 @@
+use SpatialKey;
+use num::NumCast;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// Equal-width bins splitting a range of coordinates.
+struct Bins<T: SpatialKey> {
+    min: T,
+    width: T,
+    count: usize
+}
+
+impl<T: SpatialKey> Bins<T> {
+    /// Returns `count` bins splitting the extent of `vol` along `axis`.
+    fn new(vol: &Volume<T>, axis: usize, count: usize) -> Bins<T> {
+        Bins { min: vol.min[axis], width: vol.max[axis] - vol.min[axis], count: count }
+    }
+
+    /// Returns the bin of the coordinate `x`, which must be inside the
+    /// binned range. Every bin but the last is half-open.
+    fn of(&self, x: T) -> usize {
+        let zero: T = NumCast::from(0).unwrap();
+        if self.width <= zero {
+            return 0;
+        }
+        let count: T = NumCast::from(self.count).unwrap();
+        let bin = ((x - self.min) * count / self.width).floor().to_u64().unwrap_or(0) as usize;
+        bin.min(self.count - 1)
+    }
+}
+
+/// Returns the index of the cell of `p` in a grid split by `bins`.
+#[inline]
+fn cell_of<T: SpatialKey>(bins: &[Bins<T>; 2], p: &[T; 2]) -> usize {
+    bins[1].of(p[1]) * bins[0].count + bins[0].of(p[0])
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns the number of items inside `vol` in each of `bins`
+    /// equal-width bins splitting `vol` along `axis`, in order of
+    /// increasing coordinate. Subtrees lying entirely inside `vol` and
+    /// a single bin are counted whole from the number of items cached in
+    /// them, without testing their items.
+    ///
+    /// Panics if `axis` isn't `0` or `1`, or if `bins` is zero.
+    pub fn histogram_along_axis(&self, vol: &Volume<T>, axis: usize, bins: usize) -> Vec<usize> {
+        let _span = trace_span!("quadtree histogram", volume = %vol, axis = axis, bins = bins);
+        assert!(axis < 2);
+        assert!(bins > 0);
+        let grid = [Bins::new(vol, 0, if axis == 0 { bins } else { 1 }),
+                    Bins::new(vol, 1, if axis == 1 { bins } else { 1 })];
+        let counts = self.count_cells(vol, &grid);
+        trace_event!("quadtree histogram {}: {} items in {} bins", vol, counts.iter().sum::<usize>(),
+                     counts.len());
+        counts
+    }
+
+    /// Returns the number of items inside `vol` in each cell of a grid
+    /// of `nx` by `ny` equal cells covering `vol`, row by row, so that
+    /// the cell at column `x` and row `y` is at `y * nx + x`. Subtrees
+    /// lying entirely inside a single cell are counted whole from the
+    /// number of items cached in them, and only the items of the others
+    /// are assigned to cells one by one.
+    ///
+    /// Panics if `nx` or `ny` is zero.
+    pub fn rasterize_counts(&self, vol: &Volume<T>, nx: usize, ny: usize) -> Vec<usize> {
+        let _span = trace_span!("quadtree rasterize", volume = %vol);
+        assert!(nx > 0 && ny > 0);
+        let grid = [Bins::new(vol, 0, nx), Bins::new(vol, 1, ny)];
+        let counts = self.count_cells(vol, &grid);
+        trace_event!("quadtree rasterize {}: {} items in {}x{} cells", vol, counts.iter().sum::<usize>(),
+                     nx, ny);
+        counts
+    }
+
+    /// Returns the number of items inside `vol` in each cell of `grid`.
+    fn count_cells(&self, vol: &Volume<T>, grid: &[Bins<T>; 2]) -> Vec<usize> {
+        let mut counts = vec![0; grid[0].count * grid[1].count];
+        if vol.is_valid() {
+            self.count_node(vol, grid, &mut counts);
+        }
+        counts
+    }
+
+    /// Adds the items of this node and of its children that are inside
+    /// `vol` to `counts`.
+    fn count_node(&self, vol: &Volume<T>, grid: &[Bins<T>; 2], counts: &mut [usize]) {
+        if !self.volume.intersects(vol) {
+            return;
+        }
+        // Cells are monotonic in each coordinate, so the node lies in a
+        // single cell if both of its corners do.
+        if vol.contains(&self.volume.min) && vol.contains(&self.volume.max) {
+            let cell = cell_of(grid, &self.volume.min);
+            if cell == cell_of(grid, &self.volume.max) {
+                counts[cell] += self.count;
+                return;
+            }
+        }
+
+        for item in self.items.iter() {
+            let p = item.quadtree_index();
+            if vol.contains(&p) {
+                counts[cell_of(grid, &p)] += 1;
+            }
+        }
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.count_node(vol, grid, counts);
+                }
+            },
+            None => {}
+        }
+    }
+}
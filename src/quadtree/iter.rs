@@ -0,0 +1,218 @@
+use SpatialKey;
+use Visit;
+use std::slice;
+use std::vec;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// An iterator over the items of a `Quadtree`, created by `Quadtree::iter`.
+pub struct Iter<'a, T: SpatialKey + 'a, P: Index<T> + Clone + 'a, D: NodeData<T, P> + 'a = ()> {
+    /// Nodes still to be visited, the next one last.
+    stack: Vec<&'a Quadtree<T, P, D>>,
+    /// The remaining items of the current node.
+    items: slice::Iter<'a, P>
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Iterator for Iter<'a, T, P, D> {
+    type Item = &'a P;
+
+    fn next(&mut self) -> Option<&'a P> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            self.items = node.items.iter();
+            match node.quadrants {
+                Some(ref quadrants) => for child in quadrants.iter().rev() {
+                    self.stack.push(&**child);
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// A mutable iterator over the items of a `Quadtree`, created by
+/// `Quadtree::iter_mut`.
+pub struct IterMut<'a, T: SpatialKey + 'a, P: Index<T> + Clone + 'a, D: NodeData<T, P> + 'a = ()> {
+    stack: Vec<&'a mut Quadtree<T, P, D>>,
+    items: slice::IterMut<'a, P>
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Iterator for IterMut<'a, T, P, D> {
+    type Item = &'a mut P;
+
+    fn next(&mut self) -> Option<&'a mut P> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            let Quadtree { ref mut items, ref mut quadrants, .. } = *node;
+            self.items = items.iter_mut();
+            match *quadrants {
+                Some(ref mut quadrants) => for child in quadrants.iter_mut().rev() {
+                    self.stack.push(&mut **child);
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// A lazy iterator over the items of a `Quadtree` inside a volume,
+/// created by `Quadtree::query`.
+pub struct Query<'a, T: SpatialKey + 'a, P: Index<T> + Clone + 'a, D: NodeData<T, P> + 'a = ()> {
+    volume: Volume<T>,
+    stack: Vec<&'a Quadtree<T, P, D>>,
+    items: slice::Iter<'a, P>
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Iterator for Query<'a, T, P, D> {
+    type Item = &'a P;
+
+    fn next(&mut self) -> Option<&'a P> {
+        loop {
+            for item in self.items.by_ref() {
+                if self.volume.contains(&item.quadtree_index()) {
+                    return Some(item);
+                }
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => node,
+                None => return None
+            };
+            self.items = node.items.iter();
+            match node.quadrants {
+                Some(ref quadrants) => for (i, child) in quadrants.iter().enumerate().rev() {
+                    if node.occupied & (1 << i) != 0 && child.volume.intersects(&self.volume) {
+                        self.stack.push(&**child);
+                    }
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+/// An iterator moving the items out of a `Quadtree`, created by
+/// `into_iter`.
+pub struct IntoIter<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P> = ()> {
+    stack: Vec<Box<Quadtree<T, P, D>>>,
+    items: vec::IntoIter<P>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Iterator for IntoIter<T, P, D> {
+    type Item = P;
+
+    fn next(&mut self) -> Option<P> {
+        loop {
+            match self.items.next() {
+                Some(item) => return Some(item),
+                None => {}
+            }
+            
+            let node = match self.stack.pop() {
+                Some(node) => *node,
+                None => return None
+            };
+            let Quadtree { items, quadrants, .. } = node;
+            self.items = items.into_iter();
+            match quadrants {
+                Some(quadrants) => {
+                    let quadrants: Box<[Box<Quadtree<T, P, D>>]> = Box::new(quadrants);
+                    self.stack.extend(quadrants.into_vec().into_iter().rev());
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns an iterator over all items of the tree, in the same
+    /// depth-first order as `neighbor_lists`. Nodes are entered only as
+    /// the iteration reaches them.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T, P, D> {
+        Iter { stack: vec![self], items: [].iter() }
+    }
+
+    /// Returns an iterator over the items inside `vol`, like
+    /// `get_in_volume` but without collecting them. Nodes are entered
+    /// only as the iteration reaches them, and the only allocation is
+    /// the stack of nodes still to visit.
+    pub fn query<'a>(&'a self, vol: &Volume<T>) -> Query<'a, T, P, D> {
+        let stack = if self.volume.intersects(vol) { vec![self] } else { Vec::new() };
+        Query { volume: *vol, stack: stack, items: [].iter() }
+    }
+
+    /// Calls `f` with the volume and the items of every node, parents
+    /// before their children and children in order. The result of `f`
+    /// decides whether the children of the node are visited, and
+    /// whether to go on at all.
+    pub fn visit<F: FnMut(&Volume<T>, &[P]) -> Visit>(&self, mut f: F) {
+        self.visit_node(&mut f);
+    }
+
+    /// Visits this node and its children, returning `false` if the
+    /// traversal was stopped.
+    fn visit_node<F: FnMut(&Volume<T>, &[P]) -> Visit>(&self, f: &mut F) -> bool {
+        match f(&self.volume, &self.items) {
+            Visit::Stop => return false,
+            Visit::Skip => return true,
+            Visit::Continue => {}
+        }
+        
+        match self.quadrants {
+            Some(ref quadrants) => quadrants.iter().all(|node| node.visit_node(f)),
+            None => true
+        }
+    }
+
+    /// Returns an iterator over mutable references to all items of the
+    /// tree, in the same order as `iter`. The position of an item must
+    /// not be changed through the reference, as the item would no longer
+    /// be found in the right node.
+    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, T, P, D> {
+        self.rebuild = None;
+        IterMut { stack: vec![self], items: [].iter_mut() }
+    }
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> IntoIterator for Quadtree<T, P, D> {
+    type Item = P;
+    type IntoIter = IntoIter<T, P, D>;
+
+    fn into_iter(self) -> IntoIter<T, P, D> {
+        IntoIter { stack: vec![Box::new(self)], items: Vec::new().into_iter() }
+    }
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> IntoIterator for &'a Quadtree<T, P, D> {
+    type Item = &'a P;
+    type IntoIter = Iter<'a, T, P, D>;
+
+    fn into_iter(self) -> Iter<'a, T, P, D> {
+        self.iter()
+    }
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> IntoIterator for &'a mut Quadtree<T, P, D> {
+    type Item = &'a mut P;
+    type IntoIter = IterMut<'a, T, P, D>;
+
+    fn into_iter(self) -> IterMut<'a, T, P, D> {
+        self.iter_mut()
+    }
+}
@@ -0,0 +1,125 @@
+use SpatialKey;
+use SpatialError;
+use geom;
+use super::{Quadtree, Index, Volume, DEFAULT_CAPACITY};
+
+/// The number of layers of a `LayeredIndex`, one for each bit of a
+/// layer mask.
+static LAYERS: usize = 32;
+
+/// An index keeping a separate `Quadtree` for each of 32 layers, such
+/// as the collision categories of a physics engine. Queries take a
+/// mask of the layers to search, so items of other layers are never
+/// visited rather than filtered out one by one.
+pub struct LayeredIndex<T: SpatialKey, P: Index<T> + Clone> {
+    /// Bounding volume of every layer.
+    volume: Volume<T>,
+    /// Capacity of the nodes of the layer trees.
+    capacity: usize,
+    layers: Vec<Option<Quadtree<T, P>>>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> LayeredIndex<T, P> {
+    /// Creates an empty index whose layers cover the volume `vol`.
+    #[inline]
+    pub fn new(vol: Volume<T>) -> LayeredIndex<T, P> {
+        LayeredIndex::with_capacity(vol, DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty index whose layers cover the volume `vol` and
+    /// have nodes of `capacity`.
+    pub fn with_capacity(vol: Volume<T>, capacity: usize) -> LayeredIndex<T, P> {
+        LayeredIndex {
+            volume: vol,
+            capacity: capacity,
+            layers: (0..LAYERS).map(|_| None).collect()
+        }
+    }
+
+    /// Returns the number of items in the layers selected by `mask`.
+    pub fn len(&self, mask: u32) -> usize {
+        self.selected(mask).iter().map(|tree| tree.len()).sum()
+    }
+
+    /// Returns the tree of `layer`, if any item has been inserted into
+    /// it.
+    #[inline]
+    pub fn layer(&self, layer: usize) -> Option<&Quadtree<T, P>> {
+        self.layers.get(layer).and_then(|tree| tree.as_ref())
+    }
+
+    /// Inserts an `item` into `layer`. Returns an error if the item is
+    /// outside the volume of the index, like `Quadtree::insert`.
+    ///
+    /// Panics if `layer` is 32 or more.
+    pub fn insert(&mut self, item: P, layer: usize) -> Result<(), SpatialError> {
+        assert!(layer < LAYERS);
+        let (volume, capacity) = (self.volume, self.capacity);
+        if self.layers[layer].is_none() {
+            self.layers[layer] = Some(Quadtree::with_capacity(volume, capacity));
+        }
+        match self.layers[layer] {
+            Some(ref mut tree) => tree.insert(item),
+            None => Err(SpatialError::OutOfBounds)
+        }
+    }
+
+    /// Removes an item equal to `item` from `layer` and returns it, or
+    /// `None` if there is no such item.
+    pub fn remove(&mut self, item: &P, layer: usize) -> Option<P> where P: PartialEq {
+        match self.layers.get_mut(layer) {
+            Some(&mut Some(ref mut tree)) => tree.remove(item),
+            _ => None
+        }
+    }
+
+    /// Returns all items inside the volume `vol` in the layers selected
+    /// by `mask`, ordered by layer.
+    pub fn get_in_volume(&self, vol: &Volume<T>, mask: u32) -> Vec<&P> {
+        let mut items = Vec::new();
+        for tree in self.selected(mask) {
+            items.extend(tree.get_in_volume(vol));
+        }
+        items
+    }
+
+    /// Returns all items within `radius` of `center` in the layers
+    /// selected by `mask`, ordered by layer.
+    pub fn get_in_radius(&self, center: [T; 2], radius: T, mask: u32) -> Vec<&P> {
+        let mut items = Vec::new();
+        for tree in self.selected(mask) {
+            items.extend(tree.get_in_radius(center, radius));
+        }
+        items
+    }
+
+    /// Returns the item closest to `point` in the layers selected by
+    /// `mask`, or `None` if they're empty.
+    pub fn nearest<'a>(&'a self, point: &[T; 2], mask: u32) -> Option<&'a P> {
+        let mut nearest: Option<(T, &'a P)> = None;
+        for tree in self.selected(mask) {
+            match tree.nearest(point) {
+                Some(item) => {
+                    let d2 = geom::distance2(point, &item.quadtree_index());
+                    let closer = match nearest {
+                        Some((best, _)) => d2 < best,
+                        None => true
+                    };
+                    if closer {
+                        nearest = Some((d2, item));
+                    }
+                },
+                None => {}
+            }
+        }
+        nearest.map(|(_, item)| item)
+    }
+
+    /// Returns the trees of the layers selected by `mask`.
+    fn selected(&self, mask: u32) -> Vec<&Quadtree<T, P>> {
+        self.layers.iter().enumerate()
+            .filter(|&(i, _)| mask & (1 << i) != 0)
+            .filter_map(|(_, tree)| tree.as_ref())
+            .collect()
+    }
+}
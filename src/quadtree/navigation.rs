@@ -0,0 +1,382 @@
+use SpatialKey;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use num::NumCast;
+use super::{Quadtree, NodeData, Index, NodeRef, Volume};
+
+/// A navigation mesh covering the free space of a `Quadtree`.
+pub struct NavMesh<T: SpatialKey> {
+    /// The walkable rectangles of the mesh.
+    pub polygons: Vec<Volume<T>>,
+    /// The edges connecting neighboring polygons.
+    pub portals: Vec<Portal<T>>
+}
+
+/// A shared edge between two polygons of a `NavMesh`. Each portal is
+/// listed once; swap `left` and `right` when crossing it from `to` to
+/// `from`.
+pub struct Portal<T: SpatialKey> {
+    /// Index of the polygon the portal is crossed from.
+    pub from: usize,
+    /// Index of the polygon the portal leads to.
+    pub to: usize,
+    /// The endpoint lying counter-clockwise from `right`, as seen from
+    /// the center of `from`.
+    pub left: [T; 2],
+    /// The other endpoint.
+    pub right: [T; 2]
+}
+
+/// Directions toward a goal for every free leaf of a `Quadtree`.
+pub struct FlowField<T: SpatialKey> {
+    /// The volumes of the free leaves.
+    pub cells: Vec<Volume<T>>,
+    /// For each cell, the unit direction to move in to reach the goal,
+    /// or `None` if the goal can't be reached from the cell.
+    pub directions: Vec<Option<[T; 2]>>,
+    /// For each cell, the distance to the goal along the field.
+    pub costs: Vec<Option<T>>
+}
+
+impl<T: SpatialKey> FlowField<T> {
+    /// Returns the direction toward the goal at point `p`, or `None`
+    /// if `p` is outside the field or the goal can't be reached from
+    /// it.
+    pub fn direction(&self, p: &[T; 2]) -> Option<[T; 2]> {
+        match self.cells.iter().position(|cell| cell.contains(p)) {
+            Some(i) => self.directions[i],
+            None => None
+        }
+    }
+}
+
+/// A leaf waiting to be expanded by a search, ordered so that the
+/// cheapest one is popped first from a `BinaryHeap`.
+struct Candidate<T: SpatialKey> {
+    cost: T,
+    leaf: usize
+}
+
+impl<T: SpatialKey> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Candidate<T>) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl<T: SpatialKey> Eq for Candidate<T> {}
+
+impl<T: SpatialKey> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Candidate<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey> Ord for Candidate<T> {
+    fn cmp(&self, other: &Candidate<T>) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Returns the union of `a` and `b` if they share a whole edge.
+fn merge<T: SpatialKey>(a: &Volume<T>, b: &Volume<T>) -> Option<Volume<T>> {
+    for axis in 0..2 {
+        let other = 1 - axis;
+        if a.min[other] == b.min[other] && a.max[other] == b.max[other] &&
+            (a.max[axis] == b.min[axis] || b.max[axis] == a.min[axis]) {
+            let mut min = a.min;
+            let mut max = a.max;
+            min[axis] = a.min[axis].min(b.min[axis]);
+            max[axis] = a.max[axis].max(b.max[axis]);
+            return Some(Volume::new(min, max));
+        }
+    }
+    None
+}
+
+/// Returns the portal between `polygons[from]` and `polygons[to]` if
+/// they share an edge of non-zero length.
+fn portal<T: SpatialKey>(polygons: &[Volume<T>], from: usize, to: usize) -> Option<Portal<T>> {
+    let (a, b) = (&polygons[from], &polygons[to]);
+    if !super::edge_adjacent(a, b) {
+        return None;
+    }
+    
+    let axis = if a.max[0] == b.min[0] || a.min[0] == b.max[0] { 0 } else { 1 };
+    let other = 1 - axis;
+    let shared = if a.max[axis] == b.min[axis] { a.max[axis] } else { a.min[axis] };
+    
+    let mut p1 = [shared, shared];
+    let mut p2 = [shared, shared];
+    p1[other] = a.min[other].max(b.min[other]);
+    p2[other] = a.max[other].min(b.max[other]);
+    
+    let c = a.center();
+    let cross = (p1[0] - c[0]) * (p2[1] - c[1]) - (p1[1] - c[1]) * (p2[0] - c[0]);
+    let (left, right) = if cross > NumCast::from(0).unwrap() { (p2, p1) } else { (p1, p2) };
+    
+    Some(Portal { from: from, to: to, left: left, right: right })
+}
+
+/// Returns the unit vector pointing from `from` to `to`, or a zero
+/// vector if the points coincide.
+fn direction<T: SpatialKey>(from: [T; 2], to: [T; 2]) -> [T; 2] {
+    let d = [to[0] - from[0], to[1] - from[1]];
+    let len = d[0].hypot(d[1]);
+    if len > NumCast::from(0).unwrap() {
+        [d[0] / len, d[1] / len]
+    } else {
+        [NumCast::from(0).unwrap(), NumCast::from(0).unwrap()]
+    }
+}
+
+/// Returns the euclidean distance between `a` and `b`.
+fn distance<T: SpatialKey>(a: [T; 2], b: [T; 2]) -> T {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Finds a path from `start` to `goal` through the leaves of the
+    /// tree that contain no items, using A*. Returns the corridor of
+    /// leaves leading from the leaf containing `start` to the leaf
+    /// containing `goal`, or `None` if either point is outside free
+    /// space or the two are not connected.
+    pub fn pathfind<'a>(&'a self, start: [T; 2], goal: [T; 2]) -> Option<Vec<NodeRef<'a, T, P, D>>> {
+        let leaves = self.free_leaves();
+        let lookup: HashMap<Vec<usize>, usize> = leaves.iter().enumerate()
+            .map(|(i, leaf)| (leaf.path.clone(), i))
+            .collect();
+        
+        let find = |p: &[T; 2]| leaves.iter().position(|leaf| leaf.node.volume.contains(p));
+        let (from, to) = match (find(&start), find(&goal)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return None
+        };
+        
+        let target = leaves[to].node.volume.center();
+        let mut costs: Vec<Option<T>> = leaves.iter().map(|_| None).collect();
+        let mut previous: Vec<Option<usize>> = leaves.iter().map(|_| None).collect();
+        let mut closed: Vec<bool> = leaves.iter().map(|_| false).collect();
+        let mut open = BinaryHeap::new();
+        
+        costs[from] = Some(NumCast::from(0).unwrap());
+        open.push(Candidate { cost: distance(leaves[from].node.volume.center(), target), leaf: from });
+        
+        while let Some(Candidate { leaf, .. }) = open.pop() {
+            if leaf == to {
+                let mut corridor = vec![leaf];
+                while let Some(prev) = previous[*corridor.last().unwrap()] {
+                    corridor.push(prev);
+                }
+                return Some(corridor.iter().rev().map(|&i| {
+                    NodeRef { path: leaves[i].path.clone(), node: leaves[i].node }
+                }).collect());
+            }
+            if closed[leaf] {
+                continue;
+            }
+            closed[leaf] = true;
+            
+            let center = leaves[leaf].node.volume.center();
+            let cost = costs[leaf].unwrap();
+            
+            for neighbor in self.leaf_neighbors(&leaves[leaf].path).iter() {
+                let next = match lookup.get(&neighbor.path) {
+                    Some(&next) if !closed[next] => next,
+                    _ => continue
+                };
+                
+                let next_center = neighbor.node.volume.center();
+                let next_cost = cost + distance(center, next_center);
+                let shorter = match costs[next] {
+                    Some(c) => next_cost < c,
+                    None => true
+                };
+                if shorter {
+                    costs[next] = Some(next_cost);
+                    previous[next] = Some(leaf);
+                    open.push(Candidate { cost: next_cost + distance(next_center, target), leaf: next });
+                }
+            }
+        }
+        
+        None
+    }
+    
+    /// Builds a navigation mesh out of the free leaves of the tree.
+    /// Neighboring leaves are greedily merged into larger rectangles
+    /// wherever they share a whole edge, and a portal is emitted for
+    /// every pair of rectangles sharing part of an edge.
+    pub fn navmesh(&self) -> NavMesh<T> {
+        let mut polygons: Vec<Volume<T>> = self.free_leaves().iter()
+            .map(|leaf| leaf.node.volume)
+            .collect();
+        
+        let mut merged = true;
+        while merged {
+            merged = false;
+            let mut i = 0;
+            while i < polygons.len() {
+                let mut j = i + 1;
+                while j < polygons.len() {
+                    match merge(&polygons[i], &polygons[j]) {
+                        Some(union) => {
+                            polygons[i] = union;
+                            polygons.swap_remove(j);
+                            merged = true;
+                            j = i + 1;
+                        },
+                        None => j += 1
+                    }
+                }
+                i += 1;
+            }
+        }
+        
+        let mut portals = Vec::new();
+        for i in 0..polygons.len() {
+            for j in i + 1..polygons.len() {
+                match portal(&polygons, i, j) {
+                    Some(portal) => portals.push(portal),
+                    None => {}
+                }
+            }
+        }
+        
+        NavMesh { polygons: polygons, portals: portals }
+    }
+    
+    /// Computes a flow field leading every free leaf of the tree to
+    /// `goal`, by running Dijkstra's algorithm outward from the leaf
+    /// containing the goal. Each cell points toward the center of the
+    /// next cell on its shortest path, and the goal cell points at the
+    /// goal itself.
+    pub fn flow_field(&self, goal: [T; 2]) -> FlowField<T> {
+        let leaves = self.free_leaves();
+        let lookup: HashMap<Vec<usize>, usize> = leaves.iter().enumerate()
+            .map(|(i, leaf)| (leaf.path.clone(), i))
+            .collect();
+        
+        let mut costs: Vec<Option<T>> = leaves.iter().map(|_| None).collect();
+        let mut directions: Vec<Option<[T; 2]>> = leaves.iter().map(|_| None).collect();
+        let mut closed: Vec<bool> = leaves.iter().map(|_| false).collect();
+        let mut open = BinaryHeap::new();
+        
+        match leaves.iter().position(|leaf| leaf.node.volume.contains(&goal)) {
+            Some(target) => {
+                costs[target] = Some(NumCast::from(0).unwrap());
+                directions[target] = Some(direction(leaves[target].node.volume.center(), goal));
+                open.push(Candidate { cost: NumCast::from(0).unwrap(), leaf: target });
+            },
+            None => {}
+        }
+        
+        while let Some(Candidate { leaf, .. }) = open.pop() {
+            if closed[leaf] {
+                continue;
+            }
+            closed[leaf] = true;
+            
+            let center = leaves[leaf].node.volume.center();
+            let cost = costs[leaf].unwrap();
+            
+            for neighbor in self.leaf_neighbors(&leaves[leaf].path).iter() {
+                let next = match lookup.get(&neighbor.path) {
+                    Some(&next) if !closed[next] => next,
+                    _ => continue
+                };
+                
+                let next_center = neighbor.node.volume.center();
+                let next_cost = cost + distance(center, next_center);
+                let shorter = match costs[next] {
+                    Some(c) => next_cost < c,
+                    None => true
+                };
+                if shorter {
+                    costs[next] = Some(next_cost);
+                    directions[next] = Some(direction(next_center, center));
+                    open.push(Candidate { cost: next_cost, leaf: next });
+                }
+            }
+        }
+        
+        FlowField {
+            cells: leaves.iter().map(|leaf| leaf.node.volume).collect(),
+            directions: directions,
+            costs: costs
+        }
+    }
+    
+    /// Spreads per-leaf values to neighboring leaves, as used for
+    /// influence maps. Every leaf starts with the value `seed` returns
+    /// for it. In each of the `iterations`, a leaf moves the fraction
+    /// `rate` of the way from its value to the average of its
+    /// edge-adjacent neighbors. Returns the final value of every leaf,
+    /// keyed by its path.
+    pub fn diffuse<F>(&self, seed: F, iterations: usize, rate: T) -> HashMap<Vec<usize>, T>
+        where F: FnMut(&NodeRef<T, P, D>) -> T {
+        let mut leaves = Vec::new();
+        self.collect_leaves(&mut Vec::new(), &mut leaves);
+        let lookup: HashMap<Vec<usize>, usize> = leaves.iter().enumerate()
+            .map(|(i, leaf)| (leaf.path.clone(), i))
+            .collect();
+        let adjacency: Vec<Vec<usize>> = leaves.iter()
+            .map(|leaf| self.leaf_neighbors(&leaf.path).iter().map(|neighbor| lookup[&neighbor.path]).collect())
+            .collect();
+        
+        let mut values: Vec<T> = leaves.iter().map(seed).collect();
+        for _ in 0..iterations {
+            values = adjacency.iter().enumerate().map(|(i, neighbors)| {
+                if neighbors.is_empty() {
+                    return values[i];
+                }
+                let count: T = NumCast::from(neighbors.len()).unwrap();
+                let sum = neighbors.iter().fold(NumCast::from(0).unwrap(), |sum: T, &j| sum + values[j]);
+                values[i] + (sum / count - values[i]) * rate
+            }).collect();
+        }
+        
+        leaves.into_iter().map(|leaf| leaf.path).zip(values).collect()
+    }
+    
+    /// Pushes every leaf below this node, found at `path`, into `out`.
+    fn collect_leaves<'a>(&'a self, path: &mut Vec<usize>, out: &mut Vec<NodeRef<'a, T, P, D>>) {
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                path.push(i);
+                node.collect_leaves(path, out);
+                path.pop();
+            },
+            None => out.push(NodeRef { path: path.clone(), node: self })
+        }
+    }
+    
+    /// Returns every leaf of the tree whose volume contains no items,
+    /// including items stored in the leaf's ancestors.
+    fn free_leaves<'a>(&'a self) -> Vec<NodeRef<'a, T, P, D>> {
+        let mut leaves = Vec::new();
+        self.collect_free_leaves(&[], &mut Vec::new(), &mut leaves);
+        leaves
+    }
+    
+    fn collect_free_leaves<'a>(&'a self, inherited: &[[T; 2]], path: &mut Vec<usize>,
+                               out: &mut Vec<NodeRef<'a, T, P, D>>) {
+        let mut positions: Vec<[T; 2]> = inherited.iter()
+            .filter(|p| self.volume.contains(p))
+            .cloned()
+            .collect();
+        for item in self.items.iter() {
+            positions.push(item.quadtree_index());
+        }
+        
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                path.push(i);
+                node.collect_free_leaves(&positions, path, out);
+                path.pop();
+            },
+            None => if positions.is_empty() {
+                out.push(NodeRef { path: path.clone(), node: self });
+            }
+        }
+    }
+}
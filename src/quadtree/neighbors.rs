@@ -0,0 +1,137 @@
+use SpatialKey;
+use geom;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// A node of a flattened `Quadtree`, with its items numbered in
+/// depth-first order.
+struct Flat<T: SpatialKey> {
+    volume: Volume<T>,
+    items: Vec<(u32, [T; 2])>,
+    children: Vec<usize>
+}
+
+/// Links `i` and `j` as neighbors if `p` and `q` are within range.
+#[inline]
+fn link<T: SpatialKey>(i: u32, p: [T; 2], j: u32, q: [T; 2], radius: T, lists: &mut [Vec<u32>]) {
+    if geom::point_in_sphere(&p, &q, radius) {
+        lists[i as usize].push(j);
+        lists[j as usize].push(i);
+    }
+}
+
+/// Finds all neighboring pairs within the subtree rooted at `n`.
+fn within<T: SpatialKey>(nodes: &[Flat<T>], n: usize, radius: T, lists: &mut [Vec<u32>]) {
+    let node = &nodes[n];
+    
+    for (a, &(i, p)) in node.items.iter().enumerate() {
+        for &(j, q) in node.items[a + 1..].iter() {
+            link(i, p, j, q, radius, lists);
+        }
+    }
+    
+    for &c in node.children.iter() {
+        against(nodes, &node.items, c, radius, lists);
+    }
+    
+    for (a, &c) in node.children.iter().enumerate() {
+        within(nodes, c, radius, lists);
+        for &d in node.children[a + 1..].iter() {
+            between(nodes, c, d, radius, lists);
+        }
+    }
+}
+
+/// Finds all neighboring pairs with one item in the subtree rooted at
+/// `a` and the other in the disjoint subtree rooted at `b`.
+fn between<T: SpatialKey>(nodes: &[Flat<T>], a: usize, b: usize, radius: T, lists: &mut [Vec<u32>]) {
+    let (na, nb) = (&nodes[a], &nodes[b]);
+    if !geom::aabb_near_aabb(&na.volume.min, &na.volume.max, &nb.volume.min, &nb.volume.max, radius) {
+        return;
+    }
+    
+    for &(i, p) in na.items.iter() {
+        for &(j, q) in nb.items.iter() {
+            link(i, p, j, q, radius, lists);
+        }
+    }
+    
+    for &c in nb.children.iter() {
+        against(nodes, &na.items, c, radius, lists);
+    }
+    for &c in na.children.iter() {
+        against(nodes, &nb.items, c, radius, lists);
+    }
+    
+    for &c in na.children.iter() {
+        for &d in nb.children.iter() {
+            between(nodes, c, d, radius, lists);
+        }
+    }
+}
+
+/// Finds all neighboring pairs between `items` and the items in the
+/// subtree rooted at `n`.
+fn against<T: SpatialKey>(nodes: &[Flat<T>], items: &[(u32, [T; 2])], n: usize, radius: T,
+                          lists: &mut [Vec<u32>]) {
+    let node = &nodes[n];
+    if items.iter().all(|&(_, p)| !geom::sphere_intersects_aabb(&p, radius, &node.volume.min, &node.volume.max)) {
+        return;
+    }
+    
+    for &(i, p) in items.iter() {
+        for &(j, q) in node.items.iter() {
+            link(i, p, j, q, radius, lists);
+        }
+    }
+    
+    for &c in node.children.iter() {
+        against(nodes, items, c, radius, lists);
+    }
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns, for every item in the tree, the indices of all other
+    /// items within `radius` of it, in ascending order. Items are
+    /// numbered in the order a depth-first walk visits them: a node's
+    /// own items first, followed by the items of its quadrants in
+    /// order.
+    ///
+    /// All lists are computed in a single simultaneous traversal of
+    /// pairs of nodes, skipping pairs of nodes that are farther than
+    /// `radius` apart.
+    pub fn neighbor_lists(&self, radius: T) -> Vec<Vec<u32>> {
+        let mut nodes = Vec::new();
+        let mut count = 0;
+        self.flatten(&mut nodes, &mut count);
+        
+        let mut lists: Vec<Vec<u32>> = (0..count).map(|_| Vec::new()).collect();
+        within(&nodes, 0, radius, &mut lists);
+        
+        for list in lists.iter_mut() {
+            list.sort();
+        }
+        lists
+    }
+    
+    /// Pushes this node and its descendants into `nodes`, numbering
+    /// their items starting from `count`. Returns the index of this
+    /// node.
+    fn flatten(&self, nodes: &mut Vec<Flat<T>>, count: &mut u32) -> usize {
+        let index = nodes.len();
+        let items = self.items.iter().map(|item| {
+            let i = *count;
+            *count += 1;
+            (i, item.quadtree_index())
+        }).collect();
+        nodes.push(Flat { volume: self.volume, items: items, children: Vec::new() });
+        
+        match self.quadrants {
+            Some(ref quadrants) => for node in quadrants.iter() {
+                let child = node.flatten(nodes, count);
+                nodes[index].children.push(child);
+            },
+            None => {}
+        }
+        index
+    }
+}
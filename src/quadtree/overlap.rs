@@ -0,0 +1,154 @@
+use SpatialKey;
+use geom;
+use std::collections::HashMap;
+use super::{Quadtree, NodeData, Index};
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns every pair of an item of this tree and an item of
+    /// `other` whose positions are within `tolerance` of each other,
+    /// such as detected objects and the ground truth they match.
+    ///
+    /// Both trees are traversed simultaneously, skipping pairs of nodes
+    /// that are farther than `tolerance` apart, so the trees don't need
+    /// to share their volume or capacity.
+    pub fn overlaps_with<'a, 'b, Q, E>(&'a self, other: &'b Quadtree<T, Q, E>, tolerance: T) -> Vec<(&'a P, &'b Q)>
+        where Q: Index<T> + Clone, E: NodeData<T, Q> {
+        let mut pairs = Vec::new();
+        self.overlap(other, tolerance, &mut pairs);
+        pairs
+    }
+
+    /// Returns the groups of items whose positions coincide within
+    /// `eps`, such as points recorded twice. Items within `eps` of each
+    /// other end up in the same group, and so do chains of them. Only
+    /// groups of more than one item are returned, each in the same
+    /// depth-first order as `neighbor_lists`.
+    ///
+    /// The items of each node are compared with each other, and with
+    /// only those of other nodes that are within `eps` of their own.
+    pub fn find_duplicates(&self, eps: T) -> Vec<Vec<&P>> {
+        let mut pairs = Vec::new();
+        self.self_overlap(eps, &mut pairs);
+        
+        let items: Vec<&P> = self.iter().collect();
+        let indices: HashMap<*const P, usize> = items.iter().enumerate()
+            .map(|(i, &item)| (item as *const P, i))
+            .collect();
+        
+        // Join the pairs into groups, with every group pointing to its
+        // first item.
+        let mut parents: Vec<usize> = (0..items.len()).collect();
+        for &(a, b) in pairs.iter() {
+            let a = root(&mut parents, indices[&(a as *const P)]);
+            let b = root(&mut parents, indices[&(b as *const P)]);
+            parents[a.max(b)] = a.min(b);
+        }
+        
+        let mut groups: Vec<Vec<&P>> = Vec::new();
+        let mut group_of = HashMap::new();
+        for i in 0..items.len() {
+            let r = root(&mut parents, i);
+            if r == i {
+                continue;
+            }
+            let group = *group_of.entry(r).or_insert_with(|| {
+                groups.push(vec![items[r]]);
+                groups.len() - 1
+            });
+            groups[group].push(items[i]);
+        }
+        groups
+    }
+
+    /// Collects the pairs of items of this subtree within the tolerance
+    /// of each other.
+    fn self_overlap<'a>(&'a self, tolerance: T, pairs: &mut Vec<(&'a P, &'a P)>) {
+        for (i, item) in self.items.iter().enumerate() {
+            let p = item.quadtree_index();
+            for other in self.items[i + 1..].iter() {
+                if geom::point_in_sphere(&p, &other.quadtree_index(), tolerance) {
+                    pairs.push((item, other));
+                }
+            }
+        }
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                for item in self.items.iter() {
+                    node.overlap_item(&item.quadtree_index(), tolerance, &mut |found| pairs.push((item, found)));
+                }
+                for other_node in quadrants[i + 1..].iter() {
+                    node.overlap(other_node, tolerance, pairs);
+                }
+                node.self_overlap(tolerance, pairs);
+            },
+            None => {}
+        }
+    }
+
+    /// Collects the pairs with one item in this subtree and the other in
+    /// the subtree `other`.
+    fn overlap<'a, 'b, Q, E>(&'a self, other: &'b Quadtree<T, Q, E>, tolerance: T, pairs: &mut Vec<(&'a P, &'b Q)>)
+        where Q: Index<T> + Clone, E: NodeData<T, Q> {
+        if !geom::aabb_near_aabb(&self.volume.min, &self.volume.max, &other.volume.min, &other.volume.max, tolerance) {
+            return;
+        }
+
+        // The items of this node against the whole of `other`, the items
+        // of `other` against the rest of this subtree, and the children
+        // against each other.
+        for item in self.items.iter() {
+            other.overlap_item(&item.quadtree_index(), tolerance, &mut |found| pairs.push((item, found)));
+        }
+        match self.quadrants {
+            Some(ref quadrants) => for node in quadrants.iter() {
+                for item in other.items.iter() {
+                    node.overlap_item(&item.quadtree_index(), tolerance, &mut |found| pairs.push((found, item)));
+                }
+            },
+            None => {}
+        }
+        match (&self.quadrants, &other.quadrants) {
+            (Some(ours), Some(theirs)) => for node in ours.iter() {
+                for other_node in theirs.iter() {
+                    node.overlap(other_node, tolerance, pairs);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Passes every item of this subtree within the tolerance of `p` to
+    /// `f`.
+    fn overlap_item<'a, F: FnMut(&'a P)>(&'a self, p: &[T; 2], tolerance: T, f: &mut F) {
+        if !geom::sphere_intersects_aabb(p, tolerance, &self.volume.min, &self.volume.max) {
+            return;
+        }
+        for own in self.items.iter() {
+            if geom::point_in_sphere(p, &own.quadtree_index(), tolerance) {
+                f(own);
+            }
+        }
+        match self.quadrants {
+            Some(ref quadrants) => for node in quadrants.iter() {
+                node.overlap_item(p, tolerance, f);
+            },
+            None => {}
+        }
+    }
+}
+
+/// Returns the first item of the group of item `i`, pointing the items
+/// on the way straight to it.
+fn root(parents: &mut [usize], i: usize) -> usize {
+    let mut r = i;
+    while parents[r] != r {
+        r = parents[r];
+    }
+    let mut j = i;
+    while parents[j] != r {
+        let next = parents[j];
+        parents[j] = r;
+        j = next;
+    }
+    r
+}
@@ -0,0 +1,96 @@
+use SpatialKey;
+use num::traits::Float;
+use geom;
+use std::cmp::Ordering;
+use super::{Quadtree, NodeData, Index, Ray};
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns the items of all nodes that `ray` passes through, paired
+    /// with the distance along the ray at which it enters their node and
+    /// ordered by it. Children are visited front to back, and those the
+    /// ray misses are never entered.
+    pub fn raycast<'a>(&'a self, ray: &Ray<T>) -> Vec<(T, &'a P)> {
+        self.raycast_filtered(ray, Float::infinity(), !0)
+    }
+
+    /// Returns the items of the nodes that `ray` enters within distance
+    /// `t_max` along it, like `raycast`, keeping only those sharing a
+    /// layer bit with `mask`. Nodes entered beyond `t_max` are skipped
+    /// entirely.
+    pub fn raycast_filtered<'a>(&'a self, ray: &Ray<T>, t_max: T, mask: u32) -> Vec<(T, &'a P)> {
+        let mut hits = Vec::new();
+        match self.volume.intersect_ray(ray) {
+            Some((entry, _)) if entry <= t_max => self.raycast_node(ray, entry, t_max, mask, &mut hits),
+            _ => {}
+        }
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    /// Returns all items at most `radius` away from the line segment
+    /// from `a` to `b`, such as the path of a bullet or a line of sight.
+    /// Quadrants the segment doesn't come within `radius` of are never
+    /// entered.
+    pub fn get_along_segment<'a>(&'a self, a: &[T; 2], b: &[T; 2], radius: T) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        self.segment_node(a, b, radius, &mut items);
+        items
+    }
+
+    /// Collects the items of this node, entered at `entry`, and of the
+    /// children hit by `ray` within `t_max`, that share a layer bit with
+    /// `mask`.
+    fn raycast_node<'a>(&'a self, ray: &Ray<T>, entry: T, t_max: T, mask: u32, hits: &mut Vec<(T, &'a P)>) {
+        for item in self.items.iter() {
+            if item.quadtree_layers() & mask != 0 {
+                hits.push((entry, item));
+            }
+        }
+
+        match self.quadrants {
+            Some(ref quadrants) => {
+                let mut children: Vec<(T, &Quadtree<T, P, D>)> = Vec::new();
+                for (i, node) in quadrants.iter().enumerate() {
+                    if self.occupied & (1 << i) == 0 {
+                        continue;
+                    }
+                    match node.volume.intersect_ray(ray) {
+                        Some((entry, _)) if entry <= t_max => children.push((entry, &**node)),
+                        _ => {}
+                    }
+                }
+                children.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+                for &(entry, node) in children.iter() {
+                    node.raycast_node(ray, entry, t_max, mask, hits);
+                }
+            },
+            None => {}
+        }
+    }
+
+    /// Collects the items of this node and its children that are within
+    /// `radius` of the segment, if the segment touches the node grown by
+    /// `radius`.
+    fn segment_node<'a>(&'a self, a: &[T; 2], b: &[T; 2], radius: T, items: &mut Vec<&'a P>) {
+        let min = [self.volume.min[0] - radius, self.volume.min[1] - radius];
+        let max = [self.volume.max[0] + radius, self.volume.max[1] + radius];
+        if !geom::segment_intersects_aabb(a, b, &min, &max) {
+            return;
+        }
+
+        for item in self.items.iter() {
+            if geom::point_near_segment(&item.quadtree_index(), a, b, radius) {
+                items.push(item);
+            }
+        }
+
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.segment_node(a, b, radius, items);
+                }
+            },
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use SpatialKey;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// A trait for items whose position can be moved, for `Quadtree::rebase`.
+pub trait Translate<T: SpatialKey>: Index<T> {
+    /// Moves `self` by `offset`, in order of `[x, y]`.
+    fn translate(&mut self, offset: [T; 2]);
+}
+
+impl<T: SpatialKey, P: Translate<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Moves the origin of the coordinate system to `new_origin`,
+    /// translating every item and the volume of every node in a single
+    /// pass without restructuring the tree. This keeps coordinates
+    /// small in floating-origin schemes, where the world is shifted back
+    /// whenever the camera strays too far from the origin.
+    ///
+    /// A rebuild in progress is discarded, and the user data of the
+    /// nodes is left as is.
+    pub fn rebase(&mut self, new_origin: [T; 2]) {
+        self.rebuild = None;
+        self.translate_node(&[-new_origin[0], -new_origin[1]]);
+        trace_event!("quadtree rebase: root now covers {}", self.volume);
+    }
+
+    /// Translates the volume and the items of this subtree by `offset`.
+    fn translate_node(&mut self, offset: &[T; 2]) {
+        // Every coordinate is shifted by the same amount, so items stay
+        // inside the volumes of their nodes despite rounding.
+        self.volume = Volume::new([self.volume.min[0] + offset[0], self.volume.min[1] + offset[1]],
+                                  [self.volume.max[0] + offset[0], self.volume.max[1] + offset[1]]);
+        for item in self.items.iter_mut() {
+            match self.changes {
+                Some(ref mut changes) => {
+                    changes.remove(item);
+                    item.translate(*offset);
+                    changes.add(item);
+                },
+                None => item.translate(*offset)
+            }
+        }
+        match self.quadrants {
+            Some(ref mut quadrants) => for node in quadrants.iter_mut() {
+                node.translate_node(offset);
+            },
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use SpatialKey;
+use SpatialError;
+use ItemOrder;
+use super::{Quadtree, NodeData, Index};
+
+/// The state of a rebuild in progress: the replacement tree, and the
+/// nodes of the current tree whose items haven't been copied to it yet.
+pub struct Rebuild<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> {
+    tree: Quadtree<T, P, D>,
+    /// Paths from the root to the nodes left to copy.
+    pending: Vec<Vec<usize>>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Advances a rebuild of the tree by copying the items of up to
+    /// `budget_nodes` of its nodes into a fresh tree, starting the
+    /// rebuild if none is in progress. Once every node has been copied,
+    /// the tree is replaced by the rebuilt one, which drops subdivisions
+    /// left behind by removals, and `Ok(true)` is returned. If an item
+    /// can't be copied, the rebuild is discarded, the tree is left as it
+    /// was and the error is returned.
+    ///
+    /// The tree stays fully usable in between calls, but any change to
+    /// its items discards the rebuild in progress, which then starts
+    /// over on the next call. The user data of the rebuilt nodes is
+    /// created anew, as if the items had just been inserted.
+    pub fn rebuild_incremental(&mut self, budget_nodes: usize) -> Result<bool, SpatialError> {
+        let _span = trace_span!("quadtree rebuild", budget = budget_nodes);
+        let mut rebuild = match self.rebuild.take() {
+            Some(rebuild) => rebuild,
+            None => Box::new(Rebuild {
+                tree: Quadtree::empty(self.volume, self.capacity, self.order),
+                pending: vec![Vec::new()]
+            })
+        };
+
+        for _ in 0..budget_nodes {
+            let path = match rebuild.pending.pop() {
+                Some(path) => path,
+                None => break
+            };
+            let node = self.node_at(&path).unwrap();
+            for (i, item) in node.items.iter().enumerate() {
+                let sequence = if node.order == ItemOrder::Insertion { node.sequence[i] } else { 0 };
+                match rebuild.tree.insert_at(item.clone(), sequence, &mut Vec::new(), &mut ()) {
+                    Ok(()) => {},
+                    Err(err) => return Err(err)
+                }
+            }
+            for i in 0..4 {
+                if node.occupied & (1 << i) != 0 {
+                    let mut child = path.clone();
+                    child.push(i);
+                    rebuild.pending.push(child);
+                }
+            }
+        }
+
+        if !rebuild.pending.is_empty() {
+            trace_event!("quadtree rebuild: {} nodes pending", rebuild.pending.len());
+            self.rebuild = Some(rebuild);
+            return Ok(false);
+        }
+        trace_event!("quadtree rebuild: swapping in {} nodes", rebuild.tree.node_count());
+        let mut tree = rebuild.tree;
+        tree.next_sequence = self.next_sequence;
+        if self.changes.is_some() {
+            // Every item is taken out of its old node and stored in its
+            // new one.
+            self.clear();
+            tree.changes = self.changes.take();
+            tree.track();
+        }
+        *self = tree;
+        Ok(true)
+    }
+
+    /// Returns `true` if a rebuild started by `rebuild_incremental` is
+    /// in progress.
+    #[inline]
+    pub fn is_rebuilding(&self) -> bool {
+        self.rebuild.is_some()
+    }
+}
@@ -0,0 +1,457 @@
+use SpatialKey;
+use num::NumCast;
+use std::collections::{HashMap, HashSet};
+
+/// A node of a `RegionQuadtree`.
+#[derive(Clone)]
+enum Node<T: SpatialKey> {
+    /// A square of uniform value.
+    Leaf(T),
+    /// Four quadrants, in the same order as those of a `Quadtree`.
+    Split(Box<[Node<T>; 4]>)
+}
+
+/// A quadtree partitioning a raster, such as an image or an occupancy
+/// grid, into squares of uniform value. The root covers the smallest
+/// power-of-two square enclosing the raster, with the raster anchored
+/// at its upper-left corner.
+#[derive(Clone)]
+pub struct RegionQuadtree<T: SpatialKey> {
+    width: usize,
+    height: usize,
+    /// Side length of the root, in pixels.
+    size: usize,
+    root: Node<T>
+}
+
+/// A raster being encoded, with the threshold its squares are merged
+/// by.
+struct Raster<'a, T: 'a> {
+    pixels: &'a [T],
+    width: usize,
+    height: usize,
+    threshold: T
+}
+
+/// Builds the node covering the `size` by `size` square at `x`, `y`.
+fn encode_node<T: SpatialKey>(raster: &Raster<T>, x: usize, y: usize, size: usize) -> Node<T> {
+    let (pixels, width, height, threshold) = (raster.pixels, raster.width, raster.height, raster.threshold);
+    let zero: T = NumCast::from(0).unwrap();
+    let (mut min, mut max, mut sum, mut count) = (zero, zero, zero, 0);
+    for py in y..height.min(y + size) {
+        for px in x..width.min(x + size) {
+            let value = pixels[py * width + px];
+            min = if count == 0 { value } else { min.min(value) };
+            max = if count == 0 { value } else { max.max(value) };
+            sum = sum + value;
+            count += 1;
+        }
+    }
+    
+    // Squares entirely outside the raster are never read back.
+    if count == 0 {
+        return Node::Leaf(zero);
+    }
+    if size == 1 || max - min <= threshold {
+        let n: T = NumCast::from(count).unwrap();
+        return Node::Leaf(sum / n);
+    }
+    
+    let half = size / 2;
+    let quadrant = |i: usize| encode_node(raster, x + (i & 1) * half, y + (i >> 1) * half, half);
+    Node::Split(Box::new([quadrant(0), quadrant(1), quadrant(2), quadrant(3)]))
+}
+
+/// Combines two nodes covering the same square with `op`, treating
+/// non-zero values as set.
+fn combine<T: SpatialKey>(a: &Node<T>, b: &Node<T>, op: fn(bool, bool) -> bool) -> Node<T> {
+    let zero: T = NumCast::from(0).unwrap();
+    let children = match (a, b) {
+        (&Node::Leaf(x), &Node::Leaf(y)) => {
+            let value = if op(x != zero, y != zero) { NumCast::from(1).unwrap() } else { zero };
+            return Node::Leaf(value);
+        },
+        (&Node::Leaf(_), Node::Split(b)) => [combine(a, &b[0], op), combine(a, &b[1], op),
+                                             combine(a, &b[2], op), combine(a, &b[3], op)],
+        (Node::Split(a), &Node::Leaf(_)) => [combine(&a[0], b, op), combine(&a[1], b, op),
+                                             combine(&a[2], b, op), combine(&a[3], b, op)],
+        (Node::Split(a), Node::Split(b)) => [combine(&a[0], &b[0], op), combine(&a[1], &b[1], op),
+                                                       combine(&a[2], &b[2], op), combine(&a[3], &b[3], op)]
+    };
+    
+    // Merge quadrants that came out uniform.
+    match (&children[0], &children[1], &children[2], &children[3]) {
+        (Node::Leaf(w), Node::Leaf(x), Node::Leaf(y), Node::Leaf(z)) if w == x && x == y && y == z => {
+            return Node::Leaf(*w);
+        },
+        _ => {}
+    }
+    Node::Split(Box::new(children))
+}
+
+/// Collects the leaves under `node`, covering the `size` by `size`
+/// `square` at `x`, `y`, that overlap the pixels `[x0, x1)` by
+/// `[y0, y1)` of `window`.
+fn collect_leaves<T: SpatialKey>(node: &Node<T>, square: (usize, usize, usize),
+                                 window: (usize, usize, usize, usize),
+                                 leaves: &mut Vec<(usize, usize, usize, T)>) {
+    let ((x, y, size), (x0, y0, x1, y1)) = (square, window);
+    if x >= x1 || y >= y1 || x + size <= x0 || y + size <= y0 {
+        return;
+    }
+    
+    match *node {
+        Node::Leaf(value) => leaves.push((x, y, size, value)),
+        Node::Split(ref children) => {
+            let half = size / 2;
+            for (i, child) in children.iter().enumerate() {
+                collect_leaves(child, (x + (i & 1) * half, y + (i >> 1) * half, half), window, leaves);
+            }
+        }
+    }
+}
+
+/// Sets the leaves under `node` found in `region` to `label`, merging
+/// quadrants that come out uniform.
+fn fill_node<T: SpatialKey>(node: &mut Node<T>, x: usize, y: usize, size: usize,
+                            region: &HashSet<(usize, usize, usize)>, label: T) {
+    let merged = match *node {
+        Node::Leaf(ref mut value) => {
+            if region.contains(&(x, y, size)) {
+                *value = label;
+            }
+            return;
+        },
+        Node::Split(ref mut children) => {
+            let half = size / 2;
+            for (i, child) in children.iter_mut().enumerate() {
+                fill_node(child, x + (i & 1) * half, y + (i >> 1) * half, half, region, label);
+            }
+            match (&children[0], &children[1], &children[2], &children[3]) {
+                (&Node::Leaf(a), &Node::Leaf(b), &Node::Leaf(c), &Node::Leaf(d)) if a == b && b == c && c == d => {
+                    Some(a)
+                },
+                _ => None
+            }
+        }
+    };
+    
+    match merged {
+        Some(value) => *node = Node::Leaf(value),
+        None => {}
+    }
+}
+
+/// Returns the direction of the edge from `a` to `b` as the signs of its
+/// components.
+fn direction(a: (usize, usize), b: (usize, usize)) -> (isize, isize) {
+    let sign = |from: usize, to: usize| if to > from { 1 } else if to < from { -1 } else { 0 };
+    (sign(a.0, b.0), sign(a.1, b.1))
+}
+
+fn or(a: bool, b: bool) -> bool { a || b }
+fn and(a: bool, b: bool) -> bool { a && b }
+fn and_not(a: bool, b: bool) -> bool { a && !b }
+fn xor(a: bool, b: bool) -> bool { a != b }
+
+impl<T: SpatialKey> RegionQuadtree<T> {
+    /// Encodes a raster of `width` by `height` pixels, stored row by
+    /// row. Squares whose pixels differ by at most `threshold` are
+    /// merged into a single leaf holding their mean, so a `threshold`
+    /// of zero is lossless.
+    ///
+    /// Panics if `pixels` doesn't hold `width * height` values.
+    pub fn encode(pixels: &[T], width: usize, height: usize, threshold: T) -> RegionQuadtree<T> {
+        assert_eq!(pixels.len(), width * height);
+        let mut size = 1;
+        while size < width || size < height {
+            size *= 2;
+        }
+        
+        RegionQuadtree {
+            width: width,
+            height: height,
+            size: size,
+            root: encode_node(&Raster { pixels: pixels, width: width, height: height, threshold: threshold }, 0, 0, size)
+        }
+    }
+
+    /// Returns the width of the raster, in pixels.
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the raster, in pixels.
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of leaves of the tree.
+    pub fn leaf_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match *node {
+                Node::Leaf(_) => count += 1,
+                Node::Split(ref children) => for child in children.iter() {
+                    stack.push(child);
+                }
+            }
+        }
+        count
+    }
+
+    /// Returns the value of the pixel at `x`, `y`, or `None` if it lies
+    /// outside the raster.
+    pub fn get(&self, x: usize, y: usize) -> Option<T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        
+        let mut node = &self.root;
+        let mut half = self.size / 2;
+        loop {
+            match *node {
+                Node::Leaf(value) => return Some(value),
+                Node::Split(ref children) => {
+                    let i = (if x & half != 0 { 1 } else { 0 }) | (if y & half != 0 { 2 } else { 0 });
+                    node = &children[i];
+                    half /= 2;
+                }
+            }
+        }
+    }
+
+    /// Decodes the tree back into a raster of `width` by `height`
+    /// pixels, stored row by row.
+    pub fn decode(&self) -> Vec<T> {
+        let zero: T = NumCast::from(0).unwrap();
+        let mut pixels = vec![zero; self.width * self.height];
+        let mut stack = vec![(&self.root, 0, 0, self.size)];
+        while let Some((node, x, y, size)) = stack.pop() {
+            match *node {
+                Node::Leaf(value) => for py in y..self.height.min(y + size) {
+                    for px in x..self.width.min(x + size) {
+                        pixels[py * self.width + px] = value;
+                    }
+                },
+                Node::Split(ref children) => {
+                    let half = size / 2;
+                    for (i, child) in children.iter().enumerate() {
+                        stack.push((child, x + (i & 1) * half, y + (i >> 1) * half, half));
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Grows a region from the pixel at `seed` across edge-adjacent
+    /// leaves whose value satisfies `predicate`, and sets the whole
+    /// region to `label`. Returns the number of pixels filled, which is
+    /// zero if the seed lies outside the raster or doesn't satisfy
+    /// `predicate`.
+    pub fn flood_fill<F>(&mut self, seed: (usize, usize), label: T, predicate: F) -> usize
+        where F: Fn(T) -> bool {
+        let (sx, sy) = seed;
+        if sx >= self.width || sy >= self.height {
+            return 0;
+        }
+        
+        let mut stack = Vec::new();
+        self.leaves_in(sx, sy, sx + 1, sy + 1, &mut stack);
+        stack.retain(|&(_, _, _, value)| predicate(value));
+        
+        let mut region = HashSet::new();
+        let mut filled = 0;
+        while let Some((x, y, size, _)) = stack.pop() {
+            if !region.insert((x, y, size)) {
+                continue;
+            }
+            let (x1, y1) = (self.width.min(x + size), self.height.min(y + size));
+            filled += (x1 - x) * (y1 - y);
+            
+            // Strips one pixel wide along each edge, clipped to the
+            // raster so padding is never entered.
+            let mut neighbors = Vec::new();
+            if x > 0 {
+                self.leaves_in(x - 1, y, x, y1, &mut neighbors);
+            }
+            if x1 < self.width {
+                self.leaves_in(x1, y, x1 + 1, y1, &mut neighbors);
+            }
+            if y > 0 {
+                self.leaves_in(x, y - 1, x1, y, &mut neighbors);
+            }
+            if y1 < self.height {
+                self.leaves_in(x, y1, x1, y1 + 1, &mut neighbors);
+            }
+            for &leaf in neighbors.iter() {
+                let (nx, ny, nsize, value) = leaf;
+                if !region.contains(&(nx, ny, nsize)) && predicate(value) {
+                    stack.push(leaf);
+                }
+            }
+        }
+        
+        fill_node(&mut self.root, 0, 0, self.size, &region, label);
+        filled
+    }
+
+    /// Traces the boundaries between set and unset pixels into closed
+    /// polygons, with vertices at pixel corners. Outer boundaries run
+    /// clockwise and holes counterclockwise, with `y` pointing down as in
+    /// the raster. Pixels are set if they're non-zero, and everything
+    /// outside the raster counts as unset.
+    ///
+    /// Each set leaf is compared with the leaves along its edges, so
+    /// edges between leaves of different depths are split where the
+    /// smaller leaves meet. Pixels touching only at a corner get
+    /// separate polygons.
+    pub fn extract_contours(&self) -> Vec<Vec<[T; 2]>> {
+        let zero: T = NumCast::from(0).unwrap();
+        let mut leaves = Vec::new();
+        self.leaves_in(0, 0, self.width, self.height, &mut leaves);
+        
+        // Boundary edges, with the set side on their right.
+        let mut edges = Vec::new();
+        for &(x, y, size, value) in leaves.iter() {
+            if value == zero {
+                continue;
+            }
+            let (x1, y1) = (self.width.min(x + size), self.height.min(y + size));
+            
+            let unset = |x0: usize, y0: usize, x1: usize, y1: usize| {
+                let mut neighbors = Vec::new();
+                self.leaves_in(x0, y0, x1, y1, &mut neighbors);
+                neighbors.into_iter()
+                    .filter(|&(_, _, _, value)| value == zero)
+                    .map(|(nx, ny, nsize, _)| (nx.max(x0), ny.max(y0), (nx + nsize).min(x1), (ny + nsize).min(y1)))
+                    .collect::<Vec<_>>()
+            };
+            
+            if y == 0 {
+                edges.push(((x, y), (x1, y)));
+            } else {
+                for (a, _, b, _) in unset(x, y - 1, x1, y) {
+                    edges.push(((a, y), (b, y)));
+                }
+            }
+            if x1 == self.width {
+                edges.push(((x1, y), (x1, y1)));
+            } else {
+                for (_, a, _, b) in unset(x1, y, x1 + 1, y1) {
+                    edges.push(((x1, a), (x1, b)));
+                }
+            }
+            if y1 == self.height {
+                edges.push(((x1, y1), (x, y1)));
+            } else {
+                for (a, _, b, _) in unset(x, y1, x1, y1 + 1) {
+                    edges.push(((b, y1), (a, y1)));
+                }
+            }
+            if x == 0 {
+                edges.push(((x, y1), (x, y)));
+            } else {
+                for (_, a, _, b) in unset(x - 1, y, x, y1) {
+                    edges.push(((x, b), (x, a)));
+                }
+            }
+        }
+        
+        let mut outgoing: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (i, &(start, _)) in edges.iter().enumerate() {
+            outgoing.entry(start).or_default().push(i);
+        }
+        
+        let mut used = vec![false; edges.len()];
+        let mut contours = Vec::new();
+        for first in 0..edges.len() {
+            if used[first] {
+                continue;
+            }
+            
+            let mut corners = Vec::new();
+            let mut current = first;
+            loop {
+                used[current] = true;
+                let (start, end) = edges[current];
+                corners.push(start);
+                if end == edges[first].0 {
+                    break;
+                }
+                
+                // Where two loops touch at a corner, turning right keeps
+                // to the pixel being traced.
+                let (dx, dy) = direction(start, end);
+                let turns = [(-dy, dx), (dx, dy), (dy, -dx)];
+                let next = turns.iter()
+                    .filter_map(|&turn| outgoing[&end].iter().cloned()
+                                .find(|&i| !used[i] && direction(edges[i].0, edges[i].1) == turn))
+                    .next();
+                match next {
+                    Some(next) => current = next,
+                    None => break
+                }
+            }
+            
+            // Drop corners in the middle of straight runs.
+            let n = corners.len();
+            let contour = (0..n)
+                .filter(|&i| {
+                    let (prev, corner, next) = (corners[(i + n - 1) % n], corners[i], corners[(i + 1) % n]);
+                    direction(prev, corner) != direction(corner, next)
+                })
+                .map(|i| [NumCast::from(corners[i].0).unwrap(), NumCast::from(corners[i].1).unwrap()])
+                .collect();
+            contours.push(contour);
+        }
+        contours
+    }
+
+    /// Returns a tree whose pixels are set where they are set in either
+    /// `self` or `other`. Pixels are set if they're non-zero, and the
+    /// result holds ones and zeros.
+    ///
+    /// Panics if the rasters differ in size.
+    pub fn union(&self, other: &RegionQuadtree<T>) -> RegionQuadtree<T> {
+        self.combine(other, or)
+    }
+
+    /// Returns a tree whose pixels are set where they are set in both
+    /// `self` and `other`, like `union`.
+    pub fn intersection(&self, other: &RegionQuadtree<T>) -> RegionQuadtree<T> {
+        self.combine(other, and)
+    }
+
+    /// Returns a tree whose pixels are set where they are set in `self`
+    /// but not in `other`, like `union`.
+    pub fn difference(&self, other: &RegionQuadtree<T>) -> RegionQuadtree<T> {
+        self.combine(other, and_not)
+    }
+
+    /// Returns a tree whose pixels are set where they are set in exactly
+    /// one of `self` and `other`, like `union`.
+    pub fn xor(&self, other: &RegionQuadtree<T>) -> RegionQuadtree<T> {
+        self.combine(other, xor)
+    }
+
+    fn combine(&self, other: &RegionQuadtree<T>, op: fn(bool, bool) -> bool) -> RegionQuadtree<T> {
+        assert!(self.width == other.width && self.height == other.height);
+        RegionQuadtree {
+            width: self.width,
+            height: self.height,
+            size: self.size,
+            root: combine(&self.root, &other.root, op)
+        }
+    }
+
+    /// Collects the leaves overlapping the pixels `[x0, x1)` by
+    /// `[y0, y1)`, as their corner, size and value.
+    fn leaves_in(&self, x0: usize, y0: usize, x1: usize, y1: usize, leaves: &mut Vec<(usize, usize, usize, T)>) {
+        collect_leaves(&self.root, (0, 0, self.size), (x0, y0, x1, y1), leaves);
+    }
+}
@@ -0,0 +1,179 @@
+use SpatialKey;
+use SpatialError;
+use ItemOrder;
+use snapshot::{self, Snapshot};
+use std::io::{Read, Write};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use super::{Quadtree, NodeData, Index, Volume, MAX_DEPTH};
+
+impl<T, P, D> Quadtree<T, P, D>
+    where T: SpatialKey + Snapshot, P: Index<T> + Clone + Snapshot, D: NodeData<T, P> + Snapshot {
+    /// Writes a binary snapshot of the tree to `writer`: its volume,
+    /// capacity and item order, followed by its nodes in depth-first
+    /// order with their items and user data. The volumes of the nodes
+    /// below the root aren't written, as they follow from it.
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), SpatialError> {
+        let mut out = Vec::new();
+        snapshot::write_header(&mut out, 2);
+        self.capacity.write_snapshot(&mut out);
+        out.push(match self.order {
+            ItemOrder::Unordered => 0,
+            ItemOrder::Insertion => 1,
+            ItemOrder::Morton => 2
+        });
+        self.next_sequence.write_snapshot(&mut out);
+        self.volume.min.write_snapshot(&mut out);
+        self.volume.max.write_snapshot(&mut out);
+        self.write_node(&mut out);
+        writer.write_all(&out).map_err(SpatialError::Io)
+    }
+
+    /// Reads a tree from the snapshot written by `save_to` that makes up
+    /// the rest of `reader`, restoring its nodes as they were rather
+    /// than inserting its items again. Returns
+    /// `SpatialError::Serialization` if the snapshot is of another
+    /// version of the format or is malformed, such as when an item lies
+    /// outside of its node or a node holds more items than the capacity.
+    pub fn load_from<R: Read>(reader: &mut R) -> Result<Quadtree<T, P, D>, SpatialError> {
+        let _span = trace_span!("quadtree load");
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let mut data = Vec::new();
+        match reader.read_to_end(&mut data) {
+            Ok(_) => {},
+            Err(err) => return Err(SpatialError::Io(err))
+        }
+        let mut input = &data[..];
+
+        match snapshot::read_header(&mut input, 2) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        let capacity = match usize::read_snapshot(&mut input) {
+            Ok(capacity) => capacity,
+            Err(err) => return Err(err)
+        };
+        let order = match u8::read_snapshot(&mut input) {
+            Ok(0) => ItemOrder::Unordered,
+            Ok(1) => ItemOrder::Insertion,
+            Ok(2) => ItemOrder::Morton,
+            Ok(order) => return Err(SpatialError::Serialization(format!("invalid item order {}", order))),
+            Err(err) => return Err(err)
+        };
+        let next_sequence = match u64::read_snapshot(&mut input) {
+            Ok(next_sequence) => next_sequence,
+            Err(err) => return Err(err)
+        };
+        let min = match <[T; 2]>::read_snapshot(&mut input) {
+            Ok(min) => min,
+            Err(err) => return Err(err)
+        };
+        let max = match <[T; 2]>::read_snapshot(&mut input) {
+            Ok(max) => max,
+            Err(err) => return Err(err)
+        };
+        let vol = Volume::new(min, max);
+        if !vol.is_valid() {
+            return Err(SpatialError::Serialization("invalid volume".to_string()));
+        }
+
+        let mut tree = Quadtree::empty(vol, capacity, order);
+        tree.next_sequence = next_sequence;
+        match tree.read_node(&mut input, 0) {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+        if !input.is_empty() {
+            return Err(SpatialError::Serialization("trailing bytes after snapshot".to_string()));
+        }
+        trace_event!("quadtree load: {} items in {:?}", tree.len(), start.elapsed());
+        Ok(tree)
+    }
+
+    /// Appends this node and its descendants to `out`.
+    fn write_node(&self, out: &mut Vec<u8>) {
+        self.items.len().write_snapshot(out);
+        for (i, item) in self.items.iter().enumerate() {
+            item.write_snapshot(out);
+            if self.order == ItemOrder::Insertion {
+                self.sequence[i].write_snapshot(out);
+            }
+        }
+        self.quadrants.is_some().write_snapshot(out);
+        self.data.write_snapshot(out);
+        match self.quadrants {
+            Some(ref quadrants) => for node in quadrants.iter() {
+                node.write_node(out);
+            },
+            None => {}
+        }
+    }
+
+    /// Reads the items and user data of this empty node, found at
+    /// `depth`, and its descendants from the front of `input`.
+    fn read_node(&mut self, input: &mut &[u8], depth: usize) -> Result<(), SpatialError> {
+        let count = match usize::read_snapshot(input) {
+            Ok(count) => count,
+            Err(err) => return Err(err)
+        };
+        if count > self.capacity {
+            return Err(SpatialError::Serialization("more items in a node than its capacity".to_string()));
+        }
+        for _ in 0..count {
+            match P::read_snapshot(input) {
+                Ok(item) => self.items.push(item),
+                Err(err) => return Err(err)
+            }
+            if self.order == ItemOrder::Insertion {
+                match u64::read_snapshot(input) {
+                    Ok(sequence) => self.sequence.push(sequence),
+                    Err(err) => return Err(err)
+                }
+            }
+        }
+        match self.check_items() {
+            Ok(()) => {},
+            Err(err) => return Err(err)
+        }
+
+        let subdivided = match bool::read_snapshot(input) {
+            Ok(subdivided) => subdivided,
+            Err(err) => return Err(err)
+        };
+        // The data is only put in place once the node is subdivided,
+        // which calls back into it.
+        let data = match D::read_snapshot(input) {
+            Ok(data) => data,
+            Err(err) => return Err(err)
+        };
+        if subdivided {
+            if depth >= MAX_DEPTH {
+                return Err(SpatialError::Serialization("node deeper than the depth limit".to_string()));
+            }
+            self.subdivide();
+            let mut occupied = 0;
+            let mut count = self.items.len();
+            match self.quadrants {
+                Some(ref mut quadrants) => for (i, node) in quadrants.iter_mut().enumerate() {
+                    match node.read_node(input, depth + 1) {
+                        Ok(()) => {},
+                        Err(err) => return Err(err)
+                    }
+                    if !node.items.is_empty() || node.occupied != 0 {
+                        occupied |= 1 << i;
+                    }
+                    count += node.count;
+                },
+                None => {}
+            }
+            self.occupied = occupied;
+            self.count = count;
+        } else {
+            self.count = self.items.len();
+        }
+        self.data = data;
+        Ok(())
+    }
+}
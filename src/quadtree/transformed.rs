@@ -0,0 +1,107 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// Returns the point `p` transformed by the affine matrix `m`.
+fn apply<T: SpatialKey>(m: &[[T; 3]; 3], p: &[T; 2]) -> [T; 2] {
+    [m[0][0] * p[0] + m[0][1] * p[1] + m[0][2],
+     m[1][0] * p[0] + m[1][1] * p[1] + m[1][2]]
+}
+
+/// Returns the inverse of the affine matrix `m`, or `None` if it can't
+/// be inverted.
+fn invert<T: SpatialKey>(m: &[[T; 3]; 3]) -> Option<[[T; 3]; 3]> {
+    let zero: T = NumCast::from(0).unwrap();
+    let one: T = NumCast::from(1).unwrap();
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if det == zero || !det.is_finite() {
+        return None;
+    }
+
+    let a = [[m[1][1] / det, -m[0][1] / det],
+             [-m[1][0] / det, m[0][0] / det]];
+    Some([[a[0][0], a[0][1], -(a[0][0] * m[0][2] + a[0][1] * m[1][2])],
+          [a[1][0], a[1][1], -(a[1][0] * m[0][2] + a[1][1] * m[1][2])],
+          [zero, zero, one]])
+}
+
+/// A view of a `Quadtree` stored in local space, placed in world space
+/// by an affine transform. Queries are given in world space and mapped
+/// into the tree, so the tree doesn't need to be rebuilt when the
+/// object it belongs to moves.
+pub struct TransformedView<'a, T: SpatialKey + 'a, P: Index<T> + Clone + 'a, D: NodeData<T, P> + 'a> {
+    tree: &'a Quadtree<T, P, D>,
+    /// Transform from local to world space.
+    matrix: [[T; 3]; 3],
+    /// Transform from world to local space.
+    inverse: [[T; 3]; 3]
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns a view of the tree placed in world space by the affine
+    /// transform `m`, or `None` if `m` can't be inverted. The matrix is
+    /// indexed as `m[row][column]` and transforms column vectors; its
+    /// last row is ignored.
+    pub fn transformed<'a>(&'a self, m: &[[T; 3]; 3]) -> Option<TransformedView<'a, T, P, D>> {
+        invert(m).map(|inverse| TransformedView {
+            tree: self,
+            matrix: *m,
+            inverse: inverse
+        })
+    }
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> TransformedView<'a, T, P, D> {
+    /// Returns the tree seen through the view.
+    #[inline]
+    pub fn tree(&self) -> &'a Quadtree<T, P, D> {
+        self.tree
+    }
+
+    /// Maps `p` from the local space of the tree to world space.
+    #[inline]
+    pub fn to_world(&self, p: &[T; 2]) -> [T; 2] {
+        apply(&self.matrix, p)
+    }
+
+    /// Maps `p` from world space to the local space of the tree.
+    #[inline]
+    pub fn to_local(&self, p: &[T; 2]) -> [T; 2] {
+        apply(&self.inverse, p)
+    }
+
+    /// Returns all items whose position in world space is inside the
+    /// world space volume `vol`.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&'a P> {
+        self.candidates(vol).into_iter()
+            .filter(|item| vol.contains(&self.to_world(&item.quadtree_index())))
+            .collect()
+    }
+
+    /// Returns all items whose position in world space is within
+    /// `radius` of the world space point `center`.
+    pub fn get_in_radius(&self, center: [T; 2], radius: T) -> Vec<&'a P> {
+        let vol = Volume::new([center[0] - radius, center[1] - radius],
+                              [center[0] + radius, center[1] + radius]);
+        self.candidates(&vol).into_iter()
+            .filter(|item| geom::point_in_sphere(&self.to_world(&item.quadtree_index()), &center, radius))
+            .collect()
+    }
+
+    /// Returns the items inside the local space bounding box of the
+    /// world space volume `vol`.
+    fn candidates(&self, vol: &Volume<T>) -> Vec<&'a P> {
+        let first = self.to_local(&vol.min);
+        let (mut min, mut max) = (first, first);
+        for corner in 1..4 {
+            let p = self.to_local(&[if corner & 1 == 0 { vol.min[0] } else { vol.max[0] },
+                                    if corner & 2 == 0 { vol.min[1] } else { vol.max[1] }]);
+            for i in 0..2 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        self.tree.get_in_volume(&Volume::new(min, max))
+    }
+}
@@ -0,0 +1,104 @@
+use SpatialKey;
+use super::{Quadtree, NodeData, Index, Volume, Ray, Iter, Query};
+
+/// A read-only view of a `Quadtree` that only offers its queries, for
+/// handing query access to other parts of a program without exposing
+/// the tree itself, its node data or its structure. The view is only a
+/// reference, so it's as cheap to copy as one, and the results of its
+/// queries live as long as the tree does rather than the view.
+pub struct TreeView<'a, T: SpatialKey + 'a, P: Index<T> + Clone + 'a, D: NodeData<T, P> + 'a = ()> {
+    tree: &'a Quadtree<T, P, D>
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Clone for TreeView<'a, T, P, D> {
+    fn clone(&self) -> TreeView<'a, T, P, D> {
+        *self
+    }
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Copy for TreeView<'a, T, P, D> {}
+
+impl<T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns a read-only view of the tree that only offers its
+    /// queries.
+    #[inline]
+    pub fn view<'a>(&'a self) -> TreeView<'a, T, P, D> {
+        TreeView { tree: self }
+    }
+}
+
+impl<'a, T: SpatialKey, P: Index<T> + Clone, D: NodeData<T, P>> TreeView<'a, T, P, D> {
+    /// Returns the volume the tree covers.
+    #[inline]
+    pub fn bounds(&self) -> &'a Volume<T> {
+        self.tree.bounds()
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.len() == 0
+    }
+
+    /// Returns an iterator over all items of the tree, like
+    /// `Quadtree::iter`.
+    #[inline]
+    pub fn iter(&self) -> Iter<'a, T, P, D> {
+        self.tree.iter()
+    }
+
+    /// Returns a lazy iterator over the items inside `vol`, like
+    /// `Quadtree::query`.
+    #[inline]
+    pub fn query(&self, vol: &Volume<T>) -> Query<'a, T, P, D> {
+        self.tree.query(vol)
+    }
+
+    /// Returns all items inside the volume `vol`.
+    #[inline]
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&'a P> {
+        self.tree.get_in_volume(vol)
+    }
+
+    /// Returns all items within `radius` of `center`.
+    #[inline]
+    pub fn get_in_radius(&self, center: [T; 2], radius: T) -> Vec<&'a P> {
+        self.tree.get_in_radius(center, radius)
+    }
+
+    /// Returns the item closest to `point`, or `None` if the tree is
+    /// empty.
+    #[inline]
+    pub fn nearest(&self, point: &[T; 2]) -> Option<&'a P> {
+        self.tree.nearest(point)
+    }
+
+    /// Returns up to `k` items closest to `point`, ordered from the
+    /// closest to the farthest.
+    #[inline]
+    pub fn nearest_n(&self, point: &[T; 2], k: usize) -> Vec<&'a P> {
+        self.tree.nearest_n(point, k)
+    }
+
+    /// Returns the items of all nodes that `ray` passes through, paired
+    /// with the distance at which it enters their node, like
+    /// `Quadtree::raycast`.
+    #[inline]
+    pub fn raycast(&self, ray: &Ray<T>) -> Vec<(T, &'a P)> {
+        self.tree.raycast(ray)
+    }
+
+    /// Returns the items of the nodes that `ray` enters within `t_max`
+    /// and that share a layer bit with `mask`, like
+    /// `Quadtree::raycast_filtered`.
+    #[inline]
+    pub fn raycast_filtered(&self, ray: &Ray<T>, t_max: T, mask: u32) -> Vec<(T, &'a P)> {
+        self.tree.raycast_filtered(ray, t_max, mask)
+    }
+}
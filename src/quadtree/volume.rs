@@ -1,8 +1,12 @@
 use SpatialKey;
+use num::NumCast;
+use geom;
 use std::fmt;
 use std::fmt::Display;
 
 /// A two-dimensional bounding volume for a `Quadtree` node.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Volume<T: SpatialKey> {
     /// The upper-left corner.
     pub min: [T; 2],
@@ -33,24 +37,112 @@ impl<T: SpatialKey> Volume<T> {
         self.max
     }
     
+    /// Returns the center point of the volume.
+    #[inline]
+    pub fn center(&self) -> [T; 2] {
+        let val2: T = NumCast::from(2).unwrap();
+        [(self.min[0] + self.max[0]) / val2, (self.min[1] + self.max[1]) / val2]
+    }
+    
     /// Returns `true` if `p` is inside the volume, `false` otherwise.
     #[inline]
     pub fn contains(&self, p: &[T; 2]) -> bool {
-        let min = self.min;
-        let max = self.max;
-        
-        p[0] >= min[0] && p[0] <= max[0] &&
-            p[1] >= min[1] && p[1] <= max[1]
+        geom::point_in_aabb(p, &self.min, &self.max)
+    }
+    
+    /// Returns `true` if the minimum of the volume is at or below its
+    /// maximum on every axis, `false` otherwise, including when either
+    /// is NaN.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        (0..2).all(|i| self.min[i] <= self.max[i])
     }
     
     /// Returns `true` if `other` intersects the volume, `false`
-    /// otherwise.
+    /// otherwise. Volumes that only touch each other are considered
+    /// intersecting.
     #[inline]
     pub fn intersects(&self, other: &Volume<T>) -> bool {
-        let min = self.min;
-        let max = self.max;
-        min[0] < other.max[0] && max[0] > other.min[0] &&
-            min[1] < other.max[1] && max[1] > other.min[1]
+        geom::aabb_intersects_aabb(&self.min, &self.max, &other.min, &other.max)
+    }
+    
+    /// Returns the distances along `ray` at which it enters and exits
+    /// the volume, or `None` if the ray misses it. The entry distance
+    /// is `0` if the ray starts inside the volume.
+    #[inline]
+    pub fn intersect_ray(&self, ray: &Ray<T>) -> Option<(T, T)> {
+        geom::ray_intersects_aabb(&ray.origin, &ray.inv_direction, &self.min, &self.max)
+    }
+}
+
+/// A ray in 2D-space. The reciprocal of the direction is precomputed
+/// to speed up repeated intersection tests.
+#[derive(Clone, Copy)]
+pub struct Ray<T: SpatialKey> {
+    origin: [T; 2],
+    direction: [T; 2],
+    inv_direction: [T; 2],
+    /// `1` for each axis along which the direction is negative, `0`
+    /// otherwise.
+    sign: [usize; 2]
+}
+
+impl<T: SpatialKey> Ray<T> {
+    /// Creates a new ray starting from `origin` and extending toward
+    /// `direction`, which doesn't need to be normalized.
+    #[inline]
+    pub fn new(origin: [T; 2], direction: [T; 2]) -> Ray<T> {
+        let one: T = NumCast::from(1).unwrap();
+        let zero: T = NumCast::from(0).unwrap();
+        let mut inv_direction = direction;
+        let mut sign = [0, 0];
+        for i in 0..2 {
+            inv_direction[i] = one / direction[i];
+            sign[i] = if direction[i] < zero { 1 } else { 0 };
+        }
+        
+        Ray {
+            origin: origin,
+            direction: direction,
+            inv_direction: inv_direction,
+            sign: sign
+        }
+    }
+
+    /// Returns the starting point of the ray.
+    #[inline]
+    pub fn origin(&self) -> [T; 2] {
+        self.origin
+    }
+
+    /// Returns the direction of the ray.
+    #[inline]
+    pub fn direction(&self) -> [T; 2] {
+        self.direction
+    }
+
+    /// Returns the component-wise reciprocal of the direction.
+    #[inline]
+    pub fn inv_direction(&self) -> [T; 2] {
+        self.inv_direction
+    }
+
+    /// Returns `1` for each axis along which the direction is negative,
+    /// `0` otherwise.
+    #[inline]
+    pub fn sign(&self) -> [usize; 2] {
+        self.sign
+    }
+
+    /// Returns the point at distance `t` along the ray, measured in
+    /// multiples of the direction.
+    #[inline]
+    pub fn at(&self, t: T) -> [T; 2] {
+        let mut p = self.origin;
+        for (x, &d) in p.iter_mut().zip(self.direction.iter()) {
+            *x = *x + d * t;
+        }
+        p
     }
 }
 
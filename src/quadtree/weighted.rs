@@ -0,0 +1,192 @@
+use SpatialKey;
+use geom;
+use num::NumCast;
+use num::traits::Float;
+use super::{Quadtree, NodeData, Index, Volume};
+
+/// A trait for items carrying a weight, such as a population, an
+/// influence or a mass, for `Quadtree::weighted_centroid` and
+/// `Quadtree::for_each_force_source`.
+pub trait Weighted<T: SpatialKey>: Index<T> {
+    /// Returns the weight of `self`.
+    fn weight(&self) -> T;
+}
+
+/// A source of force acting on a point, found by
+/// `Quadtree::for_each_force_source`.
+pub enum ForceSource<'a, T: SpatialKey, P: 'a> {
+    /// The items of a subtree far enough from the point to act as a
+    /// single body, with their total weight at their weighted centroid.
+    Node(T, [T; 2]),
+    /// An item close to the point, acting on its own.
+    Item(&'a P)
+}
+
+/// Node data keeping the total weight of the items in the subtree of its
+/// node and the sum of their positions times their weights, for
+/// `Quadtree::for_each_force_source`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mass<T: SpatialKey> {
+    /// The total weight and weighted sum of the items of the node.
+    own: (T, [T; 2]),
+    /// The total weight and weighted sum of the items below the node.
+    below: (T, [T; 2])
+}
+
+/// Returns the total weight and weighted sum of no items.
+fn no_mass<T: SpatialKey>() -> (T, [T; 2]) {
+    let zero: T = NumCast::from(0).unwrap();
+    (zero, [zero; 2])
+}
+
+/// Returns the weight of `item` and its position times its weight.
+fn mass_of<T: SpatialKey, P: Weighted<T>>(item: &P) -> (T, [T; 2]) {
+    let (p, w) = (item.quadtree_index(), item.weight());
+    (w, [p[0] * w, p[1] * w])
+}
+
+/// Returns the total weight and weighted sum of the items of `a` and `b`.
+fn add_mass<T: SpatialKey>(a: (T, [T; 2]), b: (T, [T; 2])) -> (T, [T; 2]) {
+    (a.0 + b.0, [a.1[0] + b.1[0], a.1[1] + b.1[1]])
+}
+
+impl<T: SpatialKey> Default for Mass<T> {
+    fn default() -> Mass<T> {
+        Mass { own: no_mass(), below: no_mass() }
+    }
+}
+
+impl<T: SpatialKey> Mass<T> {
+    /// Returns the total weight of the items in the subtree.
+    #[inline]
+    pub fn weight(&self) -> T {
+        self.own.0 + self.below.0
+    }
+
+    /// Returns the weighted centroid of the items in the subtree, or
+    /// `None` if their total weight is zero.
+    pub fn centroid(&self) -> Option<[T; 2]> {
+        let (weight, sum) = add_mass(self.own, self.below);
+        if weight == NumCast::from(0).unwrap() {
+            return None;
+        }
+        Some([sum[0] / weight, sum[1] / weight])
+    }
+}
+
+impl<T: SpatialKey, P: Weighted<T>> NodeData<T, P> for Mass<T> {
+    fn on_insert(&mut self, item: &P) {
+        self.own = add_mass(self.own, mass_of(item));
+    }
+
+    fn on_remove(&mut self, item: &P) {
+        let (w, sum) = mass_of(item);
+        self.own = add_mass(self.own, (-w, [-sum[0], -sum[1]]));
+    }
+
+    fn on_children_change(&mut self, children: [&Mass<T>; 4]) {
+        self.below = children.iter().fold(no_mass(), |below, child| add_mass(below, add_mass(child.own, child.below)));
+    }
+
+    fn on_merge(&mut self) {
+        self.own = add_mass(self.own, self.below);
+        self.below = no_mass();
+    }
+}
+
+/// Returns the weighted centroid of `items` and their total weight in
+/// the type `convert` converts them to, or `None` if one of them doesn't
+/// convert.
+fn weighted_mean<T, P, F, C>(items: &[&P], convert: C) -> Option<([F; 2], F)>
+    where T: SpatialKey, P: Weighted<T>, F: Float, C: Fn(T) -> Option<F> {
+    let mut sum = [F::zero(); 2];
+    let mut total = F::zero();
+    for item in items.iter() {
+        let p = item.quadtree_index();
+        let w = match convert(item.weight()) {
+            Some(w) => w,
+            None => return None
+        };
+        for axis in 0..2 {
+            match convert(p[axis]) {
+                Some(x) => sum[axis] = sum[axis] + x * w,
+                None => return None
+            }
+        }
+        total = total + w;
+    }
+    Some(([sum[0] / total, sum[1] / total], total))
+}
+
+impl<T: SpatialKey, P: Weighted<T> + Clone, D: NodeData<T, P>> Quadtree<T, P, D> {
+    /// Returns the weighted centroid of the items inside `vol` along
+    /// with their total weight, or `None` if the total weight is zero.
+    pub fn weighted_centroid(&self, vol: &Volume<T>) -> Option<([T; 2], T)> {
+        let items = self.get_in_volume(vol);
+        // Sum up in f64 so that many items far from the origin don't
+        // drown each other's contributions, unless they don't convert
+        // to it.
+        let narrowed = match weighted_mean(&items, geom::wide) {
+            Some((c, total)) => match (geom::narrow(c[0]), geom::narrow(c[1]), geom::narrow(total)) {
+                (Some(x), Some(y), Some(total)) => Some(([x, y], total)),
+                _ => None
+            },
+            None => None
+        };
+        let (centroid, total) = match narrowed.or_else(|| weighted_mean(&items, Some)) {
+            Some(mean) => mean,
+            None => return None
+        };
+        
+        let zero: T = NumCast::from(0).unwrap();
+        if total == zero {
+            return None;
+        }
+        Some((centroid, total))
+    }
+}
+
+impl<T: SpatialKey, P: Weighted<T> + Clone> Quadtree<T, P, Mass<T>> {
+    /// Calls `f` with the sources of force acting on `point` under the
+    /// Barnes-Hut approximation, for n-body simulations and force
+    /// directed layouts. A subtree whose size is less than `theta` times
+    /// its distance from `point` acts as a single body at the weighted
+    /// centroid of its items, while the items of closer ones act on
+    /// their own. A `theta` of zero yields every item, and greater ones
+    /// fewer and coarser sources, with `0.5` a common choice. Subtrees
+    /// around `point` are never taken as a whole.
+    pub fn for_each_force_source<F>(&self, point: [T; 2], theta: T, mut f: F)
+        where F: FnMut(ForceSource<T, P>)
+    {
+        self.force_sources(&point, theta, &mut f);
+    }
+
+    fn force_sources<'a, F>(&'a self, point: &[T; 2], theta: T, f: &mut F)
+        where F: FnMut(ForceSource<'a, T, P>)
+    {
+        if !self.volume.contains(point) {
+            match self.data.centroid() {
+                Some(centroid) => {
+                    let size = (self.volume.max[0] - self.volume.min[0]).max(self.volume.max[1] - self.volume.min[1]);
+                    if size * size < theta * theta * geom::distance2(point, &centroid) {
+                        f(ForceSource::Node(self.data.weight(), centroid));
+                        return;
+                    }
+                },
+                None => {}
+            }
+        }
+        
+        for item in self.items.iter() {
+            f(ForceSource::Item(item));
+        }
+        match self.quadrants {
+            Some(ref quadrants) => for (i, node) in quadrants.iter().enumerate() {
+                if self.occupied & (1 << i) != 0 {
+                    node.force_sources(point, theta, f);
+                }
+            },
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use SpatialKey;
+use SpatialError;
+use geom;
+use num::NumCast;
+use std::collections::HashMap;
+use super::{Quadtree, Index, Volume, DEFAULT_CAPACITY};
+
+/// An index over an unbounded world, split into square chunks of equal
+/// size with a `Quadtree` each. Chunks are created as items are
+/// inserted into them and dropped once they're empty again, and queries
+/// merge the results of all chunks they overlap.
+pub struct WorldIndex<T: SpatialKey, P: Index<T> + Clone> {
+    /// Side length of the chunks.
+    chunk_size: T,
+    /// Capacity of the nodes of the chunk trees.
+    capacity: usize,
+    chunks: HashMap<(i64, i64), Quadtree<T, P>>
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> WorldIndex<T, P> {
+    /// Creates an empty index with chunks of `chunk_size` by
+    /// `chunk_size`.
+    #[inline]
+    pub fn new(chunk_size: T) -> WorldIndex<T, P> {
+        WorldIndex::with_capacity(chunk_size, DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty index with chunks of `chunk_size` by
+    /// `chunk_size`, whose trees have nodes of `capacity`.
+    pub fn with_capacity(chunk_size: T, capacity: usize) -> WorldIndex<T, P> {
+        WorldIndex {
+            chunk_size: chunk_size,
+            capacity: capacity,
+            chunks: HashMap::new()
+        }
+    }
+
+    /// Returns the number of items in the index.
+    pub fn len(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.len()).sum()
+    }
+
+    /// Returns `true` if the index holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.values().all(|chunk| chunk.is_empty())
+    }
+
+    /// Returns the number of chunks holding items.
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns the coordinates of the chunk containing `p`, or `None` if
+    /// they can't be represented.
+    pub fn chunk_of(&self, p: &[T; 2]) -> Option<(i64, i64)> {
+        match (self.chunk_coordinate(p[0]), self.chunk_coordinate(p[1])) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None
+        }
+    }
+
+    /// Returns the tree of the chunk at `key`, if it holds any items.
+    #[inline]
+    pub fn chunk(&self, key: (i64, i64)) -> Option<&Quadtree<T, P>> {
+        self.chunks.get(&key)
+    }
+
+    /// Returns the volume covered by the chunk at `key`.
+    pub fn chunk_volume(&self, key: (i64, i64)) -> Volume<T> {
+        let x: T = NumCast::from(key.0).unwrap();
+        let y: T = NumCast::from(key.1).unwrap();
+        Volume::new([x * self.chunk_size, y * self.chunk_size],
+                    [(x + NumCast::from(1).unwrap()) * self.chunk_size,
+                     (y + NumCast::from(1).unwrap()) * self.chunk_size])
+    }
+
+    /// Returns the chunk coordinate of `x` along one axis, such that the
+    /// chunk volume computed by `chunk_volume` contains `x` despite
+    /// rounding.
+    fn chunk_coordinate(&self, x: T) -> Option<i64> {
+        let key: i64 = match NumCast::from((x / self.chunk_size).floor()) {
+            Some(key) => key,
+            None => return None
+        };
+        let start: T = NumCast::from(key).unwrap();
+        if x < start * self.chunk_size {
+            Some(key - 1)
+        } else if x > (start + NumCast::from(1).unwrap()) * self.chunk_size {
+            Some(key + 1)
+        } else {
+            Some(key)
+        }
+    }
+
+    /// Inserts an `item` into the chunk containing it, creating the
+    /// chunk if necessary. Returns `SpatialError::OutOfBounds` if the
+    /// position of the item isn't finite.
+    pub fn insert(&mut self, item: P) -> Result<(), SpatialError> {
+        let key = match self.chunk_of(&item.quadtree_index()) {
+            Some(key) => key,
+            None => return Err(SpatialError::OutOfBounds)
+        };
+        let (volume, capacity) = (self.chunk_volume(key), self.capacity);
+        self.chunks.entry(key).or_insert_with(|| Quadtree::with_capacity(volume, capacity)).insert(item)
+    }
+
+    /// Removes an item equal to `item` from the index and returns it,
+    /// or `None` if there is no such item.
+    pub fn remove(&mut self, item: &P) -> Option<P> where P: PartialEq {
+        let key = match self.chunk_of(&item.quadtree_index()) {
+            Some(key) => key,
+            None => return None
+        };
+        let (removed, empty) = match self.chunks.get_mut(&key) {
+            Some(chunk) => {
+                let removed = chunk.remove(item);
+                (removed, chunk.is_empty())
+            },
+            None => return None
+        };
+        if empty {
+            self.chunks.remove(&key);
+        }
+        removed
+    }
+
+    /// Returns all items inside the volume `vol`, from all the chunks it
+    /// overlaps, in no particular order.
+    pub fn get_in_volume(&self, vol: &Volume<T>) -> Vec<&P> {
+        let (min, max) = match (self.chunk_of(&vol.min), self.chunk_of(&vol.max)) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return Vec::new()
+        };
+
+        let mut items = Vec::new();
+        // Look up the overlapped chunks, unless there are more of them
+        // than chunks holding items.
+        let overlapped = (max.0 - min.0 + 1) as f64 * (max.1 - min.1 + 1) as f64;
+        if overlapped > self.chunks.len() as f64 {
+            for (key, chunk) in self.chunks.iter() {
+                if key.0 >= min.0 && key.0 <= max.0 && key.1 >= min.1 && key.1 <= max.1 {
+                    items.extend(chunk.get_in_volume(vol));
+                }
+            }
+        } else {
+            for x in min.0..max.0 + 1 {
+                for y in min.1..max.1 + 1 {
+                    match self.chunks.get(&(x, y)) {
+                        Some(chunk) => items.extend(chunk.get_in_volume(vol)),
+                        None => {}
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    /// Returns all items within `radius` of `center`, from all the
+    /// chunks the circle overlaps, in no particular order.
+    pub fn get_in_radius(&self, center: [T; 2], radius: T) -> Vec<&P> {
+        let min = [center[0] - radius, center[1] - radius];
+        let max = [center[0] + radius, center[1] + radius];
+
+        self.get_in_volume(&Volume::new(min, max)).into_iter()
+            .filter(|item| geom::point_in_sphere(&item.quadtree_index(), &center, radius))
+            .collect()
+    }
+}
@@ -0,0 +1,188 @@
+//! A two-dimensional range tree over a static set of points.
+
+use SpatialKey;
+use std::cmp::Ordering;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// A trait that must be implemented by types that are going to be
+/// stored in a `RangeTree`.
+pub trait Index<T: SpatialKey> {
+    /// Returns the position of `self` in order of `[x, y]`.
+    fn rangetree_index(&self) -> [T; 2];
+}
+
+impl<T: SpatialKey> Index<T> for [T; 2] {
+    fn rangetree_index(&self) -> [T; 2] {
+        *self
+    }
+}
+
+/// A static range tree over points in 2D-space, answering axis-aligned
+/// range queries in `O(log^2 n)` time plus the number of items
+/// reported, whatever the distribution of the points. That makes it
+/// suited for analytics over large data sets, where the worst cases of
+/// a quadtree are too slow.
+///
+/// The points are sorted by x and split in halves recursively, and
+/// every range of that split keeps its points sorted by y, for
+/// `O(n log n)` space in total. The ranges of each level of the split
+/// are stored together in a single array.
+pub struct RangeTree<T: SpatialKey, P: Index<T> + Clone> {
+    /// Items, sorted by x.
+    items: Vec<P>,
+    /// The x coordinates of the items.
+    xs: Vec<T>,
+    /// For each level, the items of every range of that level sorted by
+    /// y, as indices into `items`.
+    levels: Vec<Vec<usize>>,
+    /// The y coordinates of the items of `levels`.
+    level_ys: Vec<Vec<T>>
+}
+
+/// Returns the index of the first of the sorted `values` that isn't
+/// less than `value`.
+fn lower_bound<T: SpatialKey>(values: &[T], value: T) -> usize {
+    let (mut lo, mut hi) = (0, values.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if values[mid] < value { lo = mid + 1; } else { hi = mid; }
+    }
+    lo
+}
+
+/// Returns the index of the first of the sorted `values` that is
+/// greater than `value`.
+fn upper_bound<T: SpatialKey>(values: &[T], value: T) -> usize {
+    let (mut lo, mut hi) = (0, values.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if values[mid] <= value { lo = mid + 1; } else { hi = mid; }
+    }
+    lo
+}
+
+impl<T: SpatialKey, P: Index<T> + Clone> RangeTree<T, P> {
+    /// Builds a tree over the points `items`.
+    pub fn new(items: &[P]) -> RangeTree<T, P> {
+        let _span = trace_span!("rangetree build", items = items.len());
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let positions: Vec<[T; 2]> = items.iter().map(|item| item.rangetree_index()).collect();
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        order.sort_by(|&a, &b| positions[a][0].partial_cmp(&positions[b][0]).unwrap_or(Ordering::Equal));
+
+        let mut depth = 1;
+        while (1 << (depth - 1)) < items.len() {
+            depth += 1;
+        }
+        let mut tree = RangeTree {
+            items: order.iter().map(|&i| items[i].clone()).collect(),
+            xs: order.iter().map(|&i| positions[i][0]).collect(),
+            levels: (0..depth).map(|_| vec![0; items.len()]).collect(),
+            level_ys: Vec::new()
+        };
+        let ys: Vec<T> = order.iter().map(|&i| positions[i][1]).collect();
+        if !items.is_empty() {
+            tree.build(&ys, 0, items.len(), 0);
+        }
+        tree.level_ys = tree.levels.iter().map(|level| level.iter().map(|&i| ys[i]).collect()).collect();
+        trace_event!("rangetree build: {} items in {:?}", tree.items.len(), start.elapsed());
+        tree
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the number of items inside the rectangle from `min` to
+    /// `max`, in `O(log^2 n)` time.
+    pub fn count(&self, min: &[T; 2], max: &[T; 2]) -> usize {
+        let mut count = 0;
+        self.canonical(min, max, &mut |level, start, end| {
+            let ys = &self.level_ys[level][start..end];
+            let first = lower_bound(ys, min[1]);
+            count += upper_bound(ys, max[1]).saturating_sub(first);
+        });
+        count
+    }
+
+    /// Returns all items inside the rectangle from `min` to `max`, in
+    /// no particular order.
+    pub fn get_in_range<'a>(&'a self, min: &[T; 2], max: &[T; 2]) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        self.canonical(min, max, &mut |level, start, end| {
+            let ys = &self.level_ys[level][start..end];
+            let first = lower_bound(ys, min[1]);
+            let last = upper_bound(ys, max[1]);
+            for &i in self.levels[level][start + first..start + last.max(first)].iter() {
+                items.push(&self.items[i]);
+            }
+        });
+        items
+    }
+
+    /// Sorts the items `start..end` by y into `level`, after doing the
+    /// same for both halves of the range on the level below.
+    fn build(&mut self, ys: &[T], start: usize, end: usize, level: usize) {
+        if end - start == 1 {
+            self.levels[level][start] = start;
+            return;
+        }
+
+        let mid = start + (end - start) / 2;
+        self.build(ys, start, mid, level + 1);
+        self.build(ys, mid, end, level + 1);
+
+        // Merge the sorted halves.
+        let (mut a, mut b) = (start, mid);
+        for i in start..end {
+            let next = {
+                let below = &self.levels[level + 1];
+                if b == end || (a < mid && ys[below[a]] <= ys[below[b]]) {
+                    a += 1;
+                    below[a - 1]
+                } else {
+                    b += 1;
+                    below[b - 1]
+                }
+            };
+            self.levels[level][i] = next;
+        }
+    }
+
+    /// Passes the level and the bounds of every range that together
+    /// cover the items within the x bounds of the rectangle from `min`
+    /// to `max` to `f`.
+    fn canonical<F: FnMut(usize, usize, usize)>(&self, min: &[T; 2], max: &[T; 2], f: &mut F) {
+        let first = lower_bound(&self.xs, min[0]);
+        let last = upper_bound(&self.xs, max[0]);
+        if first < last {
+            self.canonical_node(first, last, 0, self.items.len(), 0, f);
+        }
+    }
+
+    /// Covers the items `first..last` within the range `start..end`.
+    fn canonical_node<F: FnMut(usize, usize, usize)>(&self, first: usize, last: usize,
+                                                     start: usize, end: usize, level: usize, f: &mut F) {
+        if last <= start || first >= end {
+            return;
+        }
+        if first <= start && last >= end {
+            f(level, start, end);
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        self.canonical_node(first, last, start, mid, level + 1, f);
+        self.canonical_node(first, last, mid, end, level + 1, f);
+    }
+}
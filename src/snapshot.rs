@@ -0,0 +1,257 @@
+//! Compact binary snapshots of populated trees.
+//!
+//! `Quadtree::save_to` and `Octree::save_to` write the layout of the
+//! nodes of a tree along with its items, and `load_from` restores the
+//! tree exactly as it was, without inserting the items again, so a
+//! large tree built offline can be shipped with an application and
+//! loaded at once.
+//!
+//! A snapshot starts with `MAGIC`, the `VERSION` of the format and the
+//! number of dimensions of the tree, and all numbers in it are little
+//! endian. The coordinates, items and node data of the tree are written
+//! with their `Snapshot` implementations, which are provided for the
+//! primitive types and can be written for your own.
+//!
+//! ```ignore
+//! impl Snapshot for Monster {
+//!     fn write_snapshot(&self, out: &mut Vec<u8>) {
+//!         self.position.write_snapshot(out);
+//!         self.health.write_snapshot(out);
+//!     }
+//!
+//!     fn read_snapshot(input: &mut &[u8]) -> Result<Monster, SpatialError> {
+//!         let position = match Snapshot::read_snapshot(input) {
+//!             Ok(position) => position,
+//!             Err(err) => return Err(err)
+//!         };
+//!         Snapshot::read_snapshot(input).map(|health| Monster { position: position, health: health })
+//!     }
+//! }
+//! ```
+
+use SpatialError;
+
+/// The bytes every snapshot starts with.
+pub static MAGIC: &[u8; 4] = b"SPAT";
+
+/// The version of the format written by `save_to`. Snapshots of other
+/// versions are refused by `load_from`.
+pub static VERSION: u8 = 1;
+
+/// A trait for values that can be written to and read from a snapshot.
+pub trait Snapshot: Sized {
+    /// Appends the bytes of `self` to `out`.
+    fn write_snapshot(&self, out: &mut Vec<u8>);
+
+    /// Reads a value from the front of `input`, advancing it past the
+    /// bytes read, or returns `SpatialError::Serialization` if they
+    /// don't make up a value.
+    fn read_snapshot(input: &mut &[u8]) -> Result<Self, SpatialError>;
+}
+
+/// Returns the next `n` bytes of `input`, advancing it past them, or an
+/// error if it ends before them.
+pub fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], SpatialError> {
+    if input.len() < n {
+        return Err(SpatialError::Serialization("snapshot ends early".to_string()));
+    }
+    let (bytes, rest) = input.split_at(n);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! snapshot_integer {
+    ($($t:ty: $size:expr),*) => {
+        $(impl Snapshot for $t {
+            fn write_snapshot(&self, out: &mut Vec<u8>) {
+                for i in 0..$size {
+                    out.push(((*self as u64) >> (8 * i)) as u8);
+                }
+            }
+
+            fn read_snapshot(input: &mut &[u8]) -> Result<$t, SpatialError> {
+                take(input, $size).map(|bytes| {
+                    bytes.iter().enumerate().fold(0u64, |value, (i, &byte)| value | (byte as u64) << (8 * i)) as $t
+                })
+            }
+        })*
+    }
+}
+
+snapshot_integer!(u8: 1, u16: 2, u32: 4, u64: 8, i8: 1, i16: 2, i32: 4, i64: 8);
+
+impl Snapshot for usize {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        (*self as u64).write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<usize, SpatialError> {
+        u64::read_snapshot(input).map(|value| value as usize)
+    }
+}
+
+impl Snapshot for f32 {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self.to_bits().write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<f32, SpatialError> {
+        u32::read_snapshot(input).map(f32::from_bits)
+    }
+}
+
+impl Snapshot for f64 {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self.to_bits().write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<f64, SpatialError> {
+        u64::read_snapshot(input).map(f64::from_bits)
+    }
+}
+
+impl Snapshot for bool {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<bool, SpatialError> {
+        match u8::read_snapshot(input) {
+            Ok(0) => Ok(false),
+            Ok(1) => Ok(true),
+            Ok(byte) => Err(SpatialError::Serialization(format!("invalid boolean {}", byte))),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl Snapshot for () {
+    fn write_snapshot(&self, _: &mut Vec<u8>) {}
+
+    fn read_snapshot(_: &mut &[u8]) -> Result<(), SpatialError> {
+        Ok(())
+    }
+}
+
+impl Snapshot for String {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self.len().write_snapshot(out);
+        out.extend(self.bytes());
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<String, SpatialError> {
+        let len = match usize::read_snapshot(input) {
+            Ok(len) => len,
+            Err(err) => return Err(err)
+        };
+        match take(input, len) {
+            Ok(bytes) => String::from_utf8(bytes.to_vec())
+                .map_err(|_| SpatialError::Serialization("string isn't UTF-8".to_string())),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Vec<T> {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self.len().write_snapshot(out);
+        for value in self.iter() {
+            value.write_snapshot(out);
+        }
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<Vec<T>, SpatialError> {
+        let len = match usize::read_snapshot(input) {
+            Ok(len) => len,
+            Err(err) => return Err(err)
+        };
+        // The length isn't trusted for allocating, as the values may
+        // well run out before it.
+        let mut values = Vec::new();
+        for _ in 0..len {
+            match T::read_snapshot(input) {
+                Ok(value) => values.push(value),
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Snapshot> Snapshot for [T; 2] {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self[0].write_snapshot(out);
+        self[1].write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<[T; 2], SpatialError> {
+        let x = match T::read_snapshot(input) {
+            Ok(x) => x,
+            Err(err) => return Err(err)
+        };
+        T::read_snapshot(input).map(|y| [x, y])
+    }
+}
+
+impl<T: Snapshot> Snapshot for [T; 3] {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self[0].write_snapshot(out);
+        self[1].write_snapshot(out);
+        self[2].write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<[T; 3], SpatialError> {
+        let x = match T::read_snapshot(input) {
+            Ok(x) => x,
+            Err(err) => return Err(err)
+        };
+        let y = match T::read_snapshot(input) {
+            Ok(y) => y,
+            Err(err) => return Err(err)
+        };
+        T::read_snapshot(input).map(|z| [x, y, z])
+    }
+}
+
+impl<A: Snapshot, B: Snapshot> Snapshot for (A, B) {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        self.0.write_snapshot(out);
+        self.1.write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<(A, B), SpatialError> {
+        let a = match A::read_snapshot(input) {
+            Ok(a) => a,
+            Err(err) => return Err(err)
+        };
+        B::read_snapshot(input).map(|b| (a, b))
+    }
+}
+
+/// Appends the header of a snapshot of a tree of `dimensions` to `out`.
+pub fn write_header(out: &mut Vec<u8>, dimensions: u8) {
+    out.extend(MAGIC.iter().cloned());
+    out.push(VERSION);
+    out.push(dimensions);
+}
+
+/// Reads the header of a snapshot from the front of `input`, or returns
+/// an error if it isn't the header of a snapshot of a tree of
+/// `dimensions` in the current format.
+pub fn read_header(input: &mut &[u8], dimensions: u8) -> Result<(), SpatialError> {
+    let header = match take(input, MAGIC.len() + 2) {
+        Ok(header) => header,
+        Err(_) => return Err(SpatialError::Serialization("not a snapshot".to_string()))
+    };
+    if header[..MAGIC.len()] != MAGIC[..] {
+        return Err(SpatialError::Serialization("not a snapshot".to_string()));
+    }
+    if header[MAGIC.len()] != VERSION {
+        return Err(SpatialError::Serialization(format!("unsupported snapshot version {}", header[MAGIC.len()])));
+    }
+    if header[MAGIC.len() + 1] != dimensions {
+        return Err(SpatialError::Serialization(format!("snapshot of a tree of {} dimensions, not {}",
+                                                       header[MAGIC.len() + 1], dimensions)));
+    }
+    Ok(())
+}
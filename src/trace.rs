@@ -0,0 +1,40 @@
+//! Instrumentation behind the `tracing` feature.
+//!
+//! With the feature enabled, the builds and queries of the trees run in
+//! `tracing` spans at the trace level, under the `spatial` target, named
+//! after the structure and the operation, such as `quadtree query` or
+//! `kdtree build`, and with their inputs, such as the queried volume or
+//! the number of items, as fields. Subscribers time the spans from when
+//! they're entered until they're closed. Within them, events report the
+//! number of items found or built and the time taken, along with
+//! inserts, subdivisions, merges and rebuild steps, so they show up in
+//! whatever subscriber the application has installed.
+//!
+//! Without the feature, `trace_span!` and `trace_event!` expand to
+//! nothing, and their arguments are never evaluated.
+
+/// Enters a span, which is left when the returned guard is dropped.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($arg:tt)*) => (::tracing::trace_span!(target: "spatial", $($arg)*).entered())
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => ($crate::trace::NoSpan)
+}
+
+/// Records an event in the current span.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($arg:tt)*) => (::tracing::trace!(target: "spatial", $($arg)*))
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($arg:tt)*) => (())
+}
+
+/// What `trace_span!` returns without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub struct NoSpan;
@@ -0,0 +1,219 @@
+//! A vantage-point tree over items of any metric space.
+
+use SpatialKey;
+use num::traits::Float;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// The maximum number of items in a range of the tree that is scanned
+/// rather than split further.
+static LEAF_SIZE: usize = 8;
+
+/// An item index paired with its distance, ordered so that the
+/// farthest one is popped first from a `BinaryHeap`.
+struct Farthest<T: SpatialKey> {
+    distance: T,
+    index: usize
+}
+
+impl<T: SpatialKey> PartialEq for Farthest<T> {
+    fn eq(&self, other: &Farthest<T>) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl<T: SpatialKey> Eq for Farthest<T> {}
+
+impl<T: SpatialKey> PartialOrd for Farthest<T> {
+    fn partial_cmp(&self, other: &Farthest<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: SpatialKey> Ord for Farthest<T> {
+    fn cmp(&self, other: &Farthest<T>) -> Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A vantage-point tree over a fixed set of items, for nearest-neighbor
+/// and radius queries in any metric space, such as strings under edit
+/// distance or embeddings under angular distance. The tree only ever
+/// measures distances with the function it was built with, which must
+/// be a metric: symmetric, zero only between equal items, and obeying
+/// the triangle inequality.
+///
+/// Every range of the tree picks its first item as the vantage point,
+/// and splits the rest into the half closest to it and the half
+/// farthest from it. Like the `KdTree`, it's stored implicitly in the
+/// order of its items.
+pub struct VpTree<T: SpatialKey, P, F: Fn(&P, &P) -> T> {
+    /// Items, ordered so that each subtree covers a range.
+    items: Vec<P>,
+    /// The distance separating the two halves of the range starting at
+    /// each index.
+    thresholds: Vec<T>,
+    distance: F
+}
+
+impl<T: SpatialKey, P, F: Fn(&P, &P) -> T> VpTree<T, P, F> {
+    /// Builds a tree over `items`, measuring their distances with
+    /// `distance`.
+    pub fn new(items: Vec<P>, distance: F) -> VpTree<T, P, F> {
+        let _span = trace_span!("vptree build", items = items.len());
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        let mut thresholds = vec![Float::infinity(); items.len()];
+        split(&items, &distance, &mut order, &mut thresholds);
+
+        // Move the items into the order of the tree.
+        let mut slots: Vec<Option<P>> = items.into_iter().map(Some).collect();
+        let tree = VpTree {
+            items: order.iter().map(|&i| slots[i].take().unwrap()).collect(),
+            thresholds: thresholds,
+            distance: distance
+        };
+        trace_event!("vptree build: {} items in {:?}", tree.items.len(), start.elapsed());
+        tree
+    }
+
+    /// Returns the number of items in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree holds no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the item closest to `query`, or `None` if the tree is
+    /// empty.
+    pub fn nearest(&self, query: &P) -> Option<&P> {
+        self.nearest_n(query, 1).pop()
+    }
+
+    /// Returns up to `k` items closest to `query`, ordered from the
+    /// closest to the farthest.
+    pub fn nearest_n(&self, query: &P, k: usize) -> Vec<&P> {
+        let mut found = BinaryHeap::new();
+        if k > 0 {
+            self.nearest_node(query, k, 0, self.items.len(), &mut found);
+        }
+        found.into_sorted_vec().into_iter().map(|entry| &self.items[entry.index]).collect()
+    }
+
+    /// Returns all items within `radius` of `query`, in no particular
+    /// order.
+    pub fn get_in_radius<'a>(&'a self, query: &P, radius: T) -> Vec<&'a P> {
+        let mut items = Vec::new();
+        self.radius_node(query, radius, 0, self.items.len(), &mut items);
+        items
+    }
+
+    /// Offers the item at `i`, at `distance` from the query, to the `k`
+    /// closest items found.
+    fn offer(&self, k: usize, i: usize, distance: T, found: &mut BinaryHeap<Farthest<T>>) {
+        if found.len() < k {
+            found.push(Farthest { distance: distance, index: i });
+        } else if found.peek().is_some_and(|farthest| distance < farthest.distance) {
+            found.pop();
+            found.push(Farthest { distance: distance, index: i });
+        }
+    }
+
+    /// Searches the subtree over the items `start..end` for the `k`
+    /// closest items to `query`, visiting the half on the side of
+    /// `query` first.
+    fn nearest_node(&self, query: &P, k: usize, start: usize, end: usize, found: &mut BinaryHeap<Farthest<T>>) {
+        if end - start <= LEAF_SIZE {
+            for i in start..end {
+                self.offer(k, i, (self.distance)(query, &self.items[i]), found);
+            }
+            return;
+        }
+
+        let d = (self.distance)(query, &self.items[start]);
+        self.offer(k, start, d, found);
+
+        let threshold = self.thresholds[start];
+        let mid = middle(start, end);
+        let (near, far) = if d < threshold {
+            ((start + 1, mid), (mid, end))
+        } else {
+            ((mid, end), (start + 1, mid))
+        };
+        self.nearest_node(query, k, near.0, near.1, found);
+
+        // By the triangle inequality, the far half can only hold items
+        // closer than the farthest one found if the query is closer
+        // than that to the threshold.
+        let tau = if found.len() < k { Float::infinity() } else { found.peek().unwrap().distance };
+        if (d - threshold).abs() <= tau {
+            self.nearest_node(query, k, far.0, far.1, found);
+        }
+    }
+
+    /// Collects the items of the subtree over the items `start..end`
+    /// within `radius` of `query`.
+    fn radius_node<'a>(&'a self, query: &P, radius: T, start: usize, end: usize, items: &mut Vec<&'a P>) {
+        if end - start <= LEAF_SIZE {
+            for i in start..end {
+                if (self.distance)(query, &self.items[i]) <= radius {
+                    items.push(&self.items[i]);
+                }
+            }
+            return;
+        }
+
+        let d = (self.distance)(query, &self.items[start]);
+        if d <= radius {
+            items.push(&self.items[start]);
+        }
+
+        let threshold = self.thresholds[start];
+        let mid = middle(start, end);
+        if d - radius <= threshold {
+            self.radius_node(query, radius, start + 1, mid, items);
+        }
+        if d + radius >= threshold {
+            self.radius_node(query, radius, mid, end, items);
+        }
+    }
+}
+
+/// Returns the start of the far half of the range `start..end`, whose
+/// vantage point is at `start`.
+#[inline]
+fn middle(start: usize, end: usize) -> usize {
+    start + 1 + (end - start - 1) / 2
+}
+
+/// Splits the subtree over the items `order`, recording its threshold
+/// in `thresholds` at the position of its vantage point.
+fn split<T: SpatialKey, P, F: Fn(&P, &P) -> T>(items: &[P], distance: &F, order: &mut [usize], thresholds: &mut [T]) {
+    if order.len() <= LEAF_SIZE {
+        return;
+    }
+
+    let vantage = &items[order[0]];
+    let mut rest: Vec<(T, usize)> = order[1..].iter().map(|&i| (distance(vantage, &items[i]), i)).collect();
+    rest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    for (slot, &(_, i)) in order[1..].iter_mut().zip(rest.iter()) {
+        *slot = i;
+    }
+
+    let mid = middle(0, order.len());
+    thresholds[0] = rest[mid - 1].0;
+
+    let (inner, outer) = order.split_at_mut(mid);
+    let (inner_thresholds, outer_thresholds) = thresholds.split_at_mut(mid);
+    split(items, distance, &mut inner[1..], &mut inner_thresholds[1..]);
+    split(items, distance, outer, outer_thresholds);
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "rkyv")]
+#![allow(clippy::redundant_field_names)]
+
+extern crate spatial;
+extern crate rkyv;
+
+use spatial::{Quadtree, Octree, SpatialError};
+use spatial::quadtree::{self, FlatQuadtree, FlatNode};
+use spatial::octree::{self, FlatOctree};
+use rkyv::{Archive, Serialize};
+
+#[derive(Clone, Archive, Serialize)]
+#[archive(check_bytes)]
+struct Point {
+    position: [f32; 3],
+    id: u32
+}
+
+impl quadtree::Index<f32> for Point {
+    fn quadtree_index(&self) -> [f32; 2] {
+        [self.position[0], self.position[1]]
+    }
+}
+
+impl octree::Index<f32> for Point {
+    fn octree_index(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+fn points() -> Vec<Point> {
+    (0..200).map(|i| Point {
+        position: [(i * 7 % 100) as f32 / 10.0, (i * 13 % 100) as f32 / 10.0, (i % 10) as f32],
+        id: i
+    }).collect()
+}
+
+fn ids<I: Iterator<Item=u32>>(found: I) -> Vec<u32> {
+    let mut ids: Vec<u32> = found.collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn archive_quadtree_queries() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Point> = Quadtree::with_capacity(vol, 4);
+    for point in points().into_iter() {
+        assert!(tree.insert(point).is_ok());
+    }
+
+    let bytes = tree.to_archive().unwrap();
+    let archived = FlatQuadtree::<f32, Point>::archived(&bytes).unwrap();
+    assert_eq!(archived.len(), tree.len());
+    assert_eq!(archived.nodes.len(), tree.node_count());
+    assert_eq!((archived.bounds().min(), archived.bounds().max()), (vol.min(), vol.max()));
+
+    let query = quadtree::Volume::new([2.0, 3.0], [6.5, 7.0]);
+    assert_eq!(ids(archived.get_in_volume(&query).iter().map(|p| p.id)),
+               ids(tree.get_in_volume(&query).iter().map(|p| p.id)));
+    assert_eq!(ids(archived.get_in_radius([5.0, 5.0], 2.5).iter().map(|p| p.id)),
+               ids(tree.get_in_radius([5.0, 5.0], 2.5).iter().map(|p| p.id)));
+    assert!(!archived.get_in_radius([5.0, 5.0], 2.5).is_empty());
+}
+
+#[test]
+fn archive_octree_queries() {
+    let vol = octree::Volume::new([0.0; 3], [10.0; 3]);
+    let mut tree: Octree<f32, Point> = Octree::with_capacity(vol, 4);
+    for point in points().into_iter() {
+        assert!(tree.insert(point).is_ok());
+    }
+
+    let bytes = tree.to_archive().unwrap();
+    let archived = FlatOctree::<f32, Point>::archived(&bytes).unwrap();
+    assert_eq!(archived.len(), tree.len());
+    assert_eq!(archived.nodes.len(), tree.node_count());
+
+    let query = octree::Volume::new([2.0, 3.0, 1.0], [6.5, 7.0, 5.0]);
+    assert_eq!(ids(archived.get_in_volume(&query).iter().map(|p| p.id)),
+               ids(tree.get_in_volume(&query).iter().map(|p| p.id)));
+    assert_eq!(ids(archived.get_in_radius([5.0, 5.0, 5.0], 3.0).iter().map(|p| p.id)),
+               ids(tree.get_in_radius([5.0, 5.0, 5.0], 3.0).iter().map(|p| p.id)));
+}
+
+#[test]
+fn archive_malformed() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Point> = Quadtree::with_capacity(vol, 4);
+    for point in points().into_iter() {
+        assert!(tree.insert(point).is_ok());
+    }
+    let bytes = tree.to_archive().unwrap();
+    match FlatQuadtree::<f32, Point>::archived(&bytes[..bytes.len() - 8]) {
+        Err(SpatialError::Serialization(_)) => {},
+        other => panic!("truncated archive read as {:?}", other.map(|tree| tree.len()))
+    }
+
+    // Nodes that don't make up a tree are refused, even if the archive
+    // itself is valid.
+    let node = |first_child, first_item, item_count| FlatNode {
+        min: [0.0f32, 0.0],
+        max: [1.0, 1.0],
+        first_child: first_child,
+        first_item: first_item,
+        item_count: item_count
+    };
+    let cyclic = FlatQuadtree { nodes: vec![node(0, 0, 0)], positions: vec![], items: Vec::<Point>::new() };
+    let overflowing = FlatQuadtree { nodes: vec![node(u32::MAX, 0, 1)], positions: vec![], items: Vec::<Point>::new() };
+    let empty = FlatQuadtree { nodes: vec![], positions: vec![], items: Vec::<Point>::new() };
+    // Two nodes sharing the same quadrants would make a query visit them
+    // twice, and nodes nothing refers to aren't part of the tree.
+    let mut nodes = vec![node(1, 0, 0), node(5, 0, 0), node(5, 0, 0)];
+    nodes.extend((0..6).map(|_| node(u32::MAX, 0, 0)));
+    let shared = FlatQuadtree { nodes: nodes, positions: vec![], items: Vec::<Point>::new() };
+    let orphaned = FlatQuadtree { nodes: vec![node(u32::MAX, 0, 0); 5], positions: vec![], items: Vec::<Point>::new() };
+    for flat in [cyclic, overflowing, empty, shared, orphaned].iter() {
+        let bytes = rkyv::to_bytes::<_, 256>(flat).unwrap();
+        match FlatQuadtree::<f32, Point>::archived(&bytes) {
+            Err(SpatialError::Serialization(_)) => {},
+            other => panic!("malformed tree read as {:?}", other.map(|tree| tree.len()))
+        }
+    }
+}
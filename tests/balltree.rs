@@ -0,0 +1,51 @@
+extern crate spatial;
+
+use spatial::BallTree;
+
+fn distance2(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).fold(0.0, |sum, (x, y)| sum + (x - y) * (x - y))
+}
+
+/// Returns `n` deterministic pseudo-random points of `dimensions`
+/// coordinates in `[0, 1)`.
+fn random_points(n: usize, dimensions: usize) -> Vec<Vec<f64>> {
+    let mut state: u32 = 12345;
+    (0..n).map(|_| (0..dimensions).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 8) as f64 / (1 << 24) as f64
+    }).collect()).collect()
+}
+
+#[test]
+fn balltree_nearest_n_matches_scan() {
+    let points = random_points(500, 16);
+    let tree = BallTree::new(16, &points);
+    assert_eq!(tree.len(), 500);
+    assert_eq!(tree.dimensions(), 16);
+
+    for query in random_points(5, 16).iter().chain([vec![2.0; 16], vec![-1.0; 16]].iter()) {
+        let mut expected: Vec<f64> = points.iter().map(|p| distance2(p, query)).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let found: Vec<f64> = tree.nearest_n(query, 10).into_iter().map(|p| distance2(p, query)).collect();
+        assert_eq!(found, &expected[..10]);
+        assert_eq!(distance2(tree.nearest(query).unwrap(), query), expected[0]);
+    }
+}
+
+#[test]
+fn balltree_get_in_radius() {
+    let points = random_points(300, 8);
+    let tree = BallTree::new(8, &points);
+
+    for &radius in [0.0, 0.5, 0.8, 10.0].iter() {
+        let center = &points[42];
+        let expected = points.iter().filter(|p| distance2(p, center) <= radius * radius).count();
+        assert_eq!(tree.get_in_radius(center, radius).len(), expected);
+    }
+    assert_eq!(tree.get_in_radius(&[0.5; 8], 10.0).len(), 300);
+
+    let empty: BallTree<f64, [f64; 2]> = BallTree::new(2, &[]);
+    assert!(empty.is_empty());
+    assert!(empty.nearest(&[0.0, 0.0]).is_none());
+    assert!(empty.get_in_radius(&[0.0, 0.0], 1.0).is_empty());
+}
@@ -0,0 +1,184 @@
+extern crate spatial;
+
+use spatial::bvh::{Bvh, Primitive};
+use spatial::octree::{Ray, Volume, BoundedIndex};
+
+/// A sphere, for a hierarchy over primitives other than triangles.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Sphere {
+    center: [f32; 3],
+    radius: f32
+}
+
+impl BoundedIndex<f32> for Sphere {
+    fn octree_bounds(&self) -> Volume<f32> {
+        let c = self.center;
+        let r = self.radius;
+        Volume::new([c[0] - r, c[1] - r, c[2] - r], [c[0] + r, c[1] + r, c[2] + r])
+    }
+}
+
+impl Primitive<f32> for Sphere {
+    fn intersect_ray(&self, ray: &Ray<f32>) -> Option<(f32, f32, f32)> {
+        let (o, d) = (ray.origin(), ray.direction());
+        let oc = [o[0] - self.center[0], o[1] - self.center[1], o[2] - self.center[2]];
+        let a = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+        let b = oc[0] * d[0] + oc[1] * d[1] + oc[2] * d[2];
+        let c = oc[0] * oc[0] + oc[1] * oc[1] + oc[2] * oc[2] - self.radius * self.radius;
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / a;
+        if t < 0.0 { None } else { Some((t, 0.0, 0.0)) }
+    }
+
+    fn closest_point(&self, p: &[f32; 3]) -> [f32; 3] {
+        let d = [p[0] - self.center[0], p[1] - self.center[1], p[2] - self.center[2]];
+        let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        let s = self.radius / length;
+        [self.center[0] + d[0] * s, self.center[1] + d[1] * s, self.center[2] + d[2] * s]
+    }
+}
+
+/// A unit square in the plane `z`, split into two triangles.
+fn square(z: f32) -> Vec<[[f32; 3]; 3]> {
+    vec![[[0.0, 0.0, z], [1.0, 0.0, z], [1.0, 1.0, z]],
+         [[0.0, 0.0, z], [1.0, 1.0, z], [0.0, 1.0, z]]]
+}
+
+#[test]
+fn bvh_intersect_ray() {
+    let mut triangles = Vec::new();
+    for i in 0..8 {
+        triangles.extend(square(i as f32));
+    }
+    let bvh = Bvh::new(triangles);
+    assert_eq!(bvh.len(), 16);
+    assert_eq!(bvh.bounds().unwrap().max(), [1.0, 1.0, 7.0]);
+    
+    // Shooting down the z axis hits the nearest square first.
+    let hit = bvh.intersect_ray(&Ray::new([0.75, 0.25, 10.0], [0.0, 0.0, -1.0])).unwrap();
+    assert_eq!(hit.primitive, 14);
+    assert_eq!(hit.t, 3.0);
+    assert_eq!(hit.point, [0.75, 0.25, 7.0]);
+    assert_eq!((hit.u, hit.v), (0.5, 0.25));
+    
+    let hit = bvh.intersect_ray(&Ray::new([0.25, 0.75, -1.0], [0.0, 0.0, 1.0])).unwrap();
+    assert_eq!(hit.primitive, 1);
+    assert_eq!(hit.t, 1.0);
+    
+    assert!(bvh.intersect_ray(&Ray::new([2.0, 0.5, 10.0], [0.0, 0.0, -1.0])).is_none());
+    assert!(Bvh::<f32>::new(Vec::new()).intersect_ray(&Ray::new([0.0; 3], [1.0; 3])).is_none());
+    assert!(!bvh.is_empty());
+    assert!(Bvh::<f32>::new(Vec::new()).is_empty());
+}
+
+#[test]
+fn bvh_nan_primitive() {
+    let nan = f32::NAN;
+    let mut triangles = Vec::new();
+    for i in 0..8 {
+        triangles.extend(square(i as f32));
+        triangles.push([[nan; 3]; 3]);
+    }
+    let bvh = Bvh::new(triangles);
+    assert_eq!(bvh.len(), 24);
+    
+    let hit = bvh.intersect_ray(&Ray::new([0.75, 0.25, 10.0], [0.0, 0.0, -1.0])).unwrap();
+    assert_eq!(hit.point, [0.75, 0.25, 7.0]);
+}
+
+#[test]
+fn bvh_closest_point() {
+    let mut triangles = Vec::new();
+    for i in 0..8 {
+        triangles.extend(square(i as f32));
+    }
+    let bvh = Bvh::new(triangles);
+    
+    // Above the face of the topmost square.
+    let (p, primitive, distance) = bvh.closest_point(&[0.75, 0.25, 9.0]).unwrap();
+    assert_eq!((p, primitive, distance), ([0.75, 0.25, 7.0], 14, 2.0));
+    
+    // Beside an edge of a square in the middle.
+    let (p, primitive, distance) = bvh.closest_point(&[2.0, 0.5, 3.0]).unwrap();
+    assert_eq!((p, primitive, distance), ([1.0, 0.5, 3.0], 6, 1.0));
+    
+    // Off a corner.
+    let (p, _, _) = bvh.closest_point(&[-1.0, -1.0, -1.0]).unwrap();
+    assert_eq!(p, [0.0, 0.0, 0.0]);
+    
+    assert!(Bvh::<f32>::new(Vec::new()).closest_point(&[0.0; 3]).is_none());
+}
+
+#[test]
+fn bvh_intersect_ray_filtered() {
+    let mut triangles = Vec::new();
+    let mut layers = Vec::new();
+    for i in 0..8 {
+        triangles.extend(square(i as f32));
+        layers.extend(vec![1 << (i % 2); 2]);
+    }
+    let bvh = Bvh::with_layers(triangles, layers);
+    let ray = Ray::new([0.75, 0.25, 10.0], [0.0, 0.0, -1.0]);
+    
+    // The topmost square is in layer 2, the one below it in layer 1.
+    assert_eq!(bvh.intersect_ray_filtered(&ray, 100.0, !0).unwrap().primitive, 14);
+    assert_eq!(bvh.intersect_ray_filtered(&ray, 100.0, 1).unwrap().primitive, 12);
+    assert!(bvh.intersect_ray_filtered(&ray, 100.0, 4).is_none());
+    
+    assert_eq!(bvh.intersect_ray_filtered(&ray, 4.0, 1).unwrap().t, 4.0);
+    assert!(bvh.intersect_ray_filtered(&ray, 3.5, 1).is_none());
+}
+
+#[test]
+fn bvh_raycast_many() {
+    let mut triangles = Vec::new();
+    for i in 0..8 {
+        triangles.extend(square(i as f32));
+    }
+    let bvh = Bvh::new(triangles);
+    
+    let mut rays = Vec::new();
+    for i in 0..20 {
+        let x = i as f32 * 0.1 - 0.5;
+        rays.push(Ray::new([x, 0.25, 10.0], [0.0, 0.0, -1.0]));
+        rays.push(Ray::new([x, 0.75, -1.0], [0.0, 0.0, 1.0]));
+    }
+    
+    let hits = bvh.raycast_many(&rays);
+    assert_eq!(hits.len(), rays.len());
+    for (ray, hit) in rays.iter().zip(hits.iter()) {
+        let expected = bvh.intersect_ray(ray);
+        assert_eq!(hit.map(|hit| (hit.primitive, hit.t)), expected.map(|hit| (hit.primitive, hit.t)));
+    }
+    assert!(hits.iter().any(|hit| hit.is_none()));
+    assert!(hits.iter().any(|hit| hit.is_some()));
+}
+
+#[test]
+fn bvh_send_sync() {
+    fn assert_send_sync<X: Send + Sync>() {}
+    assert_send_sync::<Bvh<f32>>();
+    assert_send_sync::<Bvh<f64>>();
+}
+
+#[test]
+fn bvh_custom_primitive() {
+    let spheres: Vec<Sphere> = (0..20).map(|i| Sphere { center: [i as f32 * 4.0, 0.0, 0.0], radius: 1.0 }).collect();
+    let bvh = Bvh::new(spheres);
+    assert_eq!(bvh.len(), 20);
+    assert_eq!(bvh.primitives()[3].center, [12.0, 0.0, 0.0]);
+    assert_eq!(bvh.bounds().unwrap().max(), [77.0, 1.0, 1.0]);
+    
+    let hit = bvh.intersect_ray(&Ray::new([13.0, 0.0, 10.0], [0.0, 0.0, -1.0])).unwrap();
+    assert_eq!((hit.primitive, hit.t, hit.point), (3, 10.0, [13.0, 0.0, 0.0]));
+    let hit = bvh.intersect_ray(&Ray::new([-10.0, 0.0, 0.0], [1.0, 0.0, 0.0])).unwrap();
+    assert_eq!((hit.primitive, hit.t), (0, 9.0));
+    assert!(bvh.intersect_ray(&Ray::new([2.0, 0.0, 10.0], [0.0, 0.0, -1.0])).is_none());
+    
+    let (p, primitive, distance) = bvh.closest_point(&[40.0, 0.0, 5.0]).unwrap();
+    assert_eq!((p, primitive, distance), ([40.0, 0.0, 1.0], 10, 4.0));
+}
+
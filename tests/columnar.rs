@@ -0,0 +1,116 @@
+#![cfg(feature = "columnar")]
+
+extern crate spatial;
+extern crate arrow;
+extern crate parquet;
+
+use spatial::{Quadtree, Octree, SpatialError};
+use spatial::quadtree;
+use spatial::octree;
+use spatial::columnar::{self, Row};
+use arrow::array::{ArrayRef, Float32Array, Float64Array, Int32Array};
+use arrow::compute;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use std::env;
+use std::fs::{self, File};
+use std::sync::Arc;
+
+fn batch(columns: Vec<(&str, ArrayRef)>) -> RecordBatch {
+    RecordBatch::try_from_iter(columns).unwrap()
+}
+
+#[test]
+fn columnar_quadtree_batches() {
+    let first = batch(vec![("id", Arc::new(Int32Array::from(vec![10, 11, 12])) as ArrayRef),
+                           ("x", Arc::new(Float64Array::from(vec![0.1, 0.5, 0.9])) as ArrayRef),
+                           ("y", Arc::new(Float64Array::from(vec![0.1, 0.5, 0.9])) as ArrayRef)]);
+    let second = batch(vec![("id", Arc::new(Int32Array::from(vec![13, 14])) as ArrayRef),
+                            ("x", Arc::new(Float32Array::from(vec![0.52, 0.2])) as ArrayRef),
+                            ("y", Arc::new(Float32Array::from(vec![0.48, 0.8])) as ArrayRef)]);
+    let vol = quadtree::Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let tree: Quadtree<f64, Row<[f64; 2]>> = Quadtree::from_batches(vol, 1, &[first.clone(), second.clone()],
+                                                                    ["x", "y"]).unwrap();
+    assert_eq!(tree.len(), 5);
+
+    let found = tree.get_in_radius([0.5, 0.5], 0.05);
+    let indices = columnar::take_indices(found.iter().cloned());
+    assert_eq!(indices.values().to_vec(), vec![1, 3]);
+
+    // Each batch is filtered by the rows found in it.
+    let selected = compute::filter_record_batch(&first, &columnar::selection(found.iter().cloned(), 0, 3)).unwrap();
+    let ids = selected.column_by_name("id").unwrap().as_any().downcast_ref::<Int32Array>().unwrap();
+    assert_eq!(ids.values().to_vec(), vec![11]);
+    let selected = compute::filter_record_batch(&second, &columnar::selection(found.iter().cloned(), 3, 2)).unwrap();
+    let ids = selected.column_by_name("id").unwrap().as_any().downcast_ref::<Int32Array>().unwrap();
+    assert_eq!(ids.values().to_vec(), vec![13]);
+}
+
+#[test]
+fn columnar_octree_parquet() {
+    let x: Vec<f64> = (0..100).map(|i| (i % 10) as f64 / 10.0).collect();
+    let y: Vec<f64> = (0..100).map(|i| (i / 10) as f64 / 10.0).collect();
+    let z: Vec<f64> = (0..100).map(|i| i as f64 / 100.0).collect();
+    let table = batch(vec![("x", Arc::new(Float64Array::from(x)) as ArrayRef),
+                           ("name", Arc::new(Int32Array::from((0..100).collect::<Vec<i32>>())) as ArrayRef),
+                           ("y", Arc::new(Float64Array::from(y)) as ArrayRef),
+                           ("z", Arc::new(Float64Array::from(z)) as ArrayRef)]);
+
+    let path = env::temp_dir().join(format!("spatial-columnar-{}.parquet", std::process::id()));
+    let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), table.schema(), None).unwrap();
+    // Two row groups, so that the rows are numbered across them.
+    writer.write(&table.slice(0, 60)).unwrap();
+    writer.flush().unwrap();
+    writer.write(&table.slice(60, 40)).unwrap();
+    writer.close().unwrap();
+
+    let vol = octree::Volume::new([0.0; 3], [1.0; 3]);
+    let tree: Octree<f64, Row<[f64; 3]>> = Octree::from_parquet(vol, 4, File::open(&path).unwrap(),
+                                                                ["x", "y", "z"]).unwrap();
+    let missing = Octree::<f64, Row<[f64; 3]>>::from_parquet(vol, 4, File::open(&path).unwrap(),
+                                                             ["x", "y", "w"]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(tree.len(), 100);
+    for item in tree.get_in_volume(&vol).iter() {
+        assert_eq!(item.position, [(item.row % 10) as f64 / 10.0, (item.row / 10) as f64 / 10.0,
+                                   item.row as f64 / 100.0]);
+    }
+    let found = tree.get_in_volume(&octree::Volume::new([0.0, 0.65, 0.0], [0.25, 1.0, 1.0]));
+    assert_eq!(columnar::take_indices(found.iter().cloned()).values().to_vec(),
+               vec![70, 71, 72, 80, 81, 82, 90, 91, 92]);
+    match missing {
+        Err(SpatialError::Serialization(_)) => {},
+        other => panic!("missing column read as {:?}", other.map(|tree| tree.len()))
+    }
+}
+
+#[test]
+fn columnar_invalid_columns() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree: Quadtree<f64, Row<[f64; 2]>> = Quadtree::new(vol);
+
+    let nulls = batch(vec![("x", Arc::new(Float64Array::from(vec![Some(0.5), None])) as ArrayRef),
+                           ("y", Arc::new(Float64Array::from(vec![0.5, 0.5])) as ArrayRef)]);
+    let ints = batch(vec![("x", Arc::new(Int32Array::from(vec![0, 1])) as ArrayRef),
+                          ("y", Arc::new(Float64Array::from(vec![0.5, 0.5])) as ArrayRef)]);
+    for batch in [&nulls, &ints].iter() {
+        match tree.insert_batch(batch, ["x", "y"], 0) {
+            Err(SpatialError::Serialization(_)) => {},
+            other => panic!("invalid column inserted with {:?}", other)
+        }
+    }
+    match tree.insert_batch(&nulls, ["x", "z"], 0) {
+        Err(SpatialError::Serialization(_)) => {},
+        other => panic!("missing column inserted with {:?}", other)
+    }
+
+    // A point out of bounds keeps the rest of the batch out.
+    let outside = batch(vec![("x", Arc::new(Float64Array::from(vec![0.5, 1.5])) as ArrayRef),
+                             ("y", Arc::new(Float64Array::from(vec![0.5, 0.5])) as ArrayRef)]);
+    match tree.insert_batch(&outside, ["x", "y"], 0) {
+        Err(SpatialError::OutOfBounds) => {},
+        other => panic!("point outside of the tree inserted with {:?}", other)
+    }
+    assert_eq!(tree.len(), 0);
+}
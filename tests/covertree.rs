@@ -0,0 +1,104 @@
+extern crate spatial;
+
+use spatial::CoverTree;
+use spatial::SpatialError;
+
+fn euclidean(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1]) + (a[2] - b[2]) * (a[2] - b[2])).sqrt()
+}
+
+fn hamming(a: &u32, b: &u32) -> f32 {
+    (a ^ b).count_ones() as f32
+}
+
+/// Asserts that the tree finds the same neighbors as a scan over
+/// `points`.
+fn assert_matches_scan<F: Fn(&[f64; 3], &[f64; 3]) -> f64>(tree: &CoverTree<f64, [f64; 3], F>, points: &[[f64; 3]]) {
+    assert_eq!(tree.len(), points.len());
+    for query in [[0.0, 0.0, 0.0], [3.3, 7.1, 0.4], [-20.0, 5.0, 50.0]].iter() {
+        let mut expected: Vec<f64> = points.iter().map(|p| euclidean(p, query)).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let found: Vec<f64> = tree.nearest_n(query, 8).into_iter().map(|p| euclidean(p, query)).collect();
+        assert_eq!(found, &expected[..8.min(expected.len())]);
+        assert_eq!(tree.get_in_radius(query, 3.0).len(), expected.iter().filter(|&&d| d <= 3.0).count());
+    }
+}
+
+#[test]
+fn covertree_insert_remove() {
+    let mut tree = CoverTree::new(euclidean);
+    let mut points = Vec::new();
+    for i in 0..300 {
+        let p = [(i * 37 % 101) as f64 / 10.0, (i * 13 % 89) as f64 / 10.0, (i % 7) as f64 * 0.01];
+        assert!(tree.insert(p).is_ok());
+        points.push(p);
+    }
+    // Duplicates and a far outlier.
+    assert!(tree.insert(points[5]).is_ok());
+    points.push(points[5]);
+    assert!(tree.insert([1000.0, 0.0, 0.0]).is_ok());
+    points.push([1000.0, 0.0, 0.0]);
+    assert_matches_scan(&tree, &points);
+
+    for i in (0..points.len()).rev().filter(|i| i % 3 == 0) {
+        assert_eq!(tree.remove(&points[i]), Some(points[i]));
+        points.remove(i);
+    }
+    assert_eq!(tree.remove(&[-1.0, -1.0, -1.0]), None);
+    assert_matches_scan(&tree, &points);
+
+    tree.clear();
+    assert!(tree.is_empty());
+    assert!(tree.nearest(&[0.0, 0.0, 0.0]).is_none());
+}
+
+#[test]
+fn covertree_hamming() {
+    let mut tree = CoverTree::new(hamming);
+    for code in [0b0000u32, 0b1111, 0b1010, 0b0101, 0b1100, 0b0011, 0b1000].iter() {
+        assert!(tree.insert(*code).is_ok());
+    }
+
+    assert_eq!(tree.nearest(&0b1110), Some(&0b1111));
+    let mut close = tree.get_in_radius(&0b0000, 1.0);
+    close.sort();
+    assert_eq!(close, vec![&0b0000, &0b1000]);
+
+    assert_eq!(tree.remove(&0b0000), Some(0b0000));
+    assert_eq!(tree.len(), 6);
+    assert_eq!(tree.nearest(&0b0000), Some(&0b1000));
+}
+
+#[test]
+fn covertree_duplicates() {
+    let mut tree = CoverTree::new(euclidean);
+    let mut points = Vec::new();
+    for i in 0..50 {
+        let p = [(i % 5) as f64, 0.0, 0.0];
+        assert!(tree.insert(p).is_ok());
+        points.push(p);
+    }
+    assert_matches_scan(&tree, &points);
+    assert_eq!(tree.get_in_radius(&[2.0, 0.0, 0.0], 0.5), vec![&[2.0, 0.0, 0.0]; 10]);
+
+    // Removing the item of a node hands its place to a duplicate.
+    for _ in 0..10 {
+        assert_eq!(tree.remove(&[0.0, 0.0, 0.0]), Some([0.0, 0.0, 0.0]));
+    }
+    assert_eq!(tree.remove(&[0.0, 0.0, 0.0]), None);
+    points.retain(|p| p[0] != 0.0);
+    assert_matches_scan(&tree, &points);
+}
+
+#[test]
+fn covertree_non_finite() {
+    let mut tree = CoverTree::new(euclidean);
+    assert!(tree.insert([0.0, 0.0, 0.0]).is_ok());
+    for p in [[f64::INFINITY, 0.0, 0.0], [0.0, f64::NAN, 0.0]].iter() {
+        match tree.insert(*p) {
+            Err(SpatialError::OutOfBounds) => {},
+            other => panic!("expected OutOfBounds, got {:?}", other)
+        }
+    }
+    assert_eq!(tree.len(), 1);
+}
@@ -0,0 +1,61 @@
+extern crate spatial;
+
+use spatial::curves;
+use spatial::quadtree;
+
+#[test]
+fn curves_hilbert_2d() {
+    // The first order curve visits the cells in a U.
+    let cells: Vec<[u64; 2]> = (0..4).map(|i| curves::hilbert_decode_2d(i, 1)).collect();
+    assert_eq!(cells, vec![[0, 0], [0, 1], [1, 1], [1, 0]]);
+    
+    // Every cell is visited once, each next to the one before.
+    let bits = 4;
+    let mut seen = vec![false; 256];
+    let mut previous = curves::hilbert_decode_2d(0, bits);
+    for i in 0..256 {
+        let cell = curves::hilbert_decode_2d(i, bits);
+        assert_eq!(curves::hilbert_encode_2d(cell, bits), i);
+        assert!(!seen[(cell[1] * 16 + cell[0]) as usize]);
+        seen[(cell[1] * 16 + cell[0]) as usize] = true;
+        let step = (cell[0] as i64 - previous[0] as i64).abs() + (cell[1] as i64 - previous[1] as i64).abs();
+        assert!(i == 0 || step == 1);
+        previous = cell;
+    }
+    
+    let far = [(1 << 32) - 1, 12345];
+    assert_eq!(curves::hilbert_decode_2d(curves::hilbert_encode_2d(far, 32), 32), far);
+}
+
+#[test]
+fn curves_hilbert_3d() {
+    let bits = 3;
+    let mut previous = curves::hilbert_decode_3d(0, bits);
+    for i in 0..512 {
+        let cell = curves::hilbert_decode_3d(i, bits);
+        assert_eq!(curves::hilbert_encode_3d(cell, bits), i);
+        let step: i64 = (0..3).map(|a| (cell[a] as i64 - previous[a] as i64).abs()).sum();
+        assert!(i == 0 || step == 1);
+        previous = cell;
+    }
+    
+    let far = [(1 << 21) - 1, 0, 777];
+    assert_eq!(curves::hilbert_decode_3d(curves::hilbert_encode_3d(far, 21), 21), far);
+}
+
+#[derive(Debug, PartialEq)]
+struct Point(f32, f32);
+
+impl quadtree::Index<f32> for Point {
+    fn quadtree_index(&self) -> [f32; 2] {
+        [self.0, self.1]
+    }
+}
+
+#[test]
+fn curves_hilbert_sort() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let points = vec![Point(0.9, 0.1), Point(0.1, 0.1), Point(0.9, 0.9), Point(0.1, 0.9)];
+    assert_eq!(curves::hilbert_sort_2d(&vol, points),
+               vec![Point(0.1, 0.1), Point(0.1, 0.9), Point(0.9, 0.9), Point(0.9, 0.1)]);
+}
@@ -0,0 +1,191 @@
+extern crate spatial;
+
+use spatial::{DynamicAabbTree, PairManager, PairEvent};
+use spatial::octree::Volume;
+
+/// A unit cube with its minimum corner at `x`, `y`, `z`.
+fn cube(x: f32, y: f32, z: f32) -> Volume<f32> {
+    Volume::new([x, y, z], [x + 1.0, y + 1.0, z + 1.0])
+}
+
+#[test]
+fn dynamic_aabb_tree_insert_remove() {
+    let mut tree = DynamicAabbTree::new(0.1);
+    let a = tree.insert(cube(0.0, 0.0, 0.0), 'a');
+    let b = tree.insert(cube(0.5, 0.5, 0.5), 'b');
+    let c = tree.insert(cube(5.0, 0.0, 0.0), 'c');
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.get(b), Some(&'b'));
+    assert_eq!(tree.fat_bounds(a).unwrap().min(), [-0.1, -0.1, -0.1]);
+    
+    let mut found = tree.query(&Volume::new([0.0, 0.0, 0.0], [0.2, 0.2, 0.2]));
+    found.sort();
+    assert_eq!(found, vec![a]);
+    assert_eq!(tree.query(&cube(4.5, 0.0, 0.0)), vec![c]);
+    
+    assert_eq!(tree.remove(b), Some('b'));
+    assert_eq!(tree.remove(b), None);
+    assert_eq!(tree.get(b), None);
+    assert_eq!(tree.len(), 2);
+    
+    // Freed proxies are reused.
+    let d = tree.insert(cube(9.0, 0.0, 0.0), 'd');
+    assert_eq!(tree.get(d), Some(&'d'));
+    assert_eq!(tree.query(&cube(9.5, 0.0, 0.0)), vec![d]);
+    assert_eq!(tree.remove(a), Some('a'));
+    assert_eq!(tree.remove(c), Some('c'));
+    assert_eq!(tree.remove(d), Some('d'));
+    assert!(tree.is_empty());
+    assert_eq!(tree.height(), 0);
+}
+
+#[test]
+fn dynamic_aabb_tree_update() {
+    let mut tree = DynamicAabbTree::new(0.25);
+    let a = tree.insert(cube(0.0, 0.0, 0.0), ());
+    let b = tree.insert(cube(3.0, 0.0, 0.0), ());
+    
+    // Moving within the margin leaves the tree as it is.
+    assert!(!tree.update(a, cube(0.2, 0.0, 0.0)));
+    assert_eq!(tree.fat_bounds(a).unwrap().min(), [-0.25, -0.25, -0.25]);
+    
+    assert!(tree.update(a, cube(2.5, 0.0, 0.0)));
+    assert_eq!(tree.fat_bounds(a).unwrap().min(), [2.25, -0.25, -0.25]);
+    let mut found = tree.query(&cube(3.0, 0.0, 0.0));
+    found.sort();
+    assert_eq!(found, vec![a, b]);
+    assert!(tree.query(&cube(0.0, 0.0, 0.0)).is_empty());
+}
+
+#[test]
+fn dynamic_aabb_tree_balance() {
+    let mut tree = DynamicAabbTree::new(0.0);
+    let mut ids = Vec::new();
+    for i in 0..1024 {
+        ids.push(tree.insert(cube(i as f32 * 2.0, 0.0, 0.0), i));
+    }
+    // A balanced tree over 1024 leaves is about 10 levels high, while
+    // inserting along a line without rotations would make it a list.
+    assert!(tree.height() <= 20);
+    assert!(tree.pairs().is_empty());
+
+    for (i, &id) in ids.iter().enumerate() {
+        assert_eq!(tree.query(&cube(i as f32 * 2.0 + 0.5, 0.0, 0.0)), vec![id]);
+    }
+    for (i, &id) in ids.iter().enumerate() {
+        if i % 2 == 0 {
+            assert!(tree.remove(id).is_some());
+        }
+    }
+    assert_eq!(tree.len(), 512);
+    assert!(tree.height() <= 20);
+}
+
+#[test]
+fn dynamic_aabb_tree_prediction() {
+    let mut tree = DynamicAabbTree::with_prediction(0.25, 2.0);
+    let a = tree.insert(cube(0.0, 0.0, 0.0), ());
+    let b = tree.insert(cube(4.0, 0.0, 0.0), ());
+    assert_eq!(tree.margin(), 0.25);
+    
+    // Leaving the margin stretches the bounds ahead of the box, so it
+    // can keep moving without being reinserted.
+    assert!(tree.update_predicted(a, cube(0.5, 0.0, 0.0), [0.5, 0.0, 0.0]));
+    assert_eq!(tree.fat_bounds(a).unwrap().min(), [0.25, -0.25, -0.25]);
+    assert_eq!(tree.fat_bounds(a).unwrap().max(), [2.75, 1.25, 1.25]);
+    assert_eq!(tree.query(tree.fat_bounds(b).unwrap()), vec![b]);
+    assert!(!tree.update_predicted(a, cube(1.0, 0.0, 0.0), [0.5, 0.0, 0.0]));
+    assert!(!tree.update_predicted(a, cube(1.5, 0.0, 0.0), [0.5, 0.0, 0.0]));
+    
+    // Moving the other way stretches them behind.
+    assert!(tree.update_predicted(b, cube(3.5, 0.0, 0.0), [-0.5, 0.0, 0.0]));
+    assert_eq!(tree.fat_bounds(b).unwrap().min(), [2.25, -0.25, -0.25]);
+    let mut found = tree.query(tree.fat_bounds(b).unwrap());
+    found.sort();
+    assert_eq!(found, vec![a, b]);
+    
+    // Stopping keeps bounds stretched by up to four margins, and
+    // shrinks larger ones.
+    assert!(!tree.update_predicted(b, cube(3.5, 0.0, 0.0), [0.0, 0.0, 0.0]));
+    let mut tree = DynamicAabbTree::with_prediction(0.1, 10.0);
+    let c = tree.insert(cube(0.0, 0.0, 0.0), ());
+    assert!(tree.update_predicted(c, cube(0.5, 0.0, 0.0), [0.5, 0.0, 0.0]));
+    assert!(tree.update_predicted(c, cube(0.5, 0.0, 0.0), [0.0, 0.0, 0.0]));
+    assert_eq!(tree.fat_bounds(c).unwrap().max(), [1.6, 1.1, 1.1]);
+    
+    // Plain updates don't predict.
+    assert!(tree.update(c, cube(3.0, 0.0, 0.0)));
+    assert_eq!(tree.fat_bounds(c).unwrap().max(), [4.1, 1.1, 1.1]);
+}
+
+#[test]
+fn dynamic_aabb_tree_pairs() {
+    let mut tree = DynamicAabbTree::new(0.25);
+    let a = tree.insert(cube(0.0, 0.0, 0.0), ());
+    let b = tree.insert(cube(3.0, 0.0, 0.0), ());
+    let c = tree.insert(cube(0.0, 3.0, 0.0), ());
+    assert!(tree.pairs().is_empty());
+    
+    assert!(tree.update(a, cube(2.5, 0.0, 0.0)));
+    assert_eq!(tree.pairs(), vec![(a, b)]);
+    assert!(tree.update(c, cube(2.8, 0.5, 0.0)));
+    assert_eq!(tree.pairs(), vec![(a, b), (a, c), (b, c)]);
+}
+
+#[test]
+fn dynamic_aabb_tree_pair_events() {
+    let mut tree = DynamicAabbTree::new(0.0);
+    let mut manager = PairManager::new();
+    assert_eq!(manager.update(&tree), vec![]);
+    
+    let a = tree.insert(cube(0.0, 0.0, 0.0), ());
+    let b = tree.insert(cube(0.5, 0.0, 0.0), ());
+    let c = tree.insert(cube(5.0, 0.0, 0.0), ());
+    assert_eq!(manager.update(&tree), vec![PairEvent::Begin(a, b)]);
+    // Pairs that persist aren't reported again.
+    assert_eq!(manager.update(&tree), vec![]);
+    assert_eq!(manager.pairs(), &[(a, b)]);
+    
+    // Boxes touching on a face begin a pair, too.
+    tree.update(c, cube(1.5, 0.0, 0.0));
+    assert_eq!(manager.update(&tree), vec![PairEvent::Begin(b, c)]);
+    
+    // Ends come before begins.
+    tree.update(b, cube(-0.5, 0.0, 0.0));
+    tree.update(c, cube(0.8, 0.0, 0.0));
+    assert_eq!(manager.update(&tree), vec![PairEvent::End(b, c), PairEvent::Begin(a, c)]);
+    
+    // Removed boxes end their pairs at once, before their ids are
+    // reused.
+    tree.remove(a);
+    assert_eq!(manager.remove(a), vec![PairEvent::End(a, b), PairEvent::End(a, c)]);
+    let d = tree.insert(cube(9.0, 0.0, 0.0), ());
+    assert_eq!(d, a);
+    assert_eq!(manager.update(&tree), vec![]);
+    assert!(manager.pairs().is_empty());
+}
+
+#[test]
+fn dynamic_aabb_tree_bulk_load() {
+    let mut boxes = Vec::new();
+    for i in 0..16 {
+        for j in 0..16 {
+            boxes.push((cube(i as f32 * 2.0, j as f32 * 2.0, 0.0), (i, j)));
+        }
+    }
+    let mut tree = DynamicAabbTree::bulk_load(0.1, boxes);
+    assert_eq!(tree.len(), 256);
+    assert_eq!(tree.height(), 8);
+    assert_eq!(tree.get(17), Some(&(1, 1)));
+    assert!(tree.pairs().is_empty());
+    assert_eq!(tree.query(&cube(6.5, 8.5, 0.0)), vec![3 * 16 + 4]);
+    
+    // The tree keeps working incrementally.
+    let extra = tree.insert(cube(6.5, 8.5, 0.0), (99, 99));
+    assert_eq!(tree.pairs(), vec![(3 * 16 + 4, extra)]);
+    assert_eq!(tree.remove(0), Some((0, 0)));
+    assert_eq!(tree.len(), 256);
+    
+    let empty: DynamicAabbTree<f32, ()> = DynamicAabbTree::bulk_load(0.1, Vec::new());
+    assert!(empty.is_empty());
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "ffi")]
+
+extern crate spatial;
+
+use spatial::ffi::*;
+use std::ptr;
+
+#[test]
+fn ffi_quadtree_octree() {
+    unsafe {
+        let tree = spatial_quadtree_new([0.0, 0.0].as_ptr(), [1.0, 1.0].as_ptr(), 8);
+        assert!(spatial_quadtree_insert(tree, [0.25, 0.25].as_ptr(), 7));
+        assert!(spatial_quadtree_insert(tree, [0.75, 0.75].as_ptr(), 9));
+        assert!(!spatial_quadtree_insert(tree, [1.5, 0.5].as_ptr(), 11));
+        assert_eq!(spatial_quadtree_len(tree), 2);
+        
+        let mut out = [0u64; 1];
+        assert_eq!(spatial_quadtree_query_volume(tree, [0.0, 0.0].as_ptr(), [1.0, 1.0].as_ptr(),
+                                                 out.as_mut_ptr(), 1), 2);
+        assert_eq!(spatial_quadtree_query_radius(tree, [0.75, 0.75].as_ptr(), 0.1,
+                                                 out.as_mut_ptr(), 1), 1);
+        assert_eq!(out, [9]);
+        spatial_quadtree_destroy(tree);
+        
+        let tree = spatial_octree_new([0.0; 3].as_ptr(), [1.0; 3].as_ptr(), 8);
+        assert!(spatial_octree_insert(tree, [0.25, 0.25, 0.25].as_ptr(), 7));
+        assert_eq!(spatial_octree_len(tree), 1);
+        assert_eq!(spatial_octree_query_radius(tree, [0.25, 0.25, 0.3].as_ptr(), 0.1,
+                                               ptr::null_mut(), 0), 1);
+        assert!(!spatial_octree_insert(tree, ptr::null(), 13));
+        assert_eq!(spatial_octree_query_radius(tree, ptr::null(), 0.1, ptr::null_mut(), 0), 0);
+        spatial_octree_destroy(tree);
+        
+        assert!(spatial_quadtree_new([0.0, 0.0].as_ptr(), [1.0, 1.0].as_ptr(), 0).is_null());
+        assert!(spatial_quadtree_new([0.0, 0.0].as_ptr(), [1.0, 1.0].as_ptr(), usize::MAX).is_null());
+        assert!(spatial_octree_new([0.0; 3].as_ptr(), [1.0; 3].as_ptr(), MAX_CAPACITY + 1).is_null());
+        let tree = spatial_octree_new([0.0; 3].as_ptr(), [1.0; 3].as_ptr(), MAX_CAPACITY);
+        assert!(!tree.is_null());
+        spatial_octree_destroy(tree);
+        assert!(spatial_quadtree_new(ptr::null(), [1.0, 1.0].as_ptr(), 8).is_null());
+        assert!(spatial_octree_new([1.0; 3].as_ptr(), [0.0; 3].as_ptr(), 8).is_null());
+        assert!(!spatial_quadtree_insert(ptr::null_mut(), [0.5, 0.5].as_ptr(), 1));
+    }
+}
@@ -0,0 +1,64 @@
+extern crate spatial;
+
+use spatial::GeohashIndex;
+use spatial::SpatialError;
+use spatial::geohash;
+
+#[test]
+fn geohash_encode_decode() {
+    assert_eq!(geohash::encode(57.64911, 10.40744, 11).unwrap(), "u4pruydqqvj");
+    assert_eq!(geohash::encode(42.6, -5.6, 5).unwrap(), "ezs42");
+    assert_eq!(geohash::encode(90.0, 180.0, 3).unwrap(), "zzz");
+    assert_eq!(geohash::encode(-90.0, -180.0, 3).unwrap(), "000");
+
+    let (min, max): ([f64; 2], [f64; 2]) = geohash::bounds("ezs42").unwrap();
+    assert!(min[0] <= 42.6 && max[0] >= 42.6 && min[1] <= -5.6 && max[1] >= -5.6);
+    let center: [f64; 2] = geohash::decode("ezs42").unwrap();
+    assert!((center[0] - 42.605).abs() < 0.01 && (center[1] + 5.603).abs() < 0.01);
+
+    assert_eq!(geohash::neighbors("ezs42").unwrap(),
+               vec!["ezs48", "ezs49", "ezs43", "ezs41", "ezs40", "ezefp", "ezefr", "ezefx"]);
+    // Across the antimeridian, but not past the poles.
+    assert_eq!(geohash::neighbors("b").unwrap(), vec!["c", "9", "8", "x", "z"]);
+
+    assert!(matches!(geohash::encode(91.0, 0.0, 5), Err(SpatialError::OutOfBounds)));
+    assert!(matches!(geohash::decode::<f64>("ezs4a"), Err(SpatialError::Serialization(_))));
+    assert!(geohash::decode::<f64>("0123456789bcd").is_err());
+}
+
+#[test]
+fn geohash_index_queries() {
+    let mut index = GeohashIndex::new();
+    let mut points = Vec::new();
+    for i in 0..2000 {
+        let p = [(i * 37 % 179) as f64 - 89.0 + (i % 7) as f64 * 0.1,
+                 (i * 101 % 359) as f64 - 179.5 + (i % 3) as f64 * 0.2];
+        index.insert(p).unwrap();
+        points.push(p);
+    }
+    assert!(index.insert([0.0, 181.0]).is_err());
+    assert_eq!(index.len(), points.len());
+
+    let queries = [([0.0, 0.0], 500000.0), ([45.0, 179.9], 300000.0), ([-60.0, -170.0], 1000000.0),
+                   ([88.0, 10.0], 400000.0), ([10.0, 20.0], 10.0), ([0.0, 0.0], 30000000.0)];
+    for &(center, radius) in queries.iter() {
+        let mut found = index.get_in_radius(center, radius);
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut expected: Vec<&[f64; 2]> = points.iter().filter(|p| geohash::distance(&center, p) <= radius).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, expected);
+    }
+
+    for prefix in ["", "u", "ez", "zzz"].iter() {
+        let found = index.get_with_prefix(prefix).unwrap();
+        assert_eq!(found.len(),
+                   points.iter().filter(|p| geohash::encode(p[0], p[1], 12).unwrap().starts_with(prefix)).count());
+    }
+    assert!(index.get_with_prefix("a").is_err());
+
+    assert_eq!(index.remove(&points[10]), Some(points[10]));
+    assert_eq!(index.remove(&[0.5, 0.5]), None);
+    assert_eq!(index.len(), points.len() - 1);
+    index.clear();
+    assert!(index.is_empty());
+}
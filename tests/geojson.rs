@@ -0,0 +1,96 @@
+#![cfg(feature = "geojson")]
+#![allow(clippy::redundant_field_names)]
+
+extern crate spatial;
+
+use spatial::Quadtree;
+use spatial::SpatialError;
+use spatial::quadtree::Volume;
+use spatial::geojson::{self, Feature, Value};
+
+static CITIES: &str = r#"{"type": "FeatureCollection", "features": [
+    {"type": "Feature", "id": 1, "geometry": {"type": "Point", "coordinates": [24.94, 60.17]},
+     "properties": {"name": "Helsinki"}},
+    {"type": "Feature", "id": "sto", "geometry": {"type": "Point", "coordinates": [18.07, 59.33, 28.0]},
+     "properties": {"name": "Stockholm", "tags": ["capital", "port"], "motto": "\"Venice of the North\"\n"}},
+    {"type": "Feature", "geometry": {"type": "Point", "coordinates": [-74.0, 40.71]}, "properties": null}
+]}"#;
+
+#[test]
+fn geojson_import_export() {
+    let world = Volume::new([-180.0, -90.0], [180.0, 90.0]);
+    let tree: Quadtree<f64, Feature<f64>> = Quadtree::from_geojson(world, 1, CITIES).unwrap();
+    assert_eq!(tree.len(), 3);
+
+    let nordic = tree.get_in_volume(&Volume::new([10.0, 55.0], [30.0, 70.0]));
+    assert_eq!(nordic.len(), 2);
+    let helsinki = nordic.iter().find(|feature| feature.id == Some(Value::from(1))).unwrap();
+    assert_eq!(helsinki.position, [24.94, 60.17]);
+    assert_eq!(helsinki.properties.get("name"), Some(&Value::from("Helsinki")));
+
+    // The exported results read back as they were.
+    let text = geojson::write_features(nordic.iter().cloned()).unwrap();
+    let points: Vec<Feature<f64>> = geojson::read_points(&text).unwrap();
+    assert_eq!(points.len(), 2);
+    for feature in nordic.iter() {
+        assert!(points.contains(feature));
+    }
+
+    // Node volumes follow the results as polygons.
+    let text = geojson::write_features_with_bounds(nordic.iter().cloned(), &tree).unwrap();
+    match geojson::parse(&text).unwrap().get("features").and_then(Value::as_array) {
+        Some(features) => {
+            assert_eq!(features.len(), 2 + tree.node_count());
+            let mut items = 0.0;
+            for node in features[2..].iter() {
+                match node.get("properties").and_then(|properties| properties.get("items")).and_then(Value::as_f64) {
+                    Some(count) => items += count,
+                    None => panic!("no item count")
+                }
+            }
+            assert_eq!(items, 3.0);
+        },
+        None => panic!("no features")
+    }
+    match geojson::read_points::<f64>(&text) {
+        Err(SpatialError::Serialization(_)) => {},
+        _ => panic!("polygons read as points")
+    }
+}
+
+#[test]
+fn geojson_malformed() {
+    for text in ["", "{", "[1, 2", "{\"a\" 1}", "{} {}"].iter() {
+        match geojson::parse(text) {
+            Err(SpatialError::Serialization(_)) => {},
+            other => panic!("{:?} parsed as {:?}", text, other)
+        }
+    }
+
+    let line = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}, "properties": {}}
+    ]}"#;
+    for text in ["[]", "{\"type\": \"FeatureCollection\"}", line].iter() {
+        match geojson::read_points::<f32>(text) {
+            Err(SpatialError::Serialization(_)) => {},
+            _ => panic!("{:?} read as points", text)
+        }
+    }
+
+    let small = Volume::new([0.0, 0.0], [10.0, 10.0]);
+    match Quadtree::<f32, Feature<f32>>::from_geojson(small, 4, CITIES) {
+        Err(SpatialError::OutOfBounds) => {},
+        _ => panic!("points outside of the tree stored")
+    }
+}
+
+#[test]
+fn geojson_non_finite() {
+    for &position in [[f64::NAN, 0.0], [0.0, f64::INFINITY]].iter() {
+        let feature = Feature { position: position, id: None, properties: Value::Null };
+        match geojson::write_features(Some(&feature)) {
+            Err(SpatialError::Serialization(_)) => {},
+            other => panic!("{:?} written as {:?}", position, other)
+        }
+    }
+}
@@ -0,0 +1,30 @@
+extern crate spatial;
+
+use spatial::geom;
+
+#[test]
+fn geom_predicates() {
+    let (min, max) = ([0.0, 0.0], [1.0, 1.0]);
+    
+    assert!(geom::point_in_aabb(&[1.0, 0.5], &min, &max));
+    assert!(!geom::point_in_aabb(&[1.5, 0.5], &min, &max));
+    
+    assert!(geom::aabb_intersects_aabb(&min, &max, &[1.0, 0.0], &[2.0, 1.0]));
+    assert!(!geom::aabb_intersects_aabb(&min, &max, &[1.5, 0.0], &[2.0, 1.0]));
+    
+    assert!(geom::sphere_intersects_aabb(&[2.0, 0.5], 1.0, &min, &max));
+    assert!(!geom::sphere_intersects_aabb(&[2.0, 2.0], 1.0, &min, &max));
+    assert!(geom::point_in_sphere(&[0.0, 1.0], &[0.0, 0.0], 1.0));
+    
+    assert_eq!(geom::ray_intersects_aabb(&[-1.0, 0.5], &[1.0, 1.0 / 0.0], &min, &max), Some((1.0, 2.0)));
+    assert_eq!(geom::ray_intersects_aabb(&[2.0, 0.5], &[1.0, 1.0 / 0.0], &min, &max), None);
+    
+    assert!(geom::segment_intersects_aabb(&[-1.0, 0.5], &[0.0, 0.5], &min, &max));
+    assert!(!geom::segment_intersects_aabb(&[-1.0, 0.5], &[-0.5, 0.5], &min, &max));
+    assert!(geom::segment_intersects_aabb(&[-1.0, -1.0], &[2.0, 2.0], &min, &max));
+    
+    assert!(geom::aabb_near_aabb(&min, &max, &[2.0, 0.0], &[3.0, 1.0], 1.0));
+    assert!(!geom::aabb_near_aabb(&min, &max, &[2.0, 2.0], &[3.0, 3.0], 1.0));
+    assert!(geom::point_near_segment(&[0.5, 1.0], &[0.0, 0.0], &[1.0, 0.0], 1.0));
+    assert!(!geom::point_near_segment(&[2.0, 1.0], &[0.0, 0.0], &[1.0, 0.0], 1.0));
+}
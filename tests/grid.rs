@@ -0,0 +1,145 @@
+extern crate spatial;
+
+use spatial::{SpatialHash, HierarchicalGrid, SpatialError};
+use spatial::grid::{Index, BoundedIndex};
+
+#[derive(Clone, PartialEq, Debug)]
+struct Particle {
+    id: usize,
+    position: [f32; 2]
+}
+
+impl Index<f32> for Particle {
+    fn grid_coordinate(&self, axis: usize) -> f32 {
+        self.position[axis]
+    }
+}
+
+#[test]
+fn spatial_hash_insert_query() {
+    let mut grid = SpatialHash::new(2, 1.0);
+    for x in 0..10 {
+        for y in 0..10 {
+            assert!(grid.insert([x as f32 + 0.5, y as f32 - 4.5]).is_ok());
+        }
+    }
+    assert!(grid.insert([f32::NAN, 0.0]).is_err());
+    assert_eq!(grid.len(), 100);
+    assert_eq!(grid.cell_count(), 100);
+    assert_eq!(grid.cell_of(&[0.5, -0.5]), Some([0, -1, 0]));
+    assert_eq!(grid.cell([0, -1, 0]), &[[0.5, -0.5]]);
+
+    assert_eq!(grid.get_in_neighborhood(&[4.5, 0.5]).len(), 9);
+    assert_eq!(grid.get_in_neighborhood(&[0.5, -4.5]).len(), 4);
+    assert_eq!(grid.get_in_range(&[1.0, -1.0], &[3.0, 1.0]).len(), 4);
+    assert_eq!(grid.get_in_radius(&[4.5, 0.5], 1.0).len(), 5);
+    assert_eq!(grid.get_in_radius(&[4.5, 0.5], 100.0).len(), 100);
+
+    grid.clear();
+    assert!(grid.is_empty());
+    assert_eq!(grid.cell_count(), 0);
+}
+
+#[test]
+fn spatial_hash_huge_query() {
+    let mut grid = SpatialHash::new(2, 1.0);
+    assert!(grid.insert([0.5f64, 0.5]).is_ok());
+    assert!(grid.insert([-3.5, 2.5]).is_ok());
+
+    // The extent of the query overflows the cell coordinates, so the
+    // cells holding items are scanned instead.
+    assert_eq!(grid.get_in_range(&[-9e18, -9e18], &[9e18, 9e18]).len(), 2);
+    assert_eq!(grid.get_in_radius(&[0.0, 0.0], 9e18).len(), 2);
+    assert_eq!(grid.get_in_neighborhood(&[-9e18, 9e18]).len(), 0);
+}
+
+#[test]
+fn spatial_hash_update_remove() {
+    let mut grid = SpatialHash::new(2, 2.0);
+    let a = Particle { id: 0, position: [0.5, 0.5] };
+    let b = Particle { id: 1, position: [1.5, 1.5] };
+    assert!(grid.insert(a.clone()).is_ok());
+    assert!(grid.insert(b.clone()).is_ok());
+    assert_eq!(grid.cell_count(), 1);
+
+    // Moving within a cell and moving to another one.
+    let a2 = Particle { id: 0, position: [1.0, 0.5] };
+    assert!(grid.update(&a, a2.clone()));
+    assert_eq!(grid.cell_count(), 1);
+    let b2 = Particle { id: 1, position: [5.0, 5.0] };
+    assert!(grid.update(&b, b2.clone()));
+    assert_eq!(grid.cell_count(), 2);
+    assert!(!grid.update(&b, b2.clone()));
+    assert_eq!(grid.get_in_radius(&[5.0, 5.0], 0.5), vec![&b2]);
+    assert_eq!(grid.len(), 2);
+
+    assert_eq!(grid.remove(&a), None);
+    assert_eq!(grid.remove(&a2), Some(a2));
+    assert_eq!(grid.remove(&b2), Some(b2));
+    assert!(grid.is_empty());
+    assert_eq!(grid.cell_count(), 0);
+}
+
+#[test]
+fn spatial_hash_three_dimensions() {
+    let mut grid = SpatialHash::new(3, 0.5);
+    for i in 0..8 {
+        let v = i as f64 * 0.25;
+        assert!(grid.insert([v, v, -v]).is_ok());
+    }
+    assert_eq!(grid.cell_count(), 8);
+    assert_eq!(grid.get_in_range(&[0.0, 0.0, -1.0], &[1.0, 1.0, 0.0]).len(), 5);
+    assert_eq!(grid.get_in_neighborhood(&[0.0, 0.0, 0.0]).len(), 3);
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Body {
+    min: [f32; 2],
+    max: [f32; 2]
+}
+
+impl BoundedIndex<f32> for Body {
+    fn grid_bounds(&self, axis: usize) -> (f32, f32) {
+        (self.min[axis], self.max[axis])
+    }
+}
+
+#[test]
+fn hierarchical_grid_insert_query() {
+    let mut grid = HierarchicalGrid::new(2, 1.0);
+    let pebble = Body { min: [0.2, 0.2], max: [0.4, 0.4] };
+    let rock = Body { min: [1.5, 0.5], max: [4.0, 2.0] };
+    let mountain = Body { min: [-50.0, -50.0], max: [50.0, 10.0] };
+    for body in [pebble.clone(), rock.clone(), mountain.clone()].iter() {
+        assert!(grid.insert(body.clone()).is_ok());
+    }
+    match grid.insert(Body { min: [1.0, 0.0], max: [0.0, 1.0] }) {
+        Err(SpatialError::InvalidVolume) => {},
+        other => panic!("expected InvalidVolume, got {:?}", other)
+    }
+    assert_eq!(grid.len(), 3);
+
+    // Items are sorted into levels by their size.
+    assert_eq!(grid.level_len(0), 1);
+    assert_eq!(grid.level_len(2), 1);
+    assert_eq!(grid.level_len(7), 1);
+    assert_eq!(grid.level_cell_size(2), 4.0);
+
+    assert_eq!(grid.get_at(&[0.3, 0.3]).len(), 2);
+    assert_eq!(grid.get_at(&[3.9, 1.9]).len(), 2);
+    assert_eq!(grid.get_at(&[49.0, 9.0]), vec![&mountain]);
+    assert!(grid.get_at(&[49.0, 11.0]).is_empty());
+    assert_eq!(grid.get_in_range(&[0.35, 0.35], &[1.6, 0.6]).len(), 3);
+    assert_eq!(grid.get_in_radius(&[5.0, 3.0], 1.5).len(), 2);
+
+    let moved = Body { min: [60.0, 60.0], max: [61.0, 61.0] };
+    assert!(grid.update(&rock, moved.clone()));
+    assert!(!grid.update(&rock, moved.clone()));
+    assert_eq!(grid.get_at(&[60.5, 60.5]), vec![&moved]);
+    assert_eq!(grid.level_len(2), 0);
+
+    assert_eq!(grid.remove(&pebble), Some(pebble));
+    assert_eq!(grid.remove(&moved), Some(moved));
+    assert_eq!(grid.remove(&mountain), Some(mountain));
+    assert!(grid.is_empty());
+}
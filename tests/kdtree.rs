@@ -0,0 +1,83 @@
+extern crate spatial;
+
+use spatial::KdTree;
+use spatial::kdtree::Index;
+
+#[derive(Clone, PartialEq, Debug)]
+struct Star {
+    id: usize,
+    position: [f64; 4]
+}
+
+impl Index<f64> for Star {
+    fn kdtree_coordinate(&self, axis: usize) -> f64 {
+        self.position[axis]
+    }
+}
+
+/// Returns a grid of `n` by `n` points with a spacing of one.
+fn grid(n: usize) -> Vec<[f32; 2]> {
+    let mut points = Vec::new();
+    for x in 0..n {
+        for y in 0..n {
+            points.push([x as f32, y as f32]);
+        }
+    }
+    points
+}
+
+#[test]
+fn kdtree_nearest() {
+    let tree = KdTree::new(2, &grid(20));
+    assert_eq!(tree.len(), 400);
+    assert_eq!(tree.nearest(&[3.2, 7.9]), Some(&[3.0, 8.0]));
+    assert_eq!(tree.nearest(&[-10.0, 100.0]), Some(&[0.0, 19.0]));
+    
+    let found = tree.nearest_n(&[5.1, 5.0], 3);
+    assert_eq!(found[0], &[5.0, 5.0]);
+    assert_eq!(found[1], &[6.0, 5.0]);
+    assert_eq!(found.len(), 3);
+    assert_eq!(tree.nearest_n(&[5.0, 5.0], 1000).len(), 400);
+    assert_eq!(tree.nearest_n(&[5.0, 5.0], 0).len(), 0);
+    
+    let empty: KdTree<f32, [f32; 3]> = KdTree::new(3, &[]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.nearest(&[0.0, 0.0, 0.0]), None);
+}
+
+#[test]
+fn kdtree_range_radius() {
+    let tree = KdTree::new(2, &grid(20));
+    let mut found: Vec<[f32; 2]> = tree.get_in_range(&[2.0, 3.0], &[3.5, 4.0]).into_iter().cloned().collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(found, vec![[2.0, 3.0], [2.0, 4.0], [3.0, 3.0], [3.0, 4.0]]);
+    assert_eq!(tree.get_in_range(&[-5.0, -5.0], &[-1.0, 30.0]).len(), 0);
+    
+    assert_eq!(tree.get_in_radius(&[10.0, 10.0], 1.0).len(), 5);
+    assert_eq!(tree.get_in_radius(&[10.0, 10.0], 100.0).len(), 400);
+}
+
+#[test]
+fn kdtree_matches_linear_scan() {
+    // Points in four dimensions, with plenty of duplicate coordinates.
+    let stars: Vec<Star> = (0..500).map(|i| Star {
+        id: i,
+        position: [(i * 7 % 13) as f64, (i * 11 % 17) as f64, (i % 5) as f64, (i * 3 % 31) as f64 / 4.0]
+    }).collect();
+    let tree = KdTree::new(4, &stars);
+    
+    let distance2 = |a: &[f64; 4], b: &[f64; 4]| (0..4).map(|i| (a[i] - b[i]) * (a[i] - b[i])).fold(0.0, |a, b| a + b);
+    for query in stars.iter().take(50) {
+        let point = [query.position[0] + 0.3, query.position[1] - 0.2, 2.5, query.position[3]];
+        let nearest = tree.nearest(&point).unwrap();
+        let best = stars.iter().map(|star| distance2(&star.position, &point)).fold(1.0 / 0.0, f64::min);
+        assert_eq!(distance2(&nearest.position, &point), best);
+        
+        let mut found: Vec<usize> = tree.get_in_radius(&point, 3.0).iter().map(|star| star.id).collect();
+        found.sort();
+        let expected: Vec<usize> = stars.iter().filter(|star| distance2(&star.position, &point) <= 9.0)
+            .map(|star| star.id)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
@@ -1,8 +1,23 @@
+#![allow(clippy::redundant_field_names)]
+
 extern crate spatial;
 
-use spatial::octree::{Octree, Index, Volume};
+#[cfg(feature = "surface")]
+use spatial::octree::SurfaceMesh;
+use std::sync::Arc;
+use std::thread;
+use std::io::Cursor;
+
+use std::collections::HashMap;
+use spatial::{Visit, ItemOrder, SpatialError};
+use spatial::snapshot::Snapshot;
+use spatial::paging::PageStats;
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+use spatial::paging::Compression;
+use spatial::octree::{Octree, NodePath, NodeDelta, Index, NodeData, Aggregate, Observer, Weighted, Translate, ForceSource, Mass, Attributes, Volume, Ray, Frustum, OccupancyOctree, Fill,
+                      BoundedOctree, BoundedIndex, LooseOctree, LinearOctree, WorldIndex, LayeredIndex, PagedBuilder, PagedOctree};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 struct Object {
     x: f32,
     y: f32,
@@ -25,20 +40,60 @@ impl Index<f32> for Object {
     }
 }
 
+impl Weighted<f32> for Object {
+    fn weight(&self) -> f32 {
+        self.x
+    }
+}
+
+impl Translate<f32> for Object {
+    fn translate(&mut self, offset: [f32; 3]) {
+        self.x += offset[0];
+        self.y += offset[1];
+        self.z += offset[2];
+    }
+}
+
+impl Attributes<f32> for Object {
+    fn attributes(&self) -> [f32; 4] {
+        [self.x, 0.0, 0.0, 1.0]
+    }
+}
+
+impl Snapshot for Object {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        [self.x, self.y, self.z].write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<Object, SpatialError> {
+        <[f32; 3]>::read_snapshot(input).map(|p| Object::new(p[0], p[1], p[2]))
+    }
+}
+
+/// Returns the force of inverse square gravity on `point` by a body of
+/// `mass` at `position`.
+fn gravity(point: [f32; 3], mass: f32, position: [f32; 3]) -> [f32; 3] {
+    let mut force = [0.0; 3];
+    let distance2: f32 = (0..3).map(|axis| (position[axis] - point[axis]).powi(2)).sum();
+    for axis in 0..3 {
+        force[axis] = mass * (position[axis] - point[axis]) / (distance2 * distance2.sqrt());
+    }
+    force
+}
 
 #[test]
 fn octree_insert_query() {
     let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
     let mut tree = Octree::new(vol);
     
-    assert_eq!(tree.insert(Object::new(0.25, 0.25, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.25, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.25, 0.75, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.75, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.25, 0.25, 0.75)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.25, 0.75)), true);
-    assert_eq!(tree.insert(Object::new(0.25, 0.75, 0.75)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.75, 0.75)), true);
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
     
     assert_eq!(tree.get_in_volume(&Volume::new([0.0, 0.0, 0.0], [0.5, 0.5, 0.5])).len(), 1);
     assert_eq!(tree.get_in_volume(&Volume::new([0.5, 0.0, 0.0], [1.0, 0.5, 0.5])).len(), 1);
@@ -55,3 +110,1885 @@ fn octree_insert_query() {
     
     assert_eq!(tree.len(), 8);
 }
+
+#[test]
+fn octree_radius_boundary() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::new(vol);
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+
+    // Items at exactly the radius are included, and so are neighbors.
+    assert_eq!(tree.get_in_radius([0.25, 0.25, 0.25], 0.5).len(), 4);
+    assert!(tree.neighbor_lists(0.5).iter().all(|list| list.len() == 3));
+}
+
+#[test]
+fn octree_insert_errors() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+
+    match tree.insert(Object::new(2.0, 0.0, 0.0)) {
+        Err(SpatialError::OutOfBounds) => {},
+        other => panic!("expected OutOfBounds, got {:?}", other)
+    }
+
+    // Every item at the same position needs a node of its own, one
+    // level deeper than the last, until the depth limit is reached.
+    for _ in 0..65 {
+        assert!(tree.insert(Object::new(0.0, 0.0, 0.0)).is_ok());
+    }
+    match tree.insert(Object::new(0.0, 0.0, 0.0)) {
+        Err(SpatialError::DepthLimit) => {},
+        other => panic!("expected DepthLimit, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 65);
+}
+
+#[test]
+fn octree_introspection() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let tree: Octree<f32, Object> = Octree::with_capacity(vol, 4);
+
+    assert_eq!(tree.bounds().min(), [0.0, 0.0, 0.0]);
+    assert_eq!(tree.bounds().max(), [1.0, 1.0, 1.0]);
+    assert_eq!(tree.capacity(), 4);
+    assert_eq!(tree.depth(), 0);
+    assert_eq!(tree.node_count(), 1);
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn octree_leaf_neighbors() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    // Subdivides the root, and then its first octant.
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.2, 0.2, 0.2)).is_ok());
+    assert!(tree.insert(Object::new(0.3, 0.3, 0.3)).is_ok());
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.depth(), 2);
+    
+    assert_eq!(tree.leaf_neighbors(&[1]).len(), 6);
+    assert_eq!(tree.leaf_neighbors(&[0, 0]).len(), 3);
+    assert_eq!(tree.leaf_neighbors(&[0, 7]).len(), 6);
+    
+    let paths: Vec<Vec<usize>> = tree.leaf_neighbors(&[0, 0]).into_iter().map(|n| n.path).collect();
+    assert_eq!(paths, vec![vec![0, 1], vec![0, 2], vec![0, 4]]);
+    
+    // Not leaves.
+    assert_eq!(tree.leaf_neighbors(&[]).len(), 0);
+    assert_eq!(tree.leaf_neighbors(&[0]).len(), 0);
+}
+
+#[test]
+fn octree_neighbor_lists() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.2, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    assert!(tree.insert(Object::new(0.85, 0.9, 0.9)).is_ok());
+    assert!(tree.insert(Object::new(0.5, 0.5, 0.5)).is_ok());
+    
+    let lists = tree.neighbor_lists(0.15);
+    assert_eq!(lists.len(), 5);
+    assert_eq!(lists.iter().filter(|list| list.is_empty()).count(), 1);
+    assert_eq!(lists.iter().map(|list| list.len()).sum::<usize>(), 4);
+    
+    // Every listed neighbor lists the item back.
+    for (i, list) in lists.iter().enumerate() {
+        for &j in list.iter() {
+            assert!(lists[j as usize].contains(&(i as u32)));
+        }
+    }
+}
+
+#[test]
+fn octree_for_each_force_source() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree: Octree<f32, Object, Mass<f32>> = Octree::with_node_data(vol, 4);
+    let mut sources = 0;
+    tree.for_each_force_source([0.6, 1.1, 0.4], 0.5, |_| sources += 1);
+    assert_eq!(sources, 0);
+    for i in 0..512 {
+        assert!(tree.insert(Object::new((i % 8) as f32 + 0.25, (i / 8 % 8) as f32 + 0.25, (i / 64) as f32 + 0.25)).is_ok());
+    }
+    
+    let point = [0.6, 1.1, 0.4];
+    let forces = |tree: &Octree<f32, Object, Mass<f32>>, theta: f32| {
+        let (mut force, mut sources) = ([0.0; 3], 0);
+        tree.for_each_force_source(point, theta, |source| {
+            let pull = match source {
+                ForceSource::Node(mass, centroid) => gravity(point, mass, centroid),
+                ForceSource::Item(item) => gravity(point, item.weight(), item.octree_index())
+            };
+            for axis in 0..3 {
+                force[axis] += pull[axis];
+            }
+            sources += 1;
+        });
+        (force, sources)
+    };
+    let error = |tree: &Octree<f32, Object, Mass<f32>>, force: [f32; 3]| {
+        let mut exact = [0.0; 3];
+        for item in tree.get_in_volume(&vol).into_iter() {
+            let pull = gravity(point, item.weight(), item.octree_index());
+            for axis in 0..3 {
+                exact[axis] += pull[axis];
+            }
+        }
+        let difference: f32 = (0..3).map(|axis| (force[axis] - exact[axis]).powi(2)).sum();
+        let magnitude: f32 = exact.iter().map(|x| x * x).sum();
+        (difference / magnitude).sqrt()
+    };
+    // Without approximation every item acts on its own.
+    let (force, sources) = forces(&tree, 0.0);
+    assert_eq!(sources, 512);
+    assert!(error(&tree, force) < 1e-4);
+    let (force, sources) = forces(&tree, 0.5);
+    assert!(sources < 512 / 3);
+    assert!(error(&tree, force) < 0.02);
+}
+
+/// Node data counting the items in the subtree of its node and summing
+/// up their `x` in quarters.
+#[derive(Default)]
+struct Sum {
+    own: (usize, i64),
+    below: (usize, i64)
+}
+
+impl NodeData<f32, Object> for Sum {
+    fn on_insert(&mut self, item: &Object) {
+        self.own = Sum::combine(self.own, Sum::of_item(item));
+    }
+
+    fn on_remove(&mut self, item: &Object) {
+        self.own = (self.own.0 - 1, self.own.1 - Sum::of_item(item).1);
+    }
+
+    fn on_children_change(&mut self, children: [&Sum; 8]) {
+        self.below = children.iter().fold(Sum::empty(), |sum, child| Sum::combine(sum, child.value()));
+    }
+
+    fn on_merge(&mut self) {
+        self.own = Sum::combine(self.own, self.below);
+        self.below = Sum::empty();
+    }
+}
+
+impl Aggregate<f32, Object> for Sum {
+    type Value = (usize, i64);
+
+    fn empty() -> (usize, i64) {
+        (0, 0)
+    }
+
+    fn of_item(item: &Object) -> (usize, i64) {
+        (1, (item.x * 4.0) as i64)
+    }
+
+    fn combine(a: (usize, i64), b: (usize, i64)) -> (usize, i64) {
+        (a.0 + b.0, a.1 + b.1)
+    }
+
+    fn value(&self) -> (usize, i64) {
+        Sum::combine(self.own, self.below)
+    }
+}
+
+#[test]
+fn octree_aggregate_in_volume() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree: Octree<f32, Object, Sum> = Octree::with_node_data(vol, 2);
+    assert_eq!((tree.aggregate_in_volume(&vol), tree.count_in_volume(&vol)), ((0, 0), 0));
+    for i in 0..200 {
+        assert!(tree.insert(Object::new((i % 8) as f32 / 2.0, (i / 8 % 8) as f32 / 2.0, (i / 64) as f32 / 2.0)).is_ok());
+    }
+    // Queries along the boundaries of nodes, and outside of the tree.
+    let check = |tree: &Octree<f32, Object, Sum>| for query in [vol, Volume::new([1.0, 1.0, 0.0], [3.0, 2.5, 1.0]), Volume::new([2.0, 0.0, 0.0], [2.0, 8.0, 8.0]),
+                                                               Volume::new([9.0, 9.0, 9.0], [10.0, 10.0, 10.0])].iter() {
+        let found = tree.get_in_volume(query);
+        assert_eq!(tree.aggregate_in_volume(query), found.iter().fold(Sum::empty(), |sum, item| Sum::combine(sum, Sum::of_item(*item))));
+        assert_eq!(tree.count_in_volume(query), found.len());
+    };
+    check(&tree);
+    assert_eq!(tree.aggregate_in_volume(&vol).0, 200);
+    
+    // The sums follow removals, moves and merges.
+    for i in (0..200).filter(|i| i % 3 == 0) {
+        assert!(tree.remove(&Object::new((i % 8) as f32 / 2.0, (i / 8 % 8) as f32 / 2.0, (i / 64) as f32 / 2.0)).is_some());
+    }
+    check(&tree);
+    assert!(tree.update(&[0.5, 0.0, 0.0], Object::new(7.5, 7.5, 7.5)).is_ok());
+    check(&tree);
+    tree.retain(|item| item.x < 2.0);
+    check(&tree);
+    assert_eq!(tree.aggregate_in_volume(&vol).0, tree.len());
+}
+
+#[test]
+fn octree_frustum_from_matrix() {
+    let identity = [[1.0, 0.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]];
+    let frustum: Frustum<f32> = Frustum::from_matrix(&identity);
+    
+    // The identity projection sees the clip-space cube.
+    assert_eq!(frustum.planes[0].normal, [1.0, 0.0, 0.0]);
+    assert_eq!(frustum.planes[0].d, 1.0);
+    assert!(frustum.contains(&[0.0, 0.0, 0.0]));
+    assert!(frustum.contains(&[1.0, -1.0, 1.0]));
+    assert!(!frustum.contains(&[0.0, 0.0, 1.5]));
+    
+    // Scaling x by two halves the visible width.
+    let scaled = [[2.0, 0.0, 0.0, 0.0],
+                  [0.0, 1.0, 0.0, 0.0],
+                  [0.0, 0.0, 1.0, 0.0],
+                  [0.0, 0.0, 0.0, 1.0]];
+    let frustum: Frustum<f32> = Frustum::from_matrix(&scaled);
+    assert!(frustum.contains(&[0.4, 0.0, 0.0]));
+    assert!(!frustum.contains(&[0.6, 0.0, 0.0]));
+}
+
+#[test]
+fn octree_get_in_frustum() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.2, 0.4, 0.6, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, 0.5, 0.5)).is_ok());
+    }
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    
+    // Scaling x by two makes the visible part of the tree x <= 0.5.
+    let scaled = [[2.0, 0.0, 0.0, 0.0],
+                  [0.0, 1.0, 0.0, 0.0],
+                  [0.0, 0.0, 1.0, 0.0],
+                  [0.0, 0.0, 0.0, 1.0]];
+    let frustum: Frustum<f32> = Frustum::from_matrix(&scaled);
+    let mut visible: Vec<f32> = tree.get_in_frustum(&frustum.planes).iter().map(|item| item.x).collect();
+    visible.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(visible, vec![0.1, 0.1, 0.2, 0.4]);
+    
+    // The clip-space cube holds the whole tree.
+    let identity = [[1.0, 0.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]];
+    let frustum: Frustum<f32> = Frustum::from_matrix(&identity);
+    assert_eq!(tree.get_in_frustum(&frustum.planes).len(), 6);
+    
+    let shifted = [[1.0, 0.0, 0.0, 3.0],
+                   [0.0, 1.0, 0.0, 0.0],
+                   [0.0, 0.0, 1.0, 0.0],
+                   [0.0, 0.0, 0.0, 1.0]];
+    let frustum: Frustum<f32> = Frustum::from_matrix(&shifted);
+    assert!(tree.get_in_frustum(&frustum.planes).is_empty());
+}
+
+#[test]
+fn octree_volume_intersect_ray() {
+    let vol = Volume::new([1.0, 1.0, 1.0], [2.0, 2.0, 2.0]);
+    
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 1.5, 1.5], [1.0, 0.0, 0.0])), Some((1.0, 2.0)));
+    assert_eq!(vol.intersect_ray(&Ray::new([3.0, 3.0, 3.0], [-1.0, -1.0, -1.0])), Some((1.0, 2.0)));
+    assert_eq!(vol.intersect_ray(&Ray::new([1.5, 1.5, 1.5], [0.0, 0.0, 2.0])), Some((0.0, 0.25)));
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 0.0, 0.0], [-1.0, 0.0, 0.0])), None);
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 1.5, 0.0], [1.0, 0.0, 0.0])), None);
+}
+
+#[test]
+fn octree_occupancy() {
+    let mut tree = OccupancyOctree::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]), 2);
+    assert_eq!(tree.voxels().len(), 0);
+    
+    // Voxels only touching the region stay unoccupied.
+    tree.fill(&Volume::new([0.0, 0.0, 0.0], [0.3, 0.25, 0.25]));
+    assert_eq!(tree.voxels().len(), 2);
+    assert!(tree.is_occupied(&[0.1, 0.1, 0.1]));
+    assert!(tree.is_occupied(&[0.4, 0.1, 0.1]));
+    assert!(!tree.is_occupied(&[0.1, 0.4, 0.1]));
+    
+    // Filling the whole volume collapses the tree into its root.
+    tree.fill(&Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]));
+    assert_eq!(tree.voxels().len(), 1);
+    assert!(!tree.is_occupied(&[1.5, 0.4, 0.4]));
+}
+
+/// Returns an `OccupancyOctree` of the unit cube with `max_depth`,
+/// occupied in the shell of voxels along its faces.
+#[cfg(feature = "surface")]
+fn unit_shell(max_depth: usize) -> OccupancyOctree<f32> {
+    let mut tree = OccupancyOctree::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]), max_depth);
+    let voxel = 1.0 / (1 << max_depth) as f32;
+    for axis in 0..3 {
+        for &side in [0.0, 1.0 - voxel].iter() {
+            let (mut min, mut max) = ([0.0; 3], [1.0; 3]);
+            min[axis] = side;
+            max[axis] = side + voxel;
+            tree.fill(&Volume::new(min, max));
+        }
+    }
+    tree
+}
+
+/// Checks that `mesh` is closed and wound consistently outwards, and
+/// returns the volume it encloses.
+#[cfg(feature = "surface")]
+fn closed_volume(mesh: &SurfaceMesh<f32>) -> f32 {
+    let mut edges = HashMap::new();
+    let mut volume = 0.0;
+    for tri in mesh.indices.chunks(3) {
+        for i in 0..3 {
+            *edges.entry((tri[i], tri[(i + 1) % 3])).or_insert(0) += 1;
+        }
+        let (a, b, c) = (mesh.vertices[tri[0] as usize], mesh.vertices[tri[1] as usize], mesh.vertices[tri[2] as usize]);
+        volume += (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                   + a[2] * (b[0] * c[1] - b[1] * c[0])) / 6.0;
+    }
+    for (&(a, b), &count) in edges.iter() {
+        assert_eq!((count, edges.get(&(b, a))), (1, Some(&1)));
+    }
+    volume
+}
+
+#[cfg(feature = "surface")]
+#[test]
+fn octree_extract_surface() {
+    let mut solid = OccupancyOctree::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]), 2);
+    solid.fill(&Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]));
+    let mesh = solid.extract_surface();
+    assert_eq!(mesh.indices.len() % 3, 0);
+    // The faces of the cube, with the edges and corners cut off at half
+    // a voxel.
+    for p in mesh.vertices.iter() {
+        assert!(p.iter().any(|&x| x == 0.0 || x == 1.0));
+        assert!(solid.bounds().contains(p));
+    }
+    let volume = closed_volume(&mesh);
+    assert!(volume > 0.9 && volume < 1.0);
+    
+    // The hollow shell has an inner surface facing the other way.
+    let volume = closed_volume(&unit_shell(2).extract_surface());
+    assert!(volume > 0.9 - 0.125 && volume < 1.0 - 0.125);
+    
+    let empty = OccupancyOctree::<f32>::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]), 2);
+    assert_eq!(empty.extract_surface(), SurfaceMesh { vertices: vec![], indices: vec![] });
+}
+
+#[cfg(feature = "surface")]
+#[test]
+fn octree_extract_surface_depths() {
+    // A large leaf next to small ones, one of which meets it only at
+    // an edge.
+    let mut tree = OccupancyOctree::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]), 4);
+    tree.fill(&Volume::new([0.0, 0.0, 0.0], [0.5, 0.5, 0.5]));
+    tree.fill(&Volume::new([0.5, 0.25, 0.25], [0.5625, 0.3125, 0.3125]));
+    tree.fill(&Volume::new([0.5, 0.5, 0.25], [0.5625, 0.5625, 0.3125]));
+    let voxels = tree.voxels();
+    assert!(voxels.iter().any(|v| v.max()[0] - v.min()[0] == 0.5));
+    assert!(voxels.iter().any(|v| v.max()[0] - v.min()[0] == 0.0625));
+    
+    // No cracks where the depths change.
+    let volume = closed_volume(&tree.extract_surface());
+    assert!(volume > 0.1 && volume < 0.125 + 2.0 * 0.0625 * 0.0625 * 0.0625);
+}
+
+/// The twelve triangles of the unit cube.
+fn unit_cube() -> Vec<[[f32; 3]; 3]> {
+    let c = |i: usize| [(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32];
+    let quads = [[0, 1, 3, 2], [4, 6, 7, 5], [0, 4, 5, 1], [2, 3, 7, 6], [0, 2, 6, 4], [1, 5, 7, 3]];
+    let mut triangles = Vec::new();
+    for q in quads.iter() {
+        triangles.push([c(q[0]), c(q[1]), c(q[2])]);
+        triangles.push([c(q[0]), c(q[2]), c(q[3])]);
+    }
+    triangles
+}
+
+#[test]
+fn octree_voxelize() {
+    let triangles = unit_cube();
+    
+    // Every voxel but the innermost eight touches a face of the cube.
+    let surface = OccupancyOctree::voxelize(&triangles, 2, Fill::Surface);
+    assert_eq!(surface.bounds().max(), [1.0, 1.0, 1.0]);
+    assert_eq!(surface.voxels().len(), 56);
+    assert!(surface.is_occupied(&[0.1, 0.1, 0.1]));
+    assert!(!surface.is_occupied(&[0.4, 0.4, 0.4]));
+    
+    // Filling the inside collapses the tree into its root.
+    let solid = OccupancyOctree::voxelize(&triangles, 2, Fill::Solid);
+    assert_eq!(solid.voxels().len(), 1);
+    assert!(solid.is_occupied(&[0.4, 0.4, 0.4]));
+    assert!(!solid.is_occupied(&[1.5, 0.4, 0.4]));
+}
+
+#[test]
+fn octree_sdf_sample() {
+    let triangles = unit_cube();
+    
+    let solid = OccupancyOctree::voxelize(&triangles, 2, Fill::Solid);
+    assert_eq!(solid.sdf_sample(&[0.5, 0.5, 0.5]), -0.5);
+    assert_eq!(solid.sdf_sample(&[0.5, 0.5, 0.25]), -0.25);
+    assert_eq!(solid.sdf_sample(&[2.0, 0.5, 0.5]), 1.0);
+    assert_eq!(solid.bake_sdf([2, 2, 2]), vec![-0.25; 8]);
+    
+    // The hollow shell is a quarter thick.
+    let surface = OccupancyOctree::voxelize(&triangles, 2, Fill::Surface);
+    assert_eq!(surface.sdf_sample(&[0.5, 0.5, 0.5]), 0.25);
+    assert_eq!(surface.sdf_sample(&[0.1, 0.5, 0.5]), -0.1);
+    assert_eq!(surface.sdf_sample(&[0.125, 0.5, 0.5]), -0.125);
+}
+
+#[test]
+fn octree_downsample() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.125, 0.125, 0.125)).is_ok());
+    assert!(tree.insert(Object::new(0.375, 0.375, 0.375)).is_ok());
+    assert!(tree.insert(Object::new(0.375, 0.125, 0.125)).is_ok());
+    assert!(tree.insert(Object::new(0.875, 0.875, 0.875)).is_ok());
+    
+    assert_eq!(tree.downsample(0.5), vec![[0.875 / 3.0, 0.625 / 3.0, 0.625 / 3.0],
+                                          [0.875, 0.875, 0.875]]);
+    assert_eq!(tree.downsample(0.25).len(), 4);
+    assert_eq!(tree.downsample(2.0).len(), 1);
+}
+
+#[test]
+fn octree_estimate_normals() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 4);
+    
+    // A slightly tilted grid, rising along y.
+    for x in 0..5 {
+        for y in 0..5 {
+            let (x, y) = (x as f32 * 0.2, y as f32 * 0.2);
+            assert!(tree.insert(Object::new(x, y, 0.25 + y * 0.5)).is_ok());
+        }
+    }
+    
+    let normals = tree.estimate_normals(6);
+    assert_eq!(normals.len(), 25);
+    
+    let expected = [0.0, -0.5 / 1.25f32.sqrt(), 1.0 / 1.25f32.sqrt()];
+    for n in normals.iter() {
+        let dot = n[0] * expected[0] + n[1] * expected[1] + n[2] * expected[2];
+        assert!((dot.abs() - 1.0).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn octree_correspondences() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    
+    let source = [[0.75, 0.75, 0.5], [0.25, 0.25, 0.5], [0.0, 1.0, 1.0]];
+    assert_eq!(tree.correspondences(&source, 0.5),
+               vec![Some((2, 0.25)), Some((0, 0.25)), None]);
+}
+
+#[test]
+fn octree_remove_outliers() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 4);
+    
+    for x in 0..4 {
+        for y in 0..4 {
+            let (x, y) = (0.1 + x as f32 * 0.05, 0.1 + y as f32 * 0.05);
+            assert!(tree.insert(Object::new(x, y, 0.1)).is_ok());
+        }
+    }
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    
+    let kept = tree.remove_radius_outliers(2, 0.06);
+    assert_eq!(kept.len(), 16);
+    assert!(kept.iter().all(|item| item.x < 0.5));
+    
+    let kept = tree.remove_statistical_outliers(4, 1.0);
+    assert_eq!(kept.len(), 16);
+    assert!(kept.iter().all(|item| item.x < 0.5));
+}
+
+/// Returns the positions of `items`, sorted.
+fn sorted_positions<'a, It: IntoIterator<Item=&'a Object>>(items: It) -> Vec<(f32, f32, f32)> {
+    let mut positions: Vec<(f32, f32, f32)> = items.into_iter().map(|o| (o.x, o.y, o.z)).collect();
+    positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    positions
+}
+
+/// Returns the temporary directories spilled to by this process,
+/// checking that only the current user can access them.
+fn spilled_files() -> usize {
+    let prefix = format!("spatial-spill-{}-", std::process::id());
+    let dirs: Vec<std::fs::DirEntry> = std::fs::read_dir(std::env::temp_dir()).unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .collect();
+    for dir in dirs.iter() {
+        assert!(dir.metadata().unwrap().is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(dir.metadata().unwrap().permissions().mode() & 0o777, 0o700);
+        }
+    }
+    dirs.len()
+}
+
+#[test]
+fn octree_paged() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree = Octree::with_capacity(vol, 4);
+    // A budget of a few items spills them to files many times over.
+    let mut builder = PagedBuilder::new(vol, 4, 2, 64);
+    for i in 0..500 {
+        let o = Object::new((i * 7 % 80) as f32 / 10.0, (i * 13 % 80) as f32 / 10.0, (i % 9) as f32 * 0.875);
+        assert!(tree.insert(o.clone()).is_ok());
+        assert!(builder.insert(o).is_ok());
+    }
+    assert!(matches!(builder.insert(Object::new(9.0, 0.0, 0.0)), Err(SpatialError::OutOfBounds)));
+    assert_eq!(builder.len(), 500);
+    assert!(spilled_files() > 0);
+    
+    let mut bytes = Vec::new();
+    assert!(builder.finish(&mut bytes).is_ok());
+    assert_eq!(spilled_files(), 0);
+    
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&bytes[..])).unwrap();
+    assert_eq!((paged.len(), paged.page_count()), (500, 64));
+    assert!(paged.bounds().min == vol.min && paged.bounds().max == vol.max);
+    // Queries on the boundaries of the pages find the items there once.
+    for query in [vol, Volume::new([2.0, 2.0, 2.0], [4.0, 6.0, 4.0]), Volume::new([1.5, 0.0, 3.0], [1.5, 8.0, 3.0])].iter() {
+        assert_eq!(sorted_positions(paged.get_in_volume(query).unwrap().iter()),
+                   sorted_positions(tree.get_in_volume(query)));
+    }
+    assert_eq!(sorted_positions(paged.get_in_radius([4.0, 4.0, 4.0], 2.5).unwrap().iter()),
+               sorted_positions(tree.get_in_radius([4.0, 4.0, 4.0], 2.5)));
+    
+    // Pages stay loaded once touched, until they're unloaded.
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(paged.resident_pages(), 0);
+    let query = Volume::new([0.5, 0.5, 0.5], [1.5, 1.5, 1.5]);
+    let found = sorted_positions(paged.get_in_volume(&query).unwrap().iter());
+    assert!(!found.is_empty());
+    assert_eq!(paged.resident_pages(), 1);
+    assert!(paged.is_resident(0) && paged.resident_bytes() > 0);
+    assert!(paged.unload(0));
+    assert!(!paged.unload(0));
+    assert_eq!((paged.resident_pages(), paged.resident_bytes()), (0, 0));
+    assert_eq!(sorted_positions(paged.get_in_volume(&query).unwrap().iter()), found);
+    assert_eq!(paged.get_in_volume(&vol).unwrap().len(), 500);
+    assert!(paged.resident_pages() > 1);
+    paged.unload_all();
+    assert_eq!((paged.resident_pages(), paged.resident_bytes()), (0, 0));
+    
+    // Touching a page again hits it, and a budget of nothing keeps only
+    // the page loaded last.
+    paged.reset_stats();
+    assert_eq!(sorted_positions(paged.get_in_volume(&query).unwrap().iter()), found);
+    assert_eq!(sorted_positions(paged.get_in_volume(&query).unwrap().iter()), found);
+    assert_eq!(paged.stats(), PageStats { hits: 1, misses: 1, evictions: 0 });
+    paged.set_budget(0);
+    assert_eq!((paged.budget(), paged.resident_pages()), (0, 0));
+    assert_eq!(paged.get_in_volume(&vol).unwrap().len(), 500);
+    assert_eq!(paged.resident_pages(), 1);
+    let stats = paged.stats();
+    assert_eq!(stats.evictions, stats.misses - 1);
+    
+    let malformed = |bytes: &[u8]| matches!(PagedOctree::<f32, Object, _>::open(Cursor::new(bytes)), Err(SpatialError::Serialization(_)));
+    assert!(malformed(&bytes[..bytes.len() - 1]));
+    assert!(malformed(&bytes[..10]));
+    assert!(malformed(&[0u8; 40]));
+    // A page can't reach past the end of a file of any size.
+    let mut end = [0; 8];
+    end.copy_from_slice(&bytes[bytes.len() - 8..]);
+    let table = u64::from_le_bytes(end) as usize;
+    let mut overflowing = bytes.clone();
+    overflowing[table + 8..table + 24].copy_from_slice(&[0xff; 16]);
+    assert!(malformed(&overflowing));
+    // Nor can it claim to decompress into more than its stored bytes
+    // could hold.
+    let mut oversized = bytes.clone();
+    oversized[table + 24..table + 32].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    assert!(malformed(&oversized));
+    // Nor can the pages hold more items than fit a `usize` together.
+    let mut crowded = bytes.clone();
+    crowded[table + 32..table + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+    crowded[table + 65..table + 73].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(malformed(&crowded));
+}
+
+/// Builds the tree of `octree_paged` as a paged snapshot with pages
+/// compressed with `compression`, returning it with the plain tree.
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+fn compressed_paged(compression: Compression) -> (Octree<f32, Object>, Vec<u8>, Vec<u8>) {
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree = Octree::with_capacity(vol, 4);
+    let mut builder = PagedBuilder::new(vol, 4, 2, 1 << 20);
+    builder.set_compression(compression);
+    let mut plain = PagedBuilder::new(vol, 4, 2, 1 << 20);
+    for i in 0..500 {
+        let o = Object::new((i * 7 % 80) as f32 / 10.0, (i * 13 % 80) as f32 / 10.0, (i % 9) as f32 * 0.875);
+        assert!(tree.insert(o.clone()).is_ok());
+        assert!(builder.insert(o.clone()).is_ok());
+        assert!(plain.insert(o).is_ok());
+    }
+    let (mut bytes, mut plain_bytes) = (Vec::new(), Vec::new());
+    assert!(builder.finish(&mut bytes).is_ok());
+    assert!(plain.finish(&mut plain_bytes).is_ok());
+    (tree, bytes, plain_bytes)
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn octree_paged_lz4() {
+    let (tree, mut compressed, plain) = compressed_paged(Compression::Lz4);
+    assert!(compressed.len() < plain.len());
+    
+    // Compressed pages answer the same, and loading one decompresses
+    // only that page.
+    let vol = *tree.bounds();
+    let query = Volume::new([0.5, 0.5, 0.5], [1.5, 1.5, 1.5]);
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&compressed[..])).unwrap();
+    assert_eq!(sorted_positions(paged.get_in_volume(&query).unwrap().iter()),
+               sorted_positions(tree.get_in_volume(&query)));
+    assert_eq!(paged.resident_pages(), 1);
+    assert_eq!(sorted_positions(paged.get_in_volume(&vol).unwrap().iter()),
+               sorted_positions(tree.get_in_volume(&vol)));
+    // Loaded pages count with their uncompressed size.
+    let mut unpacked: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&plain[..])).unwrap();
+    assert!(unpacked.get_in_volume(&vol).is_ok());
+    assert_eq!(paged.resident_bytes(), unpacked.resident_bytes());
+    // A damaged page fails the query touching it.
+    let first = 4 + 2 + 8 + 1 + 24;
+    compressed[first] ^= 0xff;
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&compressed[..])).unwrap();
+    assert!(matches!(paged.get_in_volume(&query), Err(SpatialError::Serialization(_))));
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn octree_paged_zstd() {
+    let (tree, mut bytes, plain) = compressed_paged(Compression::Zstd);
+    assert!(bytes.len() < plain.len());
+
+    let vol = *tree.bounds();
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&bytes[..])).unwrap();
+    let query = Volume::new([2.0, 2.0, 2.0], [4.0, 6.0, 4.0]);
+    assert_eq!(sorted_positions(paged.get_in_volume(&query).unwrap().iter()),
+               sorted_positions(tree.get_in_volume(&query)));
+    assert_eq!(paged.get_in_volume(&vol).unwrap().len(), 500);
+    // A damaged page fails the query touching it.
+    let first = 4 + 2 + 8 + 1 + 24;
+    bytes[first + 8] ^= 0xff;
+    let mut paged: PagedOctree<f32, Object, _> = PagedOctree::open(Cursor::new(&bytes[..])).unwrap();
+    assert!(matches!(paged.get_in_volume(&vol), Err(SpatialError::Serialization(_))));
+}
+
+#[test]
+fn octree_to_gpu_buffers() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    
+    let buffers = tree.to_gpu_buffers();
+    assert_eq!(buffers.nodes.len(), 9);
+    assert_eq!(buffers.nodes[0].first_child, 1);
+    assert_eq!(buffers.nodes[0].item_count, 1);
+    assert_eq!(buffers.nodes[8].min, [0.5, 0.5, 0.5]);
+    assert_eq!(buffers.nodes[8].first_item, 1);
+    assert_eq!(buffers.nodes[8].item_count, 1);
+    assert_eq!(buffers.nodes[8].first_child, u32::MAX);
+    assert_eq!(buffers.items[1].position, [0.75, 0.75, 0.75]);
+    assert_eq!(buffers.node_bytes().len(), 9 * 48);
+    assert_eq!(buffers.item_bytes().len(), 2 * 16);
+}
+
+#[test]
+fn octree_mipmap() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    
+    let mipmap = tree.mipmap();
+    let average = mipmap.average().unwrap();
+    assert_eq!(average.count, 2);
+    assert_eq!(average.position, [0.5, 0.5, 0.5]);
+    assert_eq!(average.attributes, [0.5, 0.0, 0.0, 1.0]);
+    
+    let far = mipmap.lod(&[10.0, 10.0, 10.0], 0.5);
+    assert_eq!(far.len(), 1);
+    assert_eq!(far[0].count, 2);
+    
+    let near = mipmap.lod(&[0.0, 0.0, 0.0], 1.0);
+    assert_eq!(near.len(), 2);
+    assert_eq!((near[0].count, near[0].size), (1, 0.0));
+    assert_eq!((near[1].count, near[1].size), (1, 0.5));
+    assert_eq!(near[1].attributes, [0.75, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn octree_k_nearest_in_volume() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.6, 0.6, 0.6)).is_ok());
+    assert!(tree.insert(Object::new(0.8, 0.8, 0.8)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    
+    let region = Volume::new([0.5, 0.5, 0.5], [1.0, 1.0, 1.0]);
+    let found = tree.k_nearest_in_volume(&region, &[0.0, 0.0, 0.0], 2);
+    assert_eq!(found.len(), 2);
+    assert_eq!((found[0].x, found[1].x), (0.6, 0.8));
+}
+
+#[test]
+fn octree_nearest_excluding() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.3, 0.3, 0.3)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    
+    let first = tree.nearest(&[0.1, 0.1, 0.1]).unwrap();
+    assert_eq!(tree.nearest_other(first).unwrap().x, 0.3);
+    assert_eq!(tree.nearest_excluding(&[0.1, 0.1, 0.1], |item| item.x < 0.5).unwrap().x, 0.9);
+    
+    let mut single = Octree::new(vol);
+    assert!(single.insert(Object::new(0.5, 0.5, 0.5)).is_ok());
+    let only = single.nearest(&[0.0, 0.0, 0.0]).unwrap();
+    assert!(single.nearest_other(only).is_none());
+}
+
+#[derive(Default)]
+struct Counter {
+    inserted: usize,
+    subdivided: bool
+}
+
+impl NodeData<f32, Object> for Counter {
+    fn on_insert(&mut self, _: &Object) {
+        self.inserted += 1;
+    }
+
+    fn on_subdivide(&mut self, _: &Volume<f32>) {
+        self.subdivided = true;
+    }
+}
+
+#[test]
+fn octree_node_data() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree: Octree<f32, Object, Counter> = Octree::with_node_data(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert_eq!((tree.data().inserted, tree.data().subdivided), (1, false));
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    assert_eq!((tree.data().inserted, tree.data().subdivided), (1, true));
+    
+    tree.data_mut().inserted = 0;
+    assert_eq!(tree.data().inserted, 0);
+}
+
+#[derive(Default)]
+struct Recorder {
+    inserted: Vec<Vec<usize>>,
+    subdivided: Vec<Vec<usize>>,
+    removed: Vec<Vec<usize>>,
+    merged: Vec<Vec<usize>>
+}
+
+impl Observer<f32, Object> for Recorder {
+    fn on_insert(&mut self, path: &[usize], _: &Object) {
+        self.inserted.push(path.to_vec());
+    }
+
+    fn on_subdivide(&mut self, path: &[usize], _: &Volume<f32>) {
+        self.subdivided.push(path.to_vec());
+    }
+
+    fn on_remove(&mut self, path: &[usize], _: &Object) {
+        self.removed.push(path.to_vec());
+    }
+
+    fn on_merge(&mut self, path: &[usize]) {
+        self.merged.push(path.to_vec());
+    }
+}
+
+#[test]
+fn octree_insert_observed() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    let mut recorder = Recorder::default();
+    
+    assert!(tree.insert_observed(Object::new(0.25, 0.25, 0.25), &mut recorder).is_ok());
+    assert!(tree.insert_observed(Object::new(0.75, 0.75, 0.75), &mut recorder).is_ok());
+    assert!(tree.insert_observed(Object::new(0.9, 0.9, 0.9), &mut recorder).is_ok());
+    assert!(tree.insert_observed(Object::new(2.0, 2.0, 2.0), &mut recorder).is_err());
+    assert_eq!(recorder.inserted, vec![vec![], vec![7], vec![7, 7]]);
+    assert_eq!(recorder.subdivided, vec![vec![], vec![7]]);
+}
+
+#[test]
+fn octree_remove() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    assert_eq!(tree.depth(), 2);
+    
+    assert_eq!(tree.remove(&Object::new(0.5, 0.5, 0.5)), None);
+    assert_eq!(tree.remove(&Object::new(0.9, 0.9, 0.9)), Some(Object::new(0.9, 0.9, 0.9)));
+    assert_eq!(tree.depth(), 1);
+    assert_eq!(tree.remove_at(&[0.25, 0.25, 0.25]), Some(Object::new(0.25, 0.25, 0.25)));
+    assert_eq!(tree.depth(), 0);
+    assert_eq!(tree.node_count(), 1);
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree.remove_at(&[0.25, 0.25, 0.25]), None);
+    
+    let mut recorder = Recorder::default();
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.remove_observed(&Object::new(0.75, 0.75, 0.75), &mut recorder).is_some());
+    assert_eq!(recorder.removed, vec![Vec::<usize>::new()]);
+    assert_eq!(recorder.merged, vec![Vec::<usize>::new()]);
+}
+
+#[test]
+fn octree_nearest() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    assert!(tree.nearest(&[0.5, 0.5, 0.5]).is_none());
+    
+    assert!(tree.insert(Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.6, 0.6, 0.6)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    
+    assert_eq!(tree.nearest(&[0.0, 0.0, 0.0]).unwrap().x, 0.1);
+    assert_eq!(tree.nearest(&[0.7, 0.7, 0.7]).unwrap().x, 0.6);
+    // Points outside the tree are fine too.
+    assert_eq!(tree.nearest(&[5.0, 5.0, 5.0]).unwrap().x, 0.9);
+}
+
+#[test]
+fn octree_child_occupancy() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert_eq!(tree.occupancy(), 0);
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.25)).is_ok());
+    assert_eq!(tree.occupancy(), 0b1000_0110);
+    
+    assert!(tree.remove_at(&[0.75, 0.25, 0.25]).is_some());
+    assert_eq!(tree.occupancy(), 0b1000_0100);
+    assert_eq!(tree.get_in_volume(&vol).len(), 3);
+}
+
+#[test]
+fn octree_nearest_n() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.9, 0.1, 0.5, 0.3, 0.7].iter() {
+        assert!(tree.insert(Object::new(x, x, x)).is_ok());
+    }
+    
+    let found: Vec<f32> = tree.nearest_n(&[0.0, 0.0, 0.0], 3).iter().map(|item| item.x).collect();
+    assert_eq!(found, vec![0.1, 0.3, 0.5]);
+    assert_eq!(tree.nearest_n(&[0.0, 0.0, 0.0], 10).len(), 5);
+    assert_eq!(tree.nearest_n(&[0.0, 0.0, 0.0], 0).len(), 0);
+}
+
+#[test]
+fn octree_iter() {
+    #[derive(Clone)]
+    struct Tagged {
+        x: f32,
+        tag: usize
+    }
+    
+    impl Index<f32> for Tagged {
+        fn octree_index(&self) -> [f32; 3] {
+            [self.x, self.x, self.x]
+        }
+    }
+    
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.5, 0.9, 0.1, 0.7].iter() {
+        assert!(tree.insert(Tagged { x: x, tag: 0 }).is_ok());
+    }
+    
+    let xs: Vec<f32> = tree.iter().map(|item| item.x).collect();
+    assert_eq!(xs, vec![0.5, 0.1, 0.9, 0.7]);
+    assert_eq!((&tree).into_iter().count(), 4);
+    
+    for (i, item) in tree.iter_mut().enumerate() {
+        item.tag = i + 1;
+    }
+    let mut tags: Vec<usize> = tree.get_in_volume(&Volume::new([0.0, 0.0, 0.0], [0.6, 0.6, 0.6])).iter().map(|item| item.tag).collect();
+    tags.sort();
+    assert_eq!(tags, vec![1, 2]);
+    
+    let xs: Vec<f32> = tree.into_iter().map(|item| item.x).collect();
+    assert_eq!(xs, vec![0.5, 0.1, 0.9, 0.7]);
+}
+
+#[test]
+fn octree_k_nearest_batch() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    
+    for i in 0..64 {
+        let (x, y, z) = ((i % 4) as f32 * 0.25, (i / 4 % 4) as f32 * 0.25, (i / 16) as f32 * 0.25);
+        assert!(tree.insert(Object::new(x + 0.1, y + 0.05, z + 0.02)).is_ok());
+    }
+    
+    let queries: Vec<[f32; 3]> = (0..20).map(|i| {
+        let t = i as f32 * 0.05;
+        [t, 1.0 - t, (t * 3.0) % 1.0]
+    }).collect();
+    
+    let batch = tree.k_nearest_batch(&queries, 3);
+    assert_eq!(batch.len(), queries.len());
+    for (query, found) in queries.iter().zip(batch.iter()) {
+        let expected = tree.nearest_n(query, 3);
+        let found: Vec<*const Object> = found.iter().map(|&item| item as *const Object).collect();
+        let expected: Vec<*const Object> = expected.iter().map(|&item| item as *const Object).collect();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn octree_get_in_volume_mut() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, 0.5, 0.5)).is_ok());
+    }
+    
+    let mut found: Vec<f32> = tree.get_in_volume_mut(&Volume::new([0.0, 0.0, 0.0], [0.5, 1.0, 1.0]))
+        .into_iter().map(|item| item.x).collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(found, vec![0.1, 0.4]);
+    
+    let found = tree.get_in_radius_mut([0.9, 0.5, 0.5], 0.35);
+    assert_eq!(found.len(), 2);
+}
+
+#[test]
+fn octree_update() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9, 0.9)).is_ok());
+    
+    // Within the same node.
+    assert!(tree.update(&[0.9, 0.9, 0.9], Object::new(0.8, 0.8, 0.8)).is_ok());
+    assert_eq!(tree.depth(), 2);
+    
+    // Across the tree; the emptied subtree is merged.
+    assert!(tree.update(&[0.8, 0.8, 0.8], Object::new(0.1, 0.1, 0.1)).is_ok());
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.nearest(&[0.0, 0.0, 0.0]).unwrap().x, 0.1);
+    assert_eq!(tree.get_in_volume(&Volume::new([0.5, 0.5, 0.5], [1.0, 1.0, 1.0])).len(), 1);
+    
+    match tree.update(&[0.5, 0.5, 0.5], Object::new(0.1, 0.1, 0.1)) {
+        Err(SpatialError::NotFound) => {},
+        other => panic!("expected NotFound, got {:?}", other)
+    }
+    match tree.update(&[0.1, 0.1, 0.1], Object::new(2.0, 2.0, 2.0)) {
+        Err(SpatialError::OutOfBounds) => {},
+        other => panic!("expected OutOfBounds, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 3);
+}
+
+#[test]
+fn octree_update_depth_limit() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for _ in 0..65 {
+        assert!(tree.insert(Object::new(0.0, 0.0, 0.0)).is_ok());
+    }
+    assert!(tree.insert(Object::new(0.4, 0.4, 0.4)).is_ok());
+    
+    match tree.update(&[0.4, 0.4, 0.4], Object::new(0.0, 0.0, 0.0)) {
+        Err(SpatialError::DepthLimit) => {},
+        other => panic!("expected DepthLimit, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 66);
+    assert_eq!(tree.get_in_volume(&Volume::new([0.25, 0.25, 0.25], [0.5, 0.5, 0.5])).len(), 1);
+}
+
+#[test]
+fn octree_rebase() {
+    let vol = Volume::new([1000.0, 1000.0, 1000.0], [1004.0, 1004.0, 1004.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [1000.5, 1003.5].iter() {
+        assert!(tree.insert(Object::new(x, x, 1000.5)).is_ok());
+    }
+    
+    tree.rebase([1002.0, 1002.0, 1002.0]);
+    assert_eq!(tree.bounds().min(), [-2.0, -2.0, -2.0]);
+    assert_eq!(tree.bounds().max(), [2.0, 2.0, 2.0]);
+    assert_eq!(tree.get_in_radius([1.5, 1.5, -1.5], 0.1), vec![&Object::new(1.5, 1.5, -1.5)]);
+    assert_eq!(tree.len(), 2);
+}
+
+#[test]
+fn octree_transformed_view() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(1.0, 0.5, 2.0)).is_ok());
+    assert!(tree.insert(Object::new(3.0, 3.0, 3.0)).is_ok());
+    
+    // Scaled by two and moved down by 10 along z.
+    let m = [[2.0, 0.0, 0.0, 0.0], [0.0, 2.0, 0.0, 0.0], [0.0, 0.0, 2.0, -10.0], [0.0, 0.0, 0.0, 1.0]];
+    let view = tree.transformed(&m).unwrap();
+    assert_eq!(view.to_world(&[1.0, 0.5, 2.0]), [2.0, 1.0, -6.0]);
+    assert_eq!(view.to_local(&[2.0, 1.0, -6.0]), [1.0, 0.5, 2.0]);
+    assert_eq!(view.get_in_volume(&Volume::new([1.5, 0.5, -7.0], [2.5, 1.5, -5.0])).len(), 1);
+    assert_eq!(view.get_in_radius([6.0, 6.0, -4.0], 0.1).len(), 1);
+    assert!(view.get_in_radius([3.0, 3.0, 3.0], 0.1).is_empty());
+}
+
+#[test]
+fn octree_overlaps_with() {
+    let mut truth = Octree::with_capacity(Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]), 2);
+    let mut detected = Octree::with_capacity(Volume::new([-1.0, -1.0, -1.0], [9.0, 9.0, 9.0]), 3);
+    let mut truths = Vec::new();
+    let mut detections = Vec::new();
+    for i in 0..64 {
+        let (x, y, z) = ((i % 4) as f32 * 2.0 + 0.5, ((i / 4) % 4) as f32 * 2.0 + 0.5, (i / 16) as f32 * 2.0 + 0.5);
+        truths.push(Object::new(x, y, z));
+        // Every other object is detected slightly off, the rest far off.
+        let offset = if i % 2 == 0 { 0.05 } else { 0.5 };
+        detections.push(Object::new(x + offset, y, z - offset));
+    }
+    for item in truths.iter() {
+        assert!(truth.insert(item.clone()).is_ok());
+    }
+    for item in detections.iter() {
+        assert!(detected.insert(item.clone()).is_ok());
+    }
+    
+    let pairs = truth.overlaps_with(&detected, 0.1);
+    assert_eq!(pairs.len(), 32);
+    for &(a, b) in pairs.iter() {
+        assert!((b.x - a.x - 0.05).abs() < 1e-4);
+    }
+    
+    let expected = truths.iter()
+        .map(|a| detections.iter().filter(|b| {
+            let (dx, dy, dz) = (a.x - b.x, a.y - b.y, a.z - b.z);
+            dx * dx + dy * dy + dz * dz <= 1.0
+        }).count())
+        .sum::<usize>();
+    assert_eq!(detected.overlaps_with(&truth, 1.0).len(), expected);
+}
+
+#[test]
+fn octree_weighted_centroid() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75, 0.75)).is_ok());
+    
+    assert_eq!(tree.weighted_centroid(&vol), Some(([0.625, 0.625, 0.625], 1.0)));
+    assert_eq!(tree.weighted_centroid(&Volume::new([0.5, 0.5, 0.5], [1.0, 1.0, 1.0])),
+               Some(([0.75, 0.75, 0.75], 0.75)));
+    assert_eq!(tree.weighted_centroid(&Volume::new([0.4, 0.0, 0.0], [0.6, 1.0, 1.0])), None);
+}
+
+#[test]
+fn octree_histogram_along_axis() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    let mut points = Vec::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            for k in 0..10 {
+                points.push([(i as f32 + 0.5) / 10.0, (j as f32 * 3.0 % 10.0 + 0.25) / 10.0,
+                             (k as f32 + 0.75) / 10.0]);
+            }
+        }
+    }
+    for p in points.iter() {
+        assert!(tree.insert(Object::new(p[0], p[1], p[2])).is_ok());
+    }
+    
+    assert_eq!(tree.histogram_along_axis(&vol, 2, 2), vec![500, 500]);
+    
+    let region = Volume::new([0.1, 0.2, 0.3], [0.8, 0.9, 0.7]);
+    for axis in 0..3 {
+        let mut expected = vec![0; 5];
+        for p in points.iter().filter(|p| region.contains(p)) {
+            let bin = ((p[axis] - region.min[axis]) * 5.0 / (region.max[axis] - region.min[axis])) as usize;
+            expected[bin.min(4)] += 1;
+        }
+        assert_eq!(tree.histogram_along_axis(&region, axis, 5), expected);
+    }
+    
+    // The counts of whole subtrees follow removals and moves.
+    for p in points.iter().filter(|p| p[2] < 0.5 && p[0] < 0.5) {
+        assert!(tree.remove(&Object::new(p[0], p[1], p[2])).is_some());
+    }
+    assert!(tree.update(&[0.95, 0.025, 0.975], Object::new(0.95, 0.025, 0.075)).is_ok());
+    assert_eq!(tree.histogram_along_axis(&vol, 2, 2), vec![251, 499]);
+}
+
+#[test]
+fn octree_rasterize_counts() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    let mut points = Vec::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            for k in 0..10 {
+                points.push([(i as f32 + 0.5) / 10.0, (j as f32 * 3.0 % 10.0 + 0.25) / 10.0,
+                             (k as f32 + 0.75) / 10.0]);
+            }
+        }
+    }
+    for p in points.iter() {
+        assert!(tree.insert(Object::new(p[0], p[1], p[2])).is_ok());
+    }
+    
+    assert_eq!(tree.rasterize_counts(&vol, 2, 1, 2), vec![250; 4]);
+    
+    let region = Volume::new([0.1, 0.2, 0.3], [0.8, 0.9, 0.7]);
+    let n = [3, 2, 4];
+    let mut expected = vec![0; 24];
+    for p in points.iter().filter(|p| region.contains(p)) {
+        let mut cell = [0; 3];
+        for axis in 0..3 {
+            let c = ((p[axis] - region.min[axis]) * n[axis] as f32 / (region.max[axis] - region.min[axis])) as usize;
+            cell[axis] = c.min(n[axis] - 1);
+        }
+        expected[(cell[2] * n[1] + cell[1]) * n[0] + cell[0]] += 1;
+    }
+    assert_eq!(tree.rasterize_counts(&region, n[0], n[1], n[2]), expected);
+    
+    // The counts of whole subtrees follow items dropped by `retain`.
+    tree.retain(|o| o.z > 0.5 || o.x < 0.5);
+    assert_eq!(tree.rasterize_counts(&vol, 2, 1, 2), vec![250, 0, 250, 250]);
+}
+
+#[test]
+fn octree_stratified_sample() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [4.0, 4.0, 4.0]);
+    let mut tree = Octree::with_capacity(vol, 4);
+    for i in 0..50 {
+        assert!(tree.insert(Object::new(0.5, 0.1 + i as f32 * 0.01, 0.5)).is_ok());
+    }
+    for &(x, y, z) in [(2.5, 2.5, 2.5), (3.5, 0.5, 3.5), (3.6, 0.6, 3.6)].iter() {
+        assert!(tree.insert(Object::new(x, y, z)).is_ok());
+    }
+    
+    let mut state = 3u32;
+    let sample = tree.stratified_sample(1.0, 3, |n| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 8) as usize % n
+    });
+    assert_eq!(sample.len(), 6);
+    assert_eq!(sample.iter().filter(|o| o.x < 1.0).count(), 3);
+    assert!(sample[0].y != sample[1].y && sample[1].y != sample[2].y && sample[0].y != sample[2].y);
+}
+
+#[test]
+fn octree_linear() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut objects = Vec::new();
+    for i in 0..3000 {
+        objects.push(Object::new((i * 37 % 1000) as f32 / 100.0, (i * 91 % 997) as f32 / 100.0,
+                                 (i * 13 % 991) as f32 / 100.0));
+    }
+    objects.push(Object::new(5.0, 12.0, 5.0));
+    let tree = LinearOctree::new(vol, &objects);
+    assert_eq!(tree.len(), objects.len());
+    assert!(tree.keys().windows(2).all(|pair| pair[0] <= pair[1]));
+    
+    for query in [Volume::new([1.0, 2.0, 3.0], [3.5, 6.1, 4.0]), vol,
+                  Volume::new([4.0, 9.0, 4.0], [6.0, 20.0, 6.0])].iter() {
+        let mut found: Vec<Object> = tree.get_in_volume(query).into_iter().cloned().collect();
+        let mut expected: Vec<Object> = objects.iter().filter(|o| query.contains(&o.octree_index())).cloned().collect();
+        found.sort_by(|a, b| (a.x, a.y, a.z).partial_cmp(&(b.x, b.y, b.z)).unwrap());
+        expected.sort_by(|a, b| (a.x, a.y, a.z).partial_cmp(&(b.x, b.y, b.z)).unwrap());
+        assert_eq!(found, expected);
+    }
+    
+    let expected = objects.iter()
+        .filter(|o| (o.x - 3.0).powi(2) + (o.y - 3.0).powi(2) + (o.z - 3.0).powi(2) <= 4.0)
+        .count();
+    assert_eq!(tree.get_in_radius([3.0, 3.0, 3.0], 2.0).len(), expected);
+}
+
+#[test]
+fn octree_find_duplicates() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    for i in 0..5 {
+        for j in 0..5 {
+            for k in 0..5 {
+                assert!(tree.insert(Object::new(i as f32 / 5.0 + 0.05, j as f32 / 5.0 + 0.05,
+                                                k as f32 / 5.0 + 0.05)).is_ok());
+            }
+        }
+    }
+    assert!(tree.insert(Object::new(0.4999, 0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(0.5, 0.5001, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.2501)).is_ok());
+    
+    let groups = tree.find_duplicates(0.001);
+    assert_eq!(groups.len(), 2);
+    let mut sizes: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    sizes.sort();
+    assert_eq!(sizes, vec![2, 2]);
+    assert!(groups.iter().any(|group| group.iter().any(|o| o.z == 0.2501) && group.iter().any(|o| o.z == 0.25)));
+}
+
+#[test]
+fn octree_locate() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    let objects: Vec<Object> = (0..6).map(|i| Object::new(i as f32 + 0.5, i as f32 * 1.3 + 0.2, 7.5 - i as f32)).collect();
+    for o in objects.iter() {
+        assert!(tree.insert(o.clone()).is_ok());
+    }
+    
+    let mut seen = Vec::new();
+    for o in objects.iter() {
+        let (depth, path, volume) = tree.locate(o).unwrap();
+        assert_eq!(depth, path.depth());
+        assert!(volume.contains(&[o.x, o.y, o.z]));
+        assert!(volume.max[0] - volume.min[0] == 8.0 / (1 << depth) as f32);
+        assert!(!seen.contains(&path));
+        seen.push(path);
+    }
+    
+    assert!(tree.locate(&Object::new(0.5, 0.3, 7.5)).is_none());
+    assert!(tree.locate(&Object::new(9.0, 9.0, 9.0)).is_none());
+}
+
+#[test]
+fn octree_node_path() {
+    let root = NodePath::root();
+    assert!(root.is_root() && root.parent().is_none());
+    let path = root.child(3).child(0).child(7);
+    assert_eq!(path.depth(), 3);
+    assert_eq!(path.indices(), vec![3, 0, 7]);
+    assert_eq!(NodePath::from_indices(&[3, 0, 7]), Some(path));
+    assert_eq!(path.parent(), Some(root.child(3).child(0)));
+    assert!(root.child(3).is_prefix_of(&path) && !root.child(1).is_prefix_of(&path));
+    assert!(NodePath::from_indices(&[8]).is_none());
+    assert!(NodePath::from_indices(&vec![1; 65]).is_none());
+    let deep = NodePath::from_indices(&vec![7; 64]).unwrap();
+    assert_eq!(deep.get(63), Some(7));
+    assert_eq!(deep.parent().unwrap().get(63), None);
+    
+    let objects: Vec<Object> = (0..6).map(|i| Object::new(i as f32 + 0.5, i as f32 * 1.3 + 0.2, 7.5 - i as f32)).collect();
+    let vol = Volume::new([0.0, 0.0, 0.0], [8.0, 8.0, 8.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    for o in objects.iter() {
+        assert!(tree.insert(o.clone()).is_ok());
+    }
+    for o in objects.iter() {
+        let (_, path, volume) = tree.locate(o).unwrap();
+        let node = tree.subtree(&path).unwrap();
+        assert!(node.bounds().min == volume.min && node.bounds().max == volume.max);
+        let computed = tree.volume_of(&path);
+        assert!(computed.min == volume.min && computed.max == volume.max);
+    }
+    let far = tree.volume_of(&NodePath::from_indices(&[7, 7]).unwrap());
+    assert!(far.min == [6.0, 6.0, 6.0] && far.max == [8.0, 8.0, 8.0]);
+}
+
+impl Snapshot for Counter {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        (self.inserted, self.subdivided).write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<Counter, SpatialError> {
+        <(usize, bool)>::read_snapshot(input).map(|(inserted, subdivided)| Counter { inserted: inserted, subdivided: subdivided })
+    }
+}
+
+#[test]
+fn octree_snapshot() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut tree: Octree<f32, Object, Counter> = Octree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for i in 0..60 {
+        assert!(tree.insert(Object::new((i * 7 % 10) as f32 + 0.25, (i * 3 % 10) as f32 + 0.5, (i % 4) as f32 + 0.75)).is_ok());
+    }
+    assert!(tree.remove(&Object::new(0.25, 0.5, 0.75)).is_some());
+    
+    let mut bytes = Vec::new();
+    assert!(tree.save_to(&mut bytes).is_ok());
+    let mut loaded: Octree<f32, Object, Counter> = Octree::load_from(&mut &bytes[..]).unwrap();
+    
+    assert_eq!(loaded.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+    assert_eq!((loaded.len(), loaded.node_count(), loaded.depth()), (tree.len(), tree.node_count(), tree.depth()));
+    assert_eq!(loaded.item_order(), ItemOrder::Insertion);
+    let query = Volume::new([2.0, 2.0, 1.0], [7.0, 6.0, 3.0]);
+    assert_eq!(loaded.get_in_volume(&query), tree.get_in_volume(&query));
+    let (_, path, _) = tree.locate(&Object::new(7.25, 3.5, 1.75)).unwrap();
+    let (a, b) = (tree.subtree(&path).unwrap().data(), loaded.subtree(&path).unwrap().data());
+    assert_eq!((a.inserted, a.subdivided), (b.inserted, b.subdivided));
+    
+    // The loaded tree goes on keeping insertion order.
+    assert!(loaded.insert(Object::new(3.25, 9.75, 1.0)).is_ok());
+    assert!(tree.insert(Object::new(3.25, 9.75, 1.0)).is_ok());
+    assert_eq!(loaded.get_in_volume(&vol), tree.get_in_volume(&vol));
+    
+    let malformed = |bytes: &[u8]| matches!(Octree::<f32, Object, Counter>::load_from(&mut &bytes[..]),
+                                            Err(SpatialError::Serialization(_)));
+    assert!(malformed(&bytes[..bytes.len() - 1]));
+    assert!(malformed(&bytes[..3]));
+    let mut versioned = bytes.clone();
+    versioned[4] += 1;
+    assert!(malformed(&versioned));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(malformed(&trailing));
+    // Nodes holding more items than the capacity, and items outside of
+    // the bounds, are refused.
+    let mut overfull = bytes.clone();
+    overfull[6] = 1;
+    assert!(malformed(&overfull));
+    let mut shrunk = bytes.clone();
+    shrunk[35..39].copy_from_slice(&[0, 0, 0xa0, 0x40]);
+    assert!(malformed(&shrunk));
+}
+
+#[test]
+fn octree_snapshot_huge_capacity() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut tree: Octree<f32, Object, Counter> = Octree::with_node_data(vol, 1);
+    assert!(tree.insert(Object::new(1.0, 2.0, 3.0)).is_ok());
+    assert!(tree.insert(Object::new(4.0, 5.0, 6.0)).is_ok());
+    let mut bytes = Vec::new();
+    assert!(tree.save_to(&mut bytes).is_ok());
+    
+    // A corrupted capacity isn't allocated for up front, neither for the
+    // root nor for the nodes it's subdivided into.
+    bytes[6..14].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    let mut loaded: Octree<f32, Object, Counter> = Octree::load_from(&mut &bytes[..]).unwrap();
+    assert_eq!(loaded.capacity(), 1 << 40);
+    assert_eq!((loaded.len(), loaded.node_count()), (tree.len(), tree.node_count()));
+    assert!(loaded.rebuild_incremental(usize::MAX).unwrap());
+    assert_eq!(loaded.len(), 2);
+}
+
+/// Applies `deltas` to `replica`, a copy of the items of each node of
+/// a tree, asserting that every item removed was there.
+fn apply_deltas(replica: &mut HashMap<NodePath, Vec<Object>>, deltas: Vec<NodeDelta<Object>>) {
+    for delta in deltas.into_iter() {
+        let items = replica.entry(delta.path).or_default();
+        for item in delta.removed.iter() {
+            let i = items.iter().position(|other| other == item).unwrap();
+            items.remove(i);
+        }
+        items.extend(delta.added);
+    }
+}
+
+/// The positions of the items of each node, by the indices of its path.
+type Nodes = Vec<(Vec<usize>, Vec<(f32, f32, f32)>)>;
+
+/// Asserts that `replica` holds the items of each node of `tree`.
+fn assert_replicated(tree: &Octree<f32, Object>, replica: &HashMap<NodePath, Vec<Object>>) {
+    let mut expected: HashMap<NodePath, Vec<Object>> = HashMap::new();
+    for item in tree.iter() {
+        let (_, path, _) = tree.locate(item).unwrap();
+        expected.entry(path).or_default().push(item.clone());
+    }
+    let normalize = |map: &HashMap<NodePath, Vec<Object>>| {
+        let mut nodes: Nodes = map.iter()
+            .filter(|&(_, items)| !items.is_empty())
+            .map(|(path, items)| {
+                let mut items: Vec<(f32, f32, f32)> = items.iter().map(|o| (o.x, o.y, o.z)).collect();
+                items.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                (path.indices(), items)
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        nodes
+    };
+    assert_eq!(normalize(replica), normalize(&expected));
+}
+
+#[test]
+fn octree_collect_dirty() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    let mut replica = HashMap::new();
+    for i in 0..10 {
+        assert!(tree.insert(Object::new(i as f32 + 0.5, 0.5, 0.5)).is_ok());
+    }
+    
+    // The first call reports everything, later ones only what changed.
+    let deltas = tree.collect_dirty();
+    assert_eq!(deltas.iter().map(|delta| delta.added.len()).sum::<usize>(), 10);
+    apply_deltas(&mut replica, deltas);
+    assert_replicated(&tree, &replica);
+    assert!(tree.collect_dirty().is_empty());
+    
+    for i in 0..40 {
+        assert!(tree.insert(Object::new((i * 7 % 16) as f32 + 0.25, (i / 3) as f32 + 1.5, (i % 5) as f32 * 3.0)).is_ok());
+    }
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    // Changes undone before the next call cancel out.
+    assert!(tree.insert(Object::new(15.5, 15.5, 15.5)).is_ok());
+    assert!(tree.remove(&Object::new(15.5, 15.5, 15.5)).is_some());
+    assert!(tree.update(&[0.5, 0.5, 0.5], Object::new(0.75, 0.5, 0.5)).is_ok());
+    assert!(tree.update(&[0.75, 0.5, 0.5], Object::new(0.5, 0.5, 0.5)).is_ok());
+    assert!(tree.collect_dirty().is_empty());
+    
+    // Removals merge nodes, which are reported as emptied.
+    for i in 0..30 {
+        assert!(tree.remove(&Object::new((i * 7 % 16) as f32 + 0.25, (i / 3) as f32 + 1.5, (i % 5) as f32 * 3.0)).is_some());
+    }
+    assert!(tree.update(&[3.5, 0.5, 0.5], Object::new(12.0, 12.0, 12.0)).is_ok());
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    tree.retain(|o| o.x < 8.0);
+    while !tree.rebuild_incremental(2).unwrap() {}
+    tree.rebase([1.0, 0.0, 0.0]);
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    tree.clear();
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert!(replica.values().all(|items| items.is_empty()));
+}
+
+#[test]
+fn octree_view() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    for i in 0..30 {
+        assert!(tree.insert(Object::new((i * 7 % 10) as f32 + 0.5, (i / 3) as f32 + 0.25, (i % 4) as f32 + 0.5)).is_ok());
+    }
+    
+    let query = Volume::new([2.0, 2.0, 0.0], [6.0, 8.0, 3.0]);
+    // Results outlive the view they came from.
+    let found = {
+        let view = tree.view();
+        view.get_in_volume(&query)
+    };
+    assert_eq!(found, tree.get_in_volume(&query));
+    
+    let view = tree.view();
+    let copy = view;
+    assert_eq!((view.len(), view.is_empty()), (30, false));
+    assert!(view.bounds().min == vol.min && view.bounds().max == vol.max);
+    assert_eq!(view.iter().count(), 30);
+    assert_eq!(view.query(&query).count(), found.len());
+    assert_eq!(copy.get_in_radius([5.0, 5.0, 1.0], 3.0), tree.get_in_radius([5.0, 5.0, 1.0], 3.0));
+    assert_eq!(copy.nearest(&[3.3, 3.3, 1.2]), tree.nearest(&[3.3, 3.3, 1.2]));
+    assert_eq!(copy.nearest_n(&[3.3, 3.3, 1.2], 4), tree.nearest_n(&[3.3, 3.3, 1.2], 4));
+    let ray = Ray::new([0.0, 5.0, 1.0], [1.0, 0.1, 0.0]);
+    assert_eq!(view.raycast(&ray).len(), tree.raycast(&ray).len());
+}
+
+#[test]
+fn octree_len() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    
+    for i in 0..40 {
+        let t = i as f32 / 40.0;
+        assert!(tree.insert(Object::new(t, 1.0 - t, 1.0 - t)).is_ok());
+    }
+    assert_eq!(tree.len(), 40);
+    
+    for i in 0..10 {
+        let t = i as f32 / 40.0;
+        assert!(tree.remove(&Object::new(t, 1.0 - t, 1.0 - t)).is_some());
+    }
+    assert!(tree.update(&[0.5, 0.5, 0.5], Object::new(0.05, 0.05, 0.05)).is_ok());
+    tree.retain(|item| item.x < 0.75);
+    assert_eq!(tree.len(), tree.iter().count());
+    assert_eq!(tree.len(), 20);
+}
+
+#[test]
+fn octree_clear_retain() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, x, x)).is_ok());
+    }
+    
+    tree.retain(|item| item.x < 0.5);
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.occupancy(), 0b0000_0001);
+    assert!(tree.iter().all(|item| item.x < 0.5));
+    
+    tree.retain(|item| item.x < 0.2);
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree.node_count(), 1);
+    
+    tree.clear();
+    assert_eq!(tree.len(), 0);
+    assert_eq!(tree.capacity(), 1);
+    assert_eq!(tree.bounds().max(), [1.0, 1.0, 1.0]);
+    assert!(tree.insert(Object::new(0.5, 0.5, 0.5)).is_ok());
+}
+
+#[test]
+fn octree_query() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9, 0.45].iter() {
+        assert!(tree.insert(Object::new(x, x, x)).is_ok());
+    }
+    
+    let region = Volume::new([0.3, 0.3, 0.3], [0.7, 0.7, 0.7]);
+    let mut found: Vec<f32> = tree.query(&region).map(|item| item.x).collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(found, vec![0.4, 0.45, 0.6]);
+    assert_eq!(tree.query(&region).count(), tree.get_in_volume(&region).len());
+    assert_eq!(tree.query(&Volume::new([2.0, 2.0, 2.0], [3.0, 3.0, 3.0])).count(), 0);
+}
+
+#[test]
+fn octree_visit() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.2, 0.9, 0.95].iter() {
+        assert!(tree.insert(Object::new(x, x, x)).is_ok());
+    }
+    assert_eq!(tree.node_count(), 17);
+    
+    let mut visited = 0;
+    tree.visit(|_, _| { visited += 1; Visit::Continue });
+    assert_eq!(visited, 17);
+    
+    // Skip the subtrees of empty nodes below the root.
+    let mut items = 0;
+    tree.visit(|_, node_items| {
+        items += node_items.len();
+        if node_items.is_empty() { Visit::Skip } else { Visit::Continue }
+    });
+    assert_eq!(items, 4);
+    
+    let mut visited = 0;
+    tree.visit(|vol, _| {
+        visited += 1;
+        if vol.min()[0] == 0.5 { Visit::Stop } else { Visit::Continue }
+    });
+    assert_eq!(visited, 3);
+}
+
+#[test]
+fn octree_raycast() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75, 0.75)).is_ok());
+    
+    // Along the x axis through the lower octants, from the far side.
+    let hits: Vec<(f32, f32)> = tree.raycast(&Ray::new([2.0, 0.1, 0.1], [-1.0, 0.0, 0.0])).iter()
+        .map(|&(t, item)| (t, item.x))
+        .collect();
+    assert_eq!(hits, vec![(1.0, 0.5), (1.0, 0.75), (1.5, 0.25)]);
+    
+    assert!(tree.raycast(&Ray::new([2.0, 2.0, 2.0], [1.0, 0.0, 0.0])).is_empty());
+}
+
+#[test]
+fn octree_raycast_filtered() {
+    #[derive(Clone)]
+    struct Layered {
+        position: [f32; 3],
+        layers: u32
+    }
+    
+    impl Index<f32> for Layered {
+        fn octree_index(&self) -> [f32; 3] {
+            self.position
+        }
+        
+        fn octree_layers(&self) -> u32 {
+            self.layers
+        }
+    }
+    
+    let vol = Volume::new([0.0; 3], [1.0; 3]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    for (i, &position) in [[0.5, 0.5, 0.5], [0.75, 0.25, 0.25], [0.25, 0.25, 0.25], [0.25, 0.75, 0.75]].iter().enumerate() {
+        assert!(tree.insert(Layered { position: position, layers: 1 << (i % 2) }).is_ok());
+    }
+    
+    let ray = Ray::new([2.0, 0.1, 0.1], [-1.0, 0.0, 0.0]);
+    let hits = |t_max: f32, mask: u32| -> Vec<(f32, f32)> {
+        tree.raycast_filtered(&ray, t_max, mask).iter().map(|&(t, item)| (t, item.position[0])).collect()
+    };
+    assert_eq!(hits(f32::INFINITY, !0), vec![(1.0, 0.5), (1.0, 0.75), (1.5, 0.25)]);
+    // Nodes entered beyond the cutoff are skipped.
+    assert_eq!(hits(1.2, !0), vec![(1.0, 0.5), (1.0, 0.75)]);
+    assert!(hits(0.5, !0).is_empty());
+    // Only items in a layer of the mask are kept.
+    assert_eq!(hits(f32::INFINITY, 1), vec![(1.0, 0.5), (1.5, 0.25)]);
+    assert_eq!(hits(1.2, 2), vec![(1.0, 0.75)]);
+    assert_eq!(tree.view().raycast_filtered(&ray, 1.2, 2).len(), 1);
+}
+
+#[test]
+fn octree_raycast_many() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 2);
+    for i in 0..200 {
+        assert!(tree.insert(Object::new((i * 37 % 100) as f32 / 100.0, (i * 13 % 97) as f32 / 97.0,
+                                        (i % 11) as f32 / 11.0)).is_ok());
+    }
+    
+    let mut rays = Vec::new();
+    for i in 0..21 {
+        let y = i as f32 * 0.06 - 0.1;
+        rays.push(Ray::new([-1.0, y, 0.3], [1.0, 0.1, 0.05]));
+        rays.push(Ray::new([0.5, 2.0, y], [0.0, -1.0, 0.0]));
+    }
+    
+    let hits = tree.raycast_many(&rays);
+    assert_eq!(hits.len(), rays.len());
+    for (ray, hits) in rays.iter().zip(hits.iter()) {
+        assert_eq!(*hits, tree.raycast(ray));
+    }
+    assert!(hits.iter().any(|hits| hits.is_empty()));
+    assert!(hits.iter().any(|hits| !hits.is_empty()));
+    assert!(tree.raycast_many(&[]).is_empty());
+}
+
+#[test]
+fn octree_query_stats() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = Octree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.2, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, x, x)).is_ok());
+    }
+    
+    let (items, stats) = tree.get_in_volume_with_stats(&vol);
+    assert_eq!(items.len(), 3);
+    assert_eq!(stats.nodes_visited + stats.nodes_pruned, tree.node_count());
+    assert_eq!(stats.items_tested, 3);
+    
+    let (items, stats) = tree.get_in_radius_with_stats([0.9, 0.9, 0.9], 0.05);
+    assert_eq!(items, vec![&Object::new(0.9, 0.9, 0.9)]);
+    assert_eq!((stats.nodes_visited, stats.max_depth, stats.nodes_pruned), (2, 1, 7));
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Crate {
+    min: [f32; 3],
+    max: [f32; 3]
+}
+
+impl BoundedIndex<f32> for Crate {
+    fn octree_bounds(&self) -> Volume<f32> {
+        Volume::new(self.min, self.max)
+    }
+}
+
+#[test]
+fn bounded_octree_insert_query() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = BoundedOctree::with_capacity(vol, 1);
+    
+    let small = Crate { min: [0.1, 0.1, 0.1], max: [0.2, 0.2, 0.2] };
+    let large = Crate { min: [0.4, 0.4, 0.4], max: [0.6, 0.6, 0.6] };
+    let corner = Crate { min: [0.7, 0.7, 0.7], max: [0.9, 0.9, 0.9] };
+    for item in [small.clone(), large.clone(), corner.clone()].iter() {
+        assert!(tree.insert(item.clone()).is_ok());
+    }
+    assert!(tree.insert(Crate { min: [0.5, 0.5, 0.5], max: [1.5, 0.6, 0.6] }).is_err());
+    match tree.insert(Crate { min: [0.6, 0.5, 0.5], max: [0.5, 0.6, 0.6] }) {
+        Err(SpatialError::InvalidVolume) => {},
+        other => panic!("expected InvalidVolume, got {:?}", other)
+    }
+    
+    assert_eq!(tree.get_at(&[0.45, 0.55, 0.45]), vec![&large]);
+    assert_eq!(tree.get_in_volume(&Volume::new([0.55, 0.55, 0.55], [0.75, 0.75, 0.75])).len(), 2);
+    
+    assert_eq!(tree.remove(&large), Some(large));
+    assert_eq!(tree.len(), 2);
+    assert!(tree.get_at(&[0.5, 0.5, 0.5]).is_empty());
+}
+
+#[test]
+fn loose_octree_insert_update() {
+    let vol = Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut tree = LooseOctree::with_capacity(vol, 2.0, 1);
+    
+    let large = Crate { min: [0.1, 0.1, 0.1], max: [0.9, 0.9, 0.9] };
+    let small = Crate { min: [0.45, 0.45, 0.45], max: [0.55, 0.55, 0.55] };
+    assert!(tree.insert(large.clone()).is_ok());
+    assert!(tree.insert(small.clone()).is_ok());
+    assert!(tree.insert(Crate { min: [-0.6, 0.0, 0.0], max: [1.6, 0.1, 0.1] }).is_err());
+    
+    assert_eq!(tree.get_in_volume(&Volume::new([0.5, 0.5, 0.5], [0.5, 0.5, 0.5])).len(), 2);
+    
+    let moved = Crate { min: [0.8, 0.8, 0.8], max: [0.9, 0.9, 0.9] };
+    assert!(tree.update(&small, moved.clone()));
+    assert_eq!(tree.get_at(&[0.5, 0.5, 0.5]), vec![&large]);
+    assert_eq!(tree.remove(&moved), Some(moved));
+    assert_eq!(tree.len(), 1);
+}
+
+fn assert_send_sync<X: Send + Sync>() {}
+
+#[test]
+fn octree_send_sync() {
+    assert_send_sync::<Octree<f32, Object>>();
+    assert_send_sync::<Octree<f32, Object, Counter>>();
+    assert_send_sync::<BoundedOctree<f32, Crate>>();
+    assert_send_sync::<LooseOctree<f32, Crate>>();
+    assert_send_sync::<OccupancyOctree<f32>>();
+    
+    let mut tree = Octree::new(Volume::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]));
+    for i in 0..100 {
+        assert!(tree.insert(Object::new(i as f32 / 100.0, 0.5, 0.5)).is_ok());
+    }
+    let tree = Arc::new(tree);
+    
+    let threads: Vec<_> = (0..4).map(|i| {
+        let tree = tree.clone();
+        thread::spawn(move || {
+            let min = i as f32 / 4.0;
+            tree.get_in_volume(&Volume::new([min, 0.0, 0.0], [min + 0.245, 1.0, 1.0])).len()
+        })
+    }).collect();
+    let total: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+    assert_eq!(total, 100);
+}
+
+#[test]
+fn world_index_insert_query() {
+    let mut world = WorldIndex::new(100.0);
+    
+    assert!(world.insert(Object::new(10.0, 10.0, 10.0)).is_ok());
+    assert!(world.insert(Object::new(-10.0, 10.0, 10.0)).is_ok());
+    assert!(world.insert(Object::new(10.0, 10.0, 510.0)).is_ok());
+    assert_eq!(world.chunk_count(), 3);
+    assert_eq!(world.chunk_of(&[10.0, 10.0, 510.0]), Some((0, 0, 5)));
+    
+    assert_eq!(world.get_in_radius([0.0, 10.0, 10.0], 10.0).len(), 2);
+    assert_eq!(world.get_in_volume(&Volume::new([0.0, 0.0, 0.0], [20.0, 20.0, 600.0])).len(), 2);
+    
+    assert_eq!(world.remove(&Object::new(10.0, 10.0, 510.0)), Some(Object::new(10.0, 10.0, 510.0)));
+    assert_eq!(world.chunk_count(), 2);
+    assert_eq!(world.len(), 2);
+    assert!(!world.is_empty());
+    assert!(WorldIndex::<f32, Object>::new(100.0).is_empty());
+}
+
+#[test]
+fn layered_index_mask_query() {
+    let mut index = LayeredIndex::with_capacity(Volume::new([0.0, 0.0, 0.0], [100.0, 100.0, 100.0]), 2);
+    
+    for i in 0..10 {
+        assert!(index.insert(Object::new(i as f32, 0.0, 0.0), i % 2).is_ok());
+    }
+    assert_eq!(index.len(0b01), 5);
+    assert_eq!(index.len(0b11), 10);
+    
+    let vol = Volume::new([0.0, 0.0, 0.0], [4.0, 1.0, 1.0]);
+    assert_eq!(index.get_in_volume(&vol, 0b10).iter().map(|item| item.x).collect::<Vec<f32>>(), vec![1.0, 3.0]);
+    assert_eq!(index.get_in_radius([2.0, 0.0, 0.0], 1.0, 0b01).len(), 1);
+    assert_eq!(index.nearest(&[4.2, 0.0, 0.0], 0b10), Some(&Object::new(5.0, 0.0, 0.0)));
+    
+    assert_eq!(index.remove(&Object::new(5.0, 0.0, 0.0), 1), Some(Object::new(5.0, 0.0, 0.0)));
+    assert_eq!(index.len(0b10), 4);
+}
+
+#[test]
+fn rebuild_incremental() {
+    let mut tree = Octree::with_capacity(Volume::new([0.0, 0.0, 0.0], [64.0, 64.0, 64.0]), 2);
+    for i in 0..32 {
+        let p = i as f32 * 2.0 + 0.5;
+        tree.insert(Object::new(p, p, p)).unwrap();
+    }
+    for i in 0..16 {
+        let p = i as f32 * 2.0 + 0.5;
+        tree.remove(&Object::new(p, p, p));
+    }
+    let nodes = tree.node_count();
+    
+    while !tree.rebuild_incremental(1).unwrap() {
+        assert_eq!(tree.len(), 16);
+    }
+    assert_eq!(tree.len(), 16);
+    assert!(tree.node_count() < nodes);
+    
+    assert!(!tree.rebuild_incremental(1).unwrap());
+    tree.clear();
+    assert!(!tree.is_rebuilding());
+}
+
+#[test]
+fn item_order_insertion() {
+    let mut tree: Octree<f32, Object> = Octree::with_item_order(Volume::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0]), 1,
+                                                                ItemOrder::Insertion);
+    let xs = [9.0, 3.0, 14.0, 1.0, 7.0, 12.0];
+    for &x in xs.iter() {
+        tree.insert(Object::new(x, x, 16.0 - x)).unwrap();
+    }
+    let found: Vec<f32> = tree.get_in_radius([8.0, 8.0, 8.0], 100.0).iter().map(|item| item.x).collect();
+    assert_eq!(found, xs.to_vec());
+    
+    tree.retain(|item| item.x > 5.0);
+    let found: Vec<f32> = tree.get_in_volume(&Volume::new([0.0, 0.0, 0.0], [16.0, 16.0, 16.0])).iter()
+        .map(|item| item.x)
+        .collect();
+    assert_eq!(found, vec![9.0, 14.0, 7.0, 12.0]);
+}
+
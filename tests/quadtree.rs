@@ -1,8 +1,21 @@
+#![allow(clippy::redundant_field_names)]
+
 extern crate spatial;
 
-use spatial::quadtree::{Quadtree, Index, Volume};
+use std::sync::Arc;
+use std::thread;
+use std::io::Cursor;
+
+use std::collections::HashMap;
+use spatial::{Visit, ItemOrder, SpatialError};
+use spatial::snapshot::Snapshot;
+use spatial::paging::PageStats;
+#[cfg(feature = "lz4")]
+use spatial::paging::Compression;
+use spatial::quadtree::{Quadtree, NodePath, NodeDelta, Index, NodeData, Aggregate, Observer, Weighted, Translate, ForceSource, Mass, RegionQuadtree, BoundedQuadtree, BoundedIndex,
+                        LooseQuadtree, LinearQuadtree, WorldIndex, LayeredIndex, Volume, Ray, PagedBuilder, PagedQuadtree};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 struct Object {
     x: f32,
     y: f32
@@ -23,16 +36,50 @@ impl Index<f32> for Object {
     }
 }
 
+impl Weighted<f32> for Object {
+    fn weight(&self) -> f32 {
+        self.x
+    }
+}
+
+impl Snapshot for Object {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        [self.x, self.y].write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<Object, SpatialError> {
+        <[f32; 2]>::read_snapshot(input).map(|p| Object::new(p[0], p[1]))
+    }
+}
+
+/// Returns the force of inverse square gravity on `point` by a body of
+/// `mass` at `position`.
+fn gravity(point: [f32; 2], mass: f32, position: [f32; 2]) -> [f32; 2] {
+    let mut force = [0.0; 2];
+    let distance2: f32 = (0..2).map(|axis| (position[axis] - point[axis]).powi(2)).sum();
+    for axis in 0..2 {
+        force[axis] = mass * (position[axis] - point[axis]) / (distance2 * distance2.sqrt());
+    }
+    force
+}
+
+impl Translate<f32> for Object {
+    fn translate(&mut self, offset: [f32; 2]) {
+        self.x += offset[0];
+        self.y += offset[1];
+    }
+}
+
 
 #[test]
 fn quadtree_insert_query() {
     let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
     let mut tree = Quadtree::new(vol);
     
-    assert_eq!(tree.insert(Object::new(0.25, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.25)), true);
-    assert_eq!(tree.insert(Object::new(0.25, 0.75)), true);
-    assert_eq!(tree.insert(Object::new(0.75, 0.75)), true);
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
     
     assert_eq!(tree.get_in_volume(&Volume::new([0.0, 0.0], [0.5, 0.5])).len(), 1);
     assert_eq!(tree.get_in_volume(&Volume::new([0.5, 0.0], [1.0, 0.5])).len(), 1);
@@ -45,3 +92,1723 @@ fn quadtree_insert_query() {
     
     assert_eq!(tree.len(), 4);
 }
+
+#[test]
+fn quadtree_radius_boundary() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::new(vol);
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+
+    // Items at exactly the radius are included, and so are neighbors.
+    assert_eq!(tree.get_in_radius([0.25, 0.25], 0.5).len(), 3);
+    assert!(tree.neighbor_lists(0.5).iter().all(|list| list.len() == 2));
+}
+
+#[test]
+fn quadtree_insert_errors() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+
+    match tree.insert(Object::new(2.0, 0.0)) {
+        Err(SpatialError::OutOfBounds) => {},
+        other => panic!("expected OutOfBounds, got {:?}", other)
+    }
+
+    // Every item at the same position needs a node of its own, one
+    // level deeper than the last, until the depth limit is reached.
+    for _ in 0..65 {
+        assert!(tree.insert(Object::new(0.0, 0.0)).is_ok());
+    }
+    match tree.insert(Object::new(0.0, 0.0)) {
+        Err(SpatialError::DepthLimit) => {},
+        other => panic!("expected DepthLimit, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 65);
+}
+
+#[test]
+fn quadtree_introspection() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let tree: Quadtree<f32, Object> = Quadtree::with_capacity(vol, 4);
+
+    assert_eq!(tree.bounds().min(), [0.0, 0.0]);
+    assert_eq!(tree.bounds().max(), [1.0, 1.0]);
+    assert_eq!(tree.capacity(), 4);
+    assert_eq!(tree.depth(), 0);
+    assert_eq!(tree.node_count(), 1);
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn quadtree_pathfind() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    // Both items end up blocking the lower-left quadrant.
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(1.5, 0.5)).is_ok());
+    
+    let corridor = tree.pathfind([3.0, 1.0], [1.0, 3.0]).unwrap();
+    let paths: Vec<Vec<usize>> = corridor.into_iter().map(|leaf| leaf.path).collect();
+    assert_eq!(paths, vec![vec![1], vec![3], vec![2]]);
+    
+    assert_eq!(tree.pathfind([3.0, 1.0], [3.5, 1.5]).unwrap().len(), 1);
+    assert!(tree.pathfind([3.0, 1.0], [1.0, 1.0]).is_none());
+    assert!(tree.pathfind([3.0, 1.0], [5.0, 5.0]).is_none());
+}
+
+#[test]
+fn quadtree_navmesh() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(1.5, 0.5)).is_ok());
+    
+    // The two free quadrants on the right merge into one rectangle.
+    let mesh = tree.navmesh();
+    assert_eq!(mesh.polygons.len(), 2);
+    assert_eq!(mesh.polygons[0].min(), [2.0, 0.0]);
+    assert_eq!(mesh.polygons[0].max(), [4.0, 4.0]);
+    
+    assert_eq!(mesh.portals.len(), 1);
+    assert_eq!((mesh.portals[0].from, mesh.portals[0].to), (0, 1));
+    assert_eq!(mesh.portals[0].left, [2.0, 2.0]);
+    assert_eq!(mesh.portals[0].right, [2.0, 4.0]);
+}
+
+#[test]
+fn quadtree_flow_field() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(1.5, 0.5)).is_ok());
+    
+    let field = tree.flow_field([1.0, 3.0]);
+    assert_eq!(field.cells.len(), 3);
+    
+    // Lower-right flows up, upper-right flows left.
+    assert_eq!(field.direction(&[3.0, 1.0]), Some([0.0, 1.0]));
+    assert_eq!(field.direction(&[3.0, 3.0]), Some([-1.0, 0.0]));
+    assert_eq!(field.direction(&[1.0, 3.0]), Some([0.0, 0.0]));
+    assert_eq!(field.direction(&[1.0, 1.0]), None);
+}
+
+#[test]
+fn quadtree_neighbor_lists() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.2, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9)).is_ok());
+    assert!(tree.insert(Object::new(0.85, 0.9)).is_ok());
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    
+    let lists = tree.neighbor_lists(0.15);
+    assert_eq!(lists.len(), 5);
+    assert_eq!(lists.iter().filter(|list| list.is_empty()).count(), 1);
+    assert_eq!(lists.iter().map(|list| list.len()).sum::<usize>(), 4);
+    
+    // Every listed neighbor lists the item back.
+    for (i, list) in lists.iter().enumerate() {
+        for &j in list.iter() {
+            assert!(lists[j as usize].contains(&(i as u32)));
+        }
+    }
+}
+
+#[test]
+fn quadtree_for_each_force_source() {
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let mut tree: Quadtree<f32, Object, Mass<f32>> = Quadtree::with_node_data(vol, 4);
+    let mut sources = 0;
+    tree.for_each_force_source([0.35, 0.65], 0.5, |_| sources += 1);
+    assert_eq!(sources, 0);
+    for i in 0..400 {
+        assert!(tree.insert(Object::new((i % 20) as f32 * 0.4 + 0.1, (i / 20) as f32 * 0.4 + 0.1)).is_ok());
+    }
+    
+    let point = [0.35, 0.65];
+    let forces = |tree: &Quadtree<f32, Object, Mass<f32>>, theta: f32| {
+        let (mut force, mut sources) = ([0.0; 2], 0);
+        tree.for_each_force_source(point, theta, |source| {
+            let pull = match source {
+                ForceSource::Node(mass, centroid) => gravity(point, mass, centroid),
+                ForceSource::Item(item) => gravity(point, item.weight(), item.quadtree_index())
+            };
+            for axis in 0..2 {
+                force[axis] += pull[axis];
+            }
+            sources += 1;
+        });
+        (force, sources)
+    };
+    let error = |tree: &Quadtree<f32, Object, Mass<f32>>, force: [f32; 2]| {
+        let mut exact = [0.0; 2];
+        for item in tree.get_in_volume(&vol).into_iter() {
+            let pull = gravity(point, item.weight(), item.quadtree_index());
+            for axis in 0..2 {
+                exact[axis] += pull[axis];
+            }
+        }
+        let difference: f32 = (0..2).map(|axis| (force[axis] - exact[axis]).powi(2)).sum();
+        let magnitude: f32 = exact.iter().map(|x| x * x).sum();
+        (difference / magnitude).sqrt()
+    };
+    // Without approximation every item acts on its own.
+    let (force, sources) = forces(&tree, 0.0);
+    assert_eq!(sources, 400);
+    assert!(error(&tree, force) < 1e-4);
+    let (force, sources) = forces(&tree, 0.5);
+    assert!(sources < 400 / 4);
+    assert!(error(&tree, force) < 0.02);
+}
+
+/// Node data counting the items in the subtree of its node and summing
+/// up their `x` in quarters.
+#[derive(Default)]
+struct Sum {
+    own: (usize, i64),
+    below: (usize, i64)
+}
+
+impl NodeData<f32, Object> for Sum {
+    fn on_insert(&mut self, item: &Object) {
+        self.own = Sum::combine(self.own, Sum::of_item(item));
+    }
+
+    fn on_remove(&mut self, item: &Object) {
+        self.own = (self.own.0 - 1, self.own.1 - Sum::of_item(item).1);
+    }
+
+    fn on_children_change(&mut self, children: [&Sum; 4]) {
+        self.below = children.iter().fold(Sum::empty(), |sum, child| Sum::combine(sum, child.value()));
+    }
+
+    fn on_merge(&mut self) {
+        self.own = Sum::combine(self.own, self.below);
+        self.below = Sum::empty();
+    }
+}
+
+impl Aggregate<f32, Object> for Sum {
+    type Value = (usize, i64);
+
+    fn empty() -> (usize, i64) {
+        (0, 0)
+    }
+
+    fn of_item(item: &Object) -> (usize, i64) {
+        (1, (item.x * 4.0) as i64)
+    }
+
+    fn combine(a: (usize, i64), b: (usize, i64)) -> (usize, i64) {
+        (a.0 + b.0, a.1 + b.1)
+    }
+
+    fn value(&self) -> (usize, i64) {
+        Sum::combine(self.own, self.below)
+    }
+}
+
+#[test]
+fn quadtree_aggregate_in_volume() {
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let mut tree: Quadtree<f32, Object, Sum> = Quadtree::with_node_data(vol, 2);
+    assert_eq!((tree.aggregate_in_volume(&vol), tree.count_in_volume(&vol)), ((0, 0), 0));
+    for i in 0..200 {
+        assert!(tree.insert(Object::new((i % 16) as f32 / 2.0, (i / 16) as f32 / 2.0)).is_ok());
+    }
+    // Queries along the boundaries of nodes, and outside of the tree.
+    let check = |tree: &Quadtree<f32, Object, Sum>| for query in [vol, Volume::new([1.0, 1.0], [5.0, 3.5]), Volume::new([2.0, 0.0], [2.0, 8.0]), Volume::new([9.0, 9.0], [10.0, 10.0])].iter() {
+        let found = tree.get_in_volume(query);
+        assert_eq!(tree.aggregate_in_volume(query), found.iter().fold(Sum::empty(), |sum, item| Sum::combine(sum, Sum::of_item(*item))));
+        assert_eq!(tree.count_in_volume(query), found.len());
+    };
+    check(&tree);
+    assert_eq!(tree.aggregate_in_volume(&vol).0, 200);
+    
+    // The sums follow removals, moves and merges.
+    for i in (0..200).filter(|i| i % 3 == 0) {
+        assert!(tree.remove(&Object::new((i % 16) as f32 / 2.0, (i / 16) as f32 / 2.0)).is_some());
+    }
+    check(&tree);
+    assert!(tree.update(&[0.5, 0.0], Object::new(7.5, 7.5)).is_ok());
+    check(&tree);
+    tree.retain(|item| item.x < 2.0);
+    check(&tree);
+    assert_eq!(tree.aggregate_in_volume(&vol).0, tree.len());
+}
+
+#[test]
+fn quadtree_volume_intersect_ray() {
+    let vol = Volume::new([1.0, 1.0], [2.0, 2.0]);
+    
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 1.5], [1.0, 0.0])), Some((1.0, 2.0)));
+    assert_eq!(vol.intersect_ray(&Ray::new([3.0, 3.0], [-1.0, -1.0])), Some((1.0, 2.0)));
+    assert_eq!(vol.intersect_ray(&Ray::new([1.5, 1.5], [0.0, 2.0])), Some((0.0, 0.25)));
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 0.0], [-1.0, 0.0])), None);
+    assert_eq!(vol.intersect_ray(&Ray::new([0.0, 0.0], [1.0, 3.0])), None);
+    assert_eq!(Ray::new([0.0, 1.0], [2.0, 0.0]).at(0.5), [1.0, 1.0]);
+}
+
+/// Returns the positions of `items`, sorted.
+fn sorted_positions<'a, It: IntoIterator<Item=&'a Object>>(items: It) -> Vec<(f32, f32)> {
+    let mut positions: Vec<(f32, f32)> = items.into_iter().map(|o| (o.x, o.y)).collect();
+    positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    positions
+}
+
+#[test]
+fn quadtree_paged() {
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let mut tree = Quadtree::with_capacity(vol, 4);
+    let mut builder = PagedBuilder::new(vol, 4, 3, 100);
+    for i in 0..300 {
+        let o = Object::new((i * 7 % 80) as f32 / 10.0, (i * 13 % 80) as f32 / 10.0);
+        assert!(tree.insert(o.clone()).is_ok());
+        assert!(builder.insert(o).is_ok());
+    }
+    let mut bytes = Vec::new();
+    assert!(builder.finish(&mut bytes).is_ok());
+    
+    let mut paged: PagedQuadtree<f32, Object, _> = PagedQuadtree::open(Cursor::new(&bytes[..])).unwrap();
+    assert_eq!((paged.len(), paged.page_count()), (300, 64));
+    for query in [vol, Volume::new([1.0, 2.0], [3.0, 3.5]), Volume::new([4.0, 0.0], [4.0, 8.0])].iter() {
+        assert_eq!(sorted_positions(paged.get_in_volume(query).unwrap().iter()),
+                   sorted_positions(tree.get_in_volume(query)));
+    }
+    assert_eq!(sorted_positions(paged.get_in_radius([2.0, 6.0], 1.5).unwrap().iter()),
+               sorted_positions(tree.get_in_radius([2.0, 6.0], 1.5)));
+    assert!(paged.resident_pages() > 1);
+    paged.unload_all();
+    assert_eq!((paged.resident_pages(), paged.resident_bytes()), (0, 0));
+    
+    // Pages of the same size, of which the two used last fit the
+    // budget.
+    let mut builder = PagedBuilder::new(vol, 4, 2, 1 << 20);
+    for i in 0..256 {
+        assert!(builder.insert(Object::new((i % 16) as f32 / 2.0 + 0.25, (i / 16) as f32 / 2.0 + 0.25)).is_ok());
+    }
+    let mut bytes = Vec::new();
+    assert!(builder.finish(&mut bytes).is_ok());
+    let mut paged: PagedQuadtree<f32, Object, _> = PagedQuadtree::open(Cursor::new(&bytes[..])).unwrap();
+    let cell = |page: usize| {
+        let (x, y) = ((page % 4) as f32 * 2.0, (page / 4) as f32 * 2.0);
+        Volume::new([x + 0.5, y + 0.5], [x + 1.5, y + 1.5])
+    };
+    assert_eq!(paged.get_in_volume(&cell(0)).unwrap().len(), 4);
+    let size = paged.resident_bytes();
+    paged.set_budget(2 * size);
+    for &page in [1, 0, 2, 0, 3].iter() {
+        assert_eq!(paged.get_in_volume(&cell(page)).unwrap().len(), 4);
+    }
+    assert!(paged.is_resident(0) && paged.is_resident(3));
+    assert_eq!((paged.resident_pages(), paged.resident_bytes()), (2, 2 * size));
+    assert_eq!(paged.stats(), PageStats { hits: 2, misses: 4, evictions: 2 });
+    paged.reset_stats();
+    assert_eq!(paged.stats(), PageStats::default());
+    
+    // The pages can't hold more items than fit a `usize` together.
+    let mut end = [0; 8];
+    end.copy_from_slice(&bytes[bytes.len() - 8..]);
+    let table = u64::from_le_bytes(end) as usize;
+    let mut crowded = bytes.clone();
+    crowded[table + 32..table + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+    crowded[table + 65..table + 73].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(PagedQuadtree::<f32, Object, _>::open(Cursor::new(&crowded[..])),
+                     Err(SpatialError::Serialization(_))));
+    
+    // A tree without items, and one in a single page.
+    let mut bytes = Vec::new();
+    assert!(PagedBuilder::<f32, Object>::new(vol, 4, 0, 100).finish(&mut bytes).is_ok());
+    let mut empty: PagedQuadtree<f32, Object, _> = PagedQuadtree::open(Cursor::new(&bytes[..])).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.page_count(), 1);
+    assert!(empty.get_in_volume(&vol).unwrap().is_empty());
+    
+    assert!(matches!(PagedQuadtree::<f32, Object, _>::open(Cursor::new(&[0u8; 40][..])),
+                     Err(SpatialError::Serialization(_))));
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn quadtree_paged_lz4() {
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let build = |compression: Compression| {
+        let mut builder = PagedBuilder::new(vol, 4, 2, 1 << 20);
+        builder.set_compression(compression);
+        for i in 0..256 {
+            assert!(builder.insert(Object::new((i % 16) as f32 / 2.0 + 0.25, (i / 16) as f32 / 2.0 + 0.25)).is_ok());
+        }
+        let mut bytes = Vec::new();
+        assert!(builder.finish(&mut bytes).is_ok());
+        bytes
+    };
+    let (plain, compressed) = (build(Compression::None), build(Compression::Lz4));
+    assert!(compressed.len() < plain.len());
+    
+    // Compressed pages take less space, and as much once loaded.
+    let query = Volume::new([2.5, 2.5], [3.5, 3.5]);
+    let mut paged: PagedQuadtree<f32, Object, _> = PagedQuadtree::open(Cursor::new(&plain[..])).unwrap();
+    assert_eq!(paged.get_in_volume(&query).unwrap().len(), 4);
+    let size = paged.resident_bytes();
+    let mut paged: PagedQuadtree<f32, Object, _> = PagedQuadtree::open(Cursor::new(&compressed[..])).unwrap();
+    assert_eq!(paged.get_in_volume(&query).unwrap().len(), 4);
+    assert_eq!((paged.resident_pages(), paged.resident_bytes()), (1, size));
+    assert_eq!(paged.get_in_volume(&vol).unwrap().len(), 256);
+}
+
+#[test]
+fn quadtree_to_gpu_buffers() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    
+    let buffers = tree.to_gpu_buffers();
+    assert_eq!(buffers.nodes.len(), 5);
+    assert_eq!(buffers.nodes[0].first_child, 1);
+    assert_eq!(buffers.nodes[4].min, [0.5, 0.5]);
+    assert_eq!(buffers.nodes[4].first_item, 1);
+    assert_eq!(buffers.items[1].position, [0.75, 0.75]);
+    assert_eq!(buffers.node_bytes().len(), 5 * 32);
+    assert_eq!(buffers.item_bytes().len(), 2 * 8);
+}
+
+#[test]
+fn quadtree_k_nearest_in_volume() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.6, 0.6)).is_ok());
+    assert!(tree.insert(Object::new(0.8, 0.8)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9)).is_ok());
+    
+    let region = Volume::new([0.5, 0.5], [1.0, 1.0]);
+    let found = tree.k_nearest_in_volume(&region, &[0.0, 0.0], 2);
+    assert_eq!(found.len(), 2);
+    assert_eq!((found[0].x, found[1].x), (0.6, 0.8));
+    assert_eq!(tree.k_nearest_in_volume(&region, &[0.0, 0.0], 0).len(), 0);
+}
+
+#[test]
+fn quadtree_nearest_excluding() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &(x, y) in [(0.1, 0.1), (0.2, 0.2), (0.2, 0.2), (0.9, 0.9)].iter() {
+        assert!(tree.insert(Object::new(x, y)).is_ok());
+    }
+    
+    let first = tree.nearest(&[0.1, 0.1]).unwrap();
+    assert_eq!(tree.nearest_other(first).unwrap().x, 0.2);
+    // A duplicate is found rather than the item itself.
+    let duplicate = tree.nearest(&[0.2, 0.2]).unwrap();
+    let other = tree.nearest_other(duplicate).unwrap();
+    assert_eq!(other, duplicate);
+    assert!(!std::ptr::eq(other, duplicate));
+    
+    assert_eq!(tree.nearest_excluding(&[0.1, 0.1], |item| item.x < 0.5).unwrap().x, 0.9);
+    assert!(tree.nearest_excluding(&[0.1, 0.1], |_| true).is_none());
+}
+
+#[derive(Default)]
+struct Counter {
+    inserted: usize,
+    subdivided: bool
+}
+
+impl NodeData<f32, Object> for Counter {
+    fn on_insert(&mut self, _: &Object) {
+        self.inserted += 1;
+    }
+
+    fn on_subdivide(&mut self, _: &Volume<f32>) {
+        self.subdivided = true;
+    }
+}
+
+#[test]
+fn quadtree_node_data() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree: Quadtree<f32, Object, Counter> = Quadtree::with_node_data(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert_eq!((tree.data().inserted, tree.data().subdivided), (1, false));
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    assert_eq!((tree.data().inserted, tree.data().subdivided), (1, true));
+    
+    tree.data_mut().inserted = 0;
+    assert_eq!(tree.data().inserted, 0);
+}
+
+#[derive(Default)]
+struct Recorder {
+    inserted: Vec<Vec<usize>>,
+    subdivided: Vec<Vec<usize>>
+}
+
+impl Observer<f32, Object> for Recorder {
+    fn on_insert(&mut self, path: &[usize], _: &Object) {
+        self.inserted.push(path.to_vec());
+    }
+
+    fn on_subdivide(&mut self, path: &[usize], _: &Volume<f32>) {
+        self.subdivided.push(path.to_vec());
+    }
+}
+
+#[test]
+fn quadtree_insert_observed() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    let mut recorder = Recorder::default();
+    
+    assert!(tree.insert_observed(Object::new(0.25, 0.25), &mut recorder).is_ok());
+    assert!(tree.insert_observed(Object::new(0.75, 0.75), &mut recorder).is_ok());
+    assert!(tree.insert_observed(Object::new(0.9, 0.9), &mut recorder).is_ok());
+    assert_eq!(recorder.inserted, vec![vec![], vec![3], vec![3, 3]]);
+    assert_eq!(recorder.subdivided, vec![vec![], vec![3]]);
+}
+
+#[test]
+fn quadtree_remove() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9)).is_ok());
+    assert_eq!(tree.depth(), 2);
+    
+    assert_eq!(tree.remove(&Object::new(0.5, 0.5)), None);
+    assert_eq!(tree.remove(&Object::new(0.9, 0.9)), Some(Object::new(0.9, 0.9)));
+    assert_eq!(tree.depth(), 1);
+    assert_eq!(tree.remove_at(&[0.25, 0.25]), Some(Object::new(0.25, 0.25)));
+    assert_eq!(tree.node_count(), 1);
+    assert_eq!(tree.len(), 1);
+}
+
+#[test]
+fn quadtree_nearest() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    assert!(tree.nearest(&[0.5, 0.5]).is_none());
+    
+    assert!(tree.insert(Object::new(0.1, 0.1)).is_ok());
+    assert!(tree.insert(Object::new(0.6, 0.6)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9)).is_ok());
+    
+    assert_eq!(tree.nearest(&[0.0, 0.0]).unwrap().x, 0.1);
+    assert_eq!(tree.nearest(&[0.7, 0.7]).unwrap().x, 0.6);
+    assert_eq!(tree.nearest(&[5.0, 5.0]).unwrap().x, 0.9);
+}
+
+#[test]
+fn quadtree_child_occupancy() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25)).is_ok());
+    assert_eq!(tree.occupancy(), 0b1010);
+    
+    assert!(tree.remove_at(&[0.75, 0.25]).is_some());
+    assert_eq!(tree.occupancy(), 0b1000);
+    assert_eq!(tree.get_in_volume(&vol).len(), 2);
+}
+
+#[test]
+fn quadtree_nearest_n() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.9, 0.1, 0.5, 0.3, 0.7].iter() {
+        assert!(tree.insert(Object::new(x, x)).is_ok());
+    }
+    
+    let found: Vec<f32> = tree.nearest_n(&[1.0, 1.0], 3).iter().map(|item| item.x).collect();
+    assert_eq!(found, vec![0.9, 0.7, 0.5]);
+    assert_eq!(tree.nearest_n(&[0.0, 0.0], 10).len(), 5);
+}
+
+#[test]
+fn quadtree_iter() {
+    #[derive(Clone)]
+    struct Tagged {
+        x: f32,
+        y: f32,
+        tag: usize
+    }
+    
+    impl Index<f32> for Tagged {
+        fn quadtree_index(&self) -> [f32; 2] {
+            [self.x, self.y]
+        }
+    }
+    
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.5, 0.9, 0.1, 0.7].iter() {
+        assert!(tree.insert(Tagged { x: x, y: x, tag: 0 }).is_ok());
+    }
+    
+    let xs: Vec<f32> = tree.iter().map(|item| item.x).collect();
+    assert_eq!(xs, vec![0.5, 0.1, 0.9, 0.7]);
+    
+    let mut tag = 0;
+    for item in &mut tree {
+        tag += 1;
+        item.tag = tag;
+    }
+    let mut tags: Vec<usize> = tree.get_in_volume(&Volume::new([0.6, 0.6], [1.0, 1.0])).iter().map(|item| item.tag).collect();
+    tags.sort();
+    assert_eq!(tags, vec![3, 4]);
+    
+    assert_eq!((&tree).into_iter().map(|item| item.tag).collect::<Vec<usize>>(), vec![1, 2, 3, 4]);
+    assert_eq!(tree.into_iter().count(), 4);
+}
+
+#[test]
+fn quadtree_k_nearest_batch() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    
+    for i in 0..16 {
+        let (x, y) = ((i % 4) as f32 * 0.25, (i / 4) as f32 * 0.25);
+        assert!(tree.insert(Object::new(x + 0.1, y + 0.05)).is_ok());
+    }
+    
+    let queries = [[0.9, 0.1], [0.0, 0.0], [0.5, 0.55], [0.12, 0.9], [2.0, 2.0]];
+    let batch = tree.k_nearest_batch(&queries, 2);
+    for (query, found) in queries.iter().zip(batch.iter()) {
+        let expected = tree.nearest_n(query, 2);
+        assert_eq!(found.iter().map(|item| (item.x, item.y)).collect::<Vec<_>>(),
+                   expected.iter().map(|item| (item.x, item.y)).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn quadtree_get_in_volume_mut() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, 0.5)).is_ok());
+    }
+    
+    assert_eq!(tree.get_in_volume_mut(&Volume::new([0.0, 0.0], [0.5, 1.0])).len(), 2);
+    let mut found: Vec<f32> = tree.get_in_radius_mut([0.9, 0.5], 0.35)
+        .into_iter().map(|item| item.x).collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(found, vec![0.6, 0.9]);
+}
+
+#[test]
+fn quadtree_update() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    assert!(tree.insert(Object::new(0.9, 0.9)).is_ok());
+    
+    assert!(tree.update(&[0.9, 0.9], Object::new(0.1, 0.9)).is_ok());
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.depth(), 1);
+    assert_eq!(tree.get_in_volume(&Volume::new([0.0, 0.5], [0.5, 1.0])).len(), 1);
+    match tree.update(&[0.9, 0.9], Object::new(0.1, 0.9)) {
+        Err(SpatialError::NotFound) => {},
+        other => panic!("expected NotFound, got {:?}", other)
+    }
+    match tree.update(&[0.1, 0.9], Object::new(1.5, 0.9)) {
+        Err(SpatialError::OutOfBounds) => {},
+        other => panic!("expected OutOfBounds, got {:?}", other)
+    }
+    assert_eq!(tree.get_in_volume(&Volume::new([0.0, 0.5], [0.5, 1.0])), vec![&Object::new(0.1, 0.9)]);
+}
+
+#[test]
+fn quadtree_update_depth_limit() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    // One item at the origin in every node down to the depth limit.
+    for _ in 0..65 {
+        assert!(tree.insert(Object::new(0.0, 0.0)).is_ok());
+    }
+    assert!(tree.insert(Object::new(0.4, 0.4)).is_ok());
+    
+    // Moving the item to the origin needs a node below the limit, so it
+    // stays where it was.
+    match tree.update(&[0.4, 0.4], Object::new(0.0, 0.0)) {
+        Err(SpatialError::DepthLimit) => {},
+        other => panic!("expected DepthLimit, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 66);
+    assert_eq!(tree.get_in_volume(&Volume::new([0.25, 0.25], [0.5, 0.5])), vec![&Object::new(0.4, 0.4)]);
+}
+
+#[test]
+fn quadtree_rebase() {
+    let vol = Volume::new([1000.0, 1000.0], [1004.0, 1004.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &(x, y) in [(1000.5, 1000.5), (1003.5, 1000.5), (1003.5, 1003.5)].iter() {
+        assert!(tree.insert(Object::new(x, y)).is_ok());
+    }
+    let nodes = tree.node_count();
+    
+    tree.rebase([1002.0, 1002.0]);
+    assert_eq!(tree.bounds().min(), [-2.0, -2.0]);
+    assert_eq!(tree.bounds().max(), [2.0, 2.0]);
+    assert_eq!(tree.node_count(), nodes);
+    assert_eq!(tree.get_in_volume(&Volume::new([1.0, -2.0], [2.0, -1.0])), vec![&Object::new(1.5, -1.5)]);
+    assert_eq!(tree.nearest(&[2.0, 2.0]), Some(&Object::new(1.5, 1.5)));
+    assert!(tree.insert(Object::new(-1.5, 1.5)).is_ok());
+    assert_eq!(tree.len(), 4);
+}
+
+#[test]
+fn quadtree_transformed_view() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(1.0, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(3.0, 3.0)).is_ok());
+    
+    // Rotated by a quarter turn and moved to (10, 10).
+    let m = [[0.0, -1.0, 10.0], [1.0, 0.0, 10.0], [0.0, 0.0, 1.0]];
+    let view = tree.transformed(&m).unwrap();
+    assert_eq!(view.to_world(&[1.0, 0.5]), [9.5, 11.0]);
+    assert_eq!(view.to_local(&[9.5, 11.0]), [1.0, 0.5]);
+    assert_eq!(view.get_in_volume(&Volume::new([9.0, 10.5], [10.0, 11.5])), vec![&Object::new(1.0, 0.5)]);
+    assert!(view.get_in_volume(&Volume::new([10.5, 10.5], [11.5, 11.5])).is_empty());
+    assert_eq!(view.get_in_radius([7.0, 13.0], 0.5), vec![&Object::new(3.0, 3.0)]);
+    
+    assert!(tree.transformed(&[[1.0, 2.0, 0.0], [2.0, 4.0, 0.0], [0.0, 0.0, 1.0]]).is_none());
+}
+
+#[test]
+fn quadtree_overlaps_with() {
+    let mut a = Quadtree::with_capacity(Volume::new([0.0, 0.0], [4.0, 4.0]), 1);
+    let mut b = Quadtree::with_capacity(Volume::new([2.0, 2.0], [6.0, 6.0]), 2);
+    
+    for &(x, y) in [(0.5, 0.5), (2.5, 2.5), (3.5, 3.5), (3.9, 2.1)].iter() {
+        assert!(a.insert(Object::new(x, y)).is_ok());
+    }
+    for &(x, y) in [(2.5, 2.6), (3.5, 3.5), (4.0, 2.0), (5.5, 5.5)].iter() {
+        assert!(b.insert(Object::new(x, y)).is_ok());
+    }
+    
+    let mut pairs: Vec<(f32, f32)> = a.overlaps_with(&b, 0.2).into_iter().map(|(p, q)| (p.x, q.x)).collect();
+    pairs.sort_by(|p, q| p.partial_cmp(q).unwrap());
+    assert_eq!(pairs, vec![(2.5, 2.5), (3.5, 3.5), (3.9, 4.0)]);
+    assert_eq!(a.overlaps_with(&b, 0.0).len(), 1);
+}
+
+#[test]
+fn quadtree_weighted_centroid() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.75)).is_ok());
+    
+    assert_eq!(tree.weighted_centroid(&vol), Some(([0.625, 0.625], 1.0)));
+    assert_eq!(tree.weighted_centroid(&Volume::new([0.4, 0.0], [0.6, 1.0])), None);
+}
+
+#[test]
+fn quadtree_weighted_centroid_precision() {
+    let vol = Volume::new([0.0, 0.0], [2000000.0, 1.0]);
+    let mut tree = Quadtree::new(vol);
+    
+    // Summed up in f32, the large coordinates would lose the fraction.
+    for i in 0..1000 {
+        assert!(tree.insert(Object::new(1000000.0 + (i % 2) as f32, i as f32 / 1000.0)).is_ok());
+    }
+    
+    let (centroid, _) = tree.weighted_centroid(&vol).unwrap();
+    assert_eq!(centroid[0], 1000000.5);
+}
+
+#[test]
+fn quadtree_histogram_along_axis() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    let mut points = Vec::new();
+    for i in 0..40 {
+        for j in 0..40 {
+            points.push([(i as f32 + 0.5) / 40.0, (j as f32 * 7.0 % 40.0 + 0.25) / 40.0]);
+        }
+    }
+    for p in points.iter() {
+        assert!(tree.insert(Object::new(p[0], p[1])).is_ok());
+    }
+    
+    assert_eq!(tree.histogram_along_axis(&vol, 0, 4), vec![400, 400, 400, 400]);
+    
+    let region = Volume::new([0.1, 0.3], [0.7, 0.9]);
+    for axis in 0..2 {
+        let mut expected = vec![0; 3];
+        for p in points.iter().filter(|p| region.contains(p)) {
+            let bin = ((p[axis] - region.min[axis]) * 3.0 / (region.max[axis] - region.min[axis])) as usize;
+            expected[bin.min(2)] += 1;
+        }
+        assert_eq!(tree.histogram_along_axis(&region, axis, 3), expected);
+    }
+    assert_eq!(tree.histogram_along_axis(&Volume::new([2.0, 2.0], [3.0, 3.0]), 1, 2), vec![0, 0]);
+    
+    // The counts of whole subtrees follow removals and moves.
+    for p in points.iter().filter(|p| p[0] < 0.25 && p[1] < 0.5) {
+        assert!(tree.remove(&Object::new(p[0], p[1])).is_some());
+    }
+    assert!(tree.update(&[0.9875, 0.00625], Object::new(0.0125, 0.00625)).is_ok());
+    assert_eq!(tree.histogram_along_axis(&vol, 0, 4), vec![201, 400, 400, 399]);
+}
+
+#[test]
+fn quadtree_rasterize_counts() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    let mut points = Vec::new();
+    for i in 0..30 {
+        for j in 0..30 {
+            points.push([(i as f32 * 11.0 % 30.0 + 0.5) / 30.0, (j as f32 + 0.25) / 30.0]);
+        }
+    }
+    for p in points.iter() {
+        assert!(tree.insert(Object::new(p[0], p[1])).is_ok());
+    }
+    
+    assert_eq!(tree.rasterize_counts(&vol, 3, 2), vec![150; 6]);
+    
+    let region = Volume::new([0.2, 0.1], [0.9, 0.6]);
+    let (nx, ny) = (7, 4);
+    let mut expected = vec![0; nx * ny];
+    for p in points.iter().filter(|p| region.contains(p)) {
+        let x = ((p[0] - 0.2) * nx as f32 / (0.9 - 0.2)) as usize;
+        let y = ((p[1] - 0.1) * ny as f32 / (0.6 - 0.1)) as usize;
+        expected[y.min(ny - 1) * nx + x.min(nx - 1)] += 1;
+    }
+    assert_eq!(tree.rasterize_counts(&region, nx, ny), expected);
+    
+    // The counts of whole subtrees follow items dropped by `retain`.
+    tree.retain(|o| o.y > 0.5 || o.x < 0.5);
+    assert_eq!(tree.rasterize_counts(&vol, 2, 2), vec![225, 0, 225, 225]);
+}
+
+#[test]
+fn quadtree_linear() {
+    let vol = Volume::new([0.0, 0.0], [100.0, 100.0]);
+    let mut objects = Vec::new();
+    for i in 0..2000 {
+        objects.push(Object::new((i * 37 % 1000) as f32 / 10.0, (i * 91 % 997) as f32 / 10.0));
+    }
+    // Items outside the volume are kept.
+    objects.push(Object::new(-5.0, 50.0));
+    let tree = LinearQuadtree::new(vol, &objects);
+    assert_eq!(tree.len(), objects.len());
+    assert!(tree.keys().windows(2).all(|pair| pair[0] <= pair[1]));
+    
+    for query in [Volume::new([10.0, 20.0], [35.5, 61.0]), Volume::new([0.0, 0.0], [100.0, 100.0]),
+                  Volume::new([-10.0, 40.0], [1.0, 60.0]), Volume::new([50.0, 50.0], [50.0, 50.0])].iter() {
+        let mut found: Vec<Object> = tree.get_in_volume(query).into_iter().cloned().collect();
+        let mut expected: Vec<Object> = objects.iter().filter(|o| query.contains(&o.quadtree_index())).cloned().collect();
+        found.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        expected.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        assert_eq!(found, expected);
+    }
+    
+    let expected = objects.iter().filter(|o| (o.x - 30.0).powi(2) + (o.y - 30.0).powi(2) <= 100.0).count();
+    assert_eq!(tree.get_in_radius([30.0, 30.0], 10.0).len(), expected);
+    assert!(LinearQuadtree::<f32, Object>::new(vol, &[]).get_in_volume(&vol).is_empty());
+}
+
+#[test]
+fn quadtree_stratified_sample() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 4);
+    // A dense cluster in one cell and a few items in two others.
+    for i in 0..100 {
+        assert!(tree.insert(Object::new(0.1 + i as f32 * 0.008, 0.5)).is_ok());
+    }
+    for &(x, y) in [(2.5, 2.5), (3.5, 0.5), (3.6, 0.6), (3.7, 0.7)].iter() {
+        assert!(tree.insert(Object::new(x, y)).is_ok());
+    }
+    
+    let mut state = 7u32;
+    let sample = tree.stratified_sample(1.0, 2, |n| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 8) as usize % n
+    });
+    assert_eq!(sample.len(), 5);
+    assert_eq!(sample.iter().filter(|o| o.x < 1.0).count(), 2);
+    assert_eq!(sample.iter().filter(|o| o.x > 3.0).count(), 2);
+    assert!(sample[0].x != sample[1].x);
+    
+    assert_eq!(tree.stratified_sample(1.0, 0, |_| 0).len(), 0);
+    assert_eq!(tree.stratified_sample(10.0, 1000, |_| 0).len(), 104);
+}
+
+#[test]
+fn quadtree_find_duplicates() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    for i in 0..10 {
+        for j in 0..10 {
+            assert!(tree.insert(Object::new(i as f32 / 10.0 + 0.03, j as f32 / 10.0 + 0.03)).is_ok());
+        }
+    }
+    // Twins inside one node, straddling the center, and a chain of three.
+    assert!(tree.insert(Object::new(0.13, 0.1301)).is_ok());
+    assert!(tree.insert(Object::new(0.4999, 0.7)).is_ok());
+    assert!(tree.insert(Object::new(0.5001, 0.7)).is_ok());
+    for &x in [0.9, 0.9008, 0.9016].iter() {
+        assert!(tree.insert(Object::new(x, 0.95)).is_ok());
+    }
+    
+    let mut groups: Vec<Vec<(f32, f32)>> = tree.find_duplicates(0.001).into_iter()
+        .map(|group| {
+            let mut group: Vec<(f32, f32)> = group.into_iter().map(|o| (o.x, o.y)).collect();
+            group.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            group
+        })
+        .collect();
+    groups.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(groups, vec![vec![(0.13, 0.13), (0.13, 0.1301)],
+                            vec![(0.4999, 0.7), (0.5001, 0.7)],
+                            vec![(0.9, 0.95), (0.9008, 0.95), (0.9016, 0.95)]]);
+    assert!(tree.find_duplicates(0.0).is_empty());
+}
+
+#[test]
+fn quadtree_locate() {
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    let objects: Vec<Object> = (0..6).map(|i| Object::new(i as f32 + 0.5, i as f32 * 1.3 + 0.2)).collect();
+    for o in objects.iter() {
+        assert!(tree.insert(o.clone()).is_ok());
+    }
+    
+    let mut seen = Vec::new();
+    for o in objects.iter() {
+        let (depth, path, volume) = tree.locate(o).unwrap();
+        assert_eq!(depth, path.depth());
+        assert!(volume.contains(&[o.x, o.y]));
+        assert!(volume.max[0] - volume.min[0] == 8.0 / (1 << depth) as f32);
+        assert!(!seen.contains(&path));
+        seen.push(path);
+    }
+    
+    assert!(tree.locate(&Object::new(0.5, 0.3)).is_none());
+    assert!(tree.locate(&Object::new(9.0, 9.0)).is_none());
+}
+
+#[test]
+fn quadtree_node_path() {
+    let root = NodePath::root();
+    assert!(root.is_root() && root.parent().is_none());
+    let path = root.child(3).child(0).child(3);
+    assert_eq!(path.depth(), 3);
+    assert_eq!(path.indices(), vec![3, 0, 3]);
+    assert_eq!(NodePath::from_indices(&[3, 0, 3]), Some(path));
+    assert_eq!(path.parent(), Some(root.child(3).child(0)));
+    assert!(root.child(3).is_prefix_of(&path) && !root.child(1).is_prefix_of(&path));
+    assert!(NodePath::from_indices(&[4]).is_none());
+    assert!(NodePath::from_indices(&vec![1; 65]).is_none());
+    let deep = NodePath::from_indices(&vec![3; 64]).unwrap();
+    assert_eq!(deep.get(63), Some(3));
+    assert_eq!(deep.parent().unwrap().get(63), None);
+    
+    let objects: Vec<Object> = (0..6).map(|i| Object::new(i as f32 + 0.5, i as f32 * 1.3 + 0.2)).collect();
+    let vol = Volume::new([0.0, 0.0], [8.0, 8.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    for o in objects.iter() {
+        assert!(tree.insert(o.clone()).is_ok());
+    }
+    for o in objects.iter() {
+        let (_, path, volume) = tree.locate(o).unwrap();
+        let node = tree.subtree(&path).unwrap();
+        assert!(node.bounds().min == volume.min && node.bounds().max == volume.max);
+        let computed = tree.volume_of(&path);
+        assert!(computed.min == volume.min && computed.max == volume.max);
+    }
+    let far = tree.volume_of(&NodePath::from_indices(&[3, 3]).unwrap());
+    assert!(far.min == [6.0, 6.0] && far.max == [8.0, 8.0]);
+}
+
+impl Snapshot for Counter {
+    fn write_snapshot(&self, out: &mut Vec<u8>) {
+        (self.inserted, self.subdivided).write_snapshot(out);
+    }
+
+    fn read_snapshot(input: &mut &[u8]) -> Result<Counter, SpatialError> {
+        <(usize, bool)>::read_snapshot(input).map(|(inserted, subdivided)| Counter { inserted: inserted, subdivided: subdivided })
+    }
+}
+
+#[test]
+fn quadtree_snapshot() {
+    let vol = Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Object, Counter> = Quadtree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for i in 0..60 {
+        assert!(tree.insert(Object::new((i * 7 % 10) as f32 + 0.25, (i * 3 % 10) as f32 + 0.5)).is_ok());
+    }
+    assert!(tree.remove(&Object::new(0.25, 0.5)).is_some());
+    
+    let mut bytes = Vec::new();
+    assert!(tree.save_to(&mut bytes).is_ok());
+    let mut loaded: Quadtree<f32, Object, Counter> = Quadtree::load_from(&mut &bytes[..]).unwrap();
+    
+    assert_eq!(loaded.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+    assert_eq!((loaded.len(), loaded.node_count(), loaded.depth()), (tree.len(), tree.node_count(), tree.depth()));
+    assert_eq!(loaded.item_order(), ItemOrder::Insertion);
+    let query = Volume::new([2.0, 2.0], [7.0, 6.0]);
+    assert_eq!(loaded.get_in_volume(&query), tree.get_in_volume(&query));
+    let (_, path, _) = tree.locate(&Object::new(7.25, 3.5)).unwrap();
+    let (a, b) = (tree.subtree(&path).unwrap().data(), loaded.subtree(&path).unwrap().data());
+    assert_eq!((a.inserted, a.subdivided), (b.inserted, b.subdivided));
+    
+    // The loaded tree goes on keeping insertion order.
+    assert!(loaded.insert(Object::new(3.25, 9.75)).is_ok());
+    assert!(tree.insert(Object::new(3.25, 9.75)).is_ok());
+    assert_eq!(loaded.get_in_volume(&vol), tree.get_in_volume(&vol));
+    
+    let malformed = |bytes: &[u8]| matches!(Quadtree::<f32, Object, Counter>::load_from(&mut &bytes[..]),
+                                            Err(SpatialError::Serialization(_)));
+    assert!(malformed(&bytes[..bytes.len() - 1]));
+    assert!(malformed(&bytes[..3]));
+    let mut versioned = bytes.clone();
+    versioned[4] += 1;
+    assert!(malformed(&versioned));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(malformed(&trailing));
+    // Nodes holding more items than the capacity, and items outside of
+    // the bounds, are refused.
+    let mut overfull = bytes.clone();
+    overfull[6] = 1;
+    assert!(malformed(&overfull));
+    let mut shrunk = bytes.clone();
+    shrunk[31..35].copy_from_slice(&[0, 0, 0xa0, 0x40]);
+    assert!(malformed(&shrunk));
+}
+
+#[test]
+fn quadtree_snapshot_huge_capacity() {
+    let vol = Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Object, Counter> = Quadtree::with_node_data(vol, 1);
+    assert!(tree.insert(Object::new(1.0, 2.0)).is_ok());
+    assert!(tree.insert(Object::new(4.0, 5.0)).is_ok());
+    let mut bytes = Vec::new();
+    assert!(tree.save_to(&mut bytes).is_ok());
+    
+    // A corrupted capacity isn't allocated for up front, neither for the
+    // root nor for the nodes it's subdivided into.
+    bytes[6..14].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    let mut loaded: Quadtree<f32, Object, Counter> = Quadtree::load_from(&mut &bytes[..]).unwrap();
+    assert_eq!(loaded.capacity(), 1 << 40);
+    assert_eq!((loaded.len(), loaded.node_count()), (tree.len(), tree.node_count()));
+    assert!(loaded.rebuild_incremental(usize::MAX).unwrap());
+    assert_eq!(loaded.len(), 2);
+}
+
+/// Applies `deltas` to `replica`, a copy of the items of each node of
+/// a tree, asserting that every item removed was there.
+fn apply_deltas(replica: &mut HashMap<NodePath, Vec<Object>>, deltas: Vec<NodeDelta<Object>>) {
+    for delta in deltas.into_iter() {
+        let items = replica.entry(delta.path).or_default();
+        for item in delta.removed.iter() {
+            let i = items.iter().position(|other| other == item).unwrap();
+            items.remove(i);
+        }
+        items.extend(delta.added);
+    }
+}
+
+/// The positions of the items of each node, by the indices of its path.
+type Nodes = Vec<(Vec<usize>, Vec<(f32, f32)>)>;
+
+/// Asserts that `replica` holds the items of each node of `tree`.
+fn assert_replicated(tree: &Quadtree<f32, Object>, replica: &HashMap<NodePath, Vec<Object>>) {
+    let mut expected: HashMap<NodePath, Vec<Object>> = HashMap::new();
+    for item in tree.iter() {
+        let (_, path, _) = tree.locate(item).unwrap();
+        expected.entry(path).or_default().push(item.clone());
+    }
+    let normalize = |map: &HashMap<NodePath, Vec<Object>>| {
+        let mut nodes: Nodes = map.iter()
+            .filter(|&(_, items)| !items.is_empty())
+            .map(|(path, items)| {
+                let mut items: Vec<(f32, f32)> = items.iter().map(|o| (o.x, o.y)).collect();
+                items.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                (path.indices(), items)
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        nodes
+    };
+    assert_eq!(normalize(replica), normalize(&expected));
+}
+
+#[test]
+fn quadtree_collect_dirty() {
+    let vol = Volume::new([0.0, 0.0], [16.0, 16.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    let mut replica = HashMap::new();
+    for i in 0..10 {
+        assert!(tree.insert(Object::new(i as f32 + 0.5, 0.5)).is_ok());
+    }
+    
+    // The first call reports everything, later ones only what changed.
+    let deltas = tree.collect_dirty();
+    assert_eq!(deltas.iter().map(|delta| delta.added.len()).sum::<usize>(), 10);
+    apply_deltas(&mut replica, deltas);
+    assert_replicated(&tree, &replica);
+    assert!(tree.collect_dirty().is_empty());
+    
+    for i in 0..40 {
+        assert!(tree.insert(Object::new((i * 7 % 16) as f32 + 0.25, (i / 3) as f32 + 1.5)).is_ok());
+    }
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    // Changes undone before the next call cancel out.
+    assert!(tree.insert(Object::new(15.5, 15.5)).is_ok());
+    assert!(tree.remove(&Object::new(15.5, 15.5)).is_some());
+    assert!(tree.update(&[0.5, 0.5], Object::new(0.75, 0.5)).is_ok());
+    assert!(tree.update(&[0.75, 0.5], Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.collect_dirty().is_empty());
+    
+    // Removals merge nodes, which are reported as emptied.
+    for i in 0..30 {
+        assert!(tree.remove(&Object::new((i * 7 % 16) as f32 + 0.25, (i / 3) as f32 + 1.5)).is_some());
+    }
+    assert!(tree.update(&[3.5, 0.5], Object::new(12.0, 12.0)).is_ok());
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    tree.retain(|o| o.x < 8.0);
+    while !tree.rebuild_incremental(2).unwrap() {}
+    tree.rebase([1.0, 0.0]);
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert_replicated(&tree, &replica);
+    
+    tree.clear();
+    apply_deltas(&mut replica, tree.collect_dirty());
+    assert!(replica.values().all(|items| items.is_empty()));
+}
+
+#[test]
+fn quadtree_view() {
+    let vol = Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    for i in 0..30 {
+        assert!(tree.insert(Object::new((i * 7 % 10) as f32 + 0.5, (i / 3) as f32 + 0.25)).is_ok());
+    }
+    
+    let query = Volume::new([2.0, 2.0], [6.0, 8.0]);
+    // Results outlive the view they came from.
+    let found = {
+        let view = tree.view();
+        view.get_in_volume(&query)
+    };
+    assert_eq!(found, tree.get_in_volume(&query));
+    
+    let view = tree.view();
+    let copy = view;
+    assert_eq!((view.len(), view.is_empty()), (30, false));
+    assert!(view.bounds().min == vol.min && view.bounds().max == vol.max);
+    assert_eq!(view.iter().count(), 30);
+    assert_eq!(view.query(&query).count(), found.len());
+    assert_eq!(copy.get_in_radius([5.0, 5.0], 3.0), tree.get_in_radius([5.0, 5.0], 3.0));
+    assert_eq!(copy.nearest(&[3.3, 3.3]), tree.nearest(&[3.3, 3.3]));
+    assert_eq!(copy.nearest_n(&[3.3, 3.3], 4), tree.nearest_n(&[3.3, 3.3], 4));
+    let ray = Ray::new([0.0, 5.0], [1.0, 0.1]);
+    assert_eq!(view.raycast(&ray).len(), tree.raycast(&ray).len());
+}
+
+#[test]
+fn quadtree_len() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 2);
+    
+    for i in 0..40 {
+        let t = i as f32 / 40.0;
+        assert!(tree.insert(Object::new(t, 1.0 - t)).is_ok());
+    }
+    assert_eq!(tree.len(), 40);
+    
+    for i in 0..10 {
+        let t = i as f32 / 40.0;
+        assert!(tree.remove(&Object::new(t, 1.0 - t)).is_some());
+    }
+    assert!(tree.update(&[0.5, 0.5], Object::new(0.05, 0.05)).is_ok());
+    tree.retain(|item| item.x < 0.75);
+    assert_eq!(tree.len(), tree.iter().count());
+    assert_eq!(tree.len(), 20);
+}
+
+#[test]
+fn quadtree_clear_retain() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, x)).is_ok());
+    }
+    
+    tree.retain(|item| item.x > 0.5);
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.occupancy(), 0b1000);
+    
+    tree.clear();
+    assert_eq!(tree.len(), 0);
+    assert_eq!(tree.node_count(), 1);
+}
+
+#[test]
+fn quadtree_query() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.4, 0.6, 0.9, 0.45].iter() {
+        assert!(tree.insert(Object::new(x, x)).is_ok());
+    }
+    
+    let region = Volume::new([0.3, 0.3], [0.7, 0.7]);
+    assert_eq!(tree.query(&region).count(), 3);
+    assert!(tree.query(&region).all(|item| item.x > 0.3 && item.x < 0.7));
+}
+
+#[test]
+fn quadtree_diffuse() {
+    let vol = Volume::new([0.0, 0.0], [4.0, 4.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(1.5, 0.5)).is_ok());
+    
+    // Four quadrants, the first one holding all the heat.
+    let values = tree.diffuse(|leaf| if leaf.path == vec![0] { 4.0 } else { 0.0 }, 1, 0.5);
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[&vec![0]], 2.0);
+    assert_eq!(values[&vec![1]], 1.0);
+    assert_eq!(values[&vec![2]], 1.0);
+    assert_eq!(values[&vec![3]], 0.0);
+    
+    let values = tree.diffuse(|leaf| if leaf.path == vec![0] { 4.0 } else { 0.0 }, 50, 0.5);
+    assert!(values.values().all(|&v| (v - 1.0).abs() < 1e-3));
+}
+
+#[test]
+fn region_quadtree_encode_decode() {
+    // The left half is black, the right half nearly white.
+    let pixels = [0.0, 0.0, 1.0, 1.0,
+                  0.0, 0.0, 1.0, 1.0,
+                  0.0, 0.0, 1.0, 0.5,
+                  0.0, 0.0, 1.0, 1.0];
+    
+    let lossless = RegionQuadtree::encode(&pixels, 4, 4, 0.0);
+    assert_eq!(lossless.leaf_count(), 7);
+    assert_eq!(lossless.decode(), pixels.to_vec());
+    assert_eq!(lossless.get(3, 2), Some(0.5));
+    assert_eq!(lossless.get(4, 0), None);
+    
+    let lossy = RegionQuadtree::encode(&pixels, 4, 4, 0.5);
+    assert_eq!(lossy.leaf_count(), 4);
+    assert_eq!(lossy.get(3, 2), Some(0.875));
+    assert_eq!(lossy.get(0, 0), Some(0.0));
+    
+    // Rasters that aren't powers of two are padded internally.
+    let odd = RegionQuadtree::encode(&[1.0, 2.0, 3.0], 3, 1, 0.0);
+    assert_eq!((odd.width(), odd.height()), (3, 1));
+    assert_eq!(odd.decode(), vec![1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn quadtree_visit() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.2, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, x)).is_ok());
+    }
+    
+    let mut visited = 0;
+    tree.visit(|_, _| { visited += 1; Visit::Continue });
+    assert_eq!(visited, tree.node_count());
+    
+    let mut visited = 0;
+    tree.visit(|_, _| { visited += 1; Visit::Skip });
+    assert_eq!(visited, 1);
+}
+
+#[test]
+fn region_quadtree_boolean_ops() {
+    let left = RegionQuadtree::encode(&[1.0, 1.0, 0.0, 0.0,
+                                        1.0, 1.0, 0.0, 0.0,
+                                        1.0, 1.0, 0.0, 0.0,
+                                        1.0, 1.0, 0.0, 0.0], 4, 4, 0.0);
+    let top = RegionQuadtree::encode(&[1.0, 1.0, 1.0, 1.0,
+                                       1.0, 1.0, 1.0, 1.0,
+                                       0.0, 0.0, 0.0, 0.0,
+                                       0.0, 0.0, 0.0, 0.5], 4, 4, 0.0);
+    
+    let union = left.union(&top);
+    assert_eq!(union.decode(), vec![1.0, 1.0, 1.0, 1.0,
+                                    1.0, 1.0, 1.0, 1.0,
+                                    1.0, 1.0, 0.0, 0.0,
+                                    1.0, 1.0, 0.0, 1.0]);
+    
+    let intersection = left.intersection(&top);
+    assert_eq!(intersection.leaf_count(), 4);
+    assert_eq!(intersection.get(0, 0), Some(1.0));
+    assert_eq!(intersection.get(3, 3), Some(0.0));
+    
+    let difference = left.difference(&top);
+    assert_eq!(difference.get(0, 0), Some(0.0));
+    assert_eq!(difference.get(0, 3), Some(1.0));
+    
+    let xor = left.xor(&top);
+    assert_eq!(xor.get(0, 0), Some(0.0));
+    assert_eq!(xor.get(3, 0), Some(1.0));
+    assert_eq!(xor.get(0, 3), Some(1.0));
+    assert_eq!(xor.get(2, 3), Some(0.0));
+    
+    // Combining a tree with itself collapses to uniform regions.
+    assert_eq!(left.xor(&left).leaf_count(), 1);
+}
+
+#[test]
+fn quadtree_raycast() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75)).is_ok());
+    
+    // Along the x axis through the lower quadrants, from the far side.
+    let hits: Vec<(f32, f32)> = tree.raycast(&Ray::new([2.0, 0.1], [-1.0, 0.0])).iter()
+        .map(|&(t, item)| (t, item.x))
+        .collect();
+    assert_eq!(hits, vec![(1.0, 0.5), (1.0, 0.75), (1.5, 0.25)]);
+    
+    assert!(tree.raycast(&Ray::new([2.0, 2.0], [1.0, 0.0])).is_empty());
+}
+
+#[test]
+fn quadtree_raycast_filtered() {
+    #[derive(Clone)]
+    struct Layered {
+        position: [f32; 2],
+        layers: u32
+    }
+    
+    impl Index<f32> for Layered {
+        fn quadtree_index(&self) -> [f32; 2] {
+            self.position
+        }
+        
+        fn quadtree_layers(&self) -> u32 {
+            self.layers
+        }
+    }
+    
+    let vol = Volume::new([0.0; 2], [1.0; 2]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    for (i, &position) in [[0.5, 0.5], [0.75, 0.25], [0.25, 0.25], [0.25, 0.75]].iter().enumerate() {
+        assert!(tree.insert(Layered { position: position, layers: 1 << (i % 2) }).is_ok());
+    }
+    
+    let ray = Ray::new([2.0, 0.1], [-1.0, 0.0]);
+    let hits = |t_max: f32, mask: u32| -> Vec<(f32, f32)> {
+        tree.raycast_filtered(&ray, t_max, mask).iter().map(|&(t, item)| (t, item.position[0])).collect()
+    };
+    assert_eq!(hits(f32::INFINITY, !0), vec![(1.0, 0.5), (1.0, 0.75), (1.5, 0.25)]);
+    // Nodes entered beyond the cutoff are skipped.
+    assert_eq!(hits(1.2, !0), vec![(1.0, 0.5), (1.0, 0.75)]);
+    assert!(hits(0.5, !0).is_empty());
+    // Only items in a layer of the mask are kept.
+    assert_eq!(hits(f32::INFINITY, 1), vec![(1.0, 0.5), (1.5, 0.25)]);
+    assert_eq!(hits(1.2, 2), vec![(1.0, 0.75)]);
+    assert_eq!(tree.view().raycast_filtered(&ray, 1.2, 2).len(), 1);
+}
+
+#[test]
+fn quadtree_get_along_segment() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    assert!(tree.insert(Object::new(0.5, 0.5)).is_ok());
+    assert!(tree.insert(Object::new(0.75, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.25)).is_ok());
+    assert!(tree.insert(Object::new(0.25, 0.75)).is_ok());
+    
+    let mut near: Vec<f32> = tree.get_along_segment(&[0.0, 0.3], &[1.0, 0.3], 0.1).iter()
+        .map(|item| item.x)
+        .collect();
+    near.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(near, vec![0.25, 0.75]);
+    assert_eq!(tree.get_along_segment(&[0.0, 0.3], &[1.0, 0.3], 0.3).len(), 3);
+    
+    // Distance is measured to the end of the segment, not the line.
+    let near = tree.get_along_segment(&[0.0, 0.75], &[0.1, 0.75], 0.2);
+    assert_eq!(near, vec![&Object::new(0.25, 0.75)]);
+    assert!(tree.get_along_segment(&[0.0, 0.9], &[0.05, 0.9], 0.1).is_empty());
+}
+
+#[test]
+fn region_quadtree_flood_fill() {
+    // Two open areas separated by a wall, in a 5 by 4 occupancy map.
+    let mut map = RegionQuadtree::encode(&[0.0, 0.0, 1.0, 0.0, 0.0,
+                                           0.0, 0.0, 1.0, 0.0, 0.0,
+                                           0.0, 0.0, 1.0, 0.0, 0.0,
+                                           0.0, 0.0, 1.0, 1.0, 0.0], 5, 4, 0.0);
+    
+    assert_eq!(map.flood_fill((0, 0), 2.0, |value| value == 0.0), 8);
+    assert_eq!(map.decode(), vec![2.0, 2.0, 1.0, 0.0, 0.0,
+                                  2.0, 2.0, 1.0, 0.0, 0.0,
+                                  2.0, 2.0, 1.0, 0.0, 0.0,
+                                  2.0, 2.0, 1.0, 1.0, 0.0]);
+    
+    // The region reaches around the corner of the wall, but not into
+    // the padding beyond the raster.
+    assert_eq!(map.flood_fill((4, 3), 3.0, |value| value == 0.0), 7);
+    assert_eq!(map.get(3, 0), Some(3.0));
+    
+    assert_eq!(map.flood_fill((2, 0), 4.0, |value| value == 0.0), 0);
+    assert_eq!(map.flood_fill((5, 0), 4.0, |value| value == 0.0), 0);
+    assert_eq!(map.get(2, 0), Some(1.0));
+}
+
+/// Returns twice the signed area of `polygon`, positive for clockwise
+/// polygons when `y` points down.
+fn area2(polygon: &[[f32; 2]]) -> f32 {
+    let n = polygon.len();
+    (0..n).fold(0.0, |sum, i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        sum + a[0] * b[1] - b[0] * a[1]
+    })
+}
+
+#[test]
+fn region_quadtree_extract_contours() {
+    // A ring with a hole in the middle.
+    let ring = RegionQuadtree::encode(&[1.0, 1.0, 1.0, 1.0,
+                                        1.0, 0.0, 0.0, 1.0,
+                                        1.0, 0.0, 0.0, 1.0,
+                                        1.0, 1.0, 1.0, 1.0], 4, 4, 0.0);
+    let mut areas: Vec<f32> = ring.extract_contours().iter().map(|contour| area2(contour)).collect();
+    areas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(areas, vec![-8.0, 32.0]);
+    
+    // A large leaf next to smaller ones.
+    let step = RegionQuadtree::encode(&[1.0, 1.0, 1.0, 0.0,
+                                        1.0, 1.0, 0.0, 0.0,
+                                        0.0, 0.0, 0.0, 0.0,
+                                        0.0, 0.0, 0.0, 0.0], 4, 4, 0.0);
+    let contours = step.extract_contours();
+    assert_eq!(contours.len(), 1);
+    assert_eq!(contours[0].len(), 6);
+    assert_eq!(area2(&contours[0]), 10.0);
+    for corner in [[0.0, 0.0], [3.0, 0.0], [3.0, 1.0], [2.0, 1.0], [2.0, 2.0], [0.0, 2.0]].iter() {
+        assert!(contours[0].contains(corner));
+    }
+    
+    // Pixels touching at a corner, and set pixels along the edge of a
+    // raster that isn't a power of two.
+    let diagonal = RegionQuadtree::encode(&[1.0, 0.0, 1.0,
+                                            0.0, 1.0, 1.0], 3, 2, 0.0);
+    let mut areas: Vec<f32> = diagonal.extract_contours().iter().map(|contour| area2(contour)).collect();
+    areas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(areas, vec![2.0, 6.0]);
+    
+    assert!(RegionQuadtree::encode(&[0.0; 4], 2, 2, 0.0).extract_contours().is_empty());
+}
+
+#[test]
+fn quadtree_query_stats() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = Quadtree::with_capacity(vol, 1);
+    
+    for &x in [0.1, 0.2, 0.9].iter() {
+        assert!(tree.insert(Object::new(x, x)).is_ok());
+    }
+    
+    // Querying the whole tree only skips the empty quadrants.
+    let (items, stats) = tree.get_in_volume_with_stats(&vol);
+    assert_eq!(items.len(), 3);
+    assert_eq!(stats.nodes_visited + stats.nodes_pruned, tree.node_count());
+    assert_eq!((stats.items_tested, stats.nodes_pruned), (3, 2));
+    
+    // A corner only enters the quadrant covering it.
+    let corner = Volume::new([0.0, 0.0], [0.15, 0.15]);
+    let (items, stats) = tree.get_in_volume_with_stats(&corner);
+    assert_eq!(items, tree.get_in_volume(&corner));
+    assert_eq!(stats.nodes_visited, 2);
+    assert_eq!(stats.max_depth, 1);
+    assert_eq!(stats.nodes_pruned, 3);
+    
+    let (items, stats) = tree.get_in_radius_with_stats([0.9, 0.9], 0.05);
+    assert_eq!(items, vec![&Object::new(0.9, 0.9)]);
+    assert_eq!(stats.nodes_visited, 2);
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Wall {
+    min: [f32; 2],
+    max: [f32; 2]
+}
+
+impl BoundedIndex<f32> for Wall {
+    fn quadtree_bounds(&self) -> Volume<f32> {
+        Volume::new(self.min, self.max)
+    }
+}
+
+#[test]
+fn bounded_quadtree_insert_query() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = BoundedQuadtree::with_capacity(vol, 1);
+    
+    let small = Wall { min: [0.1, 0.1], max: [0.2, 0.2] };
+    let long = Wall { min: [0.1, 0.4], max: [0.9, 0.6] };
+    let corner = Wall { min: [0.7, 0.7], max: [0.9, 0.9] };
+    for wall in [small.clone(), long.clone(), corner.clone()].iter() {
+        assert!(tree.insert(wall.clone()).is_ok());
+    }
+    assert!(tree.insert(Wall { min: [0.5, 0.5], max: [1.5, 0.6] }).is_err());
+    match tree.insert(Wall { min: [0.6, 0.5], max: [0.5, 0.6] }) {
+        Err(SpatialError::InvalidVolume) => {},
+        other => panic!("expected InvalidVolume, got {:?}", other)
+    }
+    assert_eq!(tree.len(), 3);
+    
+    // Items are found wherever they overlap the query, even in
+    // quadrants other than the one storing them.
+    assert_eq!(tree.get_at(&[0.8, 0.5]), vec![&long]);
+    assert_eq!(tree.get_at(&[0.15, 0.15]), vec![&small]);
+    assert!(tree.get_at(&[0.5, 0.9]).is_empty());
+    assert_eq!(tree.get_in_volume(&Volume::new([0.6, 0.55], [0.75, 0.75])).len(), 2);
+    assert_eq!(tree.get_in_volume(&vol).len(), 3);
+    
+    assert_eq!(tree.remove(&corner), Some(corner.clone()));
+    assert_eq!(tree.remove(&corner), None);
+    assert!(tree.get_at(&[0.8, 0.8]).is_empty());
+    assert_eq!(tree.remove(&small), Some(small));
+    assert_eq!(tree.remove(&long), Some(long));
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn loose_quadtree_insert_update() {
+    let vol = Volume::new([0.0, 0.0], [1.0, 1.0]);
+    let mut tree = LooseQuadtree::with_capacity(vol, 2.0, 1);
+    
+    let long = Wall { min: [0.1, 0.4], max: [0.9, 0.6] };
+    let small = Wall { min: [0.45, 0.45], max: [0.55, 0.55] };
+    let other = Wall { min: [0.1, 0.1], max: [0.2, 0.2] };
+    for wall in [long.clone(), small.clone(), other.clone()].iter() {
+        assert!(tree.insert(wall.clone()).is_ok());
+    }
+    assert!(tree.insert(Wall { min: [-0.5, 0.4], max: [1.5, 0.6] }).is_err());
+    assert!(tree.insert(Wall { min: [1.1, 0.4], max: [1.2, 0.6] }).is_err());
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.loose_bounds().min(), [-0.5, -0.5]);
+    
+    // The small wall crosses the middle of the tree but still goes down
+    // into a quadrant, whose enlarged volume holds it.
+    let mut found = tree.get_at(&[0.52, 0.52]);
+    found.sort_by(|a, b| a.min[0].partial_cmp(&b.min[0]).unwrap());
+    assert_eq!(found, vec![&long, &small]);
+    
+    let moved = Wall { min: [0.8, 0.8], max: [0.9, 0.9] };
+    assert!(tree.update(&small, moved.clone()));
+    assert!(!tree.update(&small, moved.clone()));
+    assert_eq!(tree.get_at(&[0.85, 0.85]), vec![&moved]);
+    assert_eq!(tree.get_at(&[0.52, 0.52]), vec![&long]);
+    
+    assert_eq!(tree.remove(&moved), Some(moved));
+    assert_eq!(tree.remove(&other), Some(other));
+    assert_eq!(tree.remove(&long), Some(long));
+    assert!(tree.is_empty());
+}
+
+fn assert_send_sync<X: Send + Sync>() {}
+
+#[test]
+fn quadtree_send_sync() {
+    assert_send_sync::<Quadtree<f32, Object>>();
+    assert_send_sync::<Quadtree<f32, Object, Counter>>();
+    assert_send_sync::<BoundedQuadtree<f32, Wall>>();
+    assert_send_sync::<LooseQuadtree<f32, Wall>>();
+    assert_send_sync::<RegionQuadtree<f32>>();
+    
+    let mut tree = Quadtree::new(Volume::new([0.0, 0.0], [1.0, 1.0]));
+    for i in 0..100 {
+        assert!(tree.insert(Object::new(i as f32 / 100.0, 0.5)).is_ok());
+    }
+    let tree = Arc::new(tree);
+    
+    let threads: Vec<_> = (0..4).map(|i| {
+        let tree = tree.clone();
+        thread::spawn(move || {
+            let min = i as f32 / 4.0;
+            tree.get_in_volume(&Volume::new([min, 0.0], [min + 0.245, 1.0])).len()
+        })
+    }).collect();
+    let total: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+    assert_eq!(total, 100);
+}
+
+#[test]
+fn world_index_insert_query() {
+    let mut world = WorldIndex::with_capacity(100.0, 2);
+    assert!(world.is_empty());
+    
+    assert!(world.insert(Object::new(10.0, 10.0)).is_ok());
+    assert!(world.insert(Object::new(-10.0, 10.0)).is_ok());
+    assert!(world.insert(Object::new(150.0, -250.0)).is_ok());
+    assert!(world.insert(Object::new(100.0, 0.0)).is_ok());
+    assert!(world.insert(Object::new(1.0e9, 1.0e9)).is_ok());
+    assert!(world.insert(Object::new(f32::NAN, 0.0)).is_err());
+    assert_eq!(world.len(), 5);
+    assert!(!world.is_empty());
+    assert_eq!(world.chunk_count(), 5);
+    assert_eq!(world.chunk_of(&[-10.0, 10.0]), Some((-1, 0)));
+    assert_eq!(world.chunk_of(&[100.0, 0.0]), Some((1, 0)));
+    assert_eq!(world.chunk((1, -3)).unwrap().len(), 1);
+    
+    // Queries spanning several chunks merge their results.
+    let mut found: Vec<f32> = world.get_in_volume(&Volume::new([-20.0, -300.0], [200.0, 20.0])).iter()
+        .map(|item| item.x)
+        .collect();
+    found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(found, vec![-10.0, 10.0, 100.0, 150.0]);
+    assert_eq!(world.get_in_radius([0.0, 10.0], 10.0).len(), 2);
+    assert_eq!(world.get_in_volume(&Volume::new([-1.0e10, -1.0e10], [1.0e10, 1.0e10])).len(), 5);
+    
+    // Empty chunks are dropped.
+    assert_eq!(world.remove(&Object::new(150.0, -250.0)), Some(Object::new(150.0, -250.0)));
+    assert_eq!(world.remove(&Object::new(150.0, -250.0)), None);
+    assert!(world.chunk((1, -3)).is_none());
+    assert_eq!(world.chunk_count(), 4);
+}
+
+#[test]
+fn layered_index_mask_query() {
+    const PLAYERS: u32 = 1 << 0;
+    const ENEMIES: u32 = 1 << 1;
+    const PICKUPS: u32 = 1 << 5;
+    let mut index = LayeredIndex::new(Volume::new([0.0, 0.0], [100.0, 100.0]));
+    
+    assert!(index.insert(Object::new(10.0, 10.0), 0).is_ok());
+    assert!(index.insert(Object::new(12.0, 10.0), 1).is_ok());
+    assert!(index.insert(Object::new(50.0, 50.0), 1).is_ok());
+    assert!(index.insert(Object::new(11.0, 11.0), 5).is_ok());
+    assert!(index.insert(Object::new(200.0, 0.0), 5).is_err());
+    assert_eq!(index.len(!0), 4);
+    assert_eq!(index.len(ENEMIES), 2);
+    assert!(index.layer(2).is_none());
+    
+    // Only the layers in the mask are searched.
+    let all = Volume::new([0.0, 0.0], [20.0, 20.0]);
+    assert_eq!(index.get_in_volume(&all, !0).len(), 3);
+    assert_eq!(index.get_in_volume(&all, PLAYERS | PICKUPS), vec![&Object::new(10.0, 10.0), &Object::new(11.0, 11.0)]);
+    assert_eq!(index.get_in_radius([12.0, 10.0], 1.0, ENEMIES), vec![&Object::new(12.0, 10.0)]);
+    assert_eq!(index.get_in_volume(&all, 0).len(), 0);
+    assert_eq!(index.nearest(&[11.9, 10.0], !0), Some(&Object::new(12.0, 10.0)));
+    assert_eq!(index.nearest(&[11.9, 10.0], PLAYERS | PICKUPS), Some(&Object::new(11.0, 11.0)));
+    assert_eq!(index.nearest(&[11.9, 10.0], 1 << 3), None);
+    
+    assert_eq!(index.remove(&Object::new(12.0, 10.0), 0), None);
+    assert_eq!(index.remove(&Object::new(12.0, 10.0), 1), Some(Object::new(12.0, 10.0)));
+    assert_eq!(index.len(ENEMIES), 1);
+}
+
+#[test]
+fn rebuild_incremental() {
+    let mut tree = Quadtree::with_capacity(Volume::new([0.0, 0.0], [64.0, 64.0]), 2);
+    for i in 0..32 {
+        tree.insert(Object::new(i as f32 * 2.0 + 0.5, i as f32 * 2.0 + 0.5)).unwrap();
+    }
+    // Removing the items of the upper nodes leaves their subdivisions behind.
+    for i in 0..16 {
+        tree.remove(&Object::new(i as f32 * 2.0 + 0.5, i as f32 * 2.0 + 0.5));
+    }
+    assert_eq!(tree.node_count(), 25);
+    
+    // The tree answers queries as usual while the rebuild advances.
+    let mut calls = 1;
+    while !tree.rebuild_incremental(2).unwrap() {
+        assert!(tree.is_rebuilding());
+        assert_eq!(tree.get_in_volume(&Volume::new([0.0, 0.0], [64.0, 64.0])).len(), 16);
+        calls += 1;
+    }
+    assert_eq!(calls, 5);
+    assert!(!tree.is_rebuilding());
+    assert_eq!(tree.len(), 16);
+    assert_eq!(tree.node_count(), 21);
+    assert_eq!(tree.get_in_volume(&Volume::new([32.0, 32.0], [36.0, 36.0])).len(), 2);
+    
+    // Changes to the items discard the rebuild in progress.
+    assert!(!tree.rebuild_incremental(1).unwrap());
+    tree.insert(Object::new(1.0, 1.0)).unwrap();
+    assert!(!tree.is_rebuilding());
+    assert!(tree.rebuild_incremental(100).unwrap());
+    assert_eq!(tree.len(), 17);
+}
+
+#[test]
+fn item_order_insertion() {
+    let mut tree: Quadtree<f32, Object> = Quadtree::with_item_order(Volume::new([0.0, 0.0], [16.0, 16.0]), 2,
+                                                                    ItemOrder::Insertion);
+    assert_eq!(tree.item_order(), ItemOrder::Insertion);
+    let xs = [9.0, 3.0, 14.0, 1.0, 7.0, 12.0, 5.0, 10.0];
+    for &x in xs.iter() {
+        tree.insert(Object::new(x, 16.0 - x)).unwrap();
+    }
+    let all = Volume::new([0.0, 0.0], [16.0, 16.0]);
+    let found: Vec<f32> = tree.get_in_volume(&all).iter().map(|item| item.x).collect();
+    assert_eq!(found, xs.to_vec());
+    
+    // Removals merging nodes, updates and rebuilds keep the order.
+    tree.remove(&Object::new(3.0, 13.0));
+    tree.remove(&Object::new(1.0, 15.0));
+    tree.remove(&Object::new(12.0, 4.0));
+    tree.remove(&Object::new(10.0, 6.0));
+    assert!(tree.update(&[14.0, 2.0], Object::new(2.0, 2.0)).is_ok());
+    while !tree.rebuild_incremental(1).unwrap() {}
+    let found: Vec<f32> = tree.get_in_radius([8.0, 8.0], 100.0).iter().map(|item| item.x).collect();
+    assert_eq!(found, vec![9.0, 2.0, 7.0, 5.0]);
+}
+
+#[test]
+fn item_order_morton() {
+    let mut tree: Quadtree<f32, Object> = Quadtree::with_item_order(Volume::new([0.0, 0.0], [10.0, 10.0]), 8,
+                                                                    ItemOrder::Morton);
+    tree.insert(Object::new(9.0, 9.0)).unwrap();
+    tree.insert(Object::new(1.0, 9.0)).unwrap();
+    tree.insert(Object::new(9.0, 1.0)).unwrap();
+    tree.insert(Object::new(1.0, 1.0)).unwrap();
+    let found: Vec<(f32, f32)> = tree.get_in_volume(&Volume::new([0.0, 0.0], [10.0, 10.0])).iter()
+        .map(|item| (item.x, item.y))
+        .collect();
+    assert_eq!(found, vec![(1.0, 1.0), (9.0, 1.0), (1.0, 9.0), (9.0, 9.0)]);
+}
+
@@ -0,0 +1,60 @@
+extern crate spatial;
+
+use spatial::RangeTree;
+use spatial::rangetree::Index;
+
+#[derive(Clone, PartialEq, Debug)]
+struct Sale {
+    id: usize,
+    day: f64,
+    amount: f64
+}
+
+impl Index<f64> for Sale {
+    fn rangetree_index(&self) -> [f64; 2] {
+        [self.day, self.amount]
+    }
+}
+
+/// Returns `n` points scattered with a linear congruential generator,
+/// with some duplicated coordinates.
+fn scatter(n: usize) -> Vec<[f32; 2]> {
+    let mut seed: u32 = 12345;
+    let mut next = || {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        ((seed >> 16) % 64) as f32
+    };
+    (0..n).map(|_| [next(), next()]).collect()
+}
+
+#[test]
+fn rangetree_count_matches_scan() {
+    let points = scatter(1000);
+    let tree = RangeTree::new(&points);
+    assert_eq!(tree.len(), 1000);
+
+    let queries = [([0.0, 0.0], [63.0, 63.0]), ([10.0, 20.0], [30.0, 25.0]), ([5.5, 5.5], [5.6, 60.0]),
+                   ([17.0, 17.0], [17.0, 17.0]), ([40.0, 0.0], [100.0, 10.0]), ([30.0, 30.0], [20.0, 40.0])];
+    for &(min, max) in queries.iter() {
+        let expected = points.iter()
+            .filter(|p| p[0] >= min[0] && p[0] <= max[0] && p[1] >= min[1] && p[1] <= max[1])
+            .count();
+        assert_eq!(tree.count(&min, &max), expected);
+        assert_eq!(tree.get_in_range(&min, &max).len(), expected);
+    }
+}
+
+#[test]
+fn rangetree_get_in_range() {
+    let sales: Vec<Sale> = (0..10).map(|i| Sale { id: i, day: i as f64, amount: (i * 7 % 10) as f64 }).collect();
+    let tree = RangeTree::new(&sales);
+
+    let mut found: Vec<usize> = tree.get_in_range(&[2.0, 0.0], &[6.0, 4.5]).into_iter().map(|sale| sale.id).collect();
+    found.sort();
+    assert_eq!(found, vec![2, 3, 6]);
+    assert_eq!(tree.count(&[2.0, 0.0], &[6.0, 4.5]), 3);
+
+    let empty: RangeTree<f64, Sale> = RangeTree::new(&[]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.count(&[0.0, 0.0], &[1.0, 1.0]), 0);
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "serde")]
+
+extern crate spatial;
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
+
+use spatial::{Quadtree, Octree, ItemOrder};
+use spatial::quadtree;
+use spatial::octree;
+use serde_json::Value;
+
+// Deliberately not `Default`, which deserializing a tree mustn't need.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+struct Point {
+    position: [f32; 3],
+    id: u32
+}
+
+impl quadtree::Index<f32> for Point {
+    fn quadtree_index(&self) -> [f32; 2] {
+        [self.position[0], self.position[1]]
+    }
+}
+
+impl octree::Index<f32> for Point {
+    fn octree_index(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
+fn points() -> Vec<Point> {
+    (0..40u32).map(|i| Point { position: [(i * 7 % 10) as f32 + 0.25, (i * 3 % 10) as f32 + 0.5, (i % 10) as f32], id: i }).collect()
+}
+
+#[test]
+fn serde_quadtree_round_trip() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Point> = Quadtree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for point in points() {
+        assert!(tree.insert(point).is_ok());
+    }
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let mut loaded: Quadtree<f32, Point> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+    assert_eq!((loaded.len(), loaded.node_count(), loaded.depth()), (tree.len(), tree.node_count(), tree.depth()));
+    let query = quadtree::Volume::new([2.0, 2.0], [7.0, 6.0]);
+    assert_eq!(loaded.get_in_volume(&query), tree.get_in_volume(&query));
+
+    // The loaded tree stays fully usable.
+    let first = points().remove(0);
+    assert_eq!(loaded.remove(&first), Some(first));
+    assert!(loaded.rebuild_incremental(usize::MAX).unwrap());
+    assert_eq!(loaded.len(), tree.len() - 1);
+}
+
+#[test]
+fn serde_octree_round_trip() {
+    let vol = octree::Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut tree: Octree<f32, Point> = Octree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for point in points() {
+        assert!(tree.insert(point).is_ok());
+    }
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let mut loaded: Octree<f32, Point> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+    assert_eq!((loaded.len(), loaded.node_count(), loaded.depth()), (tree.len(), tree.node_count(), tree.depth()));
+    let query = octree::Volume::new([2.0, 2.0, 2.0], [7.0, 6.0, 8.0]);
+    assert_eq!(loaded.get_in_volume(&query), tree.get_in_volume(&query));
+
+    let first = points().remove(0);
+    assert_eq!(loaded.remove(&first), Some(first));
+    assert!(loaded.rebuild_incremental(usize::MAX).unwrap());
+    assert_eq!(loaded.len(), tree.len() - 1);
+}
+
+#[test]
+fn serde_quadtree_corrupted() {
+    let vol = quadtree::Volume::new([0.0, 0.0], [10.0, 10.0]);
+    let mut tree: Quadtree<f32, Point> = Quadtree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for point in points() {
+        assert!(tree.insert(point).is_ok());
+    }
+    let json = serde_json::to_value(&tree).unwrap();
+    let corrupted = |edit: &dyn Fn(&mut Value)| {
+        let mut value = json.clone();
+        edit(&mut value);
+        serde_json::from_value::<Quadtree<f32, Point>>(value).is_err()
+    };
+
+    assert!(!corrupted(&|_| {}));
+    assert!(corrupted(&|v| v["count"] = Value::from(0)));
+    assert!(corrupted(&|v| v["quadrants"][0]["sequence"] = Value::Array(Vec::new())));
+    assert!(corrupted(&|v| v["quadrants"][1]["capacity"] = Value::from(1u64 << 40)));
+    assert!(corrupted(&|v| v["quadrants"][2]["occupied"] = Value::from(0)));
+    assert!(corrupted(&|v| v["quadrants"][3]["volume"]["max"][0] = Value::from(20.0)));
+    assert!(corrupted(&|v| v["volume"]["min"][0] = Value::from(11.0)));
+    assert!(corrupted(&|v| v["quadrants"][0]["items"][0]["position"][0] = Value::from(9.0)));
+}
+
+#[test]
+fn serde_octree_corrupted() {
+    let vol = octree::Volume::new([0.0, 0.0, 0.0], [10.0, 10.0, 10.0]);
+    let mut tree: Octree<f32, Point> = Octree::with_item_order(vol, 2, ItemOrder::Insertion);
+    for point in points() {
+        assert!(tree.insert(point).is_ok());
+    }
+    let json = serde_json::to_value(&tree).unwrap();
+    let corrupted = |edit: &dyn Fn(&mut Value)| {
+        let mut value = json.clone();
+        edit(&mut value);
+        serde_json::from_value::<Octree<f32, Point>>(value).is_err()
+    };
+
+    assert!(!corrupted(&|_| {}));
+    assert!(corrupted(&|v| v["count"] = Value::from(0)));
+    assert!(corrupted(&|v| v["octants"][0]["sequence"] = Value::Array(Vec::new())));
+    assert!(corrupted(&|v| v["octants"][1]["capacity"] = Value::from(1u64 << 40)));
+    assert!(corrupted(&|v| v["octants"][2]["occupied"] = Value::from(0)));
+    assert!(corrupted(&|v| v["octants"][3]["volume"]["max"][0] = Value::from(20.0)));
+    assert!(corrupted(&|v| v["volume"]["min"][0] = Value::from(11.0)));
+}
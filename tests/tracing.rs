@@ -0,0 +1,79 @@
+#![cfg(feature = "tracing")]
+
+extern crate spatial;
+extern crate tracing;
+
+use spatial::{Quadtree, KdTree};
+use spatial::quadtree::{self, Volume};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{Subscriber, Metadata, Event};
+use tracing::span::{Id, Attributes, Record};
+
+#[derive(Clone, PartialEq, Debug)]
+struct Point {
+    x: f32,
+    y: f32
+}
+
+impl quadtree::Index<f32> for Point {
+    fn quadtree_index(&self) -> [f32; 2] {
+        [self.x, self.y]
+    }
+}
+
+/// Records the names of the spans opened and the number of events
+/// within them.
+struct Recorder {
+    spans: Arc<Mutex<Vec<(String, String)>>>,
+    events: Arc<AtomicU64>,
+    next: AtomicU64
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let metadata = span.metadata();
+        self.spans.lock().unwrap().push((metadata.target().to_string(), metadata.name().to_string()));
+        Id::from_u64(self.next.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {
+        self.events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn tracing_spans() {
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let events = Arc::new(AtomicU64::new(0));
+    let recorder = Recorder { spans: spans.clone(), events: events.clone(), next: AtomicU64::new(0) };
+    tracing::subscriber::with_default(recorder, || {
+        let vol = Volume::new([0.0, 0.0], [10.0, 10.0]);
+        let mut tree: Quadtree<f32, Point> = Quadtree::with_capacity(vol, 4);
+        for i in 0..50 {
+            assert!(tree.insert(Point { x: (i % 10) as f32, y: (i / 10) as f32 }).is_ok());
+        }
+        assert_eq!(tree.get_in_volume(&Volume::new([2.0, 2.0], [4.0, 3.0])).len(), 6);
+        assert_eq!(tree.nearest_n(&[5.0, 2.0], 3).len(), 3);
+        let kdtree = KdTree::new(2, &[[1.0f32, 2.0], [3.0, 4.0]]);
+        assert_eq!(kdtree.len(), 2);
+    });
+
+    let spans = spans.lock().unwrap();
+    for name in ["quadtree query", "quadtree nearest", "kdtree build"].iter() {
+        assert!(spans.contains(&("spatial".to_string(), name.to_string())), "no {} span in {:?}", name, *spans);
+    }
+    assert!(events.load(Ordering::SeqCst) >= 53);
+}
@@ -0,0 +1,59 @@
+extern crate spatial;
+
+use spatial::VpTree;
+
+/// Returns the Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> f32 {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let above = row[j + 1];
+            let cost = if ca == b[j] { 0 } else { 1 };
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + cost);
+            diagonal = above;
+        }
+    }
+    row[b.len()] as f32
+}
+
+fn manhattan(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+    (a[0] - b[0]).abs() + (a[1] - b[1]).abs()
+}
+
+#[test]
+fn vptree_edit_distance() {
+    let words = ["kitten", "sitting", "mitten", "fitting", "bitten", "written", "sitter", "knitting",
+                 "kitchen", "mittens", "smitten", "sit", "kit", "fit", "tin", "ten"];
+    let tree = VpTree::new(words.iter().map(|w| w.to_string()).collect(), |a: &String, b: &String| edit_distance(a, b));
+    assert_eq!(tree.len(), words.len());
+
+    assert_eq!(tree.nearest(&"sittin".to_string()), Some(&"sitting".to_string()));
+    let mut close: Vec<&str> = tree.get_in_radius(&"kitten".to_string(), 1.0).into_iter().map(|w| &w[..]).collect();
+    close.sort();
+    assert_eq!(close, vec!["bitten", "kitten", "mitten"]);
+}
+
+#[test]
+fn vptree_nearest_n_matches_scan() {
+    let mut points = Vec::new();
+    for x in 0..20 {
+        for y in 0..20 {
+            points.push([(x * 7 % 20) as f64 + 0.1 * y as f64, (y * 3 % 20) as f64]);
+        }
+    }
+    let tree = VpTree::new(points.clone(), manhattan);
+
+    for query in [[0.0, 0.0], [10.3, 7.7], [25.0, -3.0]].iter() {
+        let mut expected: Vec<f64> = points.iter().map(|p| manhattan(p, query)).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let found: Vec<f64> = tree.nearest_n(query, 10).into_iter().map(|p| manhattan(p, query)).collect();
+        assert_eq!(found, &expected[..10]);
+        assert_eq!(tree.get_in_radius(query, 4.0).len(), expected.iter().filter(|&&d| d <= 4.0).count());
+    }
+
+    let empty: VpTree<f64, [f64; 2], _> = VpTree::new(Vec::new(), manhattan);
+    assert!(empty.nearest(&[0.0, 0.0]).is_none());
+}